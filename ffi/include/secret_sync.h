@@ -0,0 +1,25 @@
+/* C interface for the secret-sync shared library */
+
+#ifndef SECRET_SYNC_H
+#define SECRET_SYNC_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Pull the secrets described by the JSON request, returns a JSON response
+ * that must be released with secret_sync_string_free */
+char *secret_sync_pull(const char *request);
+
+/* Push the secrets described by the JSON request, returns a JSON response
+ * that must be released with secret_sync_string_free */
+char *secret_sync_push(const char *request);
+
+/* Release a string returned by secret-sync */
+void secret_sync_string_free(char *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SECRET_SYNC_H */
@@ -0,0 +1,272 @@
+//! # Secret-sync FFI
+//!
+//! C ABI for running secret-sync pulls and pushes from other languages
+//! without shelling out to the CLI.
+//!
+//! Each function accepts a JSON request as a NUL terminated UTF-8 string
+//! and returns a JSON response that must be released with
+//! [`secret_sync_string_free`]. Requests take the form:
+//!
+//! ```json
+//! {
+//!     "config": "/path/to/secret-sync.toml",
+//!     "files": ["example"],
+//!     "globs": ["example-*"],
+//!     "environment": "production",
+//!     "profile": "example",
+//!     "region": "ap-southeast-2"
+//! }
+//! ```
+//!
+//! Only `config` is required. Responses match the JSON output of the CLI,
+//! `{"success": true, ...}` on success or
+//! `{"success": false, "error": "...", "kind": "..."}` on failure, where
+//! `kind` is the kind of error (e.g. `"backend-auth"`) or null.
+
+#![warn(missing_docs)]
+
+use eyre::{Context, ContextCompat};
+use secret_sync::{
+    cancel::CancellationToken,
+    config::{filter_files, read_config_file},
+    error::SyncError,
+    fs::real::RealFs,
+    pull::{PullOptions, pull_secret_files},
+    push::{PushOptions, push_secret_files},
+    secret::create_secret_manager,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    ffi::{CStr, CString, c_char},
+    future::Future,
+    panic::{AssertUnwindSafe, catch_unwind},
+    path::{PathBuf, absolute},
+};
+
+/// Request accepted by the pull and push functions
+#[derive(Debug, Deserialize)]
+struct SyncRequest {
+    /// Path to the secret-sync config file
+    config: PathBuf,
+    /// Optional file names to match
+    files: Option<Vec<String>>,
+    /// Optional globs for file names to match
+    globs: Option<Vec<String>>,
+    /// Optional environment replacing {env} within the config
+    environment: Option<String>,
+    /// Optional AWS profile override
+    profile: Option<String>,
+    /// Optional AWS region override
+    region: Option<String>,
+}
+
+/// Operation performed for a request
+#[derive(Debug, Clone, Copy)]
+enum SyncOperation {
+    Pull,
+    Push,
+}
+
+/// Pull the secrets described by the JSON `request`
+///
+/// # Safety
+///
+/// `request` must be a valid pointer to a NUL terminated string or null.
+/// The returned string must be released with [`secret_sync_string_free`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn secret_sync_pull(request: *const c_char) -> *mut c_char {
+    // SAFETY: Caller guarantees `request` is a valid string or null
+    unsafe { handle_request(request, SyncOperation::Pull) }
+}
+
+/// Push the secrets described by the JSON `request`
+///
+/// # Safety
+///
+/// `request` must be a valid pointer to a NUL terminated string or null.
+/// The returned string must be released with [`secret_sync_string_free`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn secret_sync_push(request: *const c_char) -> *mut c_char {
+    // SAFETY: Caller guarantees `request` is a valid string or null
+    unsafe { handle_request(request, SyncOperation::Push) }
+}
+
+/// Release a string returned by this library
+///
+/// # Safety
+///
+/// `value` must be a string returned by this library that has not already
+/// been released, or null
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn secret_sync_string_free(value: *mut c_char) {
+    if value.is_null() {
+        return;
+    }
+
+    // SAFETY: Caller guarantees `value` was created by `CString::into_raw`
+    drop(unsafe { CString::from_raw(value) });
+}
+
+/// Read the raw `request` and run the `operation`, converting the outcome
+/// into a JSON response
+///
+/// # Safety
+///
+/// `request` must be a valid pointer to a NUL terminated string or null
+unsafe fn handle_request(request: *const c_char, operation: SyncOperation) -> *mut c_char {
+    let request = if request.is_null() {
+        Err(eyre::eyre!("request must not be null"))
+    } else {
+        // SAFETY: Caller guarantees `request` is a valid NUL terminated string
+        unsafe { CStr::from_ptr(request) }
+            .to_str()
+            .context("request is not valid UTF-8")
+            .map(str::to_string)
+    };
+
+    let response = request.and_then(|request| {
+        // Panics must not unwind across the FFI boundary
+        catch_unwind(AssertUnwindSafe(|| run_request(&request, operation)))
+            .unwrap_or_else(|_| Err(eyre::eyre!("secret-sync panicked")))
+    });
+
+    let response = match response {
+        Ok(value) => value,
+        Err(error) => json!({
+            "success": false,
+            "error": format!("{error:?}"),
+            "kind": SyncError::find(error.as_ref()).map(SyncError::kind),
+        }),
+    };
+
+    into_c_string(response.to_string())
+}
+
+/// Parse the JSON `request` and run the `operation`
+fn run_request(request: &str, operation: SyncOperation) -> eyre::Result<Value> {
+    let request: SyncRequest = serde_json::from_str(request).context("failed to parse request")?;
+
+    block_on(async move {
+        let config_path = absolute(&request.config).context("failed to resolve config path")?;
+        let config_directory = config_path
+            .parent()
+            .context("config file has no parent directory")?;
+
+        let mut config = read_config_file(&config_path).await?;
+        config.prepare(request.environment.as_deref(), &[])?;
+
+        let working_path = &config.paths.working_path(config_directory);
+
+        if request.profile.is_some() {
+            config.aws.profile = request.profile;
+        }
+
+        if request.region.is_some() {
+            config.aws.region = request.region;
+        }
+
+        let files = filter_files(
+            &config.files,
+            request.files.as_deref(),
+            request.globs.as_deref(),
+        );
+        let files = files.into_iter().map(|(_, file)| file);
+
+        let secret = create_secret_manager(&config).await?;
+        let fs = RealFs;
+
+        let cancel = CancellationToken::new();
+
+        match operation {
+            SyncOperation::Pull => {
+                let outcome = pull_secret_files(
+                    &fs,
+                    secret.as_ref(),
+                    working_path,
+                    files,
+                    PullOptions::default(),
+                    &(),
+                    &cancel,
+                )
+                .await?;
+                Ok(json!({ "success": true, "changed": outcome.changed }))
+            }
+            SyncOperation::Push => {
+                let options = PushOptions {
+                    max_files: config.push.max_files,
+                    ..Default::default()
+                };
+                push_secret_files(
+                    &fs,
+                    secret.as_ref(),
+                    working_path,
+                    files,
+                    &options,
+                    &(),
+                    &cancel,
+                )
+                .await?;
+                Ok(json!({ "success": true }))
+            }
+        }
+    })
+}
+
+/// Run the `future` to completion on a new runtime
+fn block_on<F: Future<Output = eyre::Result<Value>>>(future: F) -> eyre::Result<Value> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("failed to create runtime")?;
+
+    runtime.block_on(future)
+}
+
+/// Convert `value` into an owned C string for returning to the caller
+fn into_c_string(value: String) -> *mut c_char {
+    // JSON output escapes NUL characters so this cannot fail
+    CString::new(value).unwrap_or_default().into_raw()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{secret_sync_pull, secret_sync_string_free};
+    use serde_json::Value;
+    use std::ffi::{CStr, CString};
+
+    /// Call [secret_sync_pull] with `request` returning the parsed response
+    fn pull(request: &str) -> Value {
+        let request = CString::new(request).unwrap();
+
+        unsafe {
+            let response = secret_sync_pull(request.as_ptr());
+            let value = serde_json::from_str(CStr::from_ptr(response).to_str().unwrap()).unwrap();
+            secret_sync_string_free(response);
+            value
+        }
+    }
+
+    /// Tests that invalid requests produce an error response
+    #[test]
+    fn test_invalid_request() {
+        let response = pull("not json");
+        assert_eq!(response["success"], false);
+        assert!(response["error"].is_string());
+
+        let response = pull(r#"{"config": "/does/not/exist/secret-sync.toml"}"#);
+        assert_eq!(response["success"], false);
+    }
+
+    /// Tests that a null request produces an error response
+    #[test]
+    fn test_null_request() {
+        unsafe {
+            let response = secret_sync_pull(std::ptr::null());
+            let value: Value =
+                serde_json::from_str(CStr::from_ptr(response).to_str().unwrap()).unwrap();
+            secret_sync_string_free(response);
+            assert_eq!(value["success"], false);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! # Agent
+//!
+//! Long running agent (similar to ssh-agent) that holds pulled secrets in
+//! memory and serves them to local processes over a unix socket, allowing
+//! applications to fetch secrets at startup without writing them to disk.
+//!
+//! Only processes running as the same user as the agent may connect. Each
+//! request is a single line of JSON answered with a single line of JSON:
+//!
+//! - `{"command": "list"}` Names of the available files
+//! - `{"command": "get", "file": "<name>"}` Value of a file
+
+use eyre::{Context, ContextCompat};
+use indexmap::IndexMap;
+use secret_sync::{
+    config::SecretFile,
+    secret::{Secret, SecretManager},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+/// Environment variable containing the agent socket path
+pub const AGENT_SOCKET_ENV: &str = "SECRET_SYNC_AGENT_SOCK";
+
+/// Request sent to the agent
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AgentRequest {
+    /// List the available files
+    List,
+    /// Get the value of a file
+    Get {
+        /// Name of the file within the config
+        file: String,
+    },
+}
+
+/// Resolve the socket path used by agent clients
+pub fn agent_socket_path(socket: Option<PathBuf>) -> eyre::Result<PathBuf> {
+    socket
+        .or_else(|| std::env::var_os(AGENT_SOCKET_ENV).map(PathBuf::from))
+        .with_context(|| format!("missing agent socket, use --socket or set {AGENT_SOCKET_ENV}"))
+}
+
+/// Fetch the values for each of the `files` to hold in memory
+pub async fn load_agent_values<'a>(
+    secret: &dyn SecretManager,
+    files: impl IntoIterator<Item = (&'a String, &'a SecretFile)>,
+) -> eyre::Result<IndexMap<String, Secret>> {
+    let mut values = IndexMap::new();
+
+    for (name, file) in files {
+        let value = secret.get_secret(&file.secret).await?.data;
+        values.insert(name.clone(), value);
+    }
+
+    Ok(values)
+}
+
+/// Handle a single raw `request` line against the held `values`
+fn handle_request(values: &IndexMap<String, Secret>, request: &str) -> Value {
+    let request: AgentRequest = match serde_json::from_str(request) {
+        Ok(value) => value,
+        Err(error) => {
+            return json!({ "success": false, "error": format!("invalid request: {error}") });
+        }
+    };
+
+    match request {
+        AgentRequest::List => {
+            json!({ "success": true, "files": values.keys().collect::<Vec<_>>() })
+        }
+        AgentRequest::Get { file } => match values.get(&file) {
+            Some(Secret::String(value)) => json!({ "success": true, "value": value }),
+            Some(Secret::Binary(value)) => {
+                json!({ "success": true, "value": hex::encode(value), "encoding": "hex" })
+            }
+            None => json!({ "success": false, "error": format!("unknown file \"{file}\"") }),
+        },
+    }
+}
+
+/// Serve the `values` on the unix socket at `path` until interrupted
+#[cfg(unix)]
+pub async fn run_agent(path: &Path, values: IndexMap<String, Secret>) -> eyre::Result<()> {
+    use crate::serve::bind_private_socket;
+    use std::{os::unix::fs::MetadataExt, sync::Arc};
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .context("failed to create agent socket directory")?;
+    }
+
+    let listener = bind_private_socket(path)?;
+
+    // The socket is owned by the user running the agent
+    let owner = std::fs::metadata(path)
+        .context("failed to read agent socket metadata")?
+        .uid();
+
+    let values = Arc::new(values);
+
+    tracing::info!(path = %path.display(), files = values.len(), "agent started");
+
+    let result = loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let stream = match result {
+                    Ok((stream, _)) => stream,
+                    Err(error) => break Err(error).context("failed to accept connection"),
+                };
+
+                let peer = match stream.peer_cred() {
+                    Ok(value) => value,
+                    Err(error) => {
+                        tracing::warn!(?error, "failed to read peer credentials");
+                        continue;
+                    }
+                };
+
+                if peer.uid() != owner {
+                    tracing::warn!(uid = peer.uid(), pid = ?peer.pid(), "rejected connection from another user");
+                    continue;
+                }
+
+                tokio::spawn(handle_connection(stream, values.clone()));
+            }
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+    };
+
+    _ = std::fs::remove_file(path);
+    result
+}
+
+/// Serve the `values` on the unix socket at `path` until interrupted
+#[cfg(not(unix))]
+pub async fn run_agent(_path: &Path, _values: IndexMap<String, Secret>) -> eyre::Result<()> {
+    eyre::bail!("the agent requires unix sockets which are not supported on this platform")
+}
+
+/// Answer each request line sent over `stream`, the connection is closed
+/// when a line is too long or is not sent within the read timeout
+#[cfg(unix)]
+async fn handle_connection(
+    stream: tokio::net::UnixStream,
+    values: std::sync::Arc<IndexMap<String, Secret>>,
+) {
+    use crate::serve::{READ_TIMEOUT, read_line};
+    use tokio::io::{AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let line = match tokio::time::timeout(READ_TIMEOUT, read_line(&mut reader)).await {
+            Ok(Ok(line)) if line.is_empty() => break,
+            Ok(Ok(line)) => line,
+            Ok(Err(error)) => {
+                tracing::debug!(?error, "failed to read agent request");
+                break;
+            }
+            Err(_) => {
+                tracing::debug!("timed out waiting for agent request");
+                break;
+            }
+        };
+
+        let mut response = handle_request(&values, line.trim_end()).to_string();
+        response.push('\n');
+
+        if let Err(error) = writer.write_all(response.as_bytes()).await {
+            tracing::debug!(?error, "agent client disconnected");
+            break;
+        }
+    }
+}
+
+/// Send a `request` to the agent listening at `path`
+#[cfg(unix)]
+pub async fn agent_request(path: &Path, request: &AgentRequest) -> eyre::Result<Value> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let stream = tokio::net::UnixStream::connect(path)
+        .await
+        .with_context(|| format!("failed to connect to agent at \"{}\"", path.display()))?;
+
+    let (reader, mut writer) = stream.into_split();
+
+    let mut request = serde_json::to_string(request)?;
+    request.push('\n');
+    writer
+        .write_all(request.as_bytes())
+        .await
+        .context("failed to send agent request")?;
+
+    let response = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await
+        .context("failed to read agent response")?
+        .context("agent closed the connection")?;
+
+    let response: Value =
+        serde_json::from_str(&response).context("failed to parse agent response")?;
+
+    if response["success"] != true {
+        let error = response["error"].as_str().unwrap_or("unknown error");
+        eyre::bail!("agent request failed: {error}");
+    }
+
+    Ok(response)
+}
+
+/// Send a `request` to the agent listening at `path`
+#[cfg(not(unix))]
+pub async fn agent_request(_path: &Path, _request: &AgentRequest) -> eyre::Result<Value> {
+    eyre::bail!("the agent requires unix sockets which are not supported on this platform")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::agent::handle_request;
+    use indexmap::IndexMap;
+    use secret_sync::secret::Secret;
+
+    /// Tests listing and getting held values
+    #[test]
+    fn test_agent_requests() {
+        let mut values = IndexMap::new();
+        values.insert("app".to_string(), Secret::String("TEST=1".to_string()));
+        values.insert("cert".to_string(), Secret::Binary(vec![0xde, 0xad]));
+
+        let response = handle_request(&values, r#"{"command":"list"}"#);
+        assert_eq!(response["files"], serde_json::json!(["app", "cert"]));
+
+        let response = handle_request(&values, r#"{"command":"get","file":"app"}"#);
+        assert_eq!(response["value"], "TEST=1");
+
+        let response = handle_request(&values, r#"{"command":"get","file":"cert"}"#);
+        assert_eq!(response["value"], "dead");
+        assert_eq!(response["encoding"], "hex");
+
+        let response = handle_request(&values, r#"{"command":"get","file":"missing"}"#);
+        assert_eq!(response["success"], false);
+
+        let response = handle_request(&values, "not json");
+        assert_eq!(response["success"], false);
+    }
+
+    /// Tests that connections sending a line longer than the limit are closed
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_agent_line_too_long() {
+        use crate::{agent::handle_connection, serve::MAX_LINE_LENGTH};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, server) = tokio::net::UnixStream::pair().unwrap();
+        let connection = tokio::spawn(handle_connection(server, Default::default()));
+
+        client
+            .write_all("x".repeat(MAX_LINE_LENGTH).as_bytes())
+            .await
+            .unwrap();
+        connection.await.unwrap();
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+    }
+}
@@ -0,0 +1,476 @@
+//! # Approval
+//!
+//! Four-eyes approval workflow for pushes. Instead of pushing, a change
+//! request bundle is written containing the redacted push plan (hashes
+//! only, never values) signed using the private key of the requester. A
+//! second operator verifies and applies the bundle using the approve
+//! subcommand.
+//!
+//! Requesters and approvers are identified by the caller identity of their
+//! backend credentials (e.g. the ARN of the AWS credentials) rather than
+//! the local user name. Bundles are verified using the public key the
+//! config registers for the requester within `approval.signers`, so a
+//! requester cannot sign a bundle on behalf of another identity and
+//! approve it themselves
+
+use crate::{
+    clock::{Clock, unix_seconds},
+    error::{ErrorContext, Result, ResultExt, SyncError},
+    fs::FileSystem,
+    plan::{ApplySummary, Plan, apply_plan},
+    secret::SecretManager,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Environment variable containing the hex encoded Ed25519 private key the
+/// requester signs change request bundles with
+pub const APPROVAL_KEY_ENV: &str = "SECRET_SYNC_APPROVAL_KEY";
+
+/// Default file name for change request bundles
+pub const DEFAULT_BUNDLE_FILE_NAME: &str = "change-request.json";
+
+/// Change request awaiting approval
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangeRequest {
+    /// Caller identity of the backend credentials that requested the
+    /// change, see [approval_identity]
+    pub requested_by: String,
+    /// Unix timestamp (seconds) of when the change was requested
+    pub requested_at: u64,
+    /// Redacted plan of the changes to apply
+    pub plan: Plan,
+}
+
+/// Signed change request bundle
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangeRequestBundle {
+    /// The change request
+    pub request: ChangeRequest,
+    /// Hex encoded Ed25519 signature of the request by the requester
+    pub signature: String,
+}
+
+/// Outcome of approving a change request
+#[derive(Debug, PartialEq, Eq)]
+pub struct ApprovalSummary {
+    /// Caller identity of the backend credentials that approved the change
+    pub approved_by: String,
+    /// Outcome of applying the plan of the change request
+    pub applied: ApplySummary,
+}
+
+/// Get the name of the current user from the environment, only used for
+/// display as it can be set to anything
+pub fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Identity recording who requested or approved a change, taken from the
+/// caller identity of the `secret` backend credentials (see
+/// [approval_principal])
+///
+/// Fails for backends without a caller identity (e.g. the local backend)
+/// as the requester could not be told apart from the approver
+pub async fn approval_identity(secret: &dyn SecretManager) -> Result<String> {
+    let identity = secret
+        .caller_identity()
+        .await
+        .context("failed to get the identity of the backend credentials")?
+        .ok_or_else(|| {
+            SyncError::config(
+                "change requests require a backend that identifies its caller (aws, ssm, gcp, or azure)",
+            )
+        })?;
+
+    Ok(approval_principal(&identity))
+}
+
+/// Principal behind the caller `identity`, AWS assumed role sessions are
+/// named by the caller so the session name is stripped leaving the role
+///
+/// Everyone assuming the same role is therefore the same principal and
+/// cannot approve the change requests of one another
+pub fn approval_principal(identity: &str) -> String {
+    let Some((account, role)) = identity
+        .strip_prefix("arn:")
+        .and_then(|value| value.split_once(":assumed-role/"))
+    else {
+        return identity.to_string();
+    };
+
+    match role.split_once('/') {
+        Some((role, _session)) => format!("arn:{account}:assumed-role/{role}"),
+        None => identity.to_string(),
+    }
+}
+
+/// Load the approval signing key of the requester from the environment
+pub fn approval_key() -> Result<SigningKey> {
+    let key = std::env::var(APPROVAL_KEY_ENV)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            SyncError::config(format!(
+                "missing approval signing key, set {APPROVAL_KEY_ENV}"
+            ))
+        })?;
+
+    parse_signing_key(&key)
+}
+
+/// Parse a hex encoded Ed25519 private `key`
+pub fn parse_signing_key(key: &str) -> Result<SigningKey> {
+    let key: [u8; 32] = hex::decode(key.trim())
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| {
+            SyncError::config(format!(
+                "invalid approval signing key, {APPROVAL_KEY_ENV} must be 32 hex encoded bytes"
+            ))
+        })?;
+
+    Ok(SigningKey::from_bytes(&key))
+}
+
+/// Public key registered within the `signers` for the identity
+/// `requested_by`
+fn signer_key(signers: &IndexMap<String, String>, requested_by: &str) -> Result<VerifyingKey> {
+    let key = signers.get(requested_by).ok_or_else(|| {
+        SyncError::validation(format!(
+            "no approval signer is registered for \"{requested_by}\" within approval.signers"
+        ))
+    })?;
+
+    let key: [u8; 32] = hex::decode(key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| {
+            SyncError::config(format!(
+                "approval signer of \"{requested_by}\" must be a 32 byte hex encoded public key"
+            ))
+        })?;
+
+    VerifyingKey::from_bytes(&key)
+        .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+        .with_context(|| format!("invalid approval signer for \"{requested_by}\""))
+}
+
+/// Serialize the `request` for signing
+fn request_bytes(request: &ChangeRequest) -> Result<Vec<u8>> {
+    serde_json::to_vec(request)
+        .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+        .context("failed to serialize change request")
+}
+
+/// Create a change request bundle for the provided `plan` requested by the
+/// identity `requested_by` (see [approval_identity]) signed using their
+/// `key`, the request time is taken from the `clock`
+///
+/// The `key` must belong to the public key registered for the requester
+/// within the `signers`, otherwise the bundle could never be approved
+pub fn create_bundle(
+    key: &SigningKey,
+    signers: &IndexMap<String, String>,
+    clock: &dyn Clock,
+    requested_by: String,
+    plan: Plan,
+) -> Result<ChangeRequestBundle> {
+    let public_key = hex::encode(key.verifying_key().as_bytes());
+    if signer_key(signers, &requested_by).ok() != Some(key.verifying_key()) {
+        return Err(SyncError::config(format!(
+            "approval signing key is not registered for \"{requested_by}\", \
+             add \"{requested_by}\" = \"{public_key}\" to approval.signers"
+        )));
+    }
+
+    let requested_at = unix_seconds(clock);
+
+    let request = ChangeRequest {
+        requested_by,
+        requested_at,
+        plan,
+    };
+
+    let signature = hex::encode(key.sign(&request_bytes(&request)?).to_bytes());
+
+    Ok(ChangeRequestBundle { request, signature })
+}
+
+/// Verify the signature of a change request `bundle` using the public key
+/// registered for its requester within the `signers`
+pub fn verify_bundle(
+    signers: &IndexMap<String, String>,
+    bundle: &ChangeRequestBundle,
+) -> Result<()> {
+    let key = signer_key(signers, &bundle.request.requested_by)?;
+
+    let signature = hex::decode(&bundle.signature)
+        .ok()
+        .and_then(|signature| Signature::from_slice(&signature).ok())
+        .ok_or_else(|| SyncError::validation("invalid bundle signature"))?;
+
+    key.verify_strict(&request_bytes(&bundle.request)?, &signature)
+        .map_err(|_| SyncError::validation("change request bundle signature is invalid"))
+}
+
+/// Verify and apply a change request `bundle`, the plan paths are relative
+/// to the `working_path`
+///
+/// The bundle must be signed by the requester (see [verify_bundle]) and the
+/// approver is identified using [approval_identity], which must be different
+/// to the requester. Existing secrets not created by secret-sync are only
+/// overwritten when `adopt` is set
+pub async fn approve_bundle<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    signers: &IndexMap<String, String>,
+    bundle: &ChangeRequestBundle,
+    adopt: bool,
+) -> Result<ApprovalSummary> {
+    verify_bundle(signers, bundle)?;
+
+    let approved_by = approval_identity(secret).await?;
+    if approval_principal(&bundle.request.requested_by) == approved_by {
+        return Err(SyncError::validation(
+            "change request must be approved by a different user than the requester",
+        ));
+    }
+
+    let applied = apply_plan(fs, secret, working_path, &bundle.request.plan, adopt).await?;
+
+    Ok(ApprovalSummary {
+        approved_by,
+        applied,
+    })
+}
+
+/// Read a change request bundle from the file at `path`
+#[cfg(not(target_family = "wasm"))]
+pub async fn read_bundle_file(path: &Path) -> Result<ChangeRequestBundle> {
+    let value = tokio::fs::read(path)
+        .await
+        .context("failed to read change request bundle")?;
+
+    serde_json::from_slice(&value)
+        .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+        .context("failed to parse change request bundle")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        approval::{
+            approval_identity, approval_principal, approve_bundle, create_bundle,
+            parse_signing_key, verify_bundle,
+        },
+        clock::SystemClock,
+        fs::MockFileSystem,
+        plan::Plan,
+        secret::MockSecretManager,
+    };
+    use ed25519_dalek::SigningKey;
+    use indexmap::IndexMap;
+    use std::path::Path;
+
+    const ALICE: &str = "arn:aws:iam::123456789012:user/alice";
+    const BOB: &str = "arn:aws:iam::123456789012:user/bob";
+
+    /// Creates an empty plan for a change request
+    fn empty_plan() -> Plan {
+        Plan {
+            version: 1,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Creates a signing key from the repeated `byte`
+    fn signing_key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32])
+    }
+
+    /// Creates the registered signers with alice using key 1 and bob
+    /// using key 2
+    fn signers() -> IndexMap<String, String> {
+        [(ALICE, 1), (BOB, 2)]
+            .into_iter()
+            .map(|(identity, byte)| {
+                let key = signing_key(byte).verifying_key();
+                (identity.to_string(), hex::encode(key.as_bytes()))
+            })
+            .collect()
+    }
+
+    /// Creates a secret manager authenticated as the caller `identity`
+    fn caller(identity: Option<&'static str>) -> MockSecretManager {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_caller_identity()
+            .returning(move || Ok(identity.map(str::to_string)));
+        secret
+    }
+
+    /// Tests parsing hex encoded signing keys
+    #[test]
+    fn test_parse_signing_key() {
+        let key = parse_signing_key(&hex::encode([1; 32])).unwrap();
+        assert_eq!(key, signing_key(1));
+
+        assert!(parse_signing_key("not hex").is_err());
+        assert!(parse_signing_key(&hex::encode([1; 16])).is_err());
+    }
+
+    /// Tests that the session name of assumed roles is stripped
+    #[test]
+    fn test_approval_principal() {
+        assert_eq!(
+            approval_principal("arn:aws:sts::123456789012:assumed-role/deploy/alice"),
+            "arn:aws:sts::123456789012:assumed-role/deploy"
+        );
+        assert_eq!(approval_principal(ALICE), ALICE);
+        assert_eq!(approval_principal("tenant/object-id"), "tenant/object-id");
+    }
+
+    /// Tests that a bundle signature is verified with the key registered
+    /// for the requester
+    #[test]
+    fn test_verify_bundle() {
+        let signers = signers();
+        let mut bundle = create_bundle(
+            &signing_key(1),
+            &signers,
+            &SystemClock,
+            ALICE.to_string(),
+            empty_plan(),
+        )
+        .unwrap();
+        verify_bundle(&signers, &bundle).unwrap();
+
+        // Requesters without a registered signer cannot be verified
+        assert!(verify_bundle(&IndexMap::new(), &bundle).is_err());
+
+        // Tampering with the request invalidates the signature
+        bundle.request.requested_by = BOB.to_string();
+        assert!(verify_bundle(&signers, &bundle).is_err());
+
+        // Keys can only sign for the identity they are registered for
+        assert!(
+            create_bundle(
+                &signing_key(1),
+                &signers,
+                &SystemClock,
+                BOB.to_string(),
+                empty_plan(),
+            )
+            .is_err()
+        );
+    }
+
+    /// Tests that the requester cannot approve their own change, regardless
+    /// of the local user name
+    #[tokio::test]
+    async fn test_approve_own_bundle() {
+        let signers = signers();
+        let bundle = create_bundle(
+            &signing_key(1),
+            &signers,
+            &SystemClock,
+            ALICE.to_string(),
+            empty_plan(),
+        )
+        .unwrap();
+
+        let fs = MockFileSystem::new();
+        let path = Path::new("/");
+
+        let secret = caller(Some(ALICE));
+        assert!(
+            approve_bundle(&fs, &secret, path, &signers, &bundle, false)
+                .await
+                .is_err()
+        );
+
+        // Backends without a caller identity cannot tell the users apart
+        let secret = caller(None);
+        assert!(
+            approve_bundle(&fs, &secret, path, &signers, &bundle, false)
+                .await
+                .is_err()
+        );
+
+        let secret = caller(Some(BOB));
+        let summary = approve_bundle(&fs, &secret, path, &signers, &bundle, false)
+            .await
+            .unwrap();
+        assert_eq!(summary.approved_by, BOB);
+    }
+
+    /// Tests that a requester re-signing a bundle on behalf of another
+    /// identity cannot approve it themselves
+    #[tokio::test]
+    async fn test_approve_resigned_bundle() {
+        let signers = signers();
+        let mut bundle = create_bundle(
+            &signing_key(1),
+            &signers,
+            &SystemClock,
+            ALICE.to_string(),
+            empty_plan(),
+        )
+        .unwrap();
+
+        // Alice claims bob requested the change and signs it with her key
+        bundle.request.requested_by = BOB.to_string();
+        let value = serde_json::to_vec(&bundle.request).unwrap();
+        bundle.signature =
+            hex::encode(ed25519_dalek::Signer::sign(&signing_key(1), &value).to_bytes());
+
+        let fs = MockFileSystem::new();
+        let secret = caller(Some(ALICE));
+        assert!(
+            approve_bundle(&fs, &secret, Path::new("/"), &signers, &bundle, false)
+                .await
+                .is_err()
+        );
+    }
+
+    /// Tests that changing the session name of an assumed role does not
+    /// allow approving your own change request
+    #[tokio::test]
+    async fn test_approve_session_name() {
+        let secret = caller(Some(
+            "arn:aws:sts::123456789012:assumed-role/deploy/secret-sync",
+        ));
+        let requested_by = approval_identity(&secret).await.unwrap();
+
+        let mut signers = signers();
+        signers.insert(
+            requested_by.clone(),
+            hex::encode(signing_key(3).verifying_key().as_bytes()),
+        );
+
+        let bundle = create_bundle(
+            &signing_key(3),
+            &signers,
+            &SystemClock,
+            requested_by,
+            empty_plan(),
+        )
+        .unwrap();
+
+        let fs = MockFileSystem::new();
+        let secret = caller(Some(
+            "arn:aws:sts::123456789012:assumed-role/deploy/someone-else",
+        ));
+        assert!(
+            approve_bundle(&fs, &secret, Path::new("/"), &signers, &bundle, false)
+                .await
+                .is_err()
+        );
+    }
+}
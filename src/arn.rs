@@ -0,0 +1,149 @@
+//! # ARN
+//!
+//! Parsing of AWS Secrets Manager ARNs used in place of a secret name
+//! (e.g. `arn:aws:secretsmanager:eu-west-1:123456789012:secret:db-AbCdEf`).
+//! ARNs can reference secrets in other regions or accounts, the AWS
+//! backend uses the region of the ARN when sending requests for it.
+//!
+//! Complete ARNs end with a six character suffix AWS appends to the name
+//! on creation, partial ARNs without the suffix are also accepted
+
+use std::{borrow::Cow, fmt::Display};
+
+/// Length of the random suffix AWS appends to the name of a secret
+const SUFFIX_LENGTH: usize = 6;
+
+/// Parsed AWS Secrets Manager secret ARN
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretArn<'a> {
+    /// Partition of the ARN (e.g. aws or aws-cn)
+    pub partition: &'a str,
+    /// Region the secret is stored in
+    pub region: &'a str,
+    /// Account that owns the secret
+    pub account: &'a str,
+    /// Name of the secret without the random suffix
+    pub name: &'a str,
+    /// Random suffix of complete ARNs
+    pub suffix: Option<&'a str>,
+}
+
+impl<'a> SecretArn<'a> {
+    /// Parse a secret ARN from `value`, providing [None] when `value` is
+    /// not a Secrets Manager secret ARN
+    pub fn parse(value: &'a str) -> Option<Self> {
+        let mut parts = value.splitn(7, ':');
+        let (
+            Some("arn"),
+            Some(partition),
+            Some("secretsmanager"),
+            Some(region),
+            Some(account),
+            Some("secret"),
+            Some(name),
+        ) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        )
+        else {
+            return None;
+        };
+
+        if partition.is_empty() || region.is_empty() || account.is_empty() || name.is_empty() {
+            return None;
+        }
+
+        let (name, suffix) = match name.rsplit_once('-') {
+            Some((name, suffix))
+                if !name.is_empty()
+                    && suffix.len() == SUFFIX_LENGTH
+                    && suffix.bytes().all(|value| value.is_ascii_alphanumeric()) =>
+            {
+                (name, Some(suffix))
+            }
+            _ => (name, None),
+        };
+
+        Some(Self {
+            partition,
+            region,
+            account,
+            name,
+            suffix,
+        })
+    }
+}
+
+impl Display for SecretArn<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}, {})", self.name, self.account, self.region)
+    }
+}
+
+/// Name of the secret `name` to display, ARNs are shown as the name of the
+/// secret followed by the account and region
+pub fn display_secret_name(name: &str) -> Cow<'_, str> {
+    match SecretArn::parse(name) {
+        Some(arn) => Cow::Owned(arn.to_string()),
+        None => Cow::Borrowed(name),
+    }
+}
+
+/// Name of the secret `name` without the ARN, used where the name of the
+/// secret matters rather than where it is stored
+pub fn secret_base_name(name: &str) -> &str {
+    match SecretArn::parse(name) {
+        Some(arn) => arn.name,
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::arn::{SecretArn, display_secret_name, secret_base_name};
+
+    /// Tests that complete, partial, and invalid ARNs are parsed
+    #[test]
+    fn test_parse_secret_arn() {
+        let arn =
+            SecretArn::parse("arn:aws:secretsmanager:eu-west-1:123456789012:secret:app/db-AbC123")
+                .unwrap();
+        assert_eq!(
+            arn,
+            SecretArn {
+                partition: "aws",
+                region: "eu-west-1",
+                account: "123456789012",
+                name: "app/db",
+                suffix: Some("AbC123"),
+            }
+        );
+
+        let arn =
+            SecretArn::parse("arn:aws-cn:secretsmanager:cn-north-1:123456789012:secret:app-db")
+                .unwrap();
+        assert_eq!(arn.name, "app-db");
+        assert_eq!(arn.suffix, None);
+
+        assert!(SecretArn::parse("app/db").is_none());
+        assert!(SecretArn::parse("arn:aws:ssm:eu-west-1:123456789012:parameter/app").is_none());
+        assert!(
+            SecretArn::parse("arn:aws:secretsmanager:eu-west-1:123456789012:secret:").is_none()
+        );
+
+        assert_eq!(
+            display_secret_name("arn:aws:secretsmanager:eu-west-1:123456789012:secret:db-AbC123"),
+            "db (123456789012, eu-west-1)"
+        );
+        assert_eq!(display_secret_name("db"), "db");
+        assert_eq!(
+            secret_base_name("arn:aws:secretsmanager:eu-west-1:123456789012:secret:db-AbC123"),
+            "db"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! # Cancel
+//!
+//! Runtime independent cancellation of in-progress batches. Hosts keep a
+//! clone of the [CancellationToken] passed to a batch pull or push and
+//! cancel it to abort the batch, dropping the in-flight backend call
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+};
+
+/// Token used to cancel a batch, clones share the same cancellation state
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    inner: Arc<CancellationState>,
+}
+
+/// Shared state of a [CancellationToken]
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, waking any tasks waiting on [Self::cancelled]
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+
+        let wakers = match self.inner.wakers.lock() {
+            Ok(mut wakers) => std::mem::take(&mut *wakers),
+            Err(_) => return,
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Whether the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Future completing once the token is cancelled
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled { token: self }
+    }
+
+    /// Run the `future` until it completes or the token is cancelled,
+    /// providing [None] when cancelled
+    pub async fn run_until_cancelled<F: Future>(&self, future: F) -> Option<F::Output> {
+        if self.is_cancelled() {
+            return None;
+        }
+
+        let future = std::pin::pin!(future);
+        let cancelled = self.cancelled();
+
+        match futures_util::future::select(future, cancelled).await {
+            futures_util::future::Either::Left((output, _)) => Some(output),
+            futures_util::future::Either::Right(_) => None,
+        }
+    }
+}
+
+/// Outcome of a batch pull or push
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOutcome {
+    /// Number of files processed before the batch finished or was cancelled
+    pub completed: usize,
+    /// Number of the completed files that were modified
+    pub changed: usize,
+    /// Whether the batch was cancelled before every file was processed
+    pub cancelled: bool,
+}
+
+/// Future returned by [CancellationToken::cancelled]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        if let Ok(mut wakers) = self.token.inner.wakers.lock()
+            && !wakers.iter().any(|waker| waker.will_wake(cx.waker()))
+        {
+            wakers.push(cx.waker().clone());
+        }
+
+        // Cancelled between the check and registering the waker
+        match self.token.is_cancelled() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cancel::CancellationToken;
+
+    /// Tests that cancelling the token stops a pending future
+    #[tokio::test]
+    async fn test_run_until_cancelled() {
+        let token = CancellationToken::new();
+        assert_eq!(token.run_until_cancelled(async { 1 }).await, Some(1));
+
+        let cancel = token.clone();
+        let pending = token.run_until_cancelled(std::future::pending::<()>());
+        let (result, _) = tokio::join!(pending, async move { cancel.cancel() });
+
+        assert_eq!(result, None);
+        assert!(token.is_cancelled());
+        assert_eq!(token.run_until_cancelled(async { 1 }).await, None);
+    }
+}
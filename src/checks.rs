@@ -0,0 +1,198 @@
+//! # Checks
+//!
+//! Heuristics run against local values before pushing to catch values that
+//! were never filled in, such as placeholders or trivially guessable secrets
+
+use crate::{
+    config::SecretFile,
+    doctor::ValueLocation,
+    fs::FileSystem,
+    structured::{StructuredValue, value_to_string},
+};
+use serde::Serialize;
+use std::{fmt::Display, path::Path};
+
+/// Values commonly left in place of a real secret, compared ignoring case
+const PLACEHOLDER_VALUES: [&str; 10] = [
+    "changeme",
+    "change_me",
+    "change-me",
+    "replaceme",
+    "replace_me",
+    "todo",
+    "fixme",
+    "placeholder",
+    "secret",
+    "password",
+];
+
+/// Parts of key names that indicate the value is meant to be secret
+const SECRET_KEY_PARTS: [&str; 6] = ["SECRET", "PASSWORD", "PASS", "TOKEN", "KEY", "CREDENTIAL"];
+
+/// Secret values with less total entropy than this (in bits) are
+/// considered easily guessable
+const MIN_SECRET_ENTROPY_BITS: f64 = 28.0;
+
+/// Problem found with a value
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueWarning {
+    /// Value is empty or entirely whitespace
+    Empty,
+    /// Value looks like a placeholder (e.g. `changeme` or `TODO`)
+    Placeholder,
+    /// Secret value has very low entropy
+    LowEntropy,
+}
+
+impl Display for ValueWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ValueWarning::Empty => "value is empty",
+            ValueWarning::Placeholder => "value looks like a placeholder",
+            ValueWarning::LowEntropy => "value has very low entropy",
+        })
+    }
+}
+
+/// Warning for a value at a specific location
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LocatedWarning {
+    /// Location of the value
+    pub location: ValueLocation,
+    /// Problem with the value
+    pub warning: ValueWarning,
+}
+
+/// Check a single `value`, `secret` indicates the value is expected to
+/// be secret and is additionally checked for low entropy
+pub fn check_value(value: &str, secret: bool) -> Option<ValueWarning> {
+    let value = value.trim();
+
+    if value.is_empty() {
+        return Some(ValueWarning::Empty);
+    }
+
+    let is_placeholder = PLACEHOLDER_VALUES
+        .iter()
+        .any(|placeholder| value.eq_ignore_ascii_case(placeholder))
+        || value.chars().all(|char| matches!(char, 'x' | 'X' | '*'))
+        || (value.starts_with('<') && value.ends_with('>'));
+
+    if is_placeholder {
+        return Some(ValueWarning::Placeholder);
+    }
+
+    if secret && entropy_bits(value) < MIN_SECRET_ENTROPY_BITS {
+        return Some(ValueWarning::LowEntropy);
+    }
+
+    None
+}
+
+/// Check if the `key` name indicates its value is meant to be secret
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    SECRET_KEY_PARTS.iter().any(|part| key.contains(part))
+}
+
+/// Total Shannon entropy of `value` in bits based on its character
+/// frequencies
+fn entropy_bits(value: &str) -> f64 {
+    let mut counts = std::collections::HashMap::new();
+    let mut total = 0usize;
+
+    for char in value.chars() {
+        *counts.entry(char).or_insert(0usize) += 1;
+        total += 1;
+    }
+
+    let total_f = total as f64;
+    let per_char: f64 = counts
+        .values()
+        .map(|count| {
+            let probability = *count as f64 / total_f;
+            -probability * probability.log2()
+        })
+        .sum();
+
+    per_char * total_f
+}
+
+/// Check the local values of the `files` that would be pushed
+///
+/// Files that are dotenv or JSON formatted are checked key by key, keys
+/// with secret looking names are additionally checked for low entropy.
+/// Other files are checked as a whole
+pub async fn check_file_values<'a, Fs: FileSystem>(
+    fs: &Fs,
+    working_path: &Path,
+    files: impl IntoIterator<Item = (&'a String, &'a SecretFile)>,
+) -> eyre::Result<Vec<LocatedWarning>> {
+    let mut warnings = Vec::new();
+
+    for (name, file) in files {
+        let file_path = file.resolve_path(working_path);
+        let contents = fs.read_file(&file_path).await?;
+
+        let entries: Vec<(Option<String>, String, bool)> = match StructuredValue::parse(&contents) {
+            Ok(value) => value
+                .values
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        Some(key.clone()),
+                        value_to_string(value),
+                        is_secret_key(key),
+                    )
+                })
+                .collect(),
+            Err(_) => vec![(None, String::from_utf8_lossy(&contents).into_owned(), true)],
+        };
+
+        for (key, value, secret) in entries {
+            if let Some(warning) = check_value(&value, secret) {
+                warnings.push(LocatedWarning {
+                    location: ValueLocation {
+                        file: name.clone(),
+                        key,
+                    },
+                    warning,
+                });
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checks::{ValueWarning, check_value};
+
+    /// Tests the value heuristics
+    #[test]
+    fn test_check_value() {
+        assert_eq!(check_value("", false), Some(ValueWarning::Empty));
+        assert_eq!(check_value("   ", true), Some(ValueWarning::Empty));
+        assert_eq!(
+            check_value("changeme", false),
+            Some(ValueWarning::Placeholder)
+        );
+        assert_eq!(check_value("TODO", true), Some(ValueWarning::Placeholder));
+        assert_eq!(check_value("xxxxxx", true), Some(ValueWarning::Placeholder));
+        assert_eq!(
+            check_value("<api-key>", true),
+            Some(ValueWarning::Placeholder)
+        );
+        assert_eq!(check_value("hunter2", true), Some(ValueWarning::LowEntropy));
+        assert_eq!(
+            check_value("aaaaaaaaaaaaaaaaaaaa", true),
+            Some(ValueWarning::LowEntropy)
+        );
+
+        // Low entropy values are fine when not expected to be secret
+        assert_eq!(check_value("localhost", false), None);
+        assert_eq!(check_value("9f86d081884c7d659a2feaa0c55ad015", true), None);
+    }
+}
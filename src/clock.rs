@@ -0,0 +1,117 @@
+//! # Clock
+//!
+//! Time source abstraction used for expiry and staleness checks and for
+//! waiting between retries, provided alongside
+//! [FileSystem](crate::fs::FileSystem) so hosts and tests can simulate the
+//! passing of time without sleeping
+
+use futures_util::future::BoxFuture;
+use std::{
+    fmt::Debug,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time
+pub trait Clock: Debug + Send + Sync {
+    /// Get the current time
+    fn now(&self) -> SystemTime;
+
+    /// Future completing once `duration` has passed
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// Clock using the system time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    // WASM hosts have no timer runtime, the single threaded host is blocked
+    // for the duration instead
+    #[cfg(target_family = "wasm")]
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        std::thread::sleep(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Clock that only moves when advanced, for simulating time in tests
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<SystemTime>,
+}
+
+impl ManualClock {
+    /// Create a clock starting at `unix_seconds` since the unix epoch
+    pub fn new(unix_seconds: u64) -> Self {
+        Self {
+            now: Mutex::new(UNIX_EPOCH + Duration::from_secs(unix_seconds)),
+        }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        if let Ok(mut now) = self.now.lock() {
+            *now += duration;
+        }
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.now.lock().map(|now| *now).unwrap_or(UNIX_EPOCH)
+    }
+
+    /// Advances the clock by `duration` and completes immediately
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.advance(duration);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Get the current unix timestamp in seconds from the `clock`
+pub fn unix_seconds(clock: &dyn Clock) -> u64 {
+    clock
+        .now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Format the unix time `seconds` as an RFC 3339 timestamp
+pub fn format_unix_time(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // Convert days since the unix epoch into a civil date
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        (time % 3600) / 60,
+        time % 60
+    )
+}
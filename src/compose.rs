@@ -0,0 +1,152 @@
+//! # Compose
+//!
+//! Discovery of the `${VAR}` interpolation references within docker-compose
+//! files, used to write a `.env` next to the compose file containing only
+//! the variables the compose file references
+
+use eyre::ContextCompat;
+use indexmap::{IndexMap, IndexSet};
+use std::path::{Path, PathBuf};
+
+/// File names docker compose searches for, in order of priority
+const COMPOSE_FILE_NAMES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// Find the default compose file within `directory`
+pub fn find_compose_file(directory: &Path) -> eyre::Result<PathBuf> {
+    COMPOSE_FILE_NAMES
+        .iter()
+        .map(|name| directory.join(name))
+        .find(|path| path.exists())
+        .with_context(|| format!("no compose file found in \"{}\"", directory.display()))
+}
+
+/// Find the names of the variables referenced by `${VAR}` and `$VAR`
+/// interpolations in the compose file `contents`, in order of first use
+///
+/// Interpolations with defaults or errors (`${VAR:-default}`, `${VAR?err}`)
+/// are included and escaped `$$` sequences are ignored
+pub fn find_compose_references(contents: &str) -> IndexSet<String> {
+    let mut references = IndexSet::new();
+    let mut chars = contents.char_indices().peekable();
+
+    while let Some((_, char)) = chars.next() {
+        if char != '$' {
+            continue;
+        }
+
+        let braced = match chars.peek() {
+            // Escaped literal dollar sign
+            Some((_, '$')) => {
+                chars.next();
+                continue;
+            }
+            Some((_, '{')) => {
+                chars.next();
+                true
+            }
+            _ => false,
+        };
+
+        let mut name = String::new();
+        while let Some((_, char)) = chars.peek() {
+            let valid = match name.is_empty() {
+                true => char.is_ascii_alphabetic() || *char == '_',
+                false => char.is_ascii_alphanumeric() || *char == '_',
+            };
+
+            if !valid {
+                break;
+            }
+
+            name.push(*char);
+            chars.next();
+        }
+
+        if name.is_empty() {
+            continue;
+        }
+
+        // Braced references must end or continue with a modifier
+        if braced
+            && !chars
+                .peek()
+                .is_some_and(|(_, char)| matches!(char, '}' | ':' | '-' | '?' | '+'))
+        {
+            continue;
+        }
+
+        references.insert(name);
+    }
+
+    references
+}
+
+/// Select the variables from `env` that are referenced in `references`,
+/// providing the selected variables and the references that were missing
+pub fn select_compose_env<'a>(
+    env: &'a IndexMap<String, String>,
+    references: &'a IndexSet<String>,
+) -> (IndexMap<&'a String, &'a String>, Vec<&'a String>) {
+    let mut selected = IndexMap::new();
+    let mut missing = Vec::new();
+
+    for reference in references {
+        match env.get_key_value(reference) {
+            Some((key, value)) => {
+                selected.insert(key, value);
+            }
+            None => missing.push(reference),
+        }
+    }
+
+    (selected, missing)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compose::{find_compose_references, select_compose_env};
+    use indexmap::IndexMap;
+
+    /// Tests finding the supported interpolation syntax
+    #[test]
+    fn test_find_compose_references() {
+        let references = find_compose_references(
+            r#"
+services:
+  app:
+    image: "app:${TAG:-latest}"
+    environment:
+      DATABASE_URL: ${DATABASE_URL}
+      API_KEY: $API_KEY
+      REQUIRED: ${REQUIRED:?must be set}
+      LITERAL: $$NOT_A_VAR
+      REPEATED: ${DATABASE_URL}
+"#,
+        );
+
+        assert!(
+            references
+                .iter()
+                .eq(["TAG", "DATABASE_URL", "API_KEY", "REQUIRED"].iter())
+        );
+    }
+
+    /// Tests that only referenced variables are selected
+    #[test]
+    fn test_select_compose_env() {
+        let mut env = IndexMap::new();
+        env.insert("DATABASE_URL".to_string(), "postgres://".to_string());
+        env.insert("UNUSED".to_string(), "value".to_string());
+
+        let references = find_compose_references("${DATABASE_URL} ${MISSING}");
+        let (selected, missing) = select_compose_env(&env, &references);
+
+        assert!(selected.keys().eq([&"DATABASE_URL".to_string()].iter()));
+        assert_eq!(missing, vec!["MISSING"]);
+    }
+}
@@ -0,0 +1,243 @@
+//! # Concurrency
+//!
+//! Number of backend requests a batch runs at once. Besides a fixed limit
+//! an adaptive limit can be used, which ramps parallelism up while requests
+//! succeed and backs off once the backend throttles requests or their
+//! latency climbs, finding the most throughput an account's limits allow
+
+use crate::error::SyncError;
+use std::{
+    fmt::Display,
+    hash::{BuildHasher, RandomState},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Highest limit the adaptive mode ramps up to
+pub const MAX_ADAPTIVE_CONCURRENCY: usize = 64;
+
+/// Number of times the adaptive mode retries a throttled request after
+/// backing off before the error is reported
+const MAX_THROTTLED_RETRIES: usize = 5;
+
+/// Delay before the first retry of a throttled request, doubled for every
+/// following retry
+const THROTTLED_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Longest delay before retrying a throttled request
+const MAX_THROTTLED_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Limit the adaptive mode starts from
+const INITIAL_ADAPTIVE_CONCURRENCY: usize = 2;
+
+/// Requests taking longer than this multiple of the fastest observed
+/// request are treated as a sign of congestion
+const LATENCY_BACKOFF_FACTOR: u32 = 4;
+
+/// Number of concurrent requests to use for a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concurrency {
+    /// Fixed number of concurrent requests
+    Fixed(usize),
+    /// Limit adjusted based on throttling and latency
+    Adaptive,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Concurrency::Fixed(1)
+    }
+}
+
+impl FromStr for Concurrency {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("adaptive") {
+            return Ok(Concurrency::Adaptive);
+        }
+
+        match value.parse::<usize>() {
+            Ok(0) => eyre::bail!("concurrency must be at least 1"),
+            Ok(value) => Ok(Concurrency::Fixed(value)),
+            Err(_) => eyre::bail!("concurrency must be a number or \"adaptive\""),
+        }
+    }
+}
+
+impl Display for Concurrency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Concurrency::Fixed(value) => value.fmt(f),
+            Concurrency::Adaptive => f.write_str("adaptive"),
+        }
+    }
+}
+
+/// Current limit of a batch, adjusted using additive increase and
+/// multiplicative decrease when [Concurrency::Adaptive]
+#[derive(Debug)]
+pub struct ConcurrencyLimit {
+    /// Current number of concurrent requests allowed
+    limit: usize,
+    /// Whether the limit adjusts to the observed requests
+    adaptive: bool,
+    /// Whether congestion has not yet been observed, the limit grows by
+    /// one for every success until it has
+    slow_start: bool,
+    /// Successes since the limit last changed
+    successes: usize,
+    /// Fastest request observed so far
+    baseline: Option<Duration>,
+}
+
+impl ConcurrencyLimit {
+    /// Create the limit for a batch using `concurrency`
+    pub fn new(concurrency: Concurrency) -> Self {
+        let (limit, adaptive) = match concurrency {
+            Concurrency::Fixed(value) => (value.max(1), false),
+            Concurrency::Adaptive => (INITIAL_ADAPTIVE_CONCURRENCY, true),
+        };
+
+        Self {
+            limit,
+            adaptive,
+            slow_start: true,
+            successes: 0,
+            baseline: None,
+        }
+    }
+
+    /// Current number of concurrent requests allowed
+    pub fn current(&self) -> usize {
+        self.limit
+    }
+
+    /// Record a request that succeeded after `latency`
+    pub fn record_success(&mut self, latency: Duration) {
+        if !self.adaptive {
+            return;
+        }
+
+        let baseline = *self.baseline.get_or_insert(latency);
+        if latency < baseline {
+            self.baseline = Some(latency);
+        } else if latency > baseline * LATENCY_BACKOFF_FACTOR {
+            tracing::debug!(?latency, ?baseline, "request latency increased");
+            self.back_off();
+            return;
+        }
+
+        self.successes += 1;
+        if self.slow_start || self.successes >= self.limit {
+            self.successes = 0;
+            self.limit = (self.limit + 1).min(MAX_ADAPTIVE_CONCURRENCY);
+        }
+    }
+
+    /// Record a request that was throttled by the backend
+    pub fn record_throttled(&mut self) {
+        if self.adaptive {
+            self.back_off();
+        }
+    }
+
+    /// Whether a request that failed with `error` after `retries` previous
+    /// retries should be retried, only throttled requests of an adaptive
+    /// limit are retried
+    pub fn should_retry(&self, error: &SyncError, retries: usize) -> bool {
+        self.adaptive && retries < MAX_THROTTLED_RETRIES && error.is_throttled()
+    }
+
+    /// Delay before retrying a throttled request that has already been
+    /// retried `retries` times
+    ///
+    /// The delay grows exponentially with the retries, with a random half
+    /// of it as jitter so concurrent retries do not all hit the backend at
+    /// the same time
+    pub fn retry_delay(&self, retries: usize) -> Duration {
+        let delay = THROTTLED_RETRY_DELAY
+            .saturating_mul(1 << retries.min(16))
+            .min(MAX_THROTTLED_RETRY_DELAY);
+
+        let half = delay / 2;
+        let jitter = RandomState::new().hash_one(retries) % (half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+
+    /// Halve the limit after congestion was observed
+    fn back_off(&mut self) {
+        self.slow_start = false;
+        self.successes = 0;
+        self.limit = (self.limit / 2).max(1);
+        tracing::debug!(limit = self.limit, "reduced concurrency");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        concurrency::{Concurrency, ConcurrencyLimit, MAX_ADAPTIVE_CONCURRENCY},
+        error::SyncError,
+    };
+    use std::time::Duration;
+
+    /// Tests parsing fixed and adaptive concurrency
+    #[test]
+    fn test_parse_concurrency() {
+        assert_eq!("8".parse::<Concurrency>().unwrap(), Concurrency::Fixed(8));
+        assert_eq!(
+            "adaptive".parse::<Concurrency>().unwrap(),
+            Concurrency::Adaptive
+        );
+        assert!("0".parse::<Concurrency>().is_err());
+        assert!("fast".parse::<Concurrency>().is_err());
+    }
+
+    /// Tests the adaptive limit ramps up until throttled or slowed down
+    /// and only grows gradually afterwards
+    #[test]
+    fn test_adaptive_limit() {
+        let latency = Duration::from_millis(20);
+
+        let mut fixed = ConcurrencyLimit::new(Concurrency::Fixed(4));
+        fixed.record_throttled();
+        fixed.record_success(latency);
+        assert_eq!(fixed.current(), 4);
+
+        let mut limit = ConcurrencyLimit::new(Concurrency::Adaptive);
+        for _ in 0..14 {
+            limit.record_success(latency);
+        }
+        assert_eq!(limit.current(), 16);
+
+        let error = SyncError::throttled("throttled").context("failed to get secret");
+        assert!(limit.should_retry(&error, 0));
+        assert!(!limit.should_retry(&SyncError::backend_auth("denied"), 0));
+        assert!(!fixed.should_retry(&error, 0));
+
+        // Retry delays grow exponentially up to the maximum
+        for (retries, delay) in [(0, 100), (1, 200), (3, 800), (10, 5_000)] {
+            let delay = Duration::from_millis(delay);
+            let actual = limit.retry_delay(retries);
+            assert!(actual >= delay / 2 && actual <= delay, "{actual:?}");
+        }
+
+        limit.record_throttled();
+        assert_eq!(limit.current(), 8);
+
+        // Grows by one per full window of successes after congestion
+        for _ in 0..8 {
+            limit.record_success(latency);
+        }
+        assert_eq!(limit.current(), 9);
+
+        limit.record_success(latency * 10);
+        assert_eq!(limit.current(), 4);
+
+        for _ in 0..10_000 {
+            limit.record_success(latency);
+        }
+        assert_eq!(limit.current(), MAX_ADAPTIVE_CONCURRENCY);
+    }
+}
@@ -3,25 +3,423 @@
 //! Configuration structures, parsing, and locating logic related
 //! to configuration files.
 
-use eyre::{Context, ContextCompat};
+use crate::{
+    arn::{SecretArn, secret_base_name},
+    error::{ErrorContext, Result, ResultExt, SyncError},
+    secret::{MANAGED_BY_VALUE, SecretManager},
+};
 use indexmap::IndexMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     env::current_dir,
-    fmt::Debug,
-    path::{Path, PathBuf},
+    fmt::{Debug, Display},
+    path::{Component, Path, PathBuf},
 };
 
 /// Configuration structure for secret-sync.toml
 #[derive(Debug, Deserialize, Default, PartialEq, Eq)]
 #[serde(default)]
 pub struct Config {
+    /// Where the rest of the config is loaded from
+    pub config: ConfigSourceConfig,
     /// Config deciding which backend to use
     pub backend: BackendConfig,
     /// AWS specific configuration
     pub aws: AwsConfig,
+    /// AWS SSM Parameter Store specific configuration
+    pub ssm: SsmConfig,
+    /// GCP specific configuration
+    pub gcp: GcpConfig,
+    /// Azure specific configuration
+    pub azure: AzureConfig,
+    /// Memory backend specific configuration
+    pub memory: MemoryConfig,
+    /// Local age encrypted backend specific configuration
+    pub local: LocalConfig,
+    /// SOPS backend specific configuration
+    pub sops: SopsConfig,
+    /// Services of a monorepo that files may belong to
+    pub services: IndexMap<String, ServiceConfig>,
+    /// Named backends that files may store their secrets in instead of
+    /// the top-level backend
+    pub backends: IndexMap<String, BackendOverride>,
+    /// Tuning of the HTTP client used for backend requests
+    pub network: NetworkConfig,
+    /// Configuration for resolving credentials when none are available
+    pub credentials: CredentialsConfig,
+    /// Configuration for locks stored within the backend while pushing
+    pub remote_lock: RemoteLockConfig,
+    /// Limits on the number of secrets written by a single push
+    pub push: PushConfig,
+    /// Identities allowed to request changes requiring approval
+    pub approval: ApprovalConfig,
+    /// Restrictions on the secret names that may be used
+    pub tenancy: TenancyConfig,
+    /// Configuration for resolving file paths
+    pub paths: PathsConfig,
+    /// Named sequences of subcommands runnable using `secret-sync run`
+    pub tasks: IndexMap<String, Vec<String>>,
+    /// Defaults applied to every file
+    pub defaults: DefaultsConfig,
     /// The secret files to operate on
     pub files: IndexMap<String, SecretFile>,
+    /// Files whose value is generated fresh on every pull rather than
+    /// stored within the backend, these are never pushed
+    pub generated: IndexMap<String, GeneratedFile>,
+    /// Files rendered from a local template with secret values injected
+    /// on pull, these are never pushed
+    pub render: IndexMap<String, RenderFile>,
+    /// Java keystores assembled from certificate and key secrets on pull,
+    /// these are never pushed
+    pub keystore: IndexMap<String, KeystoreFile>,
+}
+
+/// Placeholder replaced with the environment within file paths and
+/// secret names (e.g. `path = ".env.{env}"`)
+pub const ENV_PLACEHOLDER: &str = "{env}";
+
+/// Placeholder replaced with the file entry name within the default
+/// description
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+/// Placeholder replaced with the secret name within the default description
+pub const SECRET_PLACEHOLDER: &str = "{secret}";
+
+/// Ensure the `environment` name is safe to substitute into file paths
+/// and secret names
+pub fn validate_environment(environment: &str) -> Result<()> {
+    if environment.is_empty() || environment.contains(['/', '\\']) || environment == ".." {
+        return Err(SyncError::config(format!(
+            "invalid environment \"{environment}\""
+        )));
+    }
+
+    Ok(())
+}
+
+impl Config {
+    /// Replace the [ENV_PLACEHOLDER] within each file path and secret name
+    /// with the `environment`
+    ///
+    /// Fails if a file uses the placeholder when no environment is provided
+    pub fn apply_environment(&mut self, environment: Option<&str>) -> Result<()> {
+        if let Some(environment) = environment {
+            validate_environment(environment)?;
+        }
+
+        for (name, file) in self.files.iter_mut() {
+            let path = file
+                .path
+                .to_str()
+                .filter(|path| path.contains(ENV_PLACEHOLDER));
+            let uses_placeholder = path.is_some() || file.secret.contains(ENV_PLACEHOLDER);
+
+            if !uses_placeholder {
+                continue;
+            }
+
+            let environment = environment.ok_or_else(|| {
+                SyncError::config(format!("file \"{name}\" uses {ENV_PLACEHOLDER} but no environment was provided, use --env"))
+            })?;
+
+            if let Some(path) = path {
+                file.path = PathBuf::from(path.replace(ENV_PLACEHOLDER, environment));
+            }
+
+            file.secret = file.secret.replace(ENV_PLACEHOLDER, environment);
+        }
+
+        for (name, file) in self.render.iter_mut() {
+            for secret in file.secrets.values_mut() {
+                if !secret.contains(ENV_PLACEHOLDER) {
+                    continue;
+                }
+
+                let environment = environment.ok_or_else(|| {
+                    SyncError::config(format!("render \"{name}\" uses {ENV_PLACEHOLDER} but no environment was provided, use --env"))
+                })?;
+
+                *secret = secret.replace(ENV_PLACEHOLDER, environment);
+            }
+        }
+
+        for (name, file) in self.keystore.iter_mut() {
+            for secret in file.secrets_mut() {
+                if !secret.contains(ENV_PLACEHOLDER) {
+                    continue;
+                }
+
+                let environment = environment.ok_or_else(|| {
+                    SyncError::config(format!("keystore \"{name}\" uses {ENV_PLACEHOLDER} but no environment was provided, use --env"))
+                })?;
+
+                *secret = secret.replace(ENV_PLACEHOLDER, environment);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolve the relative paths of the files belonging to a service
+    /// against the directory of the service and add the prefix of the
+    /// service to their secret names
+    pub fn apply_services(&mut self) {
+        let default = ServiceConfig::default();
+
+        for file in self.files.values_mut() {
+            let Some(name) = &file.service else {
+                continue;
+            };
+
+            let service = self.services.get(name).unwrap_or(&default);
+
+            if file.path.is_relative() {
+                file.path = service.dir(name).join(&file.path);
+            }
+
+            // ARNs already identify the secret and are never prefixed
+            if let Some(prefix) = &service.prefix
+                && SecretArn::parse(&file.secret).is_none()
+            {
+                file.secret = format!("{prefix}{}", file.secret);
+            }
+        }
+    }
+
+    /// Default environment of the targeted `services`, only provided when
+    /// every service uses the same environment
+    pub fn service_environment(&self, services: &[String]) -> Option<&str> {
+        let mut environments = services.iter().map(|name| {
+            self.services
+                .get(name)
+                .and_then(|service| service.environment.as_deref())
+        });
+
+        let first = environments.next()??;
+        environments
+            .all(|environment| environment == Some(first))
+            .then_some(first)
+    }
+
+    /// Prepare a loaded config for syncing, shared by every host so the
+    /// secret names are resolved and checked the same way
+    ///
+    /// Applies the services, resolves {env} using the `environment` (the
+    /// default environment of the targeted `services` when not provided),
+    /// checks the tenancy of the resolved names, then merges the defaults.
+    /// Provides the environment that was used
+    pub fn prepare(
+        &mut self,
+        environment: Option<&str>,
+        services: &[String],
+    ) -> Result<Option<String>> {
+        self.apply_services();
+
+        let environment = environment
+            .or_else(|| self.service_environment(services))
+            .map(str::to_string);
+
+        self.apply_environment(environment.as_deref())?;
+        self.check_hosts()?;
+        self.check_tenancy()?;
+        self.apply_defaults();
+
+        Ok(environment)
+    }
+
+    /// Merge the [DefaultsConfig] metadata into the metadata of every file,
+    /// values specified by a file take priority over the defaults
+    ///
+    /// The default description may use the {name} and {secret} placeholders
+    /// which are replaced with the file entry name and secret name
+    pub fn apply_defaults(&mut self) {
+        let defaults = &self.defaults.metadata;
+
+        for (name, file) in self.files.iter_mut() {
+            let metadata = &mut file.metadata;
+
+            if metadata.description.is_none()
+                && let Some(description) = &defaults.description
+            {
+                metadata.description = Some(
+                    description
+                        .replace(NAME_PLACEHOLDER, name)
+                        .replace(SECRET_PLACEHOLDER, &file.secret),
+                );
+            }
+
+            if metadata.version_comment.is_none() {
+                metadata.version_comment = defaults.version_comment.clone();
+            }
+
+            if let Some(default_tags) = &defaults.tags {
+                let mut tags = default_tags.clone();
+                tags.extend(metadata.tags.take().unwrap_or_default());
+                metadata.tags = Some(tags);
+            }
+        }
+    }
+
+    /// Ensure only secret files use a remote host, rendered and keystore
+    /// files are always written on the local machine
+    ///
+    /// Generated files are also written locally, their `host` is the
+    /// database host of the RDS IAM token generator
+    pub fn check_hosts(&self) -> Result<()> {
+        let outputs = (self
+            .render
+            .iter()
+            .map(|(name, file)| ("render", name, &file.host)))
+        .chain(
+            self.keystore
+                .iter()
+                .map(|(name, file)| ("keystore", name, &file.host)),
+        );
+
+        for (kind, name, host) in outputs {
+            if host.is_some() {
+                return Err(SyncError::config(format!(
+                    "{kind} \"{name}\" cannot use a host, remote hosts are only supported by secret files"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensure every file, render, and keystore secret name and the remote
+    /// lock secret names when enabled are within the allowed tenancy prefixes
+    pub fn check_tenancy(&self) -> Result<()> {
+        for (name, file) in &self.files {
+            self.tenancy
+                .check_secret(&file.secret)
+                .with_context(|| format!("file \"{name}\" uses a disallowed secret"))?;
+        }
+
+        for (name, file) in &self.render {
+            for secret in file.secrets.values() {
+                self.tenancy
+                    .check_secret(secret)
+                    .with_context(|| format!("render \"{name}\" uses a disallowed secret"))?;
+            }
+        }
+
+        for (name, file) in &self.keystore {
+            for secret in file.secrets() {
+                self.tenancy
+                    .check_secret(secret)
+                    .with_context(|| format!("keystore \"{name}\" uses a disallowed secret"))?;
+            }
+        }
+
+        if self.remote_lock.enabled {
+            self.tenancy
+                .check_secret(&self.remote_lock.prefix)
+                .context("remote lock prefix is disallowed")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Directory containing the service directories of a monorepo
+pub const DEFAULT_SERVICES_DIR: &str = "services";
+
+/// Defaults for the files belonging to one service of a monorepo
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ServiceConfig {
+    /// Directory the relative file paths of the service are resolved
+    /// against, relative to the working path (Default: services/<name>)
+    pub path: Option<PathBuf>,
+    /// Prefix added to the secret names of the files of the service
+    pub prefix: Option<String>,
+    /// Environment used when targeting the service without --env
+    pub environment: Option<String>,
+}
+
+impl ServiceConfig {
+    /// Directory of the service `name`, relative to the working path
+    pub fn dir(&self, name: &str) -> PathBuf {
+        match &self.path {
+            Some(path) => path.clone(),
+            None => Path::new(DEFAULT_SERVICES_DIR).join(name),
+        }
+    }
+}
+
+/// Configuration for resolving file paths
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct PathsConfig {
+    /// Directory that relative file paths are resolved against instead of
+    /// the directory containing the config file, relative to the config file
+    pub base: Option<PathBuf>,
+    /// Directory to store the state that persists between runs in instead
+    /// of the `.secret-sync` directory within the working path, relative to
+    /// the config file
+    pub state_dir: Option<PathBuf>,
+}
+
+impl PathsConfig {
+    /// Resolve the working path relative file paths are resolved against
+    /// for a config file within `config_directory`
+    pub fn working_path(&self, config_directory: &Path) -> PathBuf {
+        match &self.base {
+            Some(base) => config_directory.join(base),
+            None => config_directory.to_path_buf(),
+        }
+    }
+
+    /// Resolve the relocated state directory for a config file within
+    /// `config_directory`, [None] when the state directory is not relocated
+    pub fn state_dir(&self, config_directory: &Path) -> Option<PathBuf> {
+        self.state_dir
+            .as_ref()
+            .map(|state_dir| config_directory.join(state_dir))
+    }
+}
+
+/// Location of the config, a config stored within a secret lets a fleet
+/// of machines share a centrally managed config
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConfigSourceConfig {
+    /// Where the config is loaded from
+    pub source: ConfigSource,
+    /// Name of the secret storing the config when using
+    /// [ConfigSource::Secret]
+    pub secret: Option<String>,
+}
+
+/// Where the config is loaded from
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    /// The config file itself
+    #[default]
+    File,
+    /// A secret within the backend of the config file, the config file
+    /// only needs to contain the backend settings
+    Secret,
+}
+
+impl ConfigSourceConfig {
+    /// Name of the secret the config should be loaded from, [None] when
+    /// the config file itself is the config
+    pub fn remote_secret(&self) -> Result<Option<&str>> {
+        match self.source {
+            ConfigSource::File => Ok(None),
+            ConfigSource::Secret => self
+                .secret
+                .as_deref()
+                .ok_or_else(|| {
+                    SyncError::config("config.source is \"secret\" but config.secret is not set")
+                })
+                .map(Some),
+        }
+    }
 }
 
 /// Config around the secrets backend to use
@@ -30,150 +428,1375 @@ pub struct Config {
 pub struct BackendConfig {
     /// Provider to use
     pub provider: BackendProvider,
+    /// Probe common local emulator endpoints (LocalStack, Loker) and use
+    /// the first one running with dummy credentials
+    pub dev_endpoint_autodetect: bool,
+}
+
+/// Provider to use for secrets
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_family = "wasm"), derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum BackendProvider {
+    /// AWS (Compatible) powered backend
+    #[default]
+    Aws,
+    /// AWS SSM Parameter Store backend, using the AWS configuration for
+    /// credentials and region
+    Ssm,
+    /// Google Cloud Secret Manager backend
+    Gcp,
+    /// Azure Key Vault backend
+    Azure,
+    /// In-memory backend for tests and demos, optionally persisted to a
+    /// JSON file
+    Memory,
+    /// age encrypted secret files stored in a local directory
+    Local,
+    /// SOPS encrypted files of an existing SOPS repository, using the
+    /// sops executable
+    Sops,
+}
+
+impl Display for BackendProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendProvider::Aws => f.write_str("aws"),
+            BackendProvider::Ssm => f.write_str("ssm"),
+            BackendProvider::Gcp => f.write_str("gcp"),
+            BackendProvider::Azure => f.write_str("azure"),
+            BackendProvider::Memory => f.write_str("memory"),
+            BackendProvider::Local => f.write_str("local"),
+            BackendProvider::Sops => f.write_str("sops"),
+        }
+    }
+}
+
+/// Configuration for the SSM Parameter Store backend
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct SsmConfig {
+    /// Path prefix the parameters are stored under (e.g. "/my-app"),
+    /// secret names are used as the parameter names when not set
+    pub prefix: Option<String>,
+
+    /// Tier of created parameters, uses the Parameter Store default
+    /// ("standard") when not set
+    pub tier: Option<SsmTier>,
+
+    /// KMS key the SecureString parameters are encrypted with, uses the
+    /// AWS managed "aws/ssm" key when not set
+    pub kms_key_id: Option<String>,
+}
+
+/// Tier of a Parameter Store parameter
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SsmTier {
+    /// Free tier, values up to 4 KB
+    Standard,
+    /// Paid tier, values up to 8 KB
+    Advanced,
+    /// Standard tier, switching to advanced when a value requires it
+    IntelligentTiering,
+}
+
+/// Configuration for the GCP backend
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct GcpConfig {
+    /// Project containing the secrets, resolved from GOOGLE_CLOUD_PROJECT
+    /// or the credentials when not set
+    pub project: Option<String>,
+
+    /// Credentials file (service account key, authorized user, or external
+    /// account) to use instead of Application Default Credentials
+    pub credentials_file: Option<PathBuf>,
+
+    /// Optional custom endpoint for the Secret Manager API
+    pub endpoint: Option<String>,
+
+    /// Exchange the OIDC identity token of the CI provider for credentials
+    /// using workload identity federation, avoiding long-lived keys in CI
+    pub workload_identity: Option<GcpWorkloadIdentityConfig>,
+}
+
+/// Configuration for workload identity federation using an OIDC identity
+/// token
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct GcpWorkloadIdentityConfig {
+    /// Workload identity pool provider the token is exchanged with, in the
+    /// form "projects/<number>/locations/global/workloadIdentityPools/<pool>/providers/<provider>"
+    pub workload_provider: String,
+    /// Service account to impersonate, the federated identity is granted
+    /// access directly when not set
+    #[serde(default)]
+    pub service_account: Option<String>,
+    /// Source of the identity token
+    #[serde(flatten)]
+    pub token: IdentityTokenSource,
+}
+
+/// Configuration for the Azure backend
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct AzureConfig {
+    /// URL of the Key Vault containing the secrets (e.g.
+    /// "https://my-vault.vault.azure.net"), defaults to AZURE_KEYVAULT_URL
+    pub vault_url: Option<String>,
+
+    /// Directory (tenant) of the service principal, defaults to
+    /// AZURE_TENANT_ID
+    pub tenant_id: Option<String>,
+
+    /// Client (application) id of the service principal or user-assigned
+    /// managed identity, defaults to AZURE_CLIENT_ID
+    pub client_id: Option<String>,
+
+    /// Client secret of the service principal, defaults to
+    /// AZURE_CLIENT_SECRET. Can reference a value stored elsewhere using
+    /// "keyring:" or "env:"
+    pub client_secret: Option<String>,
+
+    /// Optional custom Microsoft Entra authority host
+    pub authority_host: Option<String>,
+
+    /// Exchange the OIDC identity token of the CI provider for credentials
+    /// of the service principal using a federated credential, avoiding
+    /// long-lived client secrets in CI
+    pub workload_identity: Option<IdentityTokenSource>,
+}
+
+/// Configuration for the memory backend
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct MemoryConfig {
+    /// JSON file the secrets are loaded from and persisted to, secrets are
+    /// only kept in memory when not set
+    pub path: Option<PathBuf>,
+}
+
+/// Configuration for the local backend
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct LocalConfig {
+    /// Directory the encrypted secret files are stored in
+    pub path: Option<PathBuf>,
+
+    /// age recipients (public keys) of everyone that may decrypt the
+    /// secrets, every secret is encrypted to all of the recipients
+    pub recipients: Vec<String>,
+
+    /// File containing the age identities (private keys) used to decrypt
+    /// the secrets, SECRET_SYNC_AGE_IDENTITY takes priority when set
+    pub identity_file: Option<PathBuf>,
+}
+
+/// Configuration for the SOPS backend
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct SopsConfig {
+    /// Directory containing the SOPS encrypted files, secret names are
+    /// the paths of the files within the directory
+    pub path: Option<PathBuf>,
+
+    /// sops executable to run, uses "sops" from the PATH when not set
+    pub command: Option<String>,
+}
+
+/// Tuning of the HTTP client used for backend requests, useful for
+/// batches of many small requests over high-latency links
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Maximum number of idle connections kept open to each backend host,
+    /// unlimited when not set
+    pub max_idle_connections: Option<usize>,
+
+    /// Duration in seconds idle connections are kept alive for reuse
+    pub keep_alive: u64,
+
+    /// HTTP version used when connecting to the backend
+    pub http_version: HttpVersion,
+
+    /// Open a connection for each concurrent request before a concurrent
+    /// pull starts, so the batch does not pay the connection setup cost
+    pub prewarm: bool,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_connections: None,
+            keep_alive: 90,
+            http_version: HttpVersion::Auto,
+            prewarm: true,
+        }
+    }
+}
+
+/// HTTP version used when connecting to the backend
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// Negotiate the version with the backend, preferring HTTP/2
+    #[default]
+    Auto,
+    /// Only use HTTP/1.1
+    Http1,
+    /// Only use HTTP/2
+    Http2,
+}
+
+/// AWS specific configuration
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+pub struct AwsConfig {
+    /// AWS profile to use the sdk with
+    pub profile: Option<String>,
+
+    /// Optionally override the AWS region
+    pub region: Option<String>,
+
+    /// Custom override for the AWS secret manager endpoint
+    pub endpoint: Option<String>,
+
+    /// Custom AWS credentials to use
+    pub credentials: Option<AwsCredentials>,
+
+    /// Optional external command that outputs AWS credentials as JSON, matching
+    /// the AWS CLI credential_process format
+    pub credential_process: Option<String>,
+
+    /// Optional role to assume before accessing secrets
+    pub role_arn: Option<String>,
+
+    /// Optional session name to use when assuming `role_arn`
+    pub role_session_name: Option<String>,
+
+    /// Serial number or ARN of the MFA device required to assume `role_arn`
+    pub mfa_serial: Option<String>,
+
+    /// Optional command to run to obtain the MFA token code, the
+    /// command output is used as the token
+    pub mfa_command: Option<String>,
+
+    /// MFA token code provided through the --mfa-token argument
+    #[serde(skip)]
+    pub mfa_token: Option<String>,
+
+    /// Use the FIPS 140-2 validated endpoints of the region
+    #[serde(default)]
+    pub use_fips: bool,
+
+    /// Use the dual-stack (IPv4 and IPv6) endpoints of the region
+    #[serde(default)]
+    pub use_dualstack: bool,
+
+    /// Exchange the OIDC identity token of the CI provider for the
+    /// credentials of a role, avoiding long-lived keys in CI
+    #[serde(default)]
+    pub web_identity: Option<WebIdentityConfig>,
+}
+
+/// Configuration for assuming a role using an OIDC identity token
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct WebIdentityConfig {
+    /// Role to assume using the identity token
+    pub role_arn: String,
+    /// Optional session name to use when assuming the role
+    #[serde(default)]
+    pub role_session_name: Option<String>,
+    /// Source of the identity token
+    #[serde(flatten)]
+    pub token: IdentityTokenSource,
+}
+
+/// Source of an OIDC identity token
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "provider", rename_all = "kebab-case")]
+pub enum IdentityTokenSource {
+    /// Token requested from GitHub Actions, requires the workflow to have
+    /// the `id-token: write` permission
+    GithubActions {
+        /// Audience of the requested token, defaults to "sts.amazonaws.com"
+        /// for AWS, the workload identity pool provider for GCP, and
+        /// "api://AzureADTokenExchange" for Azure
+        #[serde(default)]
+        audience: Option<String>,
+    },
+    /// Token provided by GitLab CI `id_tokens` through an environment variable
+    Gitlab {
+        /// Environment variable containing the token, defaults to
+        /// "SECRET_SYNC_ID_TOKEN"
+        #[serde(default)]
+        token_env: Option<String>,
+    },
+    /// Token read from a file
+    File {
+        /// Path of the file containing the token
+        path: PathBuf,
+    },
+}
+
+impl AwsConfig {
+    /// Ensure the resolved `region` is valid and supports the endpoint
+    /// options, providing the partition of the region
+    ///
+    /// Regions are not validated when a custom endpoint is used as
+    /// self-hosted secret managers may accept any region
+    pub fn validate_region(&self, region: &str) -> Result<Option<AwsPartition>> {
+        if self.endpoint.is_some() {
+            if self.use_fips || self.use_dualstack {
+                return Err(SyncError::config(
+                    "aws.use_fips and aws.use_dualstack cannot be used with a custom aws.endpoint",
+                ));
+            }
+
+            return Ok(None);
+        }
+
+        let partition = AwsPartition::from_region(region)
+            .ok_or_else(|| SyncError::config(format!("invalid AWS region \"{region}\"")))?;
+
+        if self.use_fips && !partition.supports_fips(region) {
+            return Err(SyncError::config(format!(
+                "FIPS endpoints are not available in region \"{region}\" ({} partition)",
+                partition.name()
+            )));
+        }
+
+        Ok(Some(partition))
+    }
+}
+
+/// AWS partition containing a group of regions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AwsPartition {
+    /// Standard commercial regions (aws)
+    Standard,
+    /// AWS GovCloud (US) regions (aws-us-gov)
+    GovCloud,
+    /// China regions (aws-cn)
+    China,
+    /// Isolated regions (aws-iso, aws-iso-b, etc)
+    Isolated,
+}
+
+impl AwsPartition {
+    /// Determine the partition of the `region`, [None] when the region is
+    /// not in the form of an AWS region (e.g. us-gov-west-1)
+    pub fn from_region(region: &str) -> Option<AwsPartition> {
+        let parts: Vec<&str> = region.split('-').collect();
+
+        let valid = parts.len() >= 3
+            && parts.iter().all(|part| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            })
+            && parts
+                .last()
+                .is_some_and(|part| part.chars().all(|c| c.is_ascii_digit()));
+
+        if !valid {
+            return None;
+        }
+
+        Some(match (parts[0], parts[1]) {
+            ("us", "gov") => AwsPartition::GovCloud,
+            ("cn", _) => AwsPartition::China,
+            (_, isolated) if isolated.starts_with("iso") => AwsPartition::Isolated,
+            _ => AwsPartition::Standard,
+        })
+    }
+
+    /// Name of the partition
+    pub fn name(&self) -> &'static str {
+        match self {
+            AwsPartition::Standard => "aws",
+            AwsPartition::GovCloud => "aws-us-gov",
+            AwsPartition::China => "aws-cn",
+            AwsPartition::Isolated => "aws-iso",
+        }
+    }
+
+    /// Whether FIPS endpoints are available for the `region` within the
+    /// partition, commercial FIPS endpoints only exist in US and Canada
+    pub fn supports_fips(&self, region: &str) -> bool {
+        match self {
+            AwsPartition::Standard => region.starts_with("us-") || region.starts_with("ca-"),
+            AwsPartition::GovCloud | AwsPartition::Isolated => true,
+            AwsPartition::China => false,
+        }
+    }
+}
+
+/// Configuration for resolving credentials when a backend has no
+/// configured or ambient credentials available
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct CredentialsConfig {
+    /// Interactively prompt for credentials when running in a terminal
+    /// and no credentials could be found
+    pub prompt: bool,
+
+    /// Store prompted credentials in the OS keychain and read them
+    /// back on future runs
+    pub keychain: bool,
+
+    /// Optional external command that outputs credentials as JSON for
+    /// the backend. For AWS the output must match the credential_process
+    /// format, `aws.credential_process` takes priority when set
+    pub command: Option<String>,
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        Self {
+            prompt: true,
+            keychain: false,
+            command: None,
+        }
+    }
+}
+
+/// Defaults applied to every file within the config
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct DefaultsConfig {
+    /// Metadata merged into the metadata of every file, useful for tags
+    /// required on every secret (e.g. `managed-by = "secret-sync"`)
+    pub metadata: SecretMetadata,
+}
+
+/// Configuration for locks stored within the backend while pushing,
+/// preventing teammates from pushing the same secret at the same time
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct RemoteLockConfig {
+    /// Acquire remote locks on every push
+    pub enabled: bool,
+
+    /// Duration in seconds before a held lock is considered stale
+    pub ttl: u64,
+
+    /// Prefix added to secret names to create the lock secret names
+    pub prefix: String,
+}
+
+impl Default for RemoteLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl: 300,
+            prefix: "secret-sync/locks/".to_string(),
+        }
+    }
+}
+
+/// Identities allowed to request changes using push --request-approval
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    /// Hex encoded Ed25519 public keys that change requests must be signed
+    /// with, keyed by the caller identity of the requester (e.g. the ARN of
+    /// the AWS credentials)
+    pub signers: IndexMap<String, String>,
+}
+
+/// Limits on the number of secrets written by a single push, guarding
+/// against a bad filter or glob writing hundreds of unintended secrets
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct PushConfig {
+    /// Number of secrets above which a push must be confirmed, a value
+    /// of zero never requires confirmation
+    pub confirm_threshold: usize,
+
+    /// Maximum number of secrets a single push may write
+    pub max_files: Option<usize>,
+}
+
+impl Default for PushConfig {
+    fn default() -> Self {
+        Self {
+            confirm_threshold: 25,
+            max_files: None,
+        }
+    }
+}
+
+impl PushConfig {
+    /// Ensure pushing `count` secrets stays within the maximum, the
+    /// `max_files` flag takes priority over the config
+    pub fn check_max_files(&self, count: usize, max_files: Option<usize>) -> Result<()> {
+        match max_files.or(self.max_files) {
+            Some(max_files) if count > max_files => Err(SyncError::validation(format!(
+                "refusing to push {count} secret(s), more than the maximum of {max_files} (use --max-files to raise the limit)"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether pushing `count` secrets must be confirmed
+    pub fn requires_confirmation(&self, count: usize) -> bool {
+        self.confirm_threshold != 0 && count > self.confirm_threshold
+    }
+}
+
+/// Restrictions on the secret names that may be used, guarding against
+/// config mistakes that would write into another team's namespace
+#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TenancyConfig {
+    /// Prefixes secret names must start with, any name is allowed
+    /// when empty
+    pub allowed_prefixes: Vec<String>,
+    /// Accounts secret ARNs may reference, ARNs of other accounts are
+    /// rejected when prefixes or accounts are restricted
+    pub allowed_accounts: Vec<String>,
+    /// Regions secret ARNs may reference, any region is allowed when empty
+    pub allowed_regions: Vec<String>,
+}
+
+impl TenancyConfig {
+    /// Ensure the secret `name` starts with one of the allowed prefixes,
+    /// the name within an ARN is checked for ARNs along with the account
+    /// and region of the ARN
+    pub fn check_secret(&self, name: &str) -> Result<()> {
+        if let Some(arn) = SecretArn::parse(name) {
+            let restricted = !self.allowed_prefixes.is_empty() || !self.allowed_accounts.is_empty();
+            if restricted
+                && !self
+                    .allowed_accounts
+                    .iter()
+                    .any(|account| account == arn.account)
+            {
+                return Err(SyncError::validation(format!(
+                    "secret \"{name}\" belongs to account {} which is not in tenancy.allowed_accounts",
+                    arn.account
+                )));
+            }
+
+            if !self.allowed_regions.is_empty()
+                && !self
+                    .allowed_regions
+                    .iter()
+                    .any(|region| region == arn.region)
+            {
+                return Err(SyncError::validation(format!(
+                    "secret \"{name}\" is stored in region {} which is not in tenancy.allowed_regions",
+                    arn.region
+                )));
+            }
+        }
+
+        let base_name = secret_base_name(name);
+        if self.allowed_prefixes.is_empty()
+            || self
+                .allowed_prefixes
+                .iter()
+                .any(|prefix| base_name.starts_with(prefix.as_str()))
+        {
+            return Ok(());
+        }
+
+        Err(SyncError::validation(format!(
+            "secret \"{name}\" is outside of the allowed prefixes ({})",
+            self.allowed_prefixes.join(", ")
+        )))
+    }
+}
+
+/// Prefix for config values stored in the OS keychain
+pub const KEYRING_VALUE_PREFIX: &str = "keyring:";
+
+/// Prefix for config values read from an environment variable
+pub const ENV_VALUE_PREFIX: &str = "env:";
+
+/// Check if a config `value` references a value stored elsewhere rather
+/// than being the value itself
+pub fn is_config_value_reference(value: &str) -> bool {
+    value.starts_with(KEYRING_VALUE_PREFIX) || value.starts_with(ENV_VALUE_PREFIX)
+}
+
+/// AWS credentials
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AwsCredentials {
+    /// AWS access key
+    pub access_key_id: String,
+    /// AWS access secret
+    pub access_key_secret: String,
+}
+
+impl AwsCredentials {
+    /// Check if any of the credential values are stored in plaintext
+    pub fn is_plaintext(&self) -> bool {
+        [&self.access_key_id, &self.access_key_secret]
+            .iter()
+            .any(|value| !is_config_value_reference(value))
+    }
+}
+
+impl Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &"< REDACTED >")
+            .field("access_key_secret", &"< REDACTED >")
+            .finish()
+    }
+}
+
+/// The secret file instance
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+pub struct SecretFile {
+    /// Path relative to the config file to store the secret at
+    pub path: PathBuf,
+    /// Name of the secret to store / retrieve the file based on
+    pub secret: String,
+    /// Additional secret metadata to use when pushing secrets
+    #[serde(default)]
+    pub metadata: SecretMetadata,
+    /// Patterns for the keys of a structured (dotenv or JSON) secret to
+    /// include in the file, the whole secret is used when not set
+    ///
+    /// Pushing only updates the matching keys within the secret
+    #[serde(default)]
+    pub keys: Option<Vec<String>>,
+    /// Renames for the keys of a structured secret, mapping the remote
+    /// key names to the local key names
+    #[serde(default)]
+    pub key_map: IndexMap<String, String>,
+    /// Transforms applied to the values of a structured secret when
+    /// written locally, keyed by patterns for the local key names
+    #[serde(default)]
+    pub transforms: IndexMap<String, ValueTransform>,
+    /// System that manages the secret (e.g. "terraform"), files managed by
+    /// anything other than secret-sync are skipped when pushing
+    #[serde(default)]
+    pub managed_by: Option<String>,
+    /// Command the pulled value is piped into instead of being written to
+    /// the path, files with a sink are only pulled
+    #[serde(default)]
+    pub sink: Option<SinkConfig>,
+    /// Remote host the file is read from and written to over SSH, either a
+    /// host from the SSH config or `user@host`
+    ///
+    /// Relative paths on a remote host are resolved against the home
+    /// directory of the SSH user
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Compression applied to the value before it is stored, allowing
+    /// values larger than the backend size limit to be stored
+    #[serde(default)]
+    pub compress: Option<Compression>,
+    /// Service of a monorepo the file belongs to, relative paths are
+    /// resolved against the directory of the service
+    #[serde(default)]
+    pub service: Option<String>,
+    /// Freeform notes on why the secret exists and who to ask about it,
+    /// shown alongside the file when planning and in change requests
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Ticket or issue tracking the secret (e.g. "OPS-123")
+    #[serde(default)]
+    pub ticket: Option<String>,
+    /// Name of the backend within `backends` storing the secret instead
+    /// of the top-level backend
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Backend storing the secret instead of the top-level backend,
+    /// specified inline within the file
+    #[serde(flatten)]
+    pub backend_override: BackendOverride,
+}
+
+/// Backend overriding the top-level backend, either named within
+/// `backends` or inline within a file, allowing one config to use secrets
+/// from multiple backends (e.g. Secrets Manager and Parameter Store, or
+/// multiple AWS accounts)
+///
+/// Settings that are not provided fall back to the top-level settings of
+/// the provider
+#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[serde(default)]
+pub struct BackendOverride {
+    /// Provider storing the secret
+    pub provider: Option<BackendProvider>,
+    /// AWS specific configuration
+    pub aws: Option<AwsConfig>,
+    /// AWS SSM Parameter Store specific configuration
+    pub ssm: Option<SsmConfig>,
+    /// GCP specific configuration
+    pub gcp: Option<GcpConfig>,
+    /// Azure specific configuration
+    pub azure: Option<AzureConfig>,
+    /// Memory backend specific configuration
+    pub memory: Option<MemoryConfig>,
+    /// Local age encrypted backend specific configuration
+    pub local: Option<LocalConfig>,
+    /// SOPS backend specific configuration
+    pub sops: Option<SopsConfig>,
+}
+
+impl BackendOverride {
+    /// Whether nothing is overridden
+    pub fn is_empty(&self) -> bool {
+        *self == BackendOverride::default()
+    }
+}
+
+/// Compression applied to a secret value before it is stored
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Zstandard compression
+    Zstd,
+}
+
+/// Destination a pulled value is piped into instead of a file
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct SinkConfig {
+    /// Command run through the system shell, the value is provided on its
+    /// standard input
+    pub command: String,
+}
+
+/// File whose value is produced by a [SecretGenerator] on pull
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct GeneratedFile {
+    /// Path relative to the config file to store the generated value at
+    pub path: PathBuf,
+    /// Generator producing the value
+    #[serde(flatten)]
+    pub generator: SecretGenerator,
+}
+
+impl GeneratedFile {
+    /// Resolve the path of the generated file, relative paths are resolved
+    /// against the provided `working_path`
+    pub fn resolve_path(&self, working_path: &Path) -> PathBuf {
+        resolve_relative(&self.path, working_path)
+    }
+}
+
+/// File rendered from a local template with secret values injected
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct RenderFile {
+    /// Path of the template relative to the config file
+    pub template: PathBuf,
+    /// Path relative to the config file to write the rendered output to
+    pub path: PathBuf,
+    /// Secrets available to the template, keyed by the name the template
+    /// references them by
+    #[serde(default)]
+    pub secrets: IndexMap<String, String>,
+    /// Remote hosts are only supported by secret files, a host is rejected
+    /// when preparing the config rather than writing the file locally
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+impl RenderFile {
+    /// Resolve the path of the template, relative paths are resolved
+    /// against the provided `working_path`
+    pub fn resolve_template(&self, working_path: &Path) -> PathBuf {
+        resolve_relative(&self.template, working_path)
+    }
+
+    /// Resolve the path of the rendered output, relative paths are resolved
+    /// against the provided `working_path`
+    pub fn resolve_path(&self, working_path: &Path) -> PathBuf {
+        resolve_relative(&self.path, working_path)
+    }
 }
 
-/// Provider to use for secrets
+/// Java keystore assembled from PEM certificate and key secrets
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct KeystoreFile {
+    /// Path relative to the config file to write the keystore to
+    pub path: PathBuf,
+    /// Format of the keystore
+    #[serde(default)]
+    pub format: KeystoreFormat,
+    /// Secret containing the PEM certificate chain, starting with the
+    /// certificate of the key, it may also contain the PEM private key
+    pub certificate: String,
+    /// Secret containing the PEM private key, only required when the key
+    /// is not within the `certificate` secret
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Secret containing the passphrase protecting the keystore and key
+    pub passphrase: String,
+    /// Alias of the key entry within the keystore, defaults to the name of
+    /// the keystore entry
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// Remote hosts are only supported by secret files, a host is rejected
+    /// when preparing the config rather than writing the file locally
+    #[serde(default)]
+    pub host: Option<String>,
+}
+
+/// Format of a [KeystoreFile]
 #[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-pub enum BackendProvider {
-    /// AWS (Compatible) powered backend
+pub enum KeystoreFormat {
+    /// PKCS#12 keystore, the default keystore type since Java 9
     #[default]
-    Aws,
+    Pkcs12,
+    /// Legacy Java KeyStore
+    Jks,
 }
 
-/// AWS specific configuration
-#[derive(Debug, Deserialize, Default, PartialEq, Eq)]
-pub struct AwsConfig {
-    /// AWS profile to use the sdk with
-    pub profile: Option<String>,
+impl KeystoreFile {
+    /// Resolve the path of the keystore, relative paths are resolved
+    /// against the provided `working_path`
+    pub fn resolve_path(&self, working_path: &Path) -> PathBuf {
+        resolve_relative(&self.path, working_path)
+    }
 
-    /// Optionally override the AWS region
-    pub region: Option<String>,
+    /// Names of the secrets the keystore is assembled from
+    pub fn secrets(&self) -> impl Iterator<Item = &String> {
+        [
+            Some(&self.certificate),
+            self.key.as_ref(),
+            Some(&self.passphrase),
+        ]
+        .into_iter()
+        .flatten()
+    }
 
-    /// Custom override for the AWS secret manager endpoint
-    pub endpoint: Option<String>,
+    /// Mutable names of the secrets the keystore is assembled from
+    fn secrets_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        [
+            Some(&mut self.certificate),
+            self.key.as_mut(),
+            Some(&mut self.passphrase),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
 
-    /// Custom AWS credentials to use
-    pub credentials: Option<AwsCredentials>,
+/// Resolve `path` against the `working_path` when it is relative
+fn resolve_relative(path: &Path, working_path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        working_path.join(path)
+    }
 }
 
-/// AWS credentials
-#[derive(Deserialize, PartialEq, Eq)]
-pub struct AwsCredentials {
-    /// AWS access key
-    pub access_key_id: String,
-    /// AWS access secret
-    pub access_key_secret: String,
+/// Generator producing a fresh secret value using the backend credentials
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "generator", rename_all = "kebab-case")]
+pub enum SecretGenerator {
+    /// Short-lived RDS IAM authentication token (AWS backend)
+    RdsIamToken(RdsIamTokenConfig),
+    /// Container registry credentials for the ECR registry of the account
+    /// (AWS backend)
+    EcrCredentials(RegistryCredentialsConfig),
 }
 
-impl Debug for AwsCredentials {
+impl Display for SecretGenerator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("AwsCredentials")
-            .field("access_key_id", &"< REDACTED >")
-            .field("access_key_secret", &"< REDACTED >")
-            .finish()
+        match self {
+            SecretGenerator::RdsIamToken(_) => f.write_str("rds-iam-token"),
+            SecretGenerator::EcrCredentials(_) => f.write_str("ecr-credentials"),
+        }
     }
 }
 
-/// The secret file instance
-#[derive(Debug, Deserialize, PartialEq, Eq)]
-pub struct SecretFile {
-    /// Path relative to the config file to store the secret at
-    pub path: PathBuf,
-    /// Name of the secret to store / retrieve the file based on
-    pub secret: String,
-    /// Additional secret metadata to use when pushing secrets
+/// Options for generated container registry credentials
+#[derive(Debug, Deserialize, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RegistryCredentialsConfig {
+    /// Format the credentials are written in
+    pub format: RegistryCredentialsFormat,
+}
+
+/// Format of generated container registry credentials
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegistryCredentialsFormat {
+    /// JSON output of a docker credential helper `get` command
+    #[default]
+    DockerCredential,
+    /// Docker `config.json`, the credentials are merged into the `auths`
+    /// of the existing file
+    DockerConfig,
+}
+
+/// Database connection details an RDS IAM authentication token is
+/// generated for
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct RdsIamTokenConfig {
+    /// Hostname of the database instance or proxy
+    pub host: String,
+    /// Port of the database
+    pub port: u16,
+    /// Database user to authenticate as
+    pub user: String,
+    /// Region of the database, defaults to the backend region
     #[serde(default)]
-    pub metadata: SecretMetadata,
+    pub region: Option<String>,
+}
+
+/// Transform applied to a structured secret value when written locally
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueTransform {
+    /// Always wrap the value in double quotes
+    Quote,
+    /// Percent-encode the value for embedding in URLs, decoded again
+    /// when pushing
+    UrlEncode,
+}
+
+impl SecretFile {
+    /// Whether the file is processed key by key as a structured (dotenv
+    /// or JSON) secret rather than as a whole
+    pub fn is_structured(&self) -> bool {
+        self.keys.is_some() || !self.key_map.is_empty() || !self.transforms.is_empty()
+    }
+
+    /// Whether the secret is managed by another system and must only be
+    /// pulled
+    pub fn is_externally_managed(&self) -> bool {
+        self.managed_by
+            .as_deref()
+            .is_some_and(|owner| owner != MANAGED_BY_VALUE)
+    }
+
+    /// Whether the file can only be pulled, either because it is managed
+    /// by another system or because it is piped to a sink
+    pub fn is_pull_only(&self) -> bool {
+        self.is_externally_managed() || self.sink.is_some()
+    }
+
+    /// Resolve the path of the secret file, relative paths are resolved
+    /// against the provided `working_path`
+    pub fn resolve_path(&self, working_path: &Path) -> PathBuf {
+        if self.path.is_absolute() || self.host.is_some() {
+            self.path.clone()
+        } else {
+            working_path.join(&self.path)
+        }
+    }
 }
 
 /// Metadata to use with a secret file
-#[derive(Debug, Deserialize, Default, PartialEq, Eq, Clone)]
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Eq, Clone)]
 #[serde(default)]
 pub struct SecretMetadata {
     /// Optional description of the secret, this will be attached
-    /// to the secret if using the AWS backend
+    /// to the secret if using the AWS, SSM, or GCP backend
     ///
     /// Will only be used on the first creation push
     pub description: Option<String>,
 
-    /// Optional tags to attach to the secret (AWS Backend)
+    /// Optional tags to attach to the secret (AWS, SSM, GCP, and Azure
+    /// backends)
     ///
     /// Will only be used on the first creation push
     pub tags: Option<IndexMap<String, String>>,
+
+    /// Optional comment attached to each pushed version of the secret if
+    /// using the SSM or Azure backend, which keep metadata per version
+    ///
+    /// Unlike the description this is used on every push
+    pub version_comment: Option<String>,
 }
 
 /// Name for the secrets config file (TOML)
-const CONFIG_FILE_NAME_TOML: &str = "secret-sync.toml";
+pub const CONFIG_FILE_NAME_TOML: &str = "secret-sync.toml";
 
 /// Name for the secrets config file (JSON)
 const CONFIG_FILE_NAME_JSON: &str = "secret-sync.json";
 
 /// Searches for the nearest configuration file, checks the current
 /// directory then parent directories one by one until a config is found
-pub async fn discover_nearest_config_file() -> eyre::Result<PathBuf> {
-    let mut path: PathBuf = current_dir().context("failed to determine current directory")?;
+pub async fn discover_nearest_config_file() -> Result<PathBuf> {
+    let start = current_dir().context("failed to determine current directory")?;
+    find_nearest_config_file(&start)
+}
 
-    loop {
-        let config_path_toml = path.join(CONFIG_FILE_NAME_TOML);
-        if config_path_toml.is_dir() {
-            eyre::bail!("expected secret-sync.toml to be a file but got a directory");
+/// Searches for the nearest configuration file starting from the `start`
+/// directory then each of its parent directories
+pub fn find_nearest_config_file(start: &Path) -> Result<PathBuf> {
+    for directory in start.ancestors() {
+        if let Some(path) = find_config_file_in(directory)? {
+            return Ok(path);
         }
+    }
 
-        if config_path_toml.exists() {
-            return Ok(config_path_toml);
-        }
+    Err(SyncError::config(format!(
+        "could not find {CONFIG_FILE_NAME_TOML} or {CONFIG_FILE_NAME_JSON} in \"{}\" or any parent directories, create one or specify its path using --config",
+        start.display()
+    )))
+}
+
+/// Find the config file directly within `directory`
+fn find_config_file_in(directory: &Path) -> Result<Option<PathBuf>> {
+    for name in [CONFIG_FILE_NAME_TOML, CONFIG_FILE_NAME_JSON] {
+        let path = directory.join(name);
 
-        let config_path_json = path.join(CONFIG_FILE_NAME_JSON);
-        if config_path_json.is_dir() {
-            eyre::bail!("expected secret-sync.toml to be a file but got a directory");
+        if path.is_dir() {
+            return Err(SyncError::config(format!(
+                "expected \"{}\" to be a file but got a directory",
+                path.display()
+            )));
         }
 
-        if config_path_json.exists() {
-            return Ok(config_path_json);
+        if path.exists() {
+            return Ok(Some(path));
         }
+    }
+
+    Ok(None)
+}
+
+/// Resolve a user provided config `path` into an absolute path to the
+/// config file, directories are searched for a config file
+pub fn resolve_config_path(path: &Path) -> Result<PathBuf> {
+    let absolute_path = std::path::absolute(path)
+        .with_context(|| format!("failed to resolve config path \"{}\"", path.display()))?;
+
+    if absolute_path.is_dir() {
+        return find_config_file_in(&absolute_path)?.ok_or_else(|| {
+            SyncError::config(format!(
+                "no {CONFIG_FILE_NAME_TOML} or {CONFIG_FILE_NAME_JSON} found in the config directory \"{}\"",
+                absolute_path.display()
+            ))
+        });
+    }
+
+    if !absolute_path.exists() {
+        return Err(SyncError::config(format!(
+            "config file \"{}\" does not exist",
+            absolute_path.display()
+        )));
+    }
 
-        let parent = path
-            .parent()
-            .context("could not find secret-sync.toml in any parent directories")?;
+    Ok(absolute_path)
+}
 
-        path = parent.to_path_buf();
+/// Determine the working path that relative file paths are resolved
+/// against, the directory containing the config file at `config_path`
+pub fn config_working_path(config_path: &Path) -> Result<PathBuf> {
+    match config_path.parent() {
+        // Relative paths to a file in the current directory have an empty parent
+        Some(parent) if parent.as_os_str().is_empty() => {
+            current_dir().context("failed to determine current directory")
+        }
+        Some(parent) => Ok(parent.to_path_buf()),
+        None => Err(SyncError::config(format!(
+            "unable to determine the directory containing the config file \"{}\", use --working-dir to specify the directory files are resolved against",
+            config_path.display()
+        ))),
     }
 }
 
 /// Parse a config file from bytes of the TOML file
-fn parse_config_file_toml(file: &[u8]) -> eyre::Result<Config> {
-    toml::from_slice(file).context("failed to parse config file")
+fn parse_config_file_toml(file: &[u8]) -> Result<Config> {
+    toml::from_slice(file)
+        .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+        .context("failed to parse config file")
 }
 
 /// Parse a config file from bytes of the JSON file
-fn parse_config_file_json(file: &[u8]) -> eyre::Result<Config> {
-    serde_json::from_slice(file).context("failed to parse config file")
+fn parse_config_file_json(file: &[u8]) -> Result<Config> {
+    serde_json::from_slice(file)
+        .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+        .context("failed to parse config file")
 }
 
 /// Read a TOML config file from the provided `path`
-pub async fn read_config_file(path: &Path) -> eyre::Result<Config> {
+#[cfg(not(target_family = "wasm"))]
+pub async fn read_config_file(path: &Path) -> Result<Config> {
     let value = tokio::fs::read(path)
         .await
         .context("failed to read config file")?;
 
-    let extension = match path.extension() {
-        Some(value) => value.to_str().context("invalid file extension")?,
+    let extension = path
+        .extension()
+        .map(|value| {
+            value
+                .to_str()
+                .ok_or_else(|| SyncError::config("invalid file extension"))
+        })
+        .transpose()?;
+
+    parse_config_file(&value, extension)
+}
+
+/// Read the config stored within the secret `name`, stored as JSON when
+/// the value starts with "{" otherwise as TOML
+pub async fn read_remote_config(secret: &dyn SecretManager, name: &str) -> Result<Config> {
+    let value = secret
+        .get_secret(name)
+        .await
+        .with_context(|| format!("failed to read config from secret \"{name}\""))?
+        .data;
+
+    let extension = match value.as_bytes().trim_ascii_start().starts_with(b"{") {
+        true => "json",
+        false => "toml",
+    };
+
+    let config = parse_config_file(value.as_bytes(), Some(extension))
+        .with_context(|| format!("failed to parse config from secret \"{name}\""))?;
+
+    if config.config.source != ConfigSource::File {
+        return Err(SyncError::config(format!(
+            "config from secret \"{name}\" cannot load its config from another source"
+        )));
+    }
+
+    Ok(config)
+}
+
+/// Parse a config file from its bytes, the file `extension` determines
+/// the format with TOML assumed when no extension is specified
+pub fn parse_config_file(value: &[u8], extension: Option<&str>) -> Result<Config> {
+    match extension {
+        None | Some("toml") => parse_config_file_toml(value),
+        Some("json") => parse_config_file_json(value),
+        Some(ext) => Err(SyncError::config(format!(
+            "unsupported config file extension \"{ext}\""
+        ))),
+    }
+}
 
-        // Assume TOML when no extension is specified
-        None => return parse_config_file_toml(&value),
+/// Check if the `path` is within a git repository
+pub fn is_in_git_repository(path: &Path) -> bool {
+    let Ok(path) = std::path::absolute(path) else {
+        return false;
     };
 
+    path.ancestors()
+        .any(|directory| directory.join(".git").exists())
+}
+
+/// Remove the `aws.credentials` from the contents of a config file, the
+/// file `extension` determines the format with TOML assumed when no
+/// extension is specified
+///
+/// For TOML files an `[aws.credentials]` table is removed in place so
+/// the comments and formatting of the rest of the file are kept
+pub fn remove_config_credentials(value: &str, extension: Option<&str>) -> Result<String> {
     match extension {
-        "json" => parse_config_file_json(&value),
-        "toml" => parse_config_file_toml(&value),
-        ext => eyre::bail!("unsupported config file extension \"{ext}\""),
+        None | Some("toml") => {
+            let mut output = String::with_capacity(value.len());
+            let mut in_credentials = false;
+
+            for line in value.split_inclusive('\n') {
+                let trimmed = line.trim();
+                if trimmed.starts_with('[') {
+                    in_credentials = trimmed == "[aws.credentials]";
+                }
+
+                if !in_credentials {
+                    output.push_str(line);
+                }
+            }
+
+            if parse_config_file_toml(output.as_bytes())?
+                .aws
+                .credentials
+                .is_none()
+            {
+                return Ok(output);
+            }
+
+            // Credentials specified inline require the file to be rewritten
+            let mut table: toml::Table = toml::from_str(value)
+                .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+                .context("failed to parse config file")?;
+            if let Some(toml::Value::Table(aws)) = table.get_mut("aws") {
+                aws.remove("credentials");
+            }
+
+            toml::to_string_pretty(&table)
+                .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+                .context("failed to serialize config file")
+        }
+        Some("json") => {
+            let mut json: serde_json::Value = serde_json::from_str(value)
+                .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+                .context("failed to parse config file")?;
+            if let Some(aws) = json.get_mut("aws").and_then(|aws| aws.as_object_mut()) {
+                aws.remove("credentials");
+            }
+
+            serde_json::to_string_pretty(&json)
+                .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))
+                .context("failed to serialize config file")
+        }
+        Some(ext) => Err(SyncError::config(format!(
+            "unsupported config file extension \"{ext}\""
+        ))),
+    }
+}
+
+/// Duplicate detected between two entries in the config `files`
+#[derive(Debug, PartialEq, Eq)]
+pub enum DuplicateEntry {
+    /// Two entries resolve to the same local path
+    Path {
+        /// Name of the first entry using the path
+        first: String,
+        /// Name of the second entry using the path
+        second: String,
+        /// The resolved path both entries use
+        path: PathBuf,
+    },
+
+    /// Two entries use the same secret with different local paths
+    Secret {
+        /// Name of the first entry using the secret
+        first: String,
+        /// Name of the second entry using the secret
+        second: String,
+        /// The secret both entries use
+        secret: String,
+    },
+}
+
+impl Display for DuplicateEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DuplicateEntry::Path {
+                first,
+                second,
+                path,
+            } => write!(
+                f,
+                "files \"{first}\" and \"{second}\" both resolve to the path \"{}\"",
+                path.display()
+            ),
+            DuplicateEntry::Secret {
+                first,
+                second,
+                secret,
+            } => write!(
+                f,
+                "files \"{first}\" and \"{second}\" both use the secret \"{secret}\" with different paths"
+            ),
+        }
+    }
+}
+
+/// Filter a set of `files` to those matching any of the provided `names`
+/// or `globs`, all files match when neither is provided
+pub fn filter_files<'a, F>(
+    files: &'a IndexMap<String, F>,
+    names: Option<&[String]>,
+    globs: Option<&[String]>,
+) -> Vec<(&'a String, &'a F)> {
+    files
+        .iter()
+        .filter(|(name, _file)| {
+            // Nothing to filter against
+            if names.is_none() && globs.is_none() {
+                return true;
+            }
+
+            let name_matches = names.is_some_and(|names| names.contains(name));
+
+            let glob_matches = globs.is_some_and(|globs| {
+                globs
+                    .iter()
+                    .any(|glob| fast_glob::glob_match(glob.as_bytes(), name.as_bytes()))
+            });
+
+            name_matches || glob_matches
+        })
+        .collect()
+}
+
+/// Find entries within `files` that resolve to the same local path or
+/// that share a secret while using different paths
+///
+/// Without detection these cause silent last-write-wins behavior on pull
+pub fn find_duplicate_entries(
+    files: &IndexMap<String, SecretFile>,
+    working_path: &Path,
+) -> Vec<DuplicateEntry> {
+    let mut duplicates = Vec::new();
+    let mut paths: HashMap<(Option<&str>, PathBuf), &str> = HashMap::new();
+    let mut secrets: HashMap<&str, (&str, PathBuf)> = HashMap::new();
+
+    for (name, file) in files {
+        let path = normalize_path(&file.resolve_path(working_path));
+
+        // The same path may be used on each host
+        let key = (file.host.as_deref(), path.clone());
+        match paths.get(&key) {
+            Some(first) => duplicates.push(DuplicateEntry::Path {
+                first: first.to_string(),
+                second: name.clone(),
+                path: path.clone(),
+            }),
+            None => {
+                paths.insert(key, name);
+            }
+        }
+
+        match secrets.get(file.secret.as_str()) {
+            Some((first, first_path)) => {
+                if first_path != &path {
+                    duplicates.push(DuplicateEntry::Secret {
+                        first: first.to_string(),
+                        second: name.clone(),
+                        secret: file.secret.clone(),
+                    });
+                }
+            }
+            None => {
+                secrets.insert(&file.secret, (name, path));
+            }
+        }
     }
+
+    duplicates
+}
+
+/// Lexically normalize a path removing "." components and resolving
+/// ".." components without touching the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
 }
 
 #[cfg(test)]
 mod test {
-    use crate::config::{parse_config_file_json, parse_config_file_toml};
+    use crate::{
+        config::{
+            AwsConfig, AwsPartition, BackendProvider, Config, DuplicateEntry, PushConfig,
+            RdsIamTokenConfig, RenderFile, SecretFile, SecretGenerator, SecretMetadata,
+            TenancyConfig, config_working_path, find_duplicate_entries, find_nearest_config_file,
+            parse_config_file_json, parse_config_file_toml, read_remote_config,
+            remove_config_credentials, resolve_config_path,
+        },
+        secret::{MockSecretManager, Secret},
+    };
+    use indexmap::IndexMap;
+    use mockall::predicate::eq;
+    use std::path::{Path, PathBuf};
 
     /// Tests that the example TOML configs can be parsed
     #[test]
@@ -224,4 +1847,626 @@ mod test {
             assert_eq!(config_toml, config_json);
         }
     }
+
+    /// Tests that entries resolving to the same path are detected
+    #[test]
+    fn test_duplicate_paths() {
+        let mut files = IndexMap::new();
+        files.insert(
+            "first".to_string(),
+            SecretFile {
+                path: PathBuf::from(".env"),
+                secret: "first".to_string(),
+                ..Default::default()
+            },
+        );
+        files.insert(
+            "second".to_string(),
+            SecretFile {
+                path: PathBuf::from("./nested/../.env"),
+                secret: "second".to_string(),
+                ..Default::default()
+            },
+        );
+
+        let duplicates = find_duplicate_entries(&files, Path::new("/project"));
+        assert_eq!(
+            duplicates,
+            vec![DuplicateEntry::Path {
+                first: "first".to_string(),
+                second: "second".to_string(),
+                path: PathBuf::from("/project/.env"),
+            }]
+        );
+    }
+
+    /// Tests that the same path on different hosts is not a duplicate
+    #[test]
+    fn test_duplicate_paths_hosts() {
+        let files: IndexMap<String, SecretFile> = ["web-1", "web-2"]
+            .into_iter()
+            .map(|host| {
+                let file = SecretFile {
+                    path: PathBuf::from("/etc/app/.env"),
+                    secret: "app".to_string(),
+                    host: Some(host.to_string()),
+                    ..Default::default()
+                };
+                (host.to_string(), file)
+            })
+            .collect();
+
+        assert!(find_duplicate_entries(&files, Path::new("/project")).is_empty());
+    }
+
+    /// Tests that entries sharing a secret with different paths are detected
+    #[test]
+    fn test_duplicate_secrets() {
+        let mut files = IndexMap::new();
+        files.insert(
+            "first".to_string(),
+            SecretFile {
+                path: PathBuf::from(".env"),
+                secret: "shared".to_string(),
+                ..Default::default()
+            },
+        );
+        files.insert(
+            "second".to_string(),
+            SecretFile {
+                path: PathBuf::from(".env.other"),
+                secret: "shared".to_string(),
+                ..Default::default()
+            },
+        );
+
+        let duplicates = find_duplicate_entries(&files, Path::new("/project"));
+        assert_eq!(
+            duplicates,
+            vec![DuplicateEntry::Secret {
+                first: "first".to_string(),
+                second: "second".to_string(),
+                secret: "shared".to_string(),
+            }]
+        );
+    }
+
+    /// Tests that the example configs contain no duplicates
+    #[test]
+    fn test_no_duplicates() {
+        let config = parse_config_file_toml(
+            include_str!("../tests/samples/config/example-3.toml").as_bytes(),
+        )
+        .unwrap();
+
+        assert!(find_duplicate_entries(&config.files, Path::new("/project")).is_empty());
+    }
+
+    /// Tests that files are kept in the order they are declared in the config
+    #[test]
+    fn test_files_declared_order() {
+        let expected = [
+            "example",
+            "example-2",
+            "example-3",
+            "example-4",
+            "example-5",
+        ];
+
+        let config_toml = parse_config_file_toml(
+            include_str!("../tests/samples/config/example-3.toml").as_bytes(),
+        )
+        .unwrap();
+        let config_json = parse_config_file_json(
+            include_str!("../tests/samples/config/example-3.json").as_bytes(),
+        )
+        .unwrap();
+
+        assert!(config_toml.files.keys().eq(expected.iter()));
+        assert!(config_json.files.keys().eq(expected.iter()));
+    }
+
+    /// Create a config with a templated and a static file
+    fn environment_config() -> Config {
+        let mut config = Config::default();
+        config.files.insert(
+            "app".to_string(),
+            SecretFile {
+                path: PathBuf::from(".env.{env}"),
+                secret: "app/{env}".to_string(),
+                ..Default::default()
+            },
+        );
+        config.files.insert(
+            "static".to_string(),
+            SecretFile {
+                path: PathBuf::from(".env.static"),
+                secret: "static".to_string(),
+                ..Default::default()
+            },
+        );
+        config
+    }
+
+    /// Tests that the environment placeholder is replaced in paths and secrets
+    #[test]
+    fn test_apply_environment() {
+        let mut config = environment_config();
+        config.render.insert(
+            "config".to_string(),
+            RenderFile {
+                template: PathBuf::from("config.tmpl.yaml"),
+                path: PathBuf::from("config.yaml"),
+                secrets: IndexMap::from([("db".to_string(), "db/{env}".to_string())]),
+                host: None,
+            },
+        );
+
+        config.apply_environment(Some("staging")).unwrap();
+        assert_eq!(config.files["app"].path, PathBuf::from(".env.staging"));
+        assert_eq!(config.files["app"].secret, "app/staging");
+        assert_eq!(config.files["static"].path, PathBuf::from(".env.static"));
+        assert_eq!(config.render["config"].secrets["db"], "db/staging");
+
+        assert!(
+            environment_config()
+                .apply_environment(Some("../other"))
+                .is_err()
+        );
+        assert!(environment_config().apply_environment(None).is_err());
+    }
+
+    /// Tests that secrets outside of the allowed prefixes are rejected
+    #[test]
+    fn test_tenancy_prefixes() {
+        let tenancy = TenancyConfig::default();
+        assert!(tenancy.check_secret("anything").is_ok());
+
+        let tenancy = TenancyConfig {
+            allowed_prefixes: vec!["team-a/".to_string(), "shared/".to_string()],
+            allowed_accounts: vec!["123456789012".to_string()],
+            allowed_regions: vec!["eu-west-1".to_string()],
+        };
+        assert!(tenancy.check_secret("team-a/app").is_ok());
+        assert!(tenancy.check_secret("shared/app").is_ok());
+        assert!(tenancy.check_secret("team-b/app").is_err());
+        assert!(tenancy.check_secret("team-a").is_err());
+        assert!(
+            tenancy
+                .check_secret(
+                    "arn:aws:secretsmanager:eu-west-1:123456789012:secret:team-a/app-AbC123"
+                )
+                .is_ok()
+        );
+
+        // ARNs of other accounts and regions are rejected
+        assert!(
+            tenancy
+                .check_secret(
+                    "arn:aws:secretsmanager:eu-west-1:999999999999:secret:team-a/app-AbC123"
+                )
+                .is_err()
+        );
+        assert!(
+            tenancy
+                .check_secret(
+                    "arn:aws:secretsmanager:us-east-1:123456789012:secret:team-a/app-AbC123"
+                )
+                .is_err()
+        );
+
+        // Restricting prefixes alone rejects every ARN
+        let restricted = TenancyConfig {
+            allowed_prefixes: vec!["team-a/".to_string()],
+            ..Default::default()
+        };
+        assert!(
+            restricted
+                .check_secret(
+                    "arn:aws:secretsmanager:eu-west-1:123456789012:secret:team-a/app-AbC123"
+                )
+                .is_err()
+        );
+
+        let mut config = environment_config();
+        config.tenancy = tenancy;
+        config.apply_environment(Some("staging")).unwrap();
+        assert!(config.check_tenancy().is_err());
+    }
+
+    /// Tests the push limits and their defaults
+    #[test]
+    fn test_push_limits() {
+        let push = PushConfig::default();
+        assert!(!push.requires_confirmation(25));
+        assert!(push.requires_confirmation(26));
+        assert!(push.check_max_files(1000, None).is_ok());
+        assert!(push.check_max_files(11, Some(10)).is_err());
+
+        let config = parse_config_file_toml(
+            br#"
+            [push]
+            confirm_threshold = 0
+            max_files = 5
+            "#,
+        )
+        .unwrap();
+        assert!(!config.push.requires_confirmation(1000));
+        assert!(config.push.check_max_files(6, None).is_err());
+        assert!(config.push.check_max_files(6, Some(10)).is_ok());
+    }
+
+    /// Tests that files may override the top-level backend
+    #[test]
+    fn test_file_backend_override() {
+        let config = parse_config_file_toml(
+            br#"
+            [backend]
+            provider = "aws"
+
+            [files.app]
+            path = ".env"
+            secret = "app"
+
+            [files.db]
+            path = ".env.db"
+            secret = "/app/db"
+            provider = "ssm"
+            ssm = { prefix = "/team" }
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.files["app"].backend_override.is_empty());
+
+        let backend = &config.files["db"].backend_override;
+        assert_eq!(backend.provider, Some(BackendProvider::Ssm));
+        assert!(backend.ssm.is_some());
+        assert!(backend.aws.is_none());
+    }
+
+    /// Tests that service files are resolved under the service directory
+    /// with the service defaults
+    #[test]
+    fn test_services() {
+        let mut config = parse_config_file_toml(
+            br#"
+            [services.api]
+            prefix = "api/"
+            environment = "staging"
+
+            [services.worker]
+            path = "apps/worker"
+
+            [files.api]
+            path = ".env"
+            secret = "{env}/app"
+            service = "api"
+
+            [files.worker]
+            path = ".env"
+            secret = "worker"
+            service = "worker"
+
+            [files.root]
+            path = ".env"
+            secret = "root"
+            "#,
+        )
+        .unwrap();
+
+        config.apply_services();
+        assert_eq!(config.files["api"].path, Path::new("services/api/.env"));
+        assert_eq!(config.files["api"].secret, "api/{env}/app");
+        assert_eq!(config.files["worker"].path, Path::new("apps/worker/.env"));
+        assert_eq!(config.files["root"].path, Path::new(".env"));
+
+        let api = ["api".to_string()];
+        assert_eq!(config.service_environment(&api), Some("staging"));
+        let both = ["api".to_string(), "worker".to_string()];
+        assert_eq!(config.service_environment(&both), None);
+    }
+
+    /// Tests that preparing a config resolves the service environment before
+    /// checking the tenancy of the resolved names
+    #[test]
+    fn test_prepare() {
+        let config_toml = br#"
+            [tenancy]
+            allowed_prefixes = ["api/staging/"]
+
+            [services.api]
+            prefix = "api/"
+            environment = "staging"
+
+            [files.api]
+            path = ".env"
+            secret = "{env}/app"
+            service = "api"
+
+            [defaults.metadata]
+            description = "{secret}"
+            "#;
+
+        let mut config = parse_config_file_toml(config_toml).unwrap();
+        let environment = config.prepare(None, &["api".to_string()]).unwrap();
+        assert_eq!(environment.as_deref(), Some("staging"));
+        assert_eq!(config.files["api"].secret, "api/staging/app");
+        assert_eq!(
+            config.files["api"].metadata.description.as_deref(),
+            Some("api/staging/app")
+        );
+
+        // The provided environment takes priority over the service default
+        let mut config = parse_config_file_toml(config_toml).unwrap();
+        assert!(config.prepare(Some("production"), &[]).is_err());
+
+        // Names are checked after the environment is resolved
+        let mut config = parse_config_file_toml(config_toml).unwrap();
+        assert!(config.prepare(None, &[]).is_err());
+    }
+
+    /// Tests that only secret files may use a remote host
+    #[test]
+    fn test_check_hosts() {
+        let mut config = parse_config_file_toml(
+            br#"
+            [files.app]
+            path = ".env"
+            secret = "app"
+            host = "web-1"
+
+            [keystore.tls]
+            path = "tls.p12"
+            certificate = "tls/cert"
+            passphrase = "tls/passphrase"
+            "#,
+        )
+        .unwrap();
+        config.check_hosts().unwrap();
+
+        config.keystore["tls"].host = Some("web-1".to_string());
+        assert!(config.check_hosts().is_err());
+
+        let mut config = parse_config_file_toml(
+            br#"
+            [render.config]
+            template = "config.tmpl"
+            path = "config.yaml"
+            host = "web-1"
+            "#,
+        )
+        .unwrap();
+        assert!(config.prepare(None, &[]).is_err());
+    }
+
+    /// Tests that configs are read from secrets in either format
+    #[tokio::test]
+    async fn test_read_remote_config() {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .with(eq("toml"))
+            .returning(|_name| {
+                Ok(Secret::String("[files.app]\npath = \".env\"\nsecret = \"app\"".into()).into())
+            });
+        secret
+            .expect_get_secret()
+            .with(eq("json"))
+            .returning(|_name| Ok(Secret::String(r#" {"files": {}}"#.into()).into()));
+        secret
+            .expect_get_secret()
+            .with(eq("nested"))
+            .returning(|_name| {
+                Ok(
+                    Secret::String("[config]\nsource = \"secret\"\nsecret = \"toml\"".into())
+                        .into(),
+                )
+            });
+
+        let config = read_remote_config(&secret, "toml").await.unwrap();
+        assert_eq!(config.files["app"].secret, "app");
+        assert!(read_remote_config(&secret, "json").await.is_ok());
+        assert!(read_remote_config(&secret, "nested").await.is_err());
+
+        let source = parse_config_file_toml(b"[config]\nsource = \"secret\"").unwrap();
+        assert!(source.config.remote_secret().is_err());
+    }
+
+    /// Tests discovering and resolving config files
+    #[test]
+    fn test_config_file_discovery() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert!(find_nearest_config_file(&nested).is_err());
+
+        let config_path = root.path().join("secret-sync.json");
+        std::fs::write(&config_path, "{}").unwrap();
+
+        assert_eq!(find_nearest_config_file(&nested).unwrap(), config_path);
+        assert_eq!(resolve_config_path(root.path()).unwrap(), config_path);
+        assert!(resolve_config_path(&nested).is_err());
+        assert!(resolve_config_path(&root.path().join("missing.toml")).is_err());
+    }
+
+    /// Tests resolving the working path using paths.base
+    #[test]
+    fn test_paths_base() {
+        let config: Config = parse_config_file_toml(b"[paths]\nbase = \"../app\"\n").unwrap();
+        assert_eq!(
+            config.paths.working_path(Path::new("/tmp/generated")),
+            Path::new("/tmp/generated/../app")
+        );
+
+        let config: Config = parse_config_file_toml(b"[paths]\nbase = \"/srv/app\"\n").unwrap();
+        assert_eq!(
+            config.paths.working_path(Path::new("/tmp/generated")),
+            Path::new("/srv/app")
+        );
+
+        assert_eq!(
+            Config::default()
+                .paths
+                .working_path(Path::new("/tmp/generated")),
+            Path::new("/tmp/generated")
+        );
+    }
+
+    /// Tests determining the working path from the config path
+    #[test]
+    fn test_config_working_path() {
+        assert_eq!(
+            config_working_path(Path::new("/project/secret-sync.toml")).unwrap(),
+            Path::new("/project")
+        );
+        assert_eq!(
+            config_working_path(Path::new("/secret-sync.toml")).unwrap(),
+            Path::new("/")
+        );
+        assert_eq!(
+            config_working_path(Path::new("secret-sync.toml")).unwrap(),
+            std::env::current_dir().unwrap()
+        );
+        assert!(config_working_path(Path::new("/")).is_err());
+    }
+
+    /// Tests removing credentials keeps the rest of a TOML config intact
+    #[test]
+    fn test_remove_config_credentials() {
+        let config = "# Project secrets\n[aws]\nregion = \"us-east-1\"\n\n[aws.credentials]\naccess_key_id = \"test\"\naccess_key_secret = \"secret\"\n\n[files.app]\npath = \".env\"\nsecret = \"app\"\n";
+
+        let output = remove_config_credentials(config, Some("toml")).unwrap();
+        assert_eq!(
+            output,
+            "# Project secrets\n[aws]\nregion = \"us-east-1\"\n\n[files.app]\npath = \".env\"\nsecret = \"app\"\n"
+        );
+
+        let config =
+            "[aws]\ncredentials = { access_key_id = \"test\", access_key_secret = \"secret\" }\n";
+        let output = remove_config_credentials(config, Some("toml")).unwrap();
+        let config = parse_config_file_toml(output.as_bytes()).unwrap();
+        assert!(config.aws.credentials.is_none());
+
+        let config =
+            r#"{"aws":{"credentials":{"access_key_id":"test","access_key_secret":"secret"}}}"#;
+        let output = remove_config_credentials(config, Some("json")).unwrap();
+        let config = parse_config_file_json(output.as_bytes()).unwrap();
+        assert!(config.aws.credentials.is_none());
+    }
+
+    /// Tests region validation against the partition of the region
+    #[test]
+    fn test_validate_region() {
+        let mut config = AwsConfig {
+            use_fips: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.validate_region("us-gov-west-1").unwrap(),
+            Some(AwsPartition::GovCloud)
+        );
+        assert_eq!(
+            config.validate_region("us-east-1").unwrap(),
+            Some(AwsPartition::Standard)
+        );
+        assert_eq!(
+            AwsPartition::from_region("cn-northwest-1"),
+            Some(AwsPartition::China)
+        );
+        assert_eq!(
+            AwsPartition::from_region("us-isob-east-1"),
+            Some(AwsPartition::Isolated)
+        );
+        assert!(config.validate_region("cn-north-1").is_err());
+        assert!(config.validate_region("eu-west-1").is_err());
+        assert!(config.validate_region("useast1").is_err());
+
+        // Self-hosted endpoints accept any region but not the endpoint options
+        config.endpoint = Some("http://localhost:8080".to_string());
+        assert!(config.validate_region("local").is_err());
+        config.use_fips = false;
+        assert_eq!(config.validate_region("local").unwrap(), None);
+    }
+
+    /// Tests merging the default metadata into each file
+    #[test]
+    fn test_apply_defaults() {
+        let mut config = environment_config();
+        config.defaults.metadata = SecretMetadata {
+            description: Some("{name} secret ({secret})".to_string()),
+            tags: Some(IndexMap::from([
+                ("managed-by".to_string(), "secret-sync".to_string()),
+                ("team".to_string(), "platform".to_string()),
+            ])),
+            version_comment: Some("pushed by {user}".to_string()),
+        };
+
+        let static_file = &mut config.files["static"].metadata;
+        static_file.description = Some("Static values".to_string());
+        static_file.tags = Some(IndexMap::from([(
+            "team".to_string(),
+            "payments".to_string(),
+        )]));
+
+        config.apply_environment(Some("staging")).unwrap();
+        config.apply_defaults();
+
+        let app = &config.files["app"].metadata;
+        assert_eq!(app.description.as_deref(), Some("app secret (app/staging)"));
+        assert_eq!(app.tags.as_ref(), config.defaults.metadata.tags.as_ref());
+        assert_eq!(app.version_comment.as_deref(), Some("pushed by {user}"));
+
+        let static_file = &config.files["static"].metadata;
+        assert_eq!(static_file.description.as_deref(), Some("Static values"));
+        assert_eq!(
+            static_file.tags,
+            Some(IndexMap::from([
+                ("managed-by".to_string(), "secret-sync".to_string()),
+                ("team".to_string(), "payments".to_string()),
+            ]))
+        );
+    }
+
+    /// Tests parsing generated files with their generator options
+    #[test]
+    fn test_generated_files() {
+        let config = parse_config_file_toml(
+            br#"
+            [generated.db-token]
+            path = ".db-token"
+            generator = "rds-iam-token"
+            host = "db.example.com"
+            port = 5432
+            user = "app"
+            "#,
+        )
+        .unwrap();
+
+        let file = &config.generated["db-token"];
+        assert_eq!(file.path, Path::new(".db-token"));
+        assert_eq!(
+            file.generator,
+            SecretGenerator::RdsIamToken(RdsIamTokenConfig {
+                host: "db.example.com".to_string(),
+                port: 5432,
+                user: "app".to_string(),
+                region: None,
+            })
+        );
+
+        // Unknown generators are rejected
+        assert!(
+            parse_config_file_toml(
+                br#"
+                [generated.unknown]
+                path = ".unknown"
+                generator = "unknown"
+                "#,
+            )
+            .is_err()
+        );
+    }
 }
@@ -0,0 +1,113 @@
+//! # Cost
+//!
+//! Estimation of the monthly cost of storing and retrieving secrets based
+//! on the number of secrets and how often they are pulled, using the
+//! pricing model of AWS Secrets Manager (a price per secret per month plus
+//! a price per 10,000 API calls)
+
+use serde::Serialize;
+
+/// Price of storing a single secret for a month in USD
+pub const DEFAULT_PRICE_PER_SECRET: f64 = 0.40;
+
+/// Price of 10,000 API calls in USD
+pub const DEFAULT_PRICE_PER_10K_CALLS: f64 = 0.05;
+
+/// Number of days in a month used for the estimate
+pub const DAYS_PER_MONTH: u64 = 30;
+
+/// Prices used for the estimate in USD
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct Pricing {
+    /// Price of storing a single secret for a month
+    pub per_secret: f64,
+    /// Price of 10,000 API calls
+    pub per_10k_calls: f64,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            per_secret: DEFAULT_PRICE_PER_SECRET,
+            per_10k_calls: DEFAULT_PRICE_PER_10K_CALLS,
+        }
+    }
+}
+
+/// How often the secrets are pulled
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct UsagePattern {
+    /// Number of pulls per day on each machine (e.g. 24 for an hourly
+    /// scheduled pull)
+    pub pulls_per_day: u64,
+    /// Number of machines pulling the secrets
+    pub machines: u64,
+}
+
+/// Estimated monthly cost
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CostEstimate {
+    /// Number of secrets stored
+    pub secrets: usize,
+    /// Number of API calls made each month
+    pub api_calls: u64,
+    /// Cost of storing the secrets
+    pub secrets_cost: f64,
+    /// Cost of the API calls
+    pub api_calls_cost: f64,
+    /// Total monthly cost
+    pub total: f64,
+}
+
+impl CostEstimate {
+    /// Estimate the monthly cost of `secrets` that are each fetched on
+    /// every pull of the `usage` pattern
+    pub fn new(secrets: usize, usage: &UsagePattern, pricing: &Pricing) -> Self {
+        // Each pull fetches every secret with a single API call
+        let api_calls = secrets as u64 * usage.pulls_per_day * DAYS_PER_MONTH * usage.machines;
+
+        let secrets_cost = secrets as f64 * pricing.per_secret;
+        let api_calls_cost = api_calls as f64 / 10_000.0 * pricing.per_10k_calls;
+
+        Self {
+            secrets,
+            api_calls,
+            secrets_cost,
+            api_calls_cost,
+            total: secrets_cost + api_calls_cost,
+        }
+    }
+
+    /// Render a human readable version of the estimate
+    pub fn to_text(&self) -> String {
+        format!(
+            "secrets: {} (${:.2})\napi calls: {} per month (${:.2})\nestimated monthly cost: ${:.2}",
+            self.secrets, self.secrets_cost, self.api_calls, self.api_calls_cost, self.total
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cost::{CostEstimate, Pricing, UsagePattern};
+
+    /// Tests estimating the cost of hourly pulls across multiple machines
+    #[test]
+    fn test_cost_estimate() {
+        let usage = UsagePattern {
+            pulls_per_day: 24,
+            machines: 10,
+        };
+
+        let estimate = CostEstimate::new(50, &usage, &Pricing::default());
+        assert_eq!(estimate.api_calls, 360_000);
+        assert_eq!(
+            estimate.to_text(),
+            "secrets: 50 ($20.00)\napi calls: 360000 per month ($1.80)\nestimated monthly cost: $21.80"
+        );
+
+        // Consolidating into a single secret reduces both parts of the cost
+        let consolidated = CostEstimate::new(1, &usage, &Pricing::default());
+        assert!(consolidated.total < estimate.total);
+    }
+}
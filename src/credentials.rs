@@ -0,0 +1,566 @@
+//! # Credentials
+//!
+//! Session scoped credential resolution for backends that have no
+//! configured or ambient credentials. Credentials are prompted for
+//! interactively and only kept in memory for the current run unless
+//! keychain storage is enabled.
+//!
+//! Credentials embedded in the config may reference values stored outside
+//! of the config file rather than containing the plaintext value.
+//!
+//! Ambient credentials resolved on EC2, ECS, and EKS can be diagnosed to
+//! identify their source and common misconfigurations.
+//!
+//! OIDC identity tokens issued by CI providers can be resolved for exchange
+//! with the backend, removing the need for long-lived keys in CI.
+
+use crate::{
+    config::{
+        AwsCredentials, CredentialsConfig, ENV_VALUE_PREFIX, IdentityTokenSource,
+        KEYRING_VALUE_PREFIX,
+    },
+    doctor::CredentialProblem,
+    shell::run_shell_command,
+    structured::url_encode,
+};
+use eyre::{Context, ContextCompat};
+use http_body_util::BodyExt;
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+/// Service name used for keychain entries
+const KEYCHAIN_SERVICE: &str = "secret-sync";
+
+/// Resolve the values of the `credentials` from the config, replacing any
+/// references with the values they refer to
+pub fn resolve_config_credentials(credentials: &AwsCredentials) -> eyre::Result<AwsCredentials> {
+    Ok(AwsCredentials {
+        access_key_id: resolve_config_value(&credentials.access_key_id)
+            .context("failed to resolve aws.credentials.access_key_id")?,
+        access_key_secret: resolve_config_value(&credentials.access_key_secret)
+            .context("failed to resolve aws.credentials.access_key_secret")?,
+    })
+}
+
+/// Resolve a config `value` that may reference a value stored elsewhere:
+///
+/// - `keyring:<account>` Value stored in the OS keychain under the
+///   "secret-sync" service for `<account>`
+/// - `env:<NAME>` Value of the `<NAME>` environment variable
+///
+/// Any other value is used as is
+pub fn resolve_config_value(value: &str) -> eyre::Result<String> {
+    if let Some(account) = value.strip_prefix(KEYRING_VALUE_PREFIX) {
+        return keyring::Entry::new(KEYCHAIN_SERVICE, account)
+            .and_then(|entry| entry.get_password())
+            .with_context(|| format!("failed to read \"{account}\" from the keychain"));
+    }
+
+    if let Some(name) = value.strip_prefix(ENV_VALUE_PREFIX) {
+        return std::env::var(name)
+            .with_context(|| format!("environment variable \"{name}\" is not set"));
+    }
+
+    Ok(value.to_string())
+}
+
+/// Resolve AWS credentials for the provided `profile` from the keychain
+/// or by prompting the user
+///
+/// Provides [None] when prompting is disabled or not possible
+pub fn resolve_aws_credentials(
+    config: &CredentialsConfig,
+    profile: Option<&str>,
+) -> eyre::Result<Option<AwsCredentials>> {
+    let account = format!("aws:{}", profile.unwrap_or("default"));
+
+    if config.keychain
+        && let Some(credentials) = load_keychain_credentials(&account)
+    {
+        tracing::debug!(%account, "using credentials from keychain");
+        return Ok(Some(credentials));
+    }
+
+    if !config.prompt || !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    let credentials = prompt_aws_credentials()?;
+
+    if config.keychain {
+        store_keychain_credentials(&account, &credentials)?;
+    }
+
+    Ok(Some(credentials))
+}
+
+/// Resolve the MFA token code for the device `serial`, using the provided
+/// `token` or `command` before falling back to prompting the user
+pub fn resolve_mfa_token(
+    serial: &str,
+    token: Option<&str>,
+    command: Option<&str>,
+) -> eyre::Result<String> {
+    if let Some(token) = token {
+        return Ok(token.to_string());
+    }
+
+    if let Some(command) = command {
+        let token = run_shell_command(command).context("failed to run mfa command")?;
+        return Ok(token.trim().to_string());
+    }
+
+    if !std::io::stdin().is_terminal() {
+        eyre::bail!(
+            "MFA device \"{serial}\" requires a token code, provide one using --mfa-token or aws.mfa_command"
+        );
+    }
+
+    prompt_line(&format!("MFA code for {serial}: "))
+}
+
+/// Prompt the user for AWS credentials through the terminal
+fn prompt_aws_credentials() -> eyre::Result<AwsCredentials> {
+    eprintln!("No AWS credentials were found, please enter credentials for this session");
+
+    let access_key_id = prompt_line("AWS access key ID: ")?;
+    let access_key_secret =
+        rpassword::prompt_password("AWS secret access key: ").context("failed to read input")?;
+
+    Ok(AwsCredentials {
+        access_key_id,
+        access_key_secret: access_key_secret.trim().to_string(),
+    })
+}
+
+/// Prompt the user for a single visible line of input
+pub fn prompt_line(prompt: &str) -> eyre::Result<String> {
+    eprint!("{prompt}");
+    std::io::stderr()
+        .flush()
+        .context("failed to write prompt")?;
+
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .context("failed to read input")?;
+
+    Ok(line.trim().to_string())
+}
+
+/// Load credentials stored in the keychain under `account`
+fn load_keychain_credentials(account: &str) -> Option<AwsCredentials> {
+    let entry = keyring::Entry::new(KEYCHAIN_SERVICE, account)
+        .inspect_err(|error| tracing::debug!(?error, "failed to access keychain"))
+        .ok()?;
+
+    let value = entry
+        .get_password()
+        .inspect_err(|error| tracing::debug!(?error, "no keychain credentials"))
+        .ok()?;
+
+    serde_json::from_str(&value)
+        .inspect_err(|error| tracing::warn!(?error, "invalid keychain credentials"))
+        .ok()
+}
+
+/// Store `credentials` in the keychain under `account`
+fn store_keychain_credentials(account: &str, credentials: &AwsCredentials) -> eyre::Result<()> {
+    let value = serde_json::to_string(credentials)?;
+
+    keyring::Entry::new(KEYCHAIN_SERVICE, account)
+        .and_then(|entry| entry.set_password(&value))
+        .context("failed to store credentials in keychain")?;
+
+    Ok(())
+}
+
+/// Source AWS credentials were resolved from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialSource {
+    /// Credentials embedded in the config
+    Config,
+    /// Credentials prompted for or loaded from the keychain
+    Prompt,
+    /// Credentials of an assumed role
+    AssumedRole,
+    /// AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables
+    Environment,
+    /// Static credentials from the shared profile files
+    Profile,
+    /// IAM Identity Center (SSO)
+    Sso,
+    /// External credential_process command
+    CredentialProcess,
+    /// Web identity token, used by IRSA on EKS
+    WebIdentity,
+    /// CI identity token exchanged using aws.web_identity
+    CiIdentity,
+    /// Container credentials endpoint, used by ECS task roles and EKS Pod
+    /// Identity
+    Container,
+    /// EC2 instance metadata service
+    Imds,
+    /// Provider not known to secret-sync
+    Other(String),
+}
+
+impl CredentialSource {
+    /// Determine the source from the name of the SDK credentials provider
+    pub fn from_provider_name(name: &str) -> CredentialSource {
+        match name {
+            "secret_sync" => CredentialSource::Config,
+            "secret_sync_prompt" => CredentialSource::Prompt,
+            "secret_sync_assume_role" | "AssumeRoleProvider" => CredentialSource::AssumedRole,
+            "secret_sync_web_identity" => CredentialSource::CiIdentity,
+            "EnvironmentVariable" => CredentialSource::Environment,
+            "ProfileFile" => CredentialSource::Profile,
+            "SSO" => CredentialSource::Sso,
+            "CredentialProcess" => CredentialSource::CredentialProcess,
+            "WebIdentityToken" => CredentialSource::WebIdentity,
+            "EcsContainer" => CredentialSource::Container,
+            "IMDSv2" => CredentialSource::Imds,
+            other => CredentialSource::Other(other.to_string()),
+        }
+    }
+}
+
+impl Display for CredentialSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CredentialSource::Config => "config (aws.credentials)",
+            CredentialSource::Prompt => "prompt or keychain",
+            CredentialSource::AssumedRole => "assumed role",
+            CredentialSource::Environment => "environment variables",
+            CredentialSource::Profile => "shared profile",
+            CredentialSource::Sso => "IAM Identity Center (SSO)",
+            CredentialSource::CredentialProcess => "credential process",
+            CredentialSource::WebIdentity => "web identity token (IRSA)",
+            CredentialSource::CiIdentity => "CI identity token (aws.web_identity)",
+            CredentialSource::Container => {
+                "container credentials (ECS task role or EKS Pod Identity)"
+            }
+            CredentialSource::Imds => "instance metadata (IMDSv2)",
+            CredentialSource::Other(name) => name,
+        })
+    }
+}
+
+/// Audience requested for GitHub Actions identity tokens by default
+pub const DEFAULT_GITHUB_AUDIENCE: &str = "sts.amazonaws.com";
+
+/// Environment variable containing the GitLab CI identity token by default
+pub const DEFAULT_GITLAB_TOKEN_ENV: &str = "SECRET_SYNC_ID_TOKEN";
+
+/// Resolve the OIDC identity token from the CI provider `source`
+pub async fn resolve_identity_token(source: &IdentityTokenSource) -> eyre::Result<String> {
+    let token = match source {
+        IdentityTokenSource::GithubActions { audience } => {
+            let request = GithubTokenRequest::from_vars(
+                |name| std::env::var(name).ok(),
+                audience.as_deref(),
+            )?;
+            request.send().await?
+        }
+        IdentityTokenSource::Gitlab { token_env } => {
+            let name = token_env.as_deref().unwrap_or(DEFAULT_GITLAB_TOKEN_ENV);
+            std::env::var(name).ok().with_context(|| {
+                format!("GitLab identity token is unavailable, add \"{name}\" to the job id_tokens")
+            })?
+        }
+        IdentityTokenSource::File { path } => std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read identity token \"{}\"", path.display()))?,
+    };
+
+    let token = token.trim();
+    if token.is_empty() {
+        eyre::bail!("identity token is empty");
+    }
+
+    Ok(token.to_string())
+}
+
+/// Request for an identity token from the GitHub Actions token service
+#[derive(Debug, PartialEq, Eq)]
+struct GithubTokenRequest {
+    /// URL of the token service including the requested audience
+    url: String,
+    /// Bearer token authorizing the request
+    bearer: String,
+}
+
+/// Response from the GitHub Actions token service
+#[derive(Deserialize)]
+struct GithubTokenResponse {
+    /// The identity token
+    value: String,
+}
+
+impl GithubTokenRequest {
+    /// Create the request for a token with the `audience` from the variables
+    /// provided by `var`
+    fn from_vars(
+        var: impl Fn(&str) -> Option<String>,
+        audience: Option<&str>,
+    ) -> eyre::Result<GithubTokenRequest> {
+        let (Some(url), Some(bearer)) = (
+            var("ACTIONS_ID_TOKEN_REQUEST_URL"),
+            var("ACTIONS_ID_TOKEN_REQUEST_TOKEN"),
+        ) else {
+            eyre::bail!(
+                "GitHub Actions identity token is unavailable, grant the workflow the \"id-token: write\" permission"
+            );
+        };
+
+        let separator = match url.contains('?') {
+            true => '&',
+            false => '?',
+        };
+        let audience = url_encode(audience.unwrap_or(DEFAULT_GITHUB_AUDIENCE));
+
+        Ok(GithubTokenRequest {
+            url: format!("{url}{separator}audience={audience}"),
+            bearer,
+        })
+    }
+
+    /// Request the token from the token service
+    async fn send(&self) -> eyre::Result<String> {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_provider_and_native_roots(rustls::crypto::aws_lc_rs::default_provider())
+            .context("failed to load root certificates")?
+            .https_only()
+            .enable_http1()
+            .build();
+        let client = Client::builder(TokioExecutor::new()).build(connector);
+
+        let request = http::Request::get(&self.url)
+            .header(
+                http::header::AUTHORIZATION,
+                format!("bearer {}", self.bearer),
+            )
+            .header(http::header::ACCEPT, "application/json")
+            .body(String::new())
+            .context("invalid GitHub Actions token request")?;
+
+        let response = client
+            .request(request)
+            .await
+            .context("failed to request GitHub Actions identity token")?;
+
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .context("failed to read GitHub Actions identity token")?
+            .to_bytes();
+
+        if !status.is_success() {
+            eyre::bail!("GitHub Actions identity token request failed ({status})");
+        }
+
+        let response: GithubTokenResponse = serde_json::from_slice(&body)
+            .context("invalid GitHub Actions identity token response")?;
+
+        Ok(response.value)
+    }
+}
+
+/// Details of the runtime environment relevant to resolving ambient
+/// credentials on EC2, ECS, and EKS
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CredentialEnvironment {
+    /// Running within a container
+    pub container: bool,
+    /// Running within a Kubernetes pod
+    pub kubernetes: bool,
+    /// Running as an ECS task
+    pub ecs: bool,
+    /// Container credentials endpoint is configured
+    pub container_credentials: bool,
+    /// AWS_ROLE_ARN is set for web identity federation
+    pub role_arn: bool,
+    /// Path of the web identity token file along with whether it exists
+    pub web_identity_token_file: Option<(PathBuf, bool)>,
+    /// Instance metadata lookups are disabled through AWS_EC2_METADATA_DISABLED
+    pub imds_disabled: bool,
+}
+
+impl CredentialEnvironment {
+    /// Detect the environment of the current process
+    pub fn current() -> CredentialEnvironment {
+        let mut environment = Self::from_vars(|name| std::env::var(name).ok());
+        environment.container |= Path::new("/.dockerenv").exists();
+        environment
+    }
+
+    /// Detect the environment from the variables provided by `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> CredentialEnvironment {
+        let kubernetes = var("KUBERNETES_SERVICE_HOST").is_some();
+        let ecs = var("ECS_CONTAINER_METADATA_URI_V4").is_some()
+            || var("ECS_CONTAINER_METADATA_URI").is_some();
+
+        CredentialEnvironment {
+            container: kubernetes || ecs,
+            kubernetes,
+            ecs,
+            container_credentials: var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI").is_some()
+                || var("AWS_CONTAINER_CREDENTIALS_FULL_URI").is_some(),
+            role_arn: var("AWS_ROLE_ARN").is_some(),
+            web_identity_token_file: var("AWS_WEB_IDENTITY_TOKEN_FILE").map(|path| {
+                let path = PathBuf::from(path);
+                let exists = path.exists();
+                (path, exists)
+            }),
+            imds_disabled: var("AWS_EC2_METADATA_DISABLED")
+                .is_some_and(|value| value.eq_ignore_ascii_case("true")),
+        }
+    }
+}
+
+/// Detect common misconfigurations of the `environment` given the `source`
+/// the credentials were resolved from, [None] when resolving failed
+pub fn diagnose_credentials(
+    environment: &CredentialEnvironment,
+    source: Option<&CredentialSource>,
+) -> Vec<CredentialProblem> {
+    let mut problems = Vec::new();
+
+    if environment.role_arn && environment.web_identity_token_file.is_none() {
+        problems.push(CredentialProblem {
+            problem: "AWS_ROLE_ARN is set without AWS_WEB_IDENTITY_TOKEN_FILE".to_string(),
+            hint: "ensure the service account has the eks.amazonaws.com/role-arn annotation and the pod was restarted after adding it".to_string(),
+        });
+    }
+
+    if let Some((path, false)) = &environment.web_identity_token_file {
+        problems.push(CredentialProblem {
+            problem: format!("web identity token file \"{}\" does not exist", path.display()),
+            hint: "the projected service account token is not mounted, check the pod uses the annotated service account".to_string(),
+        });
+    }
+
+    if environment.ecs && !environment.container_credentials {
+        problems.push(CredentialProblem {
+            problem: "running as an ECS task without a container credentials endpoint".to_string(),
+            hint: "assign a task role (taskRoleArn) to the task definition".to_string(),
+        });
+    }
+
+    match source {
+        Some(CredentialSource::Imds) if environment.kubernetes => {
+            problems.push(CredentialProblem {
+                problem: "pod is using the credentials of the node instance role".to_string(),
+                hint: "configure IRSA or EKS Pod Identity so the pod uses its own role".to_string(),
+            });
+        }
+
+        None if environment.imds_disabled => {
+            problems.push(CredentialProblem {
+                problem: "instance metadata is disabled through AWS_EC2_METADATA_DISABLED"
+                    .to_string(),
+                hint: "unset AWS_EC2_METADATA_DISABLED to use the instance role".to_string(),
+            });
+        }
+
+        // Containers are an extra network hop from the instance, IMDSv2
+        // responses are dropped with the default hop limit of 1
+        None if environment.container
+            && !environment.container_credentials
+            && environment.web_identity_token_file.is_none() =>
+        {
+            problems.push(CredentialProblem {
+                problem: "credentials could not be resolved from instance metadata within a container".to_string(),
+                hint: "raise the IMDSv2 hop limit using aws ec2 modify-instance-metadata-options --instance-id <id> --http-put-response-hop-limit 2".to_string(),
+            });
+        }
+
+        _ => {}
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use crate::credentials::{
+        CredentialEnvironment, CredentialSource, GithubTokenRequest, diagnose_credentials,
+        resolve_config_value,
+    };
+
+    /// Tests that plain values are used as is and references are resolved
+    #[test]
+    fn test_resolve_config_value() {
+        assert_eq!(resolve_config_value("AKIAEXAMPLE").unwrap(), "AKIAEXAMPLE");
+
+        assert_eq!(
+            resolve_config_value("env:PATH").unwrap(),
+            std::env::var("PATH").unwrap()
+        );
+        assert!(resolve_config_value("env:SECRET_SYNC_TEST_MISSING_VALUE").is_err());
+    }
+
+    /// Tests detecting credential misconfigurations on EKS and ECS
+    #[test]
+    fn test_diagnose_credentials() {
+        let irsa = CredentialEnvironment::from_vars(|name| match name {
+            "KUBERNETES_SERVICE_HOST" => Some("10.0.0.1".to_string()),
+            "AWS_ROLE_ARN" => Some("arn:aws:iam::123456789012:role/app".to_string()),
+            "AWS_WEB_IDENTITY_TOKEN_FILE" => Some("/secret-sync/missing/token".to_string()),
+            _ => None,
+        });
+        let problems = diagnose_credentials(&irsa, None);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].problem.contains("does not exist"));
+
+        let node_role = CredentialEnvironment::from_vars(|name| {
+            (name == "KUBERNETES_SERVICE_HOST").then(|| "10.0.0.1".to_string())
+        });
+        let problems = diagnose_credentials(&node_role, Some(&CredentialSource::Imds));
+        assert!(problems[0].problem.contains("node instance role"));
+
+        // Hop limit is suspected when metadata could not be reached
+        let problems = diagnose_credentials(&node_role, None);
+        assert!(problems[0].hint.contains("hop-limit 2"));
+
+        let ecs = CredentialEnvironment::from_vars(|name| {
+            (name == "ECS_CONTAINER_METADATA_URI_V4").then(|| "http://169.254.170.2/v4".to_string())
+        });
+        let problems = diagnose_credentials(&ecs, None);
+        assert!(problems[0].hint.contains("task role"));
+
+        assert!(diagnose_credentials(&CredentialEnvironment::default(), None).is_empty());
+    }
+
+    /// Tests creating the GitHub Actions token request from the workflow
+    /// environment
+    #[test]
+    fn test_github_token_request() {
+        let var = |name: &str| match name {
+            "ACTIONS_ID_TOKEN_REQUEST_URL" => {
+                Some("https://token.actions.example.com/token?api-version=2.0".to_string())
+            }
+            "ACTIONS_ID_TOKEN_REQUEST_TOKEN" => Some("request-token".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            GithubTokenRequest::from_vars(var, None).unwrap(),
+            GithubTokenRequest {
+                url: "https://token.actions.example.com/token?api-version=2.0&audience=sts.amazonaws.com"
+                    .to_string(),
+                bearer: "request-token".to_string(),
+            }
+        );
+
+        // Workflows without the id-token permission have no request variables
+        let error = GithubTokenRequest::from_vars(|_| None, None).unwrap_err();
+        assert!(error.to_string().contains("id-token: write"));
+    }
+}
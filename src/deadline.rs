@@ -0,0 +1,206 @@
+//! # Deadline
+//!
+//! Overall time budget for an invocation, when the deadline is exceeded the
+//! in-flight batch is cancelled and the number of files it completed before
+//! the deadline is reported. Warnings raised while processing are
+//! collected alongside the files so they are reported with the output
+
+use crate::last_run::{FileAction, FileResult};
+use secret_sync::cancel::BatchOutcome;
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Files processed and warnings raised during the invocation, shared with
+/// the deadline so partial results can be reported when it is exceeded and
+/// with the last run summary written once the invocation finishes
+#[derive(Clone, Default)]
+pub struct Progress {
+    state: Arc<Mutex<ProgressState>>,
+}
+
+/// Shared state of a [Progress]
+#[derive(Default)]
+struct ProgressState {
+    /// Results of the processed files
+    files: Vec<FileResult>,
+    /// Path to write the last run summary to
+    last_run_path: Option<PathBuf>,
+    /// Warnings to report alongside the output
+    warnings: Vec<String>,
+}
+
+impl Progress {
+    /// Record that the file `name` has been completed using `action`
+    /// taking `duration`
+    pub fn complete(&self, name: impl Into<String>, action: FileAction, duration: Duration) {
+        if let Ok(mut state) = self.state.lock() {
+            state
+                .files
+                .push(FileResult::new(name.into(), action, duration));
+        }
+    }
+
+    /// Record that the file `name` was pulled from the secret `version`
+    /// using `action` taking `duration`
+    pub fn complete_version(
+        &self,
+        name: impl Into<String>,
+        action: FileAction,
+        duration: Duration,
+        version: Option<String>,
+    ) {
+        if let Ok(mut state) = self.state.lock() {
+            let mut result = FileResult::new(name.into(), action, duration);
+            result.version = version;
+            state.files.push(result);
+        }
+    }
+
+    /// Record that processing the file `name` failed with `error` after
+    /// `duration`
+    pub fn fail(&self, name: impl Into<String>, error: &impl Display, duration: Duration) {
+        if let Ok(mut state) = self.state.lock() {
+            let mut result = FileResult::new(name.into(), FileAction::Failed, duration);
+            result.error = Some(error.to_string());
+            state.files.push(result);
+        }
+    }
+
+    /// Results of the processed files
+    pub fn files(&self) -> Vec<FileResult> {
+        self.state
+            .lock()
+            .map(|state| state.files.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record a warning to report alongside the output of the invocation
+    pub fn warn(&self, message: impl Into<String>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.warnings.push(message.into());
+        }
+    }
+
+    /// Warnings raised during the invocation, in the order they were raised
+    pub fn warnings(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .map(|state| state.warnings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Set the `path` the last run summary should be written to
+    pub fn set_last_run_path(&self, path: PathBuf) {
+        if let Ok(mut state) = self.state.lock() {
+            state.last_run_path = Some(path);
+        }
+    }
+
+    /// Path the last run summary should be written to, [None] until the
+    /// project has been determined
+    pub fn last_run_path(&self) -> Option<PathBuf> {
+        self.state
+            .lock()
+            .ok()
+            .and_then(|state| state.last_run_path.clone())
+    }
+}
+
+/// Parse a duration such as `500ms`, `60s`, `5m` or `1h`, plain numbers
+/// are treated as seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration \"{value}\", expected a value such as 60s"))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        "h" => Ok(Duration::from_secs(amount * 60 * 60)),
+        unit => Err(format!(
+            "unknown duration unit \"{unit}\", expected one of ms, s, m, h"
+        )),
+    }
+}
+
+/// Time given to the command to stop gracefully once the deadline is
+/// exceeded, before it is dropped
+pub const DEADLINE_GRACE: Duration = Duration::from_secs(5);
+
+/// Create the error reported when a batch of `total` files was cancelled,
+/// including the partial results from its `outcome`. Batches are only
+/// cancelled when the `deadline` is exceeded
+pub fn cancelled_error(
+    deadline: Option<Duration>,
+    outcome: &BatchOutcome,
+    total: usize,
+) -> eyre::Report {
+    let partial = format!(
+        "{} of {total} file(s) completed before cancellation",
+        outcome.completed
+    );
+
+    match deadline {
+        Some(deadline) => eyre::eyre!(
+            "deadline of {:?} exceeded, in-flight operations were cancelled ({partial})",
+            deadline
+        ),
+        None => eyre::eyre!("in-flight operations were cancelled ({partial})"),
+    }
+}
+
+/// Create the error reported when the command did not stop within the
+/// [DEADLINE_GRACE] after the `deadline` was exceeded
+pub fn deadline_error(deadline: Duration) -> eyre::Report {
+    eyre::eyre!(
+        "deadline of {:?} exceeded, in-flight operations were cancelled",
+        deadline
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::deadline::{cancelled_error, parse_duration};
+    use secret_sync::cancel::BatchOutcome;
+    use std::time::Duration;
+
+    /// Tests parsing the supported duration units
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("60s"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_duration("1d").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    /// Tests that the completed files are included in the error of a
+    /// cancelled batch
+    #[test]
+    fn test_cancelled_error() {
+        let outcome = BatchOutcome {
+            completed: 1,
+            changed: 1,
+            cancelled: true,
+        };
+
+        let error = cancelled_error(Some(Duration::from_secs(60)), &outcome, 3);
+        assert_eq!(
+            error.to_string(),
+            "deadline of 60s exceeded, in-flight operations were cancelled (1 of 3 file(s) completed before cancellation)"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! # Diff
+//!
+//! Comparison of the local secret files against the remote secrets as a
+//! unified diff, showing what a push would change. Remote values are
+//! compared in the form a pull would write them so structured files only
+//! show differences in the keys they contain.
+//!
+//! Values can be redacted, replacing each value with a short hash so the
+//! changed lines are visible without revealing the values
+
+use secret_sync::{
+    arn::display_secret_name,
+    config::SecretFile,
+    fs::FileSystem,
+    pull::file_contents,
+    secret::{Secret, SecretManager},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::{fmt::Write, path::Path};
+
+/// Comparison of a single file against its secret
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct FileDiff {
+    /// Name of the file entry within the config
+    pub name: String,
+    /// Name of the secret
+    pub secret: String,
+    /// Difference between the local file and the secret
+    pub status: DiffStatus,
+    /// Unified diff from the remote value to the local value, [None] when
+    /// the values match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Notes of the file entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Ticket of the file entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+}
+
+/// Difference between a local file and its secret
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStatus {
+    /// File only exists locally, a push creates the secret
+    Added,
+    /// Secret only exists remotely
+    Removed,
+    /// File and secret have different values
+    Changed,
+    /// File and secret have the same value
+    Unchanged,
+    /// Neither the file or secret exist
+    Missing,
+}
+
+impl DiffStatus {
+    /// Name of the status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffStatus::Added => "added",
+            DiffStatus::Removed => "removed",
+            DiffStatus::Changed => "changed",
+            DiffStatus::Unchanged => "unchanged",
+            DiffStatus::Missing => "missing",
+        }
+    }
+}
+
+/// Compare the local file of `file` against its secret
+pub async fn diff_file<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    name: &str,
+    file: &SecretFile,
+    redact: bool,
+) -> eyre::Result<FileDiff> {
+    let (local, remote) = read_values(fs, secret, working_path, file).await?;
+
+    let status = match (&local, &remote) {
+        (None, None) => DiffStatus::Missing,
+        (Some(_), None) => DiffStatus::Added,
+        (None, Some(_)) => DiffStatus::Removed,
+        (Some(local), Some(remote)) if local.as_slice() == remote.as_bytes() => {
+            DiffStatus::Unchanged
+        }
+        (Some(_), Some(_)) => DiffStatus::Changed,
+    };
+
+    let diff = match status {
+        DiffStatus::Unchanged | DiffStatus::Missing => None,
+        _ => Some(unified_diff(
+            remote.as_ref().map(Secret::as_bytes).unwrap_or_default(),
+            local.as_deref().unwrap_or_default(),
+            redact,
+        )),
+    };
+
+    Ok(FileDiff {
+        name: name.to_string(),
+        secret: file.secret.clone(),
+        status,
+        diff,
+        notes: file.notes.clone(),
+        ticket: file.ticket.clone(),
+    })
+}
+
+/// Read the local value of `file` and the remote value of its secret in
+/// the form a pull would write it, [None] when either does not exist
+pub async fn read_values<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    file: &SecretFile,
+) -> eyre::Result<(Option<Vec<u8>>, Option<Secret>)> {
+    let local = fs
+        .read_file_optional(&file.resolve_path(working_path))
+        .await?;
+    let remote = secret
+        .find_secret(&file.secret)
+        .await?
+        .map(|value| file_contents(file, value, local.as_deref()))
+        .transpose()?;
+
+    Ok((local, remote))
+}
+
+/// Create a unified diff from the `remote` value to the `local` value
+pub fn unified_diff(remote: &[u8], local: &[u8], redact: bool) -> String {
+    let (Ok(remote), Ok(local)) = (std::str::from_utf8(remote), std::str::from_utf8(local)) else {
+        return "binary values differ\n".to_string();
+    };
+
+    let (remote, local) = match redact {
+        true => (redact_text(remote), redact_text(local)),
+        false => (remote.to_string(), local.to_string()),
+    };
+
+    TextDiff::from_lines(&remote, &local)
+        .unified_diff()
+        .header("remote", "local")
+        .to_string()
+}
+
+/// Replace the values within `text` with a short hash of the value, keys
+/// of `KEY=value` and `key: value` lines are kept
+fn redact_text(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+
+    for line in text.split_inclusive('\n') {
+        let (content, ending) = match line.strip_suffix('\n') {
+            Some(content) => (content, "\n"),
+            None => (line, ""),
+        };
+
+        let trimmed = content.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            output.push_str(line);
+            continue;
+        }
+
+        let split = content
+            .find('=')
+            .map(|index| index + 1)
+            .or_else(|| content.find(": ").map(|index| index + 2));
+
+        match split {
+            Some(index) => {
+                let (key, value) = content.split_at(index);
+                _ = write!(output, "{key}{}{ending}", redacted(value));
+            }
+            None => _ = write!(output, "{}{ending}", redacted(content)),
+        }
+    }
+
+    output
+}
+
+/// Placeholder for a redacted `value`, equal values share a placeholder
+fn redacted(value: &str) -> String {
+    let hash = Sha256::digest(value.as_bytes());
+    format!("<redacted {}>", &hex::encode(hash)[..8])
+}
+
+/// Render the `diffs` along with a summary of the changes
+pub fn diff_text(diffs: &[FileDiff]) -> String {
+    let mut text = String::new();
+
+    for diff in diffs {
+        let Some(value) = &diff.diff else {
+            continue;
+        };
+
+        _ = writeln!(
+            text,
+            "{} ({}): {}",
+            diff.name,
+            display_secret_name(&diff.secret),
+            diff.status.as_str()
+        );
+
+        if let Some(ticket) = &diff.ticket {
+            _ = writeln!(text, "ticket: {ticket}");
+        }
+
+        if let Some(notes) = &diff.notes {
+            _ = writeln!(text, "notes: {notes}");
+        }
+
+        _ = writeln!(text, "{value}");
+    }
+
+    let count = |status: DiffStatus| diffs.iter().filter(|diff| diff.status == status).count();
+    _ = write!(
+        text,
+        "Diff: {} added, {} removed, {} changed, {} unchanged, {} missing",
+        count(DiffStatus::Added),
+        count(DiffStatus::Removed),
+        count(DiffStatus::Changed),
+        count(DiffStatus::Unchanged),
+        count(DiffStatus::Missing)
+    );
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diff::{redact_text, unified_diff};
+
+    /// Tests that diffs show the changed lines and that redaction hides
+    /// the values while keeping the keys
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff(b"A=1\nB=2\n", b"A=1\nB=3\n", false);
+        assert!(diff.contains("-B=2\n"));
+        assert!(diff.contains("+B=3\n"));
+
+        let diff = unified_diff(b"A=1\nB=2\n", b"A=1\nB=3\n", true);
+        assert!(diff.contains("-B=<redacted"));
+        assert!(!diff.contains("B=2"));
+        assert!(!diff.contains("B=3"));
+
+        assert_eq!(
+            redact_text("# comment\nkey: value\nline"),
+            redact_text("# comment\nkey: value\nline")
+        );
+        assert!(redact_text("key: value").starts_with("key: <redacted "));
+        assert_eq!(unified_diff(&[0xff], b"", false), "binary values differ\n");
+    }
+}
@@ -0,0 +1,203 @@
+//! # Doctor
+//!
+//! Analysis of the configured secret files for common problems, such as
+//! the same credential being copy-pasted into multiple files
+
+use crate::{
+    config::SecretFile,
+    fs::FileSystem,
+    secret::Secret,
+    structured::{StructuredValue, value_to_string},
+};
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::{fmt::Display, path::Path};
+
+/// Values shorter than this are too common (ports, flags) to be reported
+/// as duplicates
+const MIN_DUPLICATE_VALUE_LENGTH: usize = 8;
+
+/// Location of a value within the configured files
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ValueLocation {
+    /// Name of the file entry within the config
+    pub file: String,
+    /// Key within the file for dotenv or JSON files, [None] when the
+    /// whole file is the value
+    pub key: Option<String>,
+}
+
+impl Display for ValueLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{}:{}", self.file, key),
+            None => self.file.fmt(f),
+        }
+    }
+}
+
+/// Value that appears in multiple locations
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct DuplicateValue {
+    /// Hash of the duplicated value
+    pub hash: String,
+    /// Locations the value appears in
+    pub locations: Vec<ValueLocation>,
+}
+
+/// Find values that appear in more than one of the local `files`, compared
+/// by hash. Files that are dotenv or JSON formatted are compared key by key
+///
+/// Locations that refer to the same secret are only reported once as they
+/// already share a single value
+pub async fn find_duplicate_values<'a, Fs: FileSystem>(
+    fs: &Fs,
+    working_path: &Path,
+    files: impl IntoIterator<Item = (&'a String, &'a SecretFile)>,
+) -> eyre::Result<Vec<DuplicateValue>> {
+    // Locations for each value hash along with the secret they belong to
+    let mut values: IndexMap<String, Vec<(&'a str, ValueLocation)>> = IndexMap::new();
+
+    for (name, file) in files {
+        let file_path = file.resolve_path(working_path);
+        let Some(contents) = fs.read_file_optional(&file_path).await? else {
+            continue;
+        };
+
+        let entries: Vec<(Option<String>, String)> = match StructuredValue::parse(&contents) {
+            Ok(value) => value
+                .values
+                .iter()
+                .map(|(key, value)| (Some(key.clone()), value_to_string(value)))
+                .collect(),
+            // Files that are not structured are compared as a whole
+            Err(_) => match String::from_utf8(contents) {
+                Ok(value) => vec![(None, value)],
+                Err(error) => vec![(None, hex::encode(error.into_bytes()))],
+            },
+        };
+
+        for (key, value) in entries {
+            if value.trim().len() < MIN_DUPLICATE_VALUE_LENGTH {
+                continue;
+            }
+
+            let hash = Secret::String(value).hash();
+            let locations = values.entry(hash).or_default();
+
+            // The same key of the same secret is not a duplicate
+            let duplicate = locations
+                .iter()
+                .any(|(secret, location)| *secret == file.secret && location.key == key);
+            if duplicate {
+                continue;
+            }
+
+            locations.push((
+                &file.secret,
+                ValueLocation {
+                    file: name.clone(),
+                    key,
+                },
+            ));
+        }
+    }
+
+    Ok(values
+        .into_iter()
+        .filter(|(_, locations)| locations.len() > 1)
+        .map(|(hash, locations)| DuplicateValue {
+            hash,
+            locations: locations
+                .into_iter()
+                .map(|(_, location)| location)
+                .collect(),
+        })
+        .collect())
+}
+
+/// Diagnosis of the credentials used by a backend
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct CredentialDiagnosis {
+    /// Description of where the credentials were resolved from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Error resolving the credentials failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Misconfigurations detected in the environment
+    pub problems: Vec<CredentialProblem>,
+}
+
+/// Misconfiguration that prevents or affects resolving credentials
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CredentialProblem {
+    /// Description of the problem
+    pub problem: String,
+    /// Guidance on fixing the problem
+    pub hint: String,
+}
+
+impl Display for CredentialProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.problem, self.hint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::SecretFile,
+        doctor::{ValueLocation, find_duplicate_values},
+        fs::MockFileSystem,
+    };
+    use indexmap::IndexMap;
+    use mockall::predicate::eq;
+    use std::path::{Path, PathBuf};
+
+    /// Tests that values repeated across files are found
+    #[tokio::test]
+    async fn test_find_duplicate_values() {
+        let mut files = IndexMap::new();
+        for (name, secret) in [("api", "api"), ("worker", "worker"), ("copy", "api")] {
+            files.insert(
+                name.to_string(),
+                SecretFile {
+                    path: PathBuf::from(format!(".env.{name}")),
+                    secret: secret.to_string(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.api")))
+            .return_once(|_path| Ok(Some(b"DB_PASSWORD=hunter2hunter2\nPORT=8080\n".to_vec())));
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.worker")))
+            .return_once(|_path| Ok(Some(b"PASSWORD=hunter2hunter2\nPORT=8080\n".to_vec())));
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.copy")))
+            .return_once(|_path| Ok(Some(b"DB_PASSWORD=hunter2hunter2\n".to_vec())));
+
+        let duplicates = find_duplicate_values(&fs, Path::new("/"), &files)
+            .await
+            .unwrap();
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates[0].locations,
+            vec![
+                ValueLocation {
+                    file: "api".to_string(),
+                    key: Some("DB_PASSWORD".to_string()),
+                },
+                ValueLocation {
+                    file: "worker".to_string(),
+                    key: Some("PASSWORD".to_string()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! # Dotenv
+//!
+//! Parsing and rendering of dotenv formatted files and conversion of
+//! structured (dotenv or JSON object) secrets into flat key value maps
+
+use crate::structured::{StructuredValue, value_to_string};
+use eyre::ContextCompat;
+use indexmap::IndexMap;
+use std::fmt::Write;
+
+/// Parse a dotenv formatted `value` into its key value pairs
+///
+/// Supports comments, blank lines, `export` prefixes, unquoted values
+/// with trailing comments, single quoted literal values, and double
+/// quoted values with escapes that may span multiple lines
+pub fn parse_dotenv(value: &str) -> eyre::Result<IndexMap<String, String>> {
+    let mut values = IndexMap::new();
+    let mut lines = value.lines().enumerate();
+
+    while let Some((index, line)) = lines.next() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, raw_value) = line
+            .split_once('=')
+            .with_context(|| format!("invalid dotenv line {}, expected KEY=VALUE", index + 1))?;
+
+        let key = key.trim();
+        if key.is_empty() {
+            eyre::bail!("invalid dotenv line {}, missing key", index + 1);
+        }
+
+        let raw_value = raw_value.trim_start();
+
+        let value = if let Some(rest) = raw_value.strip_prefix('\'') {
+            let end = rest
+                .find('\'')
+                .with_context(|| format!("unterminated quote on dotenv line {}", index + 1))?;
+            rest[..end].to_string()
+        } else if let Some(rest) = raw_value.strip_prefix('"') {
+            // Double quoted values may span multiple lines
+            let mut quoted = rest.to_string();
+            while find_closing_quote(&quoted).is_none() {
+                let (_, next) = lines
+                    .next()
+                    .with_context(|| format!("unterminated quote on dotenv line {}", index + 1))?;
+                quoted.push('\n');
+                quoted.push_str(next);
+            }
+
+            let end = find_closing_quote(&quoted).unwrap_or(quoted.len());
+            unescape_double_quoted(&quoted[..end])
+        } else {
+            // Strip trailing comments from unquoted values
+            let value = match raw_value.find(" #") {
+                Some(index) => &raw_value[..index],
+                None => raw_value,
+            };
+            value.trim_end().to_string()
+        };
+
+        values.insert(key.to_string(), value);
+    }
+
+    Ok(values)
+}
+
+/// Find the index of the closing unescaped double quote in `value`
+pub(crate) fn find_closing_quote(value: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (index, char) in value.char_indices() {
+        match char {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(index),
+            _ => escaped = false,
+        }
+    }
+
+    None
+}
+
+/// Resolve the escape sequences within a double quoted `value`
+fn unescape_double_quoted(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            output.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some(other) => output.push(other),
+            None => output.push('\\'),
+        }
+    }
+
+    output
+}
+
+/// Render the provided key `values` in dotenv format, values are quoted
+/// when they contain characters that would otherwise be misinterpreted
+pub fn render_dotenv<'a>(values: impl IntoIterator<Item = (&'a String, &'a String)>) -> String {
+    let mut output = String::new();
+
+    for (key, value) in values {
+        _ = writeln!(output, "{key}={}", quote_dotenv_value(value));
+    }
+
+    output
+}
+
+/// Quote a dotenv `value` if required
+pub fn quote_dotenv_value(value: &str) -> String {
+    let requires_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|char| char.is_whitespace() || matches!(char, '#' | '"' | '\'' | '\\' | '$'));
+
+    if !requires_quotes {
+        return value.to_string();
+    }
+
+    double_quote_value(value)
+}
+
+/// Wrap a `value` in double quotes escaping any special characters
+pub fn double_quote_value(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for char in value.chars() {
+        match char {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            char => quoted.push(char),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a structured secret `value` as a flat key value map
+///
+/// JSON objects are flattened with non string values converted to
+/// their JSON representation, anything else is parsed as dotenv
+pub fn parse_structured(value: &[u8]) -> eyre::Result<IndexMap<String, String>> {
+    let value = StructuredValue::parse(value)?;
+
+    Ok(value
+        .values
+        .iter()
+        .map(|(key, value)| (key.clone(), value_to_string(value)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dotenv::{parse_dotenv, parse_structured, render_dotenv};
+
+    /// Tests parsing the supported dotenv syntax
+    #[test]
+    fn test_parse_dotenv() {
+        let values = parse_dotenv(
+            r#"
+# Comment
+export PLAIN=value # trailing comment
+SINGLE='literal \n # value'
+DOUBLE="escaped \"quote\"\nnext"
+MULTI="first
+second"
+EMPTY=
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(values["PLAIN"], "value");
+        assert_eq!(values["SINGLE"], "literal \\n # value");
+        assert_eq!(values["DOUBLE"], "escaped \"quote\"\nnext");
+        assert_eq!(values["MULTI"], "first\nsecond");
+        assert_eq!(values["EMPTY"], "");
+        assert!(
+            values
+                .keys()
+                .eq(["PLAIN", "SINGLE", "DOUBLE", "MULTI", "EMPTY"].iter())
+        );
+    }
+
+    /// Tests that rendered values parse back to the same values
+    #[test]
+    fn test_render_dotenv_round_trip() {
+        let values = parse_dotenv(
+            r#"
+A=plain
+B="with spaces # and hash"
+C="line\nbreak"
+D=""
+"#,
+        )
+        .unwrap();
+
+        let rendered = render_dotenv(&values);
+        assert_eq!(parse_dotenv(&rendered).unwrap(), values);
+    }
+
+    /// Tests that JSON objects are flattened
+    #[test]
+    fn test_parse_structured_json() {
+        let values =
+            parse_structured(br#"{"B": "text", "A": 1, "C": true, "D": null, "E": {"x": 1}}"#)
+                .unwrap();
+
+        assert!(values.keys().eq(["B", "A", "C", "D", "E"].iter()));
+        assert_eq!(values["A"], "1");
+        assert_eq!(values["C"], "true");
+        assert_eq!(values["D"], "");
+        assert_eq!(values["E"], r#"{"x":1}"#);
+    }
+}
@@ -0,0 +1,95 @@
+//! # Env
+//!
+//! Collection of structured (dotenv or JSON object) secrets into a single
+//! flat set of environment variables, used for tools such as Nix
+//! development shells that consume environment variables as JSON
+
+use crate::{config::SecretFile, dotenv::parse_structured, secret::SecretManager};
+use eyre::Context;
+use indexmap::IndexMap;
+use std::path::Path;
+
+/// Collect the environment variables from each of the `files` secrets,
+/// variables from later files take priority over earlier files
+pub async fn collect_env(
+    secret: &dyn SecretManager,
+    files: impl IntoIterator<Item = &SecretFile>,
+) -> eyre::Result<IndexMap<String, String>> {
+    let mut env = IndexMap::new();
+
+    for file in files {
+        let value = secret.get_secret(&file.secret).await?.data;
+        let values = parse_structured(value.as_bytes())
+            .with_context(|| format!("secret \"{}\" is not a structured secret", file.secret))?;
+
+        for (key, value) in values {
+            if env.insert(key.clone(), value).is_some() {
+                tracing::debug!(%key, secret = %file.secret, "variable overridden by later secret");
+            }
+        }
+    }
+
+    Ok(env)
+}
+
+/// Write the `env` as JSON to the stable output `path`, the file is
+/// replaced atomically so readers never observe a partial file
+pub async fn write_env_out_link(path: &Path, env: &IndexMap<String, String>) -> eyre::Result<()> {
+    let value = serde_json::to_vec_pretty(env)?;
+    let temp_path = path.with_extension("tmp");
+
+    tokio::fs::write(&temp_path, value)
+        .await
+        .context("failed to write env output")?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .context("failed to move env output into place")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{SecretFile, SecretMetadata},
+        env::collect_env,
+        secret::{MockSecretManager, Secret},
+    };
+    use mockall::predicate::eq;
+    use std::path::PathBuf;
+
+    /// Tests that dotenv and JSON secrets are merged with later
+    /// secrets taking priority
+    #[tokio::test]
+    async fn test_collect_env() {
+        let files = vec![
+            SecretFile {
+                path: PathBuf::from(".env"),
+                secret: "dotenv".to_string(),
+                metadata: SecretMetadata::default(),
+                ..Default::default()
+            },
+            SecretFile {
+                path: PathBuf::from("config.json"),
+                secret: "json".to_string(),
+                metadata: SecretMetadata::default(),
+                ..Default::default()
+            },
+        ];
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .with(eq("dotenv"))
+            .return_once(|_name| Ok(Secret::String("A=1\nB=2\n".to_string()).into()));
+        secret
+            .expect_get_secret()
+            .with(eq("json"))
+            .return_once(|_name| Ok(Secret::String(r#"{"B": "3", "C": 4}"#.to_string()).into()));
+
+        let env = collect_env(&secret, &files).await.unwrap();
+        assert!(env.keys().eq(["A", "B", "C"].iter()));
+        assert_eq!(env["B"], "3");
+        assert_eq!(env["C"], "4");
+    }
+}
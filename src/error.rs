@@ -0,0 +1,326 @@
+//! # Error
+//!
+//! Typed errors of the core pulling, pushing, and secret manager operations
+//! so library users and the structured output can match on the kind of
+//! failure. Context added while an error travels up keeps its kind, the
+//! CLI converts the [SyncError] into an `eyre::Report` at its boundary
+
+use std::{error::Error, fmt::Display};
+
+/// Boxed underlying cause of a [SyncError]
+pub type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+/// Result of a core operation
+pub type Result<T> = std::result::Result<T, SyncError>;
+
+/// Error of a core operation, categorized by the kind of failure
+#[derive(Debug)]
+pub enum SyncError {
+    /// The configuration is missing or invalid
+    Config(ErrorContext),
+    /// Reading or writing a local file failed
+    Io(ErrorContext),
+    /// The backend rejected or could not resolve the credentials
+    BackendAuth(ErrorContext),
+    /// The secret does not exist within the backend
+    BackendNotFound(ErrorContext),
+    /// The secret was changed by someone else or is owned by another system
+    Conflict(ErrorContext),
+    /// A secret name, tag, or value is invalid
+    Validation(ErrorContext),
+    /// The backend throttled the request, retrying later may succeed
+    Throttled(ErrorContext),
+    /// Any other failure of the backend, such as a network error
+    Backend(ErrorContext),
+}
+
+/// Message and underlying cause of a [SyncError]
+#[derive(Debug)]
+pub struct ErrorContext {
+    /// Message describing the failure, [None] when the error only
+    /// forwards its `source`
+    message: Option<String>,
+    /// Underlying cause of the failure
+    source: Option<BoxError>,
+}
+
+impl SyncError {
+    /// Create a [SyncError::Config] error with a `message`
+    pub fn config(message: impl Display) -> Self {
+        SyncError::Config(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::Io] error with a `message`
+    pub fn io(message: impl Display) -> Self {
+        SyncError::Io(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::BackendAuth] error with a `message`
+    pub fn backend_auth(message: impl Display) -> Self {
+        SyncError::BackendAuth(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::BackendNotFound] error with a `message`
+    pub fn not_found(message: impl Display) -> Self {
+        SyncError::BackendNotFound(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::Conflict] error with a `message`
+    pub fn conflict(message: impl Display) -> Self {
+        SyncError::Conflict(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::Validation] error with a `message`
+    pub fn validation(message: impl Display) -> Self {
+        SyncError::Validation(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::Throttled] error with a `message`
+    pub fn throttled(message: impl Display) -> Self {
+        SyncError::Throttled(ErrorContext::message(message))
+    }
+
+    /// Create a [SyncError::Backend] error with a `message`
+    pub fn backend(message: impl Display) -> Self {
+        SyncError::Backend(ErrorContext::message(message))
+    }
+
+    /// Attach the underlying `source` that caused the error
+    pub fn with_source(mut self, source: impl Into<BoxError>) -> Self {
+        self.context_mut().source = Some(source.into());
+        self
+    }
+
+    /// Wrap the error with a `message` describing what was being done,
+    /// the kind of the error is kept
+    pub fn context(self, message: impl Display) -> Self {
+        let kind = self.kind_constructor();
+        kind(ErrorContext {
+            message: Some(message.to_string()),
+            source: Some(Box::new(self)),
+        })
+    }
+
+    /// Stable kebab-case name of the kind of error, as reported in the
+    /// structured output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SyncError::Config(_) => "config",
+            SyncError::Io(_) => "io",
+            SyncError::BackendAuth(_) => "backend-auth",
+            SyncError::BackendNotFound(_) => "backend-not-found",
+            SyncError::Conflict(_) => "conflict",
+            SyncError::Validation(_) => "validation",
+            SyncError::Throttled(_) => "throttled",
+            SyncError::Backend(_) => "backend",
+        }
+    }
+
+    /// Whether the error was caused by the backend throttling the request
+    pub fn is_throttled(&self) -> bool {
+        matches!(self, SyncError::Throttled(_))
+    }
+
+    /// Kind of the [std::io::Error] that caused the error, when it was
+    /// caused by one (e.g. to tell a missing file from a permission error)
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        let mut error: &(dyn Error + 'static) = self;
+        loop {
+            if let Some(error) = error.downcast_ref::<std::io::Error>() {
+                return Some(error.kind());
+            }
+
+            // The source of transparent errors is skipped by their chain
+            error = match error.downcast_ref::<SyncError>() {
+                Some(error) => error.context_ref().source.as_deref()?,
+                None => error.source()?,
+            };
+        }
+    }
+
+    /// Find the [SyncError] within the chain of causes of the `error`
+    pub fn find<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a SyncError> {
+        std::iter::successors(Some(error), |error| (*error).source())
+            .find_map(|error| error.downcast_ref::<SyncError>())
+    }
+
+    fn context_ref(&self) -> &ErrorContext {
+        match self {
+            SyncError::Config(context)
+            | SyncError::Io(context)
+            | SyncError::BackendAuth(context)
+            | SyncError::BackendNotFound(context)
+            | SyncError::Conflict(context)
+            | SyncError::Validation(context)
+            | SyncError::Throttled(context)
+            | SyncError::Backend(context) => context,
+        }
+    }
+
+    fn context_mut(&mut self) -> &mut ErrorContext {
+        match self {
+            SyncError::Config(context)
+            | SyncError::Io(context)
+            | SyncError::BackendAuth(context)
+            | SyncError::BackendNotFound(context)
+            | SyncError::Conflict(context)
+            | SyncError::Validation(context)
+            | SyncError::Throttled(context)
+            | SyncError::Backend(context) => context,
+        }
+    }
+
+    /// Constructor of the variant of the error
+    fn kind_constructor(&self) -> fn(ErrorContext) -> SyncError {
+        match self {
+            SyncError::Config(_) => SyncError::Config,
+            SyncError::Io(_) => SyncError::Io,
+            SyncError::BackendAuth(_) => SyncError::BackendAuth,
+            SyncError::BackendNotFound(_) => SyncError::BackendNotFound,
+            SyncError::Conflict(_) => SyncError::Conflict,
+            SyncError::Validation(_) => SyncError::Validation,
+            SyncError::Throttled(_) => SyncError::Throttled,
+            SyncError::Backend(_) => SyncError::Backend,
+        }
+    }
+}
+
+impl ErrorContext {
+    fn message(message: impl Display) -> Self {
+        Self {
+            message: Some(message.to_string()),
+            source: None,
+        }
+    }
+
+    /// Create a context forwarding the message and causes of the `source`
+    /// error, used to categorize errors from other libraries
+    pub fn from_source(source: impl Into<BoxError>) -> Self {
+        Self {
+            message: None,
+            source: Some(source.into()),
+        }
+    }
+}
+
+/// The alternate format (`{:#}`) includes the chain of causes, matching
+/// the format of an `eyre::Report`
+impl Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let context = self.context_ref();
+        match (&context.message, &context.source) {
+            (Some(message), _) => f.write_str(message)?,
+            (None, Some(source)) => write!(f, "{source}")?,
+            (None, None) => f.write_str(self.kind())?,
+        }
+
+        if f.alternate() {
+            let mut source = self.source();
+            while let Some(error) = source {
+                write!(f, ": {error}")?;
+                source = error.source();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for SyncError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        let context = self.context_ref();
+        let source = context.source.as_deref()?;
+        match context.message {
+            Some(_) => Some(source),
+            // Transparent errors display their source so skip over it
+            None => source.source(),
+        }
+    }
+}
+
+impl From<std::io::Error> for SyncError {
+    fn from(error: std::io::Error) -> Self {
+        SyncError::Io(ErrorContext::from_source(error))
+    }
+}
+
+/// Reports carrying a [SyncError] keep its kind, otherwise reports caused
+/// by an I/O error are [SyncError::Io] and anything else is treated as a
+/// [SyncError::Backend] error
+impl From<eyre::Report> for SyncError {
+    fn from(report: eyre::Report) -> Self {
+        if let Some(error) = SyncError::find(report.as_ref()) {
+            let kind = error.kind_constructor();
+            return kind(ErrorContext::from_source(report));
+        }
+
+        let io = report
+            .chain()
+            .any(|error| error.downcast_ref::<std::io::Error>().is_some());
+        match io {
+            true => SyncError::Io(ErrorContext::from_source(report)),
+            false => SyncError::Backend(ErrorContext::from_source(report)),
+        }
+    }
+}
+
+/// Adds context to the error of a result while keeping the kind of the
+/// [SyncError] it converts into
+pub trait ResultExt<T> {
+    /// Wrap the error with the `context` message
+    fn context<C: Display>(self, context: C) -> Result<T>;
+
+    /// Wrap the error with the message created by `context`, which is only
+    /// called when there is an error
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<SyncError>> ResultExt<T> for std::result::Result<T, E> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.map_err(|error| error.into().context(context))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|error| error.into().context(context()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::{ResultExt, SyncError};
+
+    /// Tests that context keeps the kind of the error and that the kind is
+    /// recovered from reports wrapping the error
+    #[test]
+    fn test_error_kind() {
+        let error = Err::<(), _>(SyncError::not_found("secret \"a\" not found"))
+            .context("failed to pull \"a\"")
+            .unwrap_err();
+        assert_eq!(error.kind(), "backend-not-found");
+        assert_eq!(error.to_string(), "failed to pull \"a\"");
+        assert_eq!(
+            format!("{error:#}"),
+            "failed to pull \"a\": secret \"a\" not found"
+        );
+
+        let report = eyre::Report::new(error).wrap_err("pull failed");
+        assert_eq!(
+            SyncError::find(report.as_ref()).map(SyncError::kind),
+            Some("backend-not-found")
+        );
+        assert_eq!(SyncError::from(report).kind(), "backend-not-found");
+
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let error = SyncError::from(eyre::Report::new(io).wrap_err("failed to write"));
+        assert_eq!(error.kind(), "io");
+        assert_eq!(format!("{error:#}"), "failed to write: denied");
+
+        let missing = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        let error = SyncError::from(missing).context("failed to read \".env\"");
+        assert_eq!(error.io_kind(), Some(std::io::ErrorKind::NotFound));
+
+        let error = SyncError::from(eyre::eyre!("connection reset"));
+        assert_eq!(error.kind(), "backend");
+        assert!(!error.is_throttled());
+    }
+}
@@ -0,0 +1,120 @@
+//! # Events
+//!
+//! Progress events emitted while pulling or pushing a batch of files, so
+//! hosts can render their own progress instead of relying on the tracing
+//! output. Events are delivered to an [EventSink], which is implemented for
+//! closures, channel senders, and `()` to ignore the events
+
+use crate::error::SyncError;
+use std::path::PathBuf;
+
+/// Progress event for a single file within a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// Processing of the file started
+    FileStarted {
+        /// Secret of the file
+        secret: String,
+        /// Resolved path of the file
+        path: PathBuf,
+    },
+    /// The file was processed successfully
+    FileCompleted {
+        /// Secret of the file
+        secret: String,
+        /// Resolved path of the file
+        path: PathBuf,
+        /// Whether the file (for pulls) or secret (for pushes) was modified
+        changed: bool,
+        /// Version of the secret that was pulled, when provided by the backend
+        version_id: Option<String>,
+    },
+    /// The file was skipped without being processed
+    FileSkipped {
+        /// Secret of the file
+        secret: String,
+        /// Resolved path of the file
+        path: PathBuf,
+        /// Reason the file was skipped
+        reason: String,
+    },
+    /// Processing the file failed
+    FileFailed {
+        /// Secret of the file
+        secret: String,
+        /// Resolved path of the file
+        path: PathBuf,
+        /// Error processing the file failed with
+        error: String,
+    },
+}
+
+/// Receiver of [SyncEvent]s, events are emitted in order from the task
+/// processing the batch
+pub trait EventSink: Send + Sync {
+    /// Handle the `event`
+    fn emit(&self, event: SyncEvent);
+}
+
+/// Ignores every event
+impl EventSink for () {
+    fn emit(&self, _event: SyncEvent) {}
+}
+
+impl<F> EventSink for F
+where
+    F: Fn(SyncEvent) + Send + Sync,
+{
+    fn emit(&self, event: SyncEvent) {
+        self(event)
+    }
+}
+
+/// Events are dropped once the receiver is closed
+impl EventSink for std::sync::mpsc::Sender<SyncEvent> {
+    fn emit(&self, event: SyncEvent) {
+        _ = self.send(event);
+    }
+}
+
+/// Events are dropped once the receiver is closed
+#[cfg(not(target_family = "wasm"))]
+impl EventSink for tokio::sync::mpsc::UnboundedSender<SyncEvent> {
+    fn emit(&self, event: SyncEvent) {
+        _ = self.send(event);
+    }
+}
+
+/// Emit the [SyncEvent::FileCompleted] or [SyncEvent::FileFailed] event
+/// for the `result` of processing the file at `path`, successful results
+/// provide whether the file changed along with the version of the secret
+pub(crate) fn emit_file_result(
+    events: &dyn EventSink,
+    secret: &str,
+    path: PathBuf,
+    result: Result<(bool, Option<String>), &SyncError>,
+) {
+    events.emit(match result {
+        Ok((changed, version_id)) => SyncEvent::FileCompleted {
+            secret: secret.to_string(),
+            path,
+            changed,
+            version_id,
+        },
+        Err(error) => SyncEvent::FileFailed {
+            secret: secret.to_string(),
+            path,
+            error: format!("{error:#}"),
+        },
+    });
+}
+
+/// Emit the [SyncEvent::FileFailed] event for a file that was in progress
+/// when the batch was cancelled
+pub(crate) fn emit_file_cancelled(events: &dyn EventSink, secret: &str, path: PathBuf) {
+    events.emit(SyncEvent::FileFailed {
+        secret: secret.to_string(),
+        path,
+        error: "cancelled".to_string(),
+    });
+}
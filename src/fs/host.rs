@@ -0,0 +1,104 @@
+//! # Host
+//!
+//! File system selected by the host of a secret file, files without a host
+//! use the local file system while files with a host are accessed over SSH
+
+use crate::{
+    config::SecretFile,
+    error::{Result, SyncError},
+    fs::{FileSystem, FileSystemProvider, real::RealFs, ssh::SshFs},
+};
+use std::path::Path;
+
+/// File system of the host a secret file lives on
+pub enum HostFs {
+    /// Local file system
+    Local(RealFs),
+    /// Remote host accessed over SSH
+    Ssh(SshFs),
+}
+
+impl HostFs {
+    /// File system for the host of the `file`
+    pub fn for_file(file: &SecretFile) -> Self {
+        match &file.host {
+            Some(host) => HostFs::Ssh(SshFs::new(host.clone())),
+            None => HostFs::Local(RealFs),
+        }
+    }
+
+    /// File system for a batch of `files` that must all live on the same
+    /// host, used by operations that process the batch as a whole
+    pub fn for_files<'a>(files: impl IntoIterator<Item = &'a SecretFile>) -> Result<Self> {
+        let mut files = files.into_iter();
+        let Some(first) = files.next() else {
+            return Ok(HostFs::Local(RealFs));
+        };
+
+        if let Some(other) = files.find(|file| file.host != first.host) {
+            return Err(SyncError::config(format!(
+                "secrets \"{}\" and \"{}\" are on different hosts, this operation only supports files on a single host",
+                first.secret, other.secret
+            )));
+        }
+
+        Ok(Self::for_file(first))
+    }
+}
+
+/// Provides the [HostFs] of the host each file lives on, used for batches
+/// spanning multiple hosts
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileHosts;
+
+impl FileSystemProvider for FileHosts {
+    type Fs<'a> = HostFs;
+
+    fn for_file<'a>(&'a self, file: &SecretFile) -> Self::Fs<'a> {
+        HostFs::for_file(file)
+    }
+}
+
+impl FileSystem for HostFs {
+    async fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        match self {
+            HostFs::Local(fs) => fs.read_file(path).await,
+            HostFs::Ssh(fs) => fs.read_file(path).await,
+        }
+    }
+
+    async fn read_file_optional(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        match self {
+            HostFs::Local(fs) => fs.read_file_optional(path).await,
+            HostFs::Ssh(fs) => fs.read_file_optional(path).await,
+        }
+    }
+
+    async fn write_file(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        match self {
+            HostFs::Local(fs) => fs.write_file(path, bytes).await,
+            HostFs::Ssh(fs) => fs.write_file(path, bytes).await,
+        }
+    }
+
+    async fn remove_file(&self, path: &Path) -> Result<()> {
+        match self {
+            HostFs::Local(fs) => fs.remove_file(path).await,
+            HostFs::Ssh(fs) => fs.remove_file(path).await,
+        }
+    }
+
+    async fn rename_file(&self, from: &Path, to: &Path) -> Result<()> {
+        match self {
+            HostFs::Local(fs) => fs.rename_file(from, to).await,
+            HostFs::Ssh(fs) => fs.rename_file(from, to).await,
+        }
+    }
+
+    async fn write_command(&self, command: &str, bytes: &[u8]) -> Result<()> {
+        match self {
+            HostFs::Local(fs) => fs.write_command(command, bytes).await,
+            HostFs::Ssh(fs) => fs.write_command(command, bytes).await,
+        }
+    }
+}
@@ -1,14 +1,100 @@
+//! # File System
+//!
+//! File system abstraction used when reading and writing secret files,
+//! allowing hosts without a native file system to provide their own
+
+use crate::{config::SecretFile, error::Result};
 use mockall::automock;
 use std::path::Path;
 
+#[cfg(not(target_family = "wasm"))]
+pub mod host;
+#[cfg(not(target_family = "wasm"))]
 pub mod real;
+#[cfg(not(target_family = "wasm"))]
+pub mod ssh;
 
 /// File system abstraction
+///
+/// Failures are [SyncError::Io](crate::error::SyncError::Io) errors caused
+/// by the underlying [std::io::Error] when there is one, so its kind can be
+/// checked using [SyncError::io_kind](crate::error::SyncError::io_kind)
+///
+/// Futures are not required to be [Send] so that single threaded hosts
+/// such as WASM runtimes can implement this trait
 #[automock]
+#[allow(async_fn_in_trait)]
 pub trait FileSystem {
     /// Read a file from the provided `path`
-    async fn read_file(&self, path: &Path) -> eyre::Result<Vec<u8>>;
+    async fn read_file(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Read a file from the provided `path`, providing [None] when
+    /// the file does not exist
+    async fn read_file_optional(&self, path: &Path) -> Result<Option<Vec<u8>>>;
 
     /// Write the provided `bytes` to the file at `path`
-    async fn write_file(&self, path: &Path, bytes: &[u8]) -> eyre::Result<()>;
+    async fn write_file(&self, path: &Path, bytes: &[u8]) -> Result<()>;
+
+    /// Remove the file at `path`
+    async fn remove_file(&self, path: &Path) -> Result<()>;
+
+    /// Move the file at `from` to `to` within the same directory, replacing
+    /// any existing file at `to` as a whole
+    async fn rename_file(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Pipe the provided `bytes` into the standard input of the shell
+    /// `command`, used by files with a sink instead of writing a file
+    async fn write_command(&self, command: &str, bytes: &[u8]) -> Result<()>;
+}
+
+/// Forwards to the referenced file system
+impl<Fs: FileSystem + ?Sized> FileSystem for &Fs {
+    async fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        (**self).read_file(path).await
+    }
+
+    async fn read_file_optional(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        (**self).read_file_optional(path).await
+    }
+
+    async fn write_file(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        (**self).write_file(path, bytes).await
+    }
+
+    async fn remove_file(&self, path: &Path) -> Result<()> {
+        (**self).remove_file(path).await
+    }
+
+    async fn rename_file(&self, from: &Path, to: &Path) -> Result<()> {
+        (**self).rename_file(from, to).await
+    }
+
+    async fn write_command(&self, command: &str, bytes: &[u8]) -> Result<()> {
+        (**self).write_command(command, bytes).await
+    }
+}
+
+/// Provider of the [FileSystem] each file of a batch is accessed through,
+/// allowing a batch to span files on different hosts
+///
+/// Every [FileSystem] provides itself for all of the files
+pub trait FileSystemProvider {
+    /// File system provided for a file
+    type Fs<'a>: FileSystem
+    where
+        Self: 'a;
+
+    /// File system to access the `file` through
+    fn for_file<'a>(&'a self, file: &SecretFile) -> Self::Fs<'a>;
+}
+
+impl<Fs: FileSystem> FileSystemProvider for Fs {
+    type Fs<'a>
+        = &'a Fs
+    where
+        Self: 'a;
+
+    fn for_file<'a>(&'a self, _file: &SecretFile) -> Self::Fs<'a> {
+        self
+    }
 }
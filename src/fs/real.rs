@@ -2,30 +2,46 @@
 //!
 //! File system backed by the real host file system
 
-use crate::fs::FileSystem;
-use eyre::{Context, ContextCompat};
-use tokio::fs::create_dir_all;
+use crate::{
+    error::{Result, ResultExt, SyncError},
+    fs::FileSystem,
+    shell::shell_command,
+};
+use std::{io::ErrorKind, process::Stdio};
+use tokio::{fs::create_dir_all, io::AsyncWriteExt, process::Command};
 
 /// File system backed by real files
 pub struct RealFs;
 
 impl FileSystem for RealFs {
     #[tracing::instrument(skip(self))]
-    async fn read_file(&self, path: &std::path::Path) -> eyre::Result<Vec<u8>> {
-        if !path.exists() {
-            eyre::bail!("cannot push secret, file does not exist");
-        }
-
-        let value = tokio::fs::read(&path)
+    async fn read_file(&self, path: &std::path::Path) -> Result<Vec<u8>> {
+        tokio::fs::read(&path)
             .await
-            .context("failed to read secret file")?;
+            .map_err(|error| match error.kind() {
+                ErrorKind::NotFound => {
+                    SyncError::io("cannot push secret, file does not exist").with_source(error)
+                }
+                _ => SyncError::from(error).context("failed to read secret file"),
+            })
+    }
 
-        Ok(value)
+    #[tracing::instrument(skip(self))]
+    async fn read_file_optional(&self, path: &std::path::Path) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(&path).await {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => {
+                Err(SyncError::from(error).context("failed to read existing secret file"))
+            }
+        }
     }
 
     #[tracing::instrument(skip(self, bytes))]
-    async fn write_file(&self, path: &std::path::Path, bytes: &[u8]) -> eyre::Result<()> {
-        let parent_path = path.parent().context("file parent path does not exist")?;
+    async fn write_file(&self, path: &std::path::Path, bytes: &[u8]) -> Result<()> {
+        let parent_path = path
+            .parent()
+            .ok_or_else(|| SyncError::io("file parent path does not exist"))?;
 
         if !parent_path.exists() {
             tracing::debug!(
@@ -45,4 +61,63 @@ impl FileSystem for RealFs {
 
         Ok(())
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn remove_file(&self, path: &std::path::Path) -> Result<()> {
+        tokio::fs::remove_file(path)
+            .await
+            .context("failed to remove secret file")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn rename_file(&self, from: &std::path::Path, to: &std::path::Path) -> Result<()> {
+        // The replacement keeps the permissions of the file it replaces
+        if let Ok(metadata) = tokio::fs::metadata(to).await {
+            tokio::fs::set_permissions(from, metadata.permissions())
+                .await
+                .context("failed to copy secret file permissions")?;
+        }
+
+        tokio::fs::rename(from, to)
+            .await
+            .context("failed to move secret file")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, bytes))]
+    async fn write_command(&self, command: &str, bytes: &[u8]) -> Result<()> {
+        // Sink output is written to stderr so it does not interfere
+        // with the JSON output of secret-sync
+        let mut child = Command::from(shell_command(command))
+            .stdin(Stdio::piped())
+            .stdout(std::io::stderr())
+            .spawn()
+            .with_context(|| format!("failed to execute \"{command}\""))?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| SyncError::io("sink command has no stdin"))?;
+        stdin
+            .write_all(bytes)
+            .await
+            .context("failed to write secret to sink command")?;
+        drop(stdin);
+
+        let status = child
+            .wait()
+            .await
+            .with_context(|| format!("failed to execute \"{command}\""))?;
+
+        if !status.success() {
+            return Err(SyncError::io(format!(
+                "sink command \"{command}\" exited with {status}"
+            )));
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,215 @@
+//! # SSH
+//!
+//! File system on a remote host accessed through the system `ssh` client,
+//! so hosts, users, keys and jump hosts are configured through the usual
+//! SSH config
+
+use crate::{
+    error::{Result, ResultExt, SyncError},
+    fs::FileSystem,
+};
+use std::{
+    path::Path,
+    process::{Output, Stdio},
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// Exit code used by the remote read command when the file does not exist,
+/// distinguishing a missing file from a failed connection (255)
+const MISSING_EXIT_CODE: i32 = 100;
+
+/// File system of a remote host, relative paths are resolved by the remote
+/// shell against the home directory of the SSH user
+pub struct SshFs {
+    /// Host to connect to, either a host from the SSH config or `user@host`
+    pub host: String,
+}
+
+impl SshFs {
+    /// Create a file system for the remote `host`
+    pub fn new(host: String) -> Self {
+        Self { host }
+    }
+
+    /// Run the shell `command` on the remote host, providing `stdin` to
+    /// the command when present
+    async fn run(&self, command: &str, stdin: Option<&[u8]>) -> Result<Output> {
+        let mut child = Command::new("ssh")
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg("--")
+            .arg(&self.host)
+            .arg(command)
+            .stdin(match stdin {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to execute ssh")?;
+
+        if let Some(bytes) = stdin {
+            let mut input = child
+                .stdin
+                .take()
+                .ok_or_else(|| SyncError::io("ssh has no stdin"))?;
+            input
+                .write_all(bytes)
+                .await
+                .with_context(|| format!("failed to send secret to \"{}\"", self.host))?;
+            drop(input);
+        }
+
+        child
+            .wait_with_output()
+            .await
+            .context("failed to execute ssh")
+    }
+
+    /// Run the shell `command` on the remote host failing when it does not
+    /// exit successfully
+    async fn run_checked(&self, command: &str, stdin: Option<&[u8]>) -> Result<Vec<u8>> {
+        let output = self.run(command, stdin).await?;
+        if !output.status.success() {
+            return Err(SyncError::io(format!(
+                "command on \"{}\" exited with {}: {}",
+                self.host,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+impl FileSystem for SshFs {
+    #[tracing::instrument(skip(self), fields(host = %self.host))]
+    async fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        self.read_file_optional(path)
+            .await?
+            .ok_or_else(|| SyncError::io("cannot push secret, file does not exist"))
+    }
+
+    #[tracing::instrument(skip(self), fields(host = %self.host))]
+    async fn read_file_optional(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let output = self.run(&read_command(path)?, None).await?;
+
+        if output.status.code() == Some(MISSING_EXIT_CODE) {
+            return Ok(None);
+        }
+
+        if !output.status.success() {
+            return Err(SyncError::io(format!(
+                "failed to read secret file from \"{}\": {}",
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(Some(output.stdout))
+    }
+
+    #[tracing::instrument(skip(self, bytes), fields(host = %self.host))]
+    async fn write_file(&self, path: &Path, bytes: &[u8]) -> Result<()> {
+        self.run_checked(&write_command(path)?, Some(bytes))
+            .await
+            .context("failed to write secret to file")?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(host = %self.host))]
+    async fn remove_file(&self, path: &Path) -> Result<()> {
+        let command = format!("rm -- {}", shell_quote(path_str(path)?));
+        self.run_checked(&command, None)
+            .await
+            .context("failed to remove secret file")?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self), fields(host = %self.host))]
+    async fn rename_file(&self, from: &Path, to: &Path) -> Result<()> {
+        let command = format!(
+            "mv -f -- {} {}",
+            shell_quote(path_str(from)?),
+            shell_quote(path_str(to)?)
+        );
+        self.run_checked(&command, None)
+            .await
+            .context("failed to move secret file")?;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, bytes), fields(host = %self.host))]
+    async fn write_command(&self, command: &str, bytes: &[u8]) -> Result<()> {
+        self.run_checked(command, Some(bytes))
+            .await
+            .with_context(|| format!("sink command \"{command}\" failed"))?;
+        Ok(())
+    }
+}
+
+/// Remote command printing the file at `path`, exiting with
+/// [MISSING_EXIT_CODE] when the file does not exist
+fn read_command(path: &Path) -> Result<String> {
+    let path = shell_quote(path_str(path)?);
+    Ok(format!(
+        "[ -e {path} ] || exit {MISSING_EXIT_CODE}; cat -- {path}"
+    ))
+}
+
+/// Remote command writing its standard input to the file at `path`,
+/// creating the parent directory and keeping new files private
+fn write_command(path: &Path) -> Result<String> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    Ok(format!(
+        "umask 077 && mkdir -p -- {} && cat > {}",
+        shell_quote(path_str(parent)?),
+        shell_quote(path_str(path)?)
+    ))
+}
+
+/// Remote paths are passed through the remote shell so must be UTF-8
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| {
+        SyncError::validation(format!(
+            "remote path \"{}\" is not valid UTF-8",
+            path.display()
+        ))
+    })
+}
+
+/// Quote `value` as a single POSIX shell word
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fs::ssh::{read_command, shell_quote, write_command};
+    use std::path::Path;
+
+    /// Tests the remote commands quote paths for the remote shell
+    #[test]
+    fn test_remote_commands() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+
+        assert_eq!(
+            read_command(Path::new("/etc/app/.env")).unwrap(),
+            "[ -e '/etc/app/.env' ] || exit 100; cat -- '/etc/app/.env'"
+        );
+        assert_eq!(
+            write_command(Path::new(".env")).unwrap(),
+            "umask 077 && mkdir -p -- '.' && cat > '.env'"
+        );
+        assert_eq!(
+            write_command(Path::new("/etc/my app/.env")).unwrap(),
+            "umask 077 && mkdir -p -- '/etc/my app' && cat > '/etc/my app/.env'"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! # Grant
+//!
+//! Instructions for using temporary credentials granted for a single
+//! secret, shared with someone who has no standing access to the backend
+
+use secret_sync::secret::SecretGrant;
+use std::{fmt::Write, path::Path};
+
+/// Render the instructions for fetching the secret of the `grant` into the
+/// file at `path`
+pub fn grant_instructions(grant: &SecretGrant, path: &Path) -> String {
+    let mut text = format!("granted read access to \"{}\"", grant.secret);
+
+    if let Some(expires) = &grant.expires {
+        _ = write!(text, " until {expires}");
+    }
+
+    text.push_str("\nrun the following to fetch the secret:\n");
+
+    for (name, value) in &grant.environment {
+        _ = writeln!(text, "  export {name}={value}");
+    }
+
+    _ = write!(
+        text,
+        "  secret-sync quick-pull --path {} --secret {}",
+        path.display(),
+        grant.secret
+    );
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::grant::grant_instructions;
+    use indexmap::IndexMap;
+    use secret_sync::secret::SecretGrant;
+    use std::path::Path;
+
+    /// Tests that the instructions export the credentials before pulling
+    #[test]
+    fn test_grant_instructions() {
+        let grant = SecretGrant {
+            secret: "db".to_string(),
+            environment: IndexMap::from([
+                ("AWS_ACCESS_KEY_ID".to_string(), "ASIAEXAMPLE".to_string()),
+                ("AWS_REGION".to_string(), "us-east-1".to_string()),
+            ]),
+            expires: Some("2026-01-01T01:00:00Z".to_string()),
+        };
+
+        assert_eq!(
+            grant_instructions(&grant, Path::new(".env.db")),
+            "granted read access to \"db\" until 2026-01-01T01:00:00Z\n\
+             run the following to fetch the secret:\n  \
+             export AWS_ACCESS_KEY_ID=ASIAEXAMPLE\n  \
+             export AWS_REGION=us-east-1\n  \
+             secret-sync quick-pull --path .env.db --secret db"
+        );
+    }
+}
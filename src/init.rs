@@ -0,0 +1,181 @@
+//! # Init
+//!
+//! Generation of the config for a new project, optionally from a template
+//! containing the conventional secret files of a common stack. Secret names
+//! follow the `<project>/{env}/<file>` naming template so every environment
+//! of every project has its own secrets
+
+use clap::ValueEnum;
+use secret_sync::config::BackendProvider;
+
+/// Template of the config generated for a common stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InitTemplate {
+    /// Ruby on Rails, .env and the credentials key of the environment
+    Rails,
+    /// Django, .env loaded by django-environ or python-dotenv
+    Django,
+    /// Next.js, .env.local loaded by next
+    Nextjs,
+    /// Spring Boot, properties of the "secrets" profile
+    Spring,
+}
+
+/// File entry generated by a template
+struct TemplateFile {
+    /// Name of the file within the config
+    name: &'static str,
+    /// Path of the file relative to the config
+    path: &'static str,
+    /// Comment describing the file
+    comment: &'static str,
+}
+
+impl InitTemplate {
+    /// Conventional secret files of the stack
+    fn files(&self) -> &'static [TemplateFile] {
+        match self {
+            InitTemplate::Rails => &[
+                TemplateFile {
+                    name: "env",
+                    path: ".env",
+                    comment: "Environment variables loaded by dotenv-rails",
+                },
+                TemplateFile {
+                    name: "credentials-key",
+                    path: "config/credentials/{env}.key",
+                    comment: "Key decrypting config/credentials/<env>.yml.enc",
+                },
+            ],
+            InitTemplate::Django => &[TemplateFile {
+                name: "env",
+                path: ".env",
+                comment: "Settings loaded by django-environ or python-dotenv (SECRET_KEY, DATABASE_URL, ..)",
+            }],
+            InitTemplate::Nextjs => &[TemplateFile {
+                name: "env",
+                path: ".env.local",
+                comment: "Environment variables loaded by next, ignored by git by default",
+            }],
+            InitTemplate::Spring => &[TemplateFile {
+                name: "application-secrets",
+                path: "src/main/resources/application-secrets.yaml",
+                comment: "Properties of the \"secrets\" profile, activate with spring.profiles.include=secrets",
+            }],
+        }
+    }
+}
+
+/// Generic file generated when no template is used
+const DEFAULT_FILE: TemplateFile = TemplateFile {
+    name: "env",
+    path: ".env",
+    comment: "Environment variables of the project",
+};
+
+/// Generate the contents of a secret-sync.toml for the `project` using the
+/// `provider` and the files of the `template`
+pub fn init_config(
+    template: Option<InitTemplate>,
+    provider: BackendProvider,
+    project: &str,
+) -> String {
+    let files = match template {
+        Some(template) => template.files(),
+        None => std::slice::from_ref(&DEFAULT_FILE),
+    };
+
+    let mut output = String::from(
+        "# Generated by secret-sync init, secret names use {env} which is replaced\n\
+         # with the --env flag (e.g. secret-sync --env development pull)\n\n\
+         [backend]\n",
+    );
+    output.push_str(&format!(
+        "provider = \"{provider}\"\n\n[defaults.metadata]\n"
+    ));
+    output.push_str(&format!(
+        "description = \"{{name}} secrets of {project} managed by secret-sync\"\n"
+    ));
+    output.push_str(&format!("tags = {{ \"project\" = \"{project}\" }}\n"));
+
+    if provider == BackendProvider::Local {
+        output.push_str(
+            "\n[local]\n\
+             path = \"secrets\"\n\
+             # Public keys (from age-keygen) of everyone that may decrypt the secrets\n\
+             recipients = []\n",
+        );
+    }
+
+    for file in files {
+        output.push_str(&format!(
+            "\n# {}\n[files.{}]\npath = \"{}\"\nsecret = \"{project}/{{env}}/{}\"\n",
+            file.comment, file.name, file.path, file.name
+        ));
+    }
+
+    output
+}
+
+/// Paths of the files generated by the `template`, which should not be
+/// committed
+pub fn init_file_paths(template: Option<InitTemplate>) -> Vec<&'static str> {
+    let files = match template {
+        Some(template) => template.files(),
+        None => std::slice::from_ref(&DEFAULT_FILE),
+    };
+
+    files.iter().map(|file| file.path).collect()
+}
+
+/// Normalize a directory name into a project name usable within secret
+/// names, keeping lowercase letters, digits, and single dashes
+pub fn project_name(value: &str) -> String {
+    let mut name = String::new();
+    for char in value.chars() {
+        match char {
+            'a'..='z' | '0'..='9' => name.push(char),
+            'A'..='Z' => name.push(char.to_ascii_lowercase()),
+            _ if !name.is_empty() && !name.ends_with('-') => name.push('-'),
+            _ => {}
+        }
+    }
+
+    let name = name.trim_end_matches('-');
+    match name.is_empty() {
+        true => "app".to_string(),
+        false => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::init::{InitTemplate, init_config, project_name};
+    use clap::ValueEnum;
+    use secret_sync::config::{BackendProvider, parse_config_file};
+
+    /// Tests that every template generates a valid config following the
+    /// naming template
+    #[test]
+    fn test_init_config() {
+        for template in InitTemplate::value_variants() {
+            let contents = init_config(Some(*template), BackendProvider::Aws, "shop");
+            let mut config = parse_config_file(contents.as_bytes(), None).unwrap();
+            config.apply_environment(Some("staging")).unwrap();
+            assert!(
+                config
+                    .files
+                    .values()
+                    .all(|file| file.secret.starts_with("shop/staging/"))
+            );
+        }
+
+        let contents = init_config(None, BackendProvider::Gcp, "shop");
+        let config = parse_config_file(contents.as_bytes(), None).unwrap();
+        assert_eq!(config.backend.provider, BackendProvider::Gcp);
+        assert_eq!(config.files["env"].secret, "shop/{env}/env");
+
+        assert_eq!(project_name("My Shop_API"), "my-shop-api");
+        assert_eq!(project_name("__"), "app");
+    }
+}
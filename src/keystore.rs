@@ -0,0 +1,479 @@
+//! # Keystore
+//!
+//! Java keystores (PKCS#12 or JKS) assembled on pull from PEM certificate
+//! and private key secrets, so JVM services can use them directly without
+//! converting the PEM files using openssl or keytool
+
+use crate::{
+    config::{KeystoreFile, KeystoreFormat},
+    error::{Result, ResultExt, SyncError},
+    fs::FileSystem,
+    secret::{Secret, SecretManager},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use p12_keystore::{
+    Certificate, KeyStore, KeyStoreEntry, Pkcs12Archive, PrivateKey, PrivateKeyChain,
+};
+use sha1::{Digest, Sha1};
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Magic number at the start of a JKS keystore
+const JKS_MAGIC: u32 = 0xFEED_FEED;
+
+/// Version of the JKS format that is written
+const JKS_VERSION: u32 = 2;
+
+/// Tag of a private key entry within a JKS keystore
+const JKS_PRIVATE_KEY_TAG: u32 = 1;
+
+/// DER encoded OID (1.3.6.1.4.1.42.2.17.1.1) of the proprietary key
+/// protection algorithm used by JKS keystores
+const JKS_KEY_PROTECTOR_OID: &[u8] = &[
+    0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x2a, 0x02, 0x11, 0x01, 0x01,
+];
+
+/// Text mixed into the integrity digest of a JKS keystore
+const JKS_WHITENER: &[u8] = b"Mighty Aphrodite";
+
+/// Length of the key protection salt of a JKS keystore
+const JKS_SALT_LENGTH: usize = 20;
+
+/// DER encoded algorithm identifier of RSA keys, used to wrap PKCS#1 keys
+const RSA_ALGORITHM: &[u8] = &[
+    0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01, 0x05, 0x00,
+];
+
+/// Private key and certificate chain stored within a keystore
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEntry {
+    /// Alias of the entry
+    pub alias: String,
+    /// DER encoded PKCS#8 private key
+    pub key: Vec<u8>,
+    /// DER encoded certificates, starting with the certificate of the key
+    pub certificates: Vec<Vec<u8>>,
+}
+
+impl KeyEntry {
+    /// Create the entry `alias` from the PEM `certificate` chain and the
+    /// PEM private `key`, the key may instead be within the `certificate`
+    ///
+    /// PKCS#8 and PKCS#1 RSA keys are supported, other keys must be
+    /// converted to unencrypted PKCS#8 keys
+    pub fn from_pem(alias: &str, certificate: &str, key: Option<&str>) -> Result<Self> {
+        let mut certificates = Vec::new();
+        let mut private_key = None;
+
+        for (label, contents) in pem_blocks(certificate)?
+            .into_iter()
+            .chain(key.map(pem_blocks).transpose()?.into_iter().flatten())
+        {
+            let key = match label.as_str() {
+                "CERTIFICATE" => {
+                    certificates.push(contents);
+                    continue;
+                }
+                "PRIVATE KEY" => contents,
+                "RSA PRIVATE KEY" => der(
+                    0x30,
+                    &[&[0x02, 0x01, 0x00], RSA_ALGORITHM, &der(0x04, &contents)].concat(),
+                ),
+                "ENCRYPTED PRIVATE KEY" => {
+                    return Err(SyncError::validation(
+                        "encrypted private keys are not supported, the keystore passphrase protects the key",
+                    ));
+                }
+                label if label.ends_with("PRIVATE KEY") => {
+                    return Err(SyncError::validation(format!(
+                        "unsupported \"{label}\", convert the key to PKCS#8 using \"openssl pkcs8 -topk8 -nocrypt\""
+                    )));
+                }
+                _ => continue,
+            };
+
+            if private_key.replace(key).is_some() {
+                return Err(SyncError::validation("found more than one private key"));
+            }
+        }
+
+        if certificates.is_empty() {
+            return Err(SyncError::validation("no PEM certificates found"));
+        }
+
+        let key = private_key.ok_or_else(|| SyncError::validation("no PEM private key found"))?;
+
+        Ok(Self {
+            alias: alias.to_string(),
+            key,
+            certificates,
+        })
+    }
+}
+
+/// Assemble the keystore `file` named `name` from its secrets and write it,
+/// providing whether the file changed
+///
+/// Keystores are encrypted using random salts, so the keystore is only
+/// written when the existing keystore does not contain the same entry
+/// protected by the same passphrase
+pub async fn pull_keystore_file<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    name: &str,
+    file: &KeystoreFile,
+) -> Result<bool> {
+    let certificate = get_secret_text(secret, &file.certificate).await?;
+    let key = match &file.key {
+        Some(key) => Some(get_secret_text(secret, key).await?),
+        None => None,
+    };
+    let passphrase = get_secret_text(secret, &file.passphrase).await?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+
+    let alias = file.alias.as_deref().unwrap_or(name);
+    let entry = KeyEntry::from_pem(alias, &certificate, key.as_deref())
+        .with_context(|| format!("failed to assemble keystore \"{name}\""))?;
+
+    let file_path = file.resolve_path(working_path);
+    let previous = fs.read_file_optional(&file_path).await?;
+
+    let unchanged = previous
+        .as_deref()
+        .is_some_and(|previous| match file.format {
+            KeystoreFormat::Pkcs12 => pkcs12_contains(previous, &entry, passphrase),
+            KeystoreFormat::Jks => jks_contains(previous, &entry, passphrase),
+        });
+
+    if unchanged {
+        tracing::debug!(?file_path, "keystore unchanged, skipping write");
+        return Ok(false);
+    }
+
+    let keystore = match file.format {
+        KeystoreFormat::Pkcs12 => encode_pkcs12(&entry, passphrase)?,
+        KeystoreFormat::Jks => encode_jks(
+            &entry,
+            passphrase,
+            p12_keystore::rand::random(),
+            SystemTime::now(),
+        ),
+    };
+
+    fs.write_file(&file_path, &keystore).await?;
+
+    Ok(true)
+}
+
+/// Get the value of the secret `name` as text
+async fn get_secret_text(secret: &dyn SecretManager, name: &str) -> Result<String> {
+    let value = secret
+        .get_secret(name)
+        .await
+        .with_context(|| format!("failed to get keystore secret \"{name}\""))?
+        .data;
+
+    match value {
+        Secret::String(value) => Ok(value),
+        Secret::Binary(_) => Err(SyncError::validation(format!(
+            "keystore secret \"{name}\" is not valid UTF-8"
+        ))),
+    }
+}
+
+/// Encode the `entry` as a PKCS#12 keystore protected by the `passphrase`
+pub fn encode_pkcs12(entry: &KeyEntry, passphrase: &str) -> Result<Vec<u8>> {
+    let key = PrivateKey::from_der(&entry.key)
+        .map_err(|error| SyncError::validation("invalid private key").with_source(error))?;
+
+    let certificates = entry
+        .certificates
+        .iter()
+        .map(|certificate| Certificate::from_der(certificate))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|error| SyncError::validation("invalid certificate").with_source(error))?;
+
+    // Identifies the certificate of the key the same way keytool does
+    let local_key_id = Sha1::digest(&entry.certificates[0]).to_vec();
+
+    let mut keystore = KeyStore::new();
+    keystore.add_entry(
+        &entry.alias,
+        KeyStoreEntry::PrivateKeyChain(PrivateKeyChain::new(local_key_id, key, certificates)),
+    );
+
+    keystore.writer(passphrase).write().map_err(|error| {
+        SyncError::validation("failed to encode PKCS#12 keystore").with_source(error)
+    })
+}
+
+/// Whether the PKCS#12 keystore `keystore` protected by the `passphrase`
+/// contains only the `entry`
+fn pkcs12_contains(keystore: &[u8], entry: &KeyEntry, passphrase: &str) -> bool {
+    let Ok(archive) = Pkcs12Archive::from_pkcs12(keystore, passphrase) else {
+        return false;
+    };
+
+    let [key] = archive.keys.as_slice() else {
+        return false;
+    };
+
+    key.friendly_name.as_deref() == Some(entry.alias.as_str())
+        && key.key.as_der() == entry.key
+        && archive
+            .certs
+            .iter()
+            .map(|certificate| certificate.cert.as_der())
+            .eq(entry.certificates.iter().map(Vec::as_slice))
+}
+
+/// Encode the `entry` as a JKS keystore protected by the `passphrase`, the
+/// key is protected using the `salt` and the entry is dated `created`
+pub fn encode_jks(
+    entry: &KeyEntry,
+    passphrase: &str,
+    salt: [u8; JKS_SALT_LENGTH],
+    created: SystemTime,
+) -> Vec<u8> {
+    let password = jks_password(passphrase);
+    let created = created
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let mut keystore = Vec::new();
+    keystore.extend(JKS_MAGIC.to_be_bytes());
+    keystore.extend(JKS_VERSION.to_be_bytes());
+    keystore.extend(1u32.to_be_bytes());
+
+    // Java compares the aliases of JKS keystores in lowercase
+    keystore.extend(JKS_PRIVATE_KEY_TAG.to_be_bytes());
+    write_jks_string(&mut keystore, &entry.alias.to_lowercase());
+    keystore.extend(created.to_be_bytes());
+
+    let key = protect_jks_key(&entry.key, &password, salt);
+    keystore.extend((key.len() as u32).to_be_bytes());
+    keystore.extend(key);
+
+    keystore.extend((entry.certificates.len() as u32).to_be_bytes());
+    for certificate in &entry.certificates {
+        write_jks_string(&mut keystore, "X.509");
+        keystore.extend((certificate.len() as u32).to_be_bytes());
+        keystore.extend(certificate);
+    }
+
+    let digest = Sha1::new()
+        .chain_update(&password)
+        .chain_update(JKS_WHITENER)
+        .chain_update(&keystore)
+        .finalize();
+    keystore.extend(digest);
+
+    keystore
+}
+
+/// Whether the JKS keystore `keystore` protected by the `passphrase`
+/// contains only the `entry`
+///
+/// The keystore is compared against the `entry` encoded using the salt and
+/// creation time of the existing keystore
+fn jks_contains(keystore: &[u8], entry: &KeyEntry, passphrase: &str) -> bool {
+    jks_salt_and_created(keystore)
+        .is_some_and(|(salt, created)| encode_jks(entry, passphrase, salt, created) == keystore)
+}
+
+/// Read the key protection salt and creation time of the first entry of
+/// the JKS `keystore`, [None] when the first entry is not a private key
+fn jks_salt_and_created(keystore: &[u8]) -> Option<([u8; JKS_SALT_LENGTH], SystemTime)> {
+    let (header, rest) = keystore.split_at_checked(16)?;
+    if header[..4] != JKS_MAGIC.to_be_bytes() || header[12..] != JKS_PRIVATE_KEY_TAG.to_be_bytes() {
+        return None;
+    }
+
+    let (alias_length, rest) = rest.split_at_checked(2)?;
+    let alias_length = u16::from_be_bytes(alias_length.try_into().ok()?);
+    let rest = rest.get(alias_length as usize..)?;
+
+    let (created, rest) = rest.split_at_checked(8)?;
+    let created = u64::from_be_bytes(created.try_into().ok()?);
+
+    // EncryptedPrivateKeyInfo containing the algorithm and protected key
+    let (_tag, key_info, _rest) = der_split(rest.get(4..)?)?;
+    let (_tag, _algorithm, key_info) = der_split(key_info)?;
+    let (_tag, key, _rest) = der_split(key_info)?;
+    let salt = key.get(..JKS_SALT_LENGTH)?.try_into().ok()?;
+
+    Some((salt, UNIX_EPOCH + Duration::from_millis(created)))
+}
+
+/// Protect the PKCS#8 `key` using the JKS key protection algorithm,
+/// providing the DER encoded EncryptedPrivateKeyInfo
+fn protect_jks_key(key: &[u8], password: &[u8], salt: [u8; JKS_SALT_LENGTH]) -> Vec<u8> {
+    let mut protected = salt.to_vec();
+
+    // Keystream blocks are the digest of the password and the previous block
+    let mut block = salt.to_vec();
+    for chunk in key.chunks(JKS_SALT_LENGTH) {
+        block = Sha1::new()
+            .chain_update(password)
+            .chain_update(&block)
+            .finalize()
+            .to_vec();
+
+        protected.extend(chunk.iter().zip(&block).map(|(value, key)| value ^ key));
+    }
+
+    let check = Sha1::new()
+        .chain_update(password)
+        .chain_update(key)
+        .finalize();
+    protected.extend(check);
+
+    let algorithm = der(0x30, &[JKS_KEY_PROTECTOR_OID, &[0x05, 0x00]].concat());
+    der(0x30, &[algorithm, der(0x04, &protected)].concat())
+}
+
+/// Encode the `passphrase` as the UTF-16 bytes JKS keystores use
+fn jks_password(passphrase: &str) -> Vec<u8> {
+    passphrase
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect()
+}
+
+/// Write the length prefixed `value` to the JKS `keystore`
+fn write_jks_string(keystore: &mut Vec<u8>, value: &str) {
+    keystore.extend((value.len() as u16).to_be_bytes());
+    keystore.extend(value.as_bytes());
+}
+
+/// Decode the PEM blocks within `value` into their label and contents
+fn pem_blocks(value: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut blocks = Vec::new();
+    let mut lines = value.lines().map(str::trim);
+
+    while let Some(line) = lines.next() {
+        let Some(label) = line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|line| line.strip_suffix("-----"))
+        else {
+            continue;
+        };
+
+        let end = format!("-----END {label}-----");
+        let mut encoded = String::new();
+        loop {
+            match lines.next() {
+                Some(line) if line == end => break,
+                Some(line) => encoded.push_str(line),
+                None => {
+                    return Err(SyncError::validation(format!(
+                        "PEM \"{label}\" is missing its end line"
+                    )));
+                }
+            }
+        }
+
+        let contents = BASE64_STANDARD.decode(&encoded).map_err(|error| {
+            SyncError::validation(format!("PEM \"{label}\" is not valid base64")).with_source(error)
+        })?;
+        blocks.push((label.to_string(), contents));
+    }
+
+    Ok(blocks)
+}
+
+/// Encode a DER value with the `tag` and `contents`
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut value = vec![tag];
+
+    match contents.len() {
+        length @ 0..0x80 => value.push(length as u8),
+        length => {
+            let length = length.to_be_bytes();
+            let length = &length[length.iter().take_while(|byte| **byte == 0).count()..];
+            value.push(0x80 | length.len() as u8);
+            value.extend(length);
+        }
+    }
+
+    value.extend(contents);
+    value
+}
+
+/// Split the DER value at the start of `value` into its tag, contents, and
+/// the bytes that follow it
+fn der_split(value: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = value.split_first()?;
+    let (&length, rest) = rest.split_first()?;
+
+    let (length, rest) = match length {
+        0..0x80 => (length as usize, rest),
+        length => {
+            let (length, rest) = rest.split_at_checked((length & 0x7f) as usize)?;
+            let length = length.iter().try_fold(0usize, |total, byte| {
+                total.checked_mul(256)?.checked_add(*byte as usize)
+            })?;
+            (length, rest)
+        }
+    };
+
+    let (contents, rest) = rest.split_at_checked(length)?;
+    Some((tag, contents, rest))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::keystore::{
+        KeyEntry, encode_jks, encode_pkcs12, jks_contains, jks_salt_and_created, pkcs12_contains,
+    };
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const CERTIFICATE: &str = include_str!("../tests/samples/keystore/certificate.pem");
+    const KEY: &str = include_str!("../tests/samples/keystore/key.pem");
+
+    /// Tests that assembled PKCS#12 keystores are only considered unchanged
+    /// for the same entry and passphrase
+    #[test]
+    fn test_keystore_pkcs12() {
+        let entry = KeyEntry::from_pem("service", CERTIFICATE, Some(KEY)).unwrap();
+        assert_eq!(entry.certificates.len(), 1);
+
+        // The key may be bundled with the certificate
+        let bundled = format!("{CERTIFICATE}{KEY}");
+        assert_eq!(
+            KeyEntry::from_pem("service", &bundled, None).unwrap(),
+            entry
+        );
+        assert!(KeyEntry::from_pem("service", CERTIFICATE, None).is_err());
+
+        let keystore = encode_pkcs12(&entry, "changeit").unwrap();
+        assert!(pkcs12_contains(&keystore, &entry, "changeit"));
+        assert!(!pkcs12_contains(&keystore, &entry, "other"));
+
+        let renamed = KeyEntry {
+            alias: "worker".to_string(),
+            ..entry
+        };
+        assert!(!pkcs12_contains(&keystore, &renamed, "changeit"));
+    }
+
+    /// Tests that JKS keystores are encoded deterministically for the salt
+    /// and creation time read back from an existing keystore
+    #[test]
+    fn test_keystore_jks() {
+        let entry = KeyEntry::from_pem("Service", CERTIFICATE, Some(KEY)).unwrap();
+        let created = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+
+        let keystore = encode_jks(&entry, "changeit", [7; 20], created);
+        assert_eq!(keystore[..4], [0xfe, 0xed, 0xfe, 0xed]);
+        assert_eq!(jks_salt_and_created(&keystore), Some(([7; 20], created)));
+
+        assert!(jks_contains(&keystore, &entry, "changeit"));
+        assert!(!jks_contains(&keystore, &entry, "other"));
+        assert!(!jks_contains(b"not a keystore", &entry, "changeit"));
+    }
+}
@@ -0,0 +1,216 @@
+//! # Last Run
+//!
+//! Summary of the most recent pull or push persisted into the state
+//! directory, allowing failures to be inspected after the fact using the
+//! last subcommand without the original output
+
+use crate::state::create_state_dir;
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Display, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Summary of a pull or push run
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LastRun {
+    /// Subcommand that was run
+    pub command: String,
+    /// Unix timestamp (seconds) the run started at
+    pub started_at: u64,
+    /// Duration of the entire run in milliseconds
+    pub duration_ms: u64,
+    /// Whether the run completed successfully
+    pub success: bool,
+    /// Error the run failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Results of the files that were processed before the run finished
+    pub files: Vec<FileResult>,
+    /// Warnings raised during the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Result of processing a single file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileResult {
+    /// Path of the file
+    pub file: String,
+    /// Action that was taken
+    pub action: FileAction,
+    /// Duration spent on the file in milliseconds
+    pub duration_ms: u64,
+    /// Version of the secret that was pulled, when provided by the backend
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Error processing the file failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Action taken for a file
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAction {
+    /// Remote value was written to the file
+    Pulled,
+    /// File already matched the remote value
+    Unchanged,
+    /// Local value was pushed to the remote
+    Pushed,
+    /// File was completed by an interrupted run and skipped
+    Resumed,
+    /// Processing the file failed
+    Failed,
+}
+
+impl Display for FileAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FileAction::Pulled => "pulled",
+            FileAction::Unchanged => "unchanged",
+            FileAction::Pushed => "pushed",
+            FileAction::Resumed => "resumed",
+            FileAction::Failed => "failed",
+        })
+    }
+}
+
+impl FileResult {
+    /// Create a result for the file `name`
+    pub fn new(name: String, action: FileAction, duration: Duration) -> Self {
+        Self {
+            file: name,
+            action,
+            duration_ms: duration.as_millis() as u64,
+            version: None,
+            error: None,
+        }
+    }
+}
+
+impl LastRun {
+    /// Render a human readable version of the summary
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "last {} started at {} (unix) took {}ms and {}",
+            self.command,
+            self.started_at,
+            self.duration_ms,
+            match self.success {
+                true => "succeeded",
+                false => "failed",
+            }
+        );
+
+        if let Some(error) = &self.error {
+            _ = write!(text, ": {error}");
+        }
+
+        for file in &self.files {
+            _ = write!(
+                text,
+                "\n  {} {} ({}ms)",
+                file.file, file.action, file.duration_ms
+            );
+
+            if let Some(version) = &file.version {
+                _ = write!(text, " version {version}");
+            }
+
+            if let Some(error) = &file.error {
+                _ = write!(text, ": {error}");
+            }
+        }
+
+        for warning in &self.warnings {
+            _ = write!(text, "\n  warning: {warning}");
+        }
+
+        text
+    }
+}
+
+/// Current unix timestamp in seconds
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Write the last `run` summary to `path`, replacing any previous summary
+pub async fn write_last_run(path: &Path, run: &LastRun) -> eyre::Result<()> {
+    if let Some(parent) = path.parent() {
+        create_state_dir(parent).await?;
+    }
+
+    let value = serde_json::to_vec_pretty(run)?;
+    tokio::fs::write(path, value)
+        .await
+        .context("failed to write last run summary")?;
+
+    Ok(())
+}
+
+/// Read the last run summary from `path`
+pub async fn read_last_run(path: &Path) -> eyre::Result<LastRun> {
+    let value = match tokio::fs::read(path).await {
+        Ok(value) => value,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            eyre::bail!("no pull or push has been run for this project yet")
+        }
+        Err(error) => return Err(error).context("failed to read last run summary"),
+    };
+
+    serde_json::from_slice(&value).context("failed to parse last run summary")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::last_run::{FileAction, FileResult, LastRun, read_last_run, write_last_run};
+    use std::time::Duration;
+
+    /// Tests that a written summary can be read back
+    #[tokio::test]
+    async fn test_last_run_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(".secret-sync").join("last-run.json");
+
+        assert!(read_last_run(&path).await.is_err());
+
+        let mut failed = FileResult::new(
+            ".env.worker".to_string(),
+            FileAction::Failed,
+            Duration::from_millis(5),
+        );
+        failed.error = Some("access denied".to_string());
+
+        let run = LastRun {
+            command: "push".to_string(),
+            started_at: 1_700_000_000,
+            duration_ms: 120,
+            success: false,
+            error: Some("access denied".to_string()),
+            files: vec![
+                FileResult::new(
+                    ".env".to_string(),
+                    FileAction::Pushed,
+                    Duration::from_millis(40),
+                ),
+                failed,
+            ],
+            warnings: vec!["secret file \".env\" is world-readable".to_string()],
+        };
+
+        write_last_run(&path, &run).await.unwrap();
+        assert_eq!(read_last_run(&path).await.unwrap(), run);
+        assert_eq!(
+            run.to_text(),
+            "last push started at 1700000000 (unix) took 120ms and failed: access denied\n  .env pushed (40ms)\n  .env.worker failed (5ms): access denied\n  warning: secret file \".env\" is world-readable"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! # Secret-sync
+//!
+//! Core logic for syncing local secret files with remote secret managers,
+//! shared by the `secret-sync` CLI and hosts embedding secret-sync.
+//!
+//! The core builds for `wasm32-wasip1` with the default features disabled.
+//! Hosts provide file access through [`fs::FileSystem`] and the backend,
+//! including any HTTP transport, through [`secret::SecretManager`]. The
+//! current time is read through [`clock::Clock`] so it can be simulated.
+//! Progress of batch pulls and pushes is reported as [`events::SyncEvent`]s
+//! to an [`events::EventSink`] and can be stopped early by cancelling a
+//! [`cancel::CancellationToken`].
+//! Failures of the core operations are [`error::SyncError`]s categorized by
+//! kind so hosts can match on them.
+//! Helpers that read directly from the host file system are only available
+//! on native targets.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+pub mod approval;
+pub mod arn;
+pub mod cancel;
+pub mod checks;
+pub mod clock;
+pub mod concurrency;
+pub mod config;
+pub mod cost;
+#[cfg(any(feature = "aws", feature = "gcp", feature = "azure"))]
+pub mod credentials;
+pub mod doctor;
+pub mod dotenv;
+pub mod error;
+pub mod events;
+pub mod fs;
+pub mod keystore;
+pub mod migrate;
+pub mod plan;
+pub mod promote;
+pub mod pull;
+pub mod push;
+pub mod reconcile;
+pub mod registry;
+pub mod remote_lock;
+pub mod secret;
+pub mod shell;
+pub mod structured;
+pub mod template;
@@ -0,0 +1,74 @@
+//! # Lock
+//!
+//! Advisory file lock preventing concurrent secret-sync runs within
+//! the same project from interleaving writes
+
+use crate::state::StatePaths;
+use eyre::Context;
+use std::{fs::File, fs::TryLockError};
+
+/// Held project lock, the lock is released when dropped
+pub struct ProjectLock {
+    /// Locked file handle
+    _file: File,
+}
+
+/// Acquire the project lock within the `state` directory
+///
+/// When `wait` is set this waits for any other run to release the
+/// lock, otherwise fails immediately if the lock is held
+pub async fn acquire_project_lock(state: &StatePaths, wait: bool) -> eyre::Result<ProjectLock> {
+    state.create().await?;
+
+    let lock_path = state.lock();
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .context("failed to open lock file")?;
+
+    match file.try_lock() {
+        Ok(()) => return Ok(ProjectLock { _file: file }),
+        Err(TryLockError::WouldBlock) => {}
+        Err(TryLockError::Error(error)) => {
+            return Err(error).context("failed to acquire lock");
+        }
+    }
+
+    if !wait {
+        eyre::bail!(
+            "another secret-sync run holds the lock \"{}\"",
+            lock_path.display()
+        );
+    }
+
+    tracing::info!("waiting for another secret-sync run to finish");
+
+    let file = tokio::task::spawn_blocking(move || file.lock().map(|_| file))
+        .await
+        .context("lock task failed")?
+        .context("failed to acquire lock")?;
+
+    Ok(ProjectLock { _file: file })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lock::acquire_project_lock, state::StatePaths};
+    use tempfile::TempDir;
+
+    /// Tests that a second lock cannot be acquired without waiting
+    /// while the first lock is held
+    #[tokio::test]
+    async fn test_lock_held() {
+        let dir = TempDir::new().unwrap();
+        let state = StatePaths::for_project(dir.path(), None);
+
+        let lock = acquire_project_lock(&state, false).await.unwrap();
+        assert!(acquire_project_lock(&state, false).await.is_err());
+
+        drop(lock);
+        acquire_project_lock(&state, false).await.unwrap();
+    }
+}
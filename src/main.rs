@@ -3,31 +3,137 @@
 #![warn(missing_docs)]
 
 use crate::{
-    config::{BackendProvider, Config, SecretFile, discover_nearest_config_file, read_config_file},
-    fs::real::RealFs,
-    pull::pull_secret_files,
-    push::push_secret_files,
-    secret::aws::AwsSecretManager,
+    agent::{
+        AGENT_SOCKET_ENV, AgentRequest, agent_request, agent_socket_path, load_agent_values,
+        run_agent,
+    },
+    compose::{find_compose_file, find_compose_references, select_compose_env},
+    deadline::{DEADLINE_GRACE, Progress, cancelled_error, deadline_error, parse_duration},
+    diff::{diff_file, diff_text},
+    env::{collect_env, write_env_out_link},
+    grant::grant_instructions,
+    init::{InitTemplate, init_config, init_file_paths, project_name},
+    last_run::{FileAction, LastRun, read_last_run, unix_timestamp, write_last_run},
+    lock::acquire_project_lock,
+    man::write_man_pages,
+    mount::{Mount, run_mount},
+    ping::{ping, ping_text},
+    render::{HumanRenderer, JsonRenderer, Output, Renderer, TomlRenderer, YamlRenderer},
+    resume::{ResumeState, batch_id, local_hash, remove_resume},
+    scan::{ScanHashes, list_scan_files, scan_files},
+    seed::{SeedOptions, seed_files},
+    serve::{ServeContext, ServeListener, serve, serve_token},
+    service::{
+        ServiceOptions, install_service, schtasks_args, systemd_service_unit, systemd_timer_unit,
+    },
+    state::StatePaths,
+    stats::config_stats,
+    status::{file_status, status_text},
+    task::{run_task_steps, task_steps},
+    user_config::{NamedContext, read_user_config, write_user_config},
+    version::VersionInfo,
 };
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use eyre::{Context, ContextCompat};
+use futures_util::TryStreamExt;
 use indexmap::IndexMap;
+use secret_sync::{
+    approval::{
+        DEFAULT_BUNDLE_FILE_NAME, approval_identity, approval_key, approve_bundle, create_bundle,
+        read_bundle_file,
+    },
+    arn::display_secret_name,
+    cancel::CancellationToken,
+    checks::check_file_values,
+    clock::SystemClock,
+    concurrency::Concurrency,
+    config::{
+        self, BackendProvider, Config, SecretFile, SecretMetadata, config_working_path,
+        discover_nearest_config_file, filter_files, find_duplicate_entries, read_config_file,
+        read_remote_config, resolve_config_path,
+    },
+    cost::{
+        CostEstimate, DEFAULT_PRICE_PER_10K_CALLS, DEFAULT_PRICE_PER_SECRET, Pricing, UsagePattern,
+    },
+    doctor::find_duplicate_values,
+    dotenv::{self, render_dotenv},
+    events::SyncEvent,
+    fs::{
+        FileSystem,
+        host::{FileHosts, HostFs},
+        real::RealFs,
+    },
+    keystore::pull_keystore_file,
+    migrate::{MigrationResult, migrate_secrets, migration_count, migration_text},
+    plan::{PlanAction, apply_plan, create_plan, read_plan_file},
+    promote::{apply_promotion, create_promotion},
+    pull::{
+        DEFAULT_DISCOVER_PATH, PullOptions, discover_files, pull_generated_file, pull_secret_files,
+        pull_secret_files_atomic,
+    },
+    push::{
+        PushInfo, PushOptions, check_secret_ownership, is_metadata_ignored, push_secret_files,
+        skip_pull_only, store_push_value,
+    },
+    reconcile::{
+        DEFAULT_STATE_FILE_NAME, export_state, read_state_file, reconcile, reconcile_text,
+    },
+    remote_lock::{acquire_remote_locks, release_remote_locks},
+    secret::{
+        self, ListSecretsOptions, Secret, SecretSummary, create_named_backend,
+        create_secret_manager,
+    },
+    shell::run_shell_hook,
+    template::pull_render_file,
+};
 use serde_json::json;
 use std::{
+    collections::{HashMap, HashSet},
     env::current_dir,
-    path::{PathBuf, absolute},
+    ffi::OsString,
+    io::{IsTerminal, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf, absolute},
+    time::{Duration, Instant},
 };
+use tokio::{io::AsyncReadExt, sync::mpsc::UnboundedReceiver};
 use tracing::level_filters::LevelFilter;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard, TraceStyle};
 use tracing_indicatif::IndicatifLayer;
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{
+    EnvFilter, Layer, filter::Targets, layer::SubscriberExt, util::SubscriberInitExt,
+};
 
-mod config;
-mod fs;
-mod pull;
-mod push;
-mod secret;
+mod agent;
+mod compose;
+mod deadline;
+mod diff;
+mod env;
+mod grant;
+mod init;
+mod last_run;
+mod lock;
+mod man;
+mod mount;
+mod ping;
+mod render;
+mod resume;
+mod scan;
+mod seed;
+mod serve;
+mod service;
+mod state;
+mod stats;
+mod status;
+mod task;
+mod user_config;
+mod version;
 
 /// The arguments for the CLI tool
+///
+/// Global flags may also be provided through their SECRET_SYNC_* environment
+/// variables, flags take priority over environment variables which take
+/// priority over the config file
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
@@ -38,28 +144,108 @@ struct Args {
     /// Optional custom path to the secret-sync.toml configuration file. By default
     /// secret-sync.toml (and secret-sync.json) is searched for in each parent
     /// directory until discovered
-    #[arg(short, long)]
+    #[arg(short, long, env = "SECRET_SYNC_CONFIG")]
     config: Option<PathBuf>,
 
+    /// Load the configuration from this secret instead of the config file,
+    /// using the backend settings of the config file (when found) to read it
+    #[arg(long, env = "SECRET_SYNC_CONFIG_SECRET")]
+    config_secret: Option<String>,
+
+    /// Directory that relative file paths are resolved against, defaults
+    /// to the directory containing the config file
+    #[arg(long, env = "SECRET_SYNC_WORKING_DIR")]
+    working_dir: Option<PathBuf>,
+
+    /// Directory to store the state that persists between runs in (e.g. a
+    /// tmpfs or CI workspace path), defaults to .secret-sync within the
+    /// working directory
+    #[arg(long, env = "SECRET_SYNC_STATE_DIR")]
+    state_dir: Option<PathBuf>,
+
     /// Output format to use when providing command output
-    #[arg(short, long, default_value = "human")]
+    #[arg(short, long, default_value = "human", env = "SECRET_SYNC_FORMAT")]
     format: OutputFormat,
 
     /// Disable color in the output
-    #[arg(short, long, default_value_t = false)]
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        env = "SECRET_SYNC_DISABLE_COLOR"
+    )]
     disable_color: bool,
 
     /// Override AWS profile to use the sdk with
-    #[arg(long)]
+    #[arg(long, env = "SECRET_SYNC_PROFILE")]
     profile: Option<String>,
 
     /// Optionally override the AWS region
-    #[arg(short, long)]
+    #[arg(short, long, env = "SECRET_SYNC_REGION")]
     region: Option<String>,
 
+    /// Named context to use instead of the active context
+    #[arg(long, env = "SECRET_SYNC_CONTEXT")]
+    context: Option<String>,
+
+    /// Environment replacing {env} within file paths and secret names,
+    /// defaults to the environment of the active context
+    #[arg(long = "env", env = "SECRET_SYNC_ENV")]
+    environment: Option<String>,
+
+    /// MFA token code to use when assuming a role that requires MFA
+    #[arg(long, env = "SECRET_SYNC_MFA_TOKEN", hide_env_values = true)]
+    mfa_token: Option<String>,
+
     /// Enable verbose logging output
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, default_value_t = false, env = "SECRET_SYNC_VERBOSE")]
     verbose: bool,
+
+    /// Wait for other secret-sync runs in the same project to finish
+    /// (Default)
+    #[arg(
+        long,
+        default_value_t = false,
+        overrides_with = "no_wait",
+        env = "SECRET_SYNC_WAIT"
+    )]
+    wait: bool,
+
+    /// Fail immediately if another secret-sync run in the same project
+    /// holds the lock
+    #[arg(
+        long,
+        default_value_t = false,
+        overrides_with = "wait",
+        env = "SECRET_SYNC_NO_WAIT"
+    )]
+    no_wait: bool,
+
+    /// Warn instead of failing when multiple files resolve to the same
+    /// path or share a secret with different paths
+    #[arg(long, default_value_t = false, env = "SECRET_SYNC_ALLOW_DUPLICATES")]
+    allow_duplicates: bool,
+
+    /// Overall time budget for the entire invocation (e.g. 60s, 5m), when
+    /// exceeded the in-flight pull or push is cancelled and the number of
+    /// files completed before the deadline is reported
+    #[arg(long, value_parser = parse_duration, env = "SECRET_SYNC_DEADLINE")]
+    deadline: Option<Duration>,
+
+    /// Fail instead of warning when the config file contains plaintext
+    /// credentials and is inside a git repository
+    #[arg(long, default_value_t = false, env = "SECRET_SYNC_STRICT")]
+    strict: bool,
+
+    /// Export a Chrome trace (viewable in Perfetto or chrome://tracing) of
+    /// every operation and backend call to this file
+    #[arg(long, env = "SECRET_SYNC_TRACE_FILE")]
+    trace_file: Option<PathBuf>,
+
+    /// Detect a local emulator (LocalStack, Loker) and use it with dummy
+    /// credentials, same as backend.dev_endpoint_autodetect in the config
+    #[arg(long, default_value_t = false, env = "SECRET_SYNC_DEV")]
+    dev: bool,
 }
 
 /// Output format to use when providing program output
@@ -70,6 +256,24 @@ enum OutputFormat {
 
     /// Provide output in machine readable JSON format
     Json,
+
+    /// Provide output in machine readable YAML format
+    Yaml,
+
+    /// Provide output in machine readable TOML format
+    Toml,
+}
+
+impl OutputFormat {
+    /// Get the renderer for the output format
+    fn renderer(&self) -> Box<dyn Renderer> {
+        match self {
+            OutputFormat::Human => Box::new(HumanRenderer),
+            OutputFormat::Json => Box::new(JsonRenderer),
+            OutputFormat::Yaml => Box::new(YamlRenderer),
+            OutputFormat::Toml => Box::new(TomlRenderer),
+        }
+    }
 }
 
 /// Filters for target secret folders
@@ -86,304 +290,3151 @@ struct TargetFilter {
     /// This argument  can be specified multiple times to target multiple globs
     #[arg(short, long)]
     glob: Option<Vec<String>>,
+
+    /// Optionally specify services to match the files of, the environment
+    /// of the service is used when --env is not provided
+    ///
+    /// This argument can be specified multiple times to target multiple services
+    #[arg(long)]
+    service: Option<Vec<String>>,
 }
 
 /// Sub commands for the cli tool
 #[derive(Subcommand)]
 enum Commands {
+    /// Create a secret-sync.toml for a new project, optionally from a
+    /// template with the conventional secret files of a common stack
+    ///
+    /// Secret names follow the "<project>/{env}/<file>" naming template,
+    /// the project defaults to the name of the working directory
+    #[command(
+        after_long_help = "Examples:\n  secret-sync init\n  secret-sync init --template rails --name shop\n  secret-sync init --template spring --provider gcp"
+    )]
+    Init {
+        /// Template of the stack to generate the files for
+        #[arg(long, value_enum)]
+        template: Option<InitTemplate>,
+
+        /// Name of the project used within the secret names
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Backend provider to use
+        #[arg(long, value_enum, default_value = "aws")]
+        provider: BackendProvider,
+
+        /// Replace an existing config file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
     /// Pull the current secrets, storing the secret values
     /// in their respective files
+    #[command(
+        after_long_help = "Examples:\n  secret-sync pull\n  secret-sync pull --file app --file worker\n  secret-sync pull --atomic --exec-on-change \"systemctl reload myapp\"\n  secret-sync pull --discover --tag team=payments\n  secret-sync pull --concurrency adaptive"
+    )]
     Pull {
         #[command(flatten)]
         filter: TargetFilter,
+
+        /// Command to run after pulling when the contents of at least
+        /// one file changed (e.g. "systemctl reload myapp")
+        #[arg(long)]
+        exec_on_change: Option<String>,
+
+        /// Fetch and stage every secret before replacing any files, leaving
+        /// the files untouched if fetching or writing any of them fails
+        ///
+        /// Generated, rendered, and keystore files cannot be staged, select
+        /// only the secret files using --file or --glob
+        #[arg(long, default_value_t = false)]
+        atomic: bool,
+
+        /// Pull the remote secrets matching the --tag filters instead of
+        /// the configured files, even if they are not in the config
+        #[arg(long, default_value_t = false, requires = "tags")]
+        discover: bool,
+
+        /// Tag to filter discovered secrets by in the form KEY=VALUE
+        ///
+        /// This argument can be specified multiple times, secrets must have
+        /// every tag to be discovered
+        #[arg(long = "tag", value_parser = parse_tag, requires = "discover")]
+        tags: Vec<(String, String)>,
+
+        /// Path template for discovered secrets relative to the config file,
+        /// {name} is replaced with the secret name
+        #[arg(long, default_value = DEFAULT_DISCOVER_PATH, requires = "discover")]
+        discover_path: String,
+
+        /// Process every file instead of skipping the files completed by
+        /// an interrupted run of the same pull
+        #[arg(long, default_value_t = false)]
+        no_resume: bool,
+
+        /// Number of secrets to fetch at once, or "adaptive" to ramp up
+        /// until the backend throttles requests or slows down
+        #[arg(long, default_value_t = Concurrency::default(), env = "SECRET_SYNC_CONCURRENCY")]
+        concurrency: Concurrency,
     },
 
     /// Push a secret file updating its value in the
     /// secret manage
+    #[command(
+        after_long_help = "Examples:\n  secret-sync push\n  secret-sync push --file app --keys DATABASE_URL\n  secret-sync push --strict-checks\n  secret-sync push --request-approval\n  secret-sync push --file legacy --adopt"
+    )]
     Push {
         #[command(flatten)]
         filter: TargetFilter,
+
+        /// Instead of pushing, write a signed change request bundle that
+        /// must be applied by another user using the approve subcommand
+        ///
+        /// Signed using the private key from SECRET_SYNC_APPROVAL_KEY, which
+        /// must be registered within approval.signers for the caller identity
+        /// of the backend credentials
+        #[arg(long, num_args = 0..=1, default_missing_value = DEFAULT_BUNDLE_FILE_NAME)]
+        request_approval: Option<PathBuf>,
+
+        /// Acquire a lock within the backend for each secret while pushing,
+        /// enabled by default when remote_lock.enabled is set in the config
+        #[arg(long, default_value_t = false)]
+        remote_lock: bool,
+
+        /// Take over remote locks held by someone else
+        #[arg(long, default_value_t = false)]
+        steal_lock: bool,
+
+        /// Only update these keys within the remote structured secret,
+        /// the other keys of the secret are left untouched
+        ///
+        /// This argument can be specified multiple times to update multiple keys
+        #[arg(long = "keys", conflicts_with = "request_approval")]
+        keys: Option<Vec<String>>,
+
+        /// Warn about values that look like placeholders (e.g. "changeme"),
+        /// are empty, or are secrets with very low entropy
+        #[arg(long, default_value_t = false)]
+        check_values: bool,
+
+        /// Refuse to push when any of the value checks fail, implies
+        /// --check-values
+        #[arg(long, default_value_t = false)]
+        strict_checks: bool,
+
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+
+        /// Process every file instead of skipping the files completed by
+        /// an interrupted run of the same push
+        #[arg(long, default_value_t = false)]
+        no_resume: bool,
+
+        /// Refuse to push when more than this number of secrets would be
+        /// written, takes priority over push.max_files in the config
+        #[arg(long)]
+        max_files: Option<usize>,
+
+        /// Push without prompting for confirmation when more secrets than
+        /// push.confirm_threshold would be written, required when not
+        /// running in a terminal
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
     },
 
-    /// Perform a quick pull without a configuration file
+    /// Verify and apply a change request bundle created using
+    /// push --request-approval
     ///
-    /// A configuration file is not required for this subcommand
-    /// but will be respected if provided or found.
-    QuickPull {
-        /// Path to the file to pull the secret into
-        #[arg(short, long)]
-        path: PathBuf,
+    /// The bundle must be approved using backend credentials of a different
+    /// identity (e.g. AWS caller ARN) than the ones that requested it, and is
+    /// verified using the key registered for the requester in
+    /// approval.signers
+    #[command(after_long_help = "Examples:\n  secret-sync approve change-request.json")]
+    Approve {
+        /// Path to the change request bundle
+        bundle: PathBuf,
 
-        /// Secret to pull from
-        #[arg(short, long)]
-        secret: String,
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
     },
 
-    /// Perform a quick push without requiring a configuration file
+    /// Copy secret values from one environment to another
     ///
-    /// A configuration file is not required for this subcommand
-    /// but will be respected if provided or found.
-    QuickPush {
-        /// Path to the file to pull the secret into
-        #[arg(short, long)]
-        path: PathBuf,
+    /// Files using {env} in their secret name are copied from the secret
+    /// for --from into the secret for --to. A diff of the changed keys
+    /// (without values) is shown and must be confirmed before promoting
+    #[command(
+        after_long_help = "Examples:\n  secret-sync promote --from staging --to production\n  secret-sync promote --from staging --to production --file app --yes"
+    )]
+    Promote {
+        #[command(flatten)]
+        filter: TargetFilter,
 
-        /// Secret to pull from
-        #[arg(short, long)]
-        secret: String,
-    },
-}
+        /// Environment to copy the secret values from
+        #[arg(long)]
+        from: String,
 
-/// Output data for a successful run
-struct Output {
-    /// Text version
-    text: String,
-    /// JSON version
-    json: serde_json::Value,
-}
+        /// Environment to copy the secret values to
+        #[arg(long)]
+        to: String,
 
-/// Main app entrypoint, handles ensuring the [app] return type
-/// matches the requested output format
-#[tokio::main]
-async fn main() -> eyre::Result<()> {
-    let args = Args::parse();
-    let format = args.format.clone();
+        /// Promote without prompting for confirmation, required when
+        /// not running in a terminal
+        #[arg(short, long, default_value_t = false)]
+        yes: bool,
 
-    match app(args).await {
-        Ok(output) => match format {
-            OutputFormat::Human => {
-                println!("{}", output.text);
-            }
-            OutputFormat::Json => {
-                println!("{}", serde_json::to_string_pretty(&output.json)?);
-            }
-        },
-        Err(error) => match format {
-            OutputFormat::Human => {
-                return Err(error);
-            }
-            OutputFormat::Json => {
-                tracing::error!(?error, "error occurred");
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+    },
 
-                println!(
-                    "{}",
-                    serde_json::to_string(&json!({
-                        "success": false,
-                        "error": error.to_string()
-                    }))?
-                );
+    /// Copy the secrets of the configured files from one backend to another
+    ///
+    /// Backends are the names of [backends.<name>] tables or "default"
+    /// for the top-level backend. Values are copied along with their
+    /// description and tags, and the result of each secret is reported
+    #[command(
+        after_long_help = "Examples:\n  secret-sync migrate --from vault --to default --dry-run\n  secret-sync migrate --from vault --to aws --file app"
+    )]
+    Migrate {
+        #[command(flatten)]
+        filter: TargetFilter,
 
-                return Err(error);
-            }
-        },
-    }
+        /// Backend to copy the secrets from
+        #[arg(long)]
+        from: String,
 
-    Ok(())
-}
+        /// Backend to copy the secrets to
+        #[arg(long)]
+        to: String,
 
-/// Initialize the logging and indicator layers
-fn init_logging(verbose: bool) -> eyre::Result<()> {
-    let indicatif_layer = IndicatifLayer::new();
+        /// Only report the secrets that would be copied
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
 
-    let env_filter = if verbose {
-        EnvFilter::builder()
-            .with_default_directive(LevelFilter::DEBUG.into())
-            .from_env_lossy()
-    } else {
-        EnvFilter::from_default_env()
-            // Provide logging from secret-sync by default
-            .add_directive("secret_sync=info".parse()?)
-            //
-            .add_directive("aws_sdk_secretsmanager=info".parse()?)
-            .add_directive("aws_runtime=info".parse()?)
-            .add_directive("aws_smithy_runtime=info".parse()?)
-            .add_directive("hyper_util=info".parse()?)
-    };
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+    },
 
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_line_number(false)
-                .with_target(false)
-                .with_file(false)
-                .with_writer(indicatif_layer.get_stderr_writer()),
-        )
-        .with(indicatif_layer)
-        .init();
+    /// Show a unified diff between the local files and the remote secrets
+    ///
+    /// Remote values are shown in the form a pull would write them, use
+    /// --redact to replace the values with a short hash of each value
+    #[command(
+        after_long_help = "Examples:\n  secret-sync diff\n  secret-sync diff --file app --redact"
+    )]
+    Diff {
+        #[command(flatten)]
+        filter: TargetFilter,
 
-    Ok(())
-}
+        /// Replace the values within the diff with a hash of the value
+        #[arg(long, default_value_t = false)]
+        redact: bool,
+    },
 
-/// Main logic entrypoint
-async fn app(args: Args) -> eyre::Result<Output> {
-    if !args.disable_color {
-        // Setup colorful error logging
-        color_eyre::install()?;
-    }
+    /// Report whether each file is in sync with its secret without
+    /// writing anything
+    ///
+    /// Files whose value differs report which side was modified last,
+    /// based on the local modification time and the last change of the
+    /// secret. Use --format json for the status of each file
+    #[command(
+        after_long_help = "Examples:\n  secret-sync status\n  secret-sync --format json status --service api"
+    )]
+    Status {
+        #[command(flatten)]
+        filter: TargetFilter,
+    },
+
+    /// Create a plan describing the actions a push would perform
+    /// for each secret file
+    ///
+    /// The plan can be saved using --out and later applied exactly
+    /// using the apply subcommand
+    #[command(
+        after_long_help = "Examples:\n  secret-sync plan\n  secret-sync plan --out plan.json"
+    )]
+    Plan {
+        #[command(flatten)]
+        filter: TargetFilter,
 
-    init_logging(args.verbose)?;
+        /// Optional path to save the plan to as JSON
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
 
-    let (config_path, working_path, mut config) = match &args.command {
-        Commands::Pull { .. } | Commands::Push { .. } => {
-            let config_path = match args.config {
-                Some(value) => value,
-                None => discover_nearest_config_file().await?,
-            };
+    /// Apply a plan previously created using the plan subcommand
+    ///
+    /// Fails without modifying any secrets if the local files or
+    /// remote secrets have changed since the plan was created
+    #[command(after_long_help = "Examples:\n  secret-sync apply plan.json")]
+    Apply {
+        /// Path to the plan file
+        plan: PathBuf,
 
-            let config_path =
-                absolute(config_path).context("failed to get absolute config path")?;
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+    },
 
-            tracing::debug!(?config_path, "found config file");
+    /// Reconcile the remote secrets to match a desired state file
+    ///
+    /// The desired state file records secret names, value hashes, and
+    /// metadata without storing the values. Local files must match the
+    /// desired hashes, matching values are pushed and secrets marked as
+    /// absent are deleted
+    #[command(
+        after_long_help = "Examples:\n  secret-sync reconcile --dry-run\n  secret-sync reconcile --state secrets.state.json"
+    )]
+    Reconcile {
+        /// Path to the desired state file, defaults to secrets.state.json
+        /// relative to the config file
+        #[arg(short, long)]
+        state: Option<PathBuf>,
 
-            let working_path = config_path
-                .parent()
-                .context("missing config parent path unable to use directory for context")?
-                .to_path_buf();
+        /// Only report the actions that would be taken
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
 
-            tracing::debug!(?working_path, "working path");
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, allowing them to be updated or deleted
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+    },
 
-            let config = read_config_file(&config_path).await?;
-            (config_path, working_path, config)
-        }
-        Commands::QuickPull { .. } | Commands::QuickPush { .. } => {
-            let current_path = current_dir().context("failed to determine current directory")?;
+    /// Check the local secret files and backend credentials for common
+    /// problems
+    ///
+    /// Reports values that appear in multiple files or keys, which usually
+    /// indicates copy-pasted credentials that should be consolidated, along
+    /// with where the backend credentials were resolved from and common
+    /// misconfigurations on EC2, ECS, and EKS
+    #[command(after_long_help = "Examples:\n  secret-sync doctor")]
+    Doctor {
+        #[command(flatten)]
+        filter: TargetFilter,
+    },
 
-            let config_path = match args.config {
-                Some(value) => Some(value),
-                None => discover_nearest_config_file().await.ok(),
-            };
+    /// Grant someone without access to the backend temporary read access
+    /// to the secret of a file
+    ///
+    /// Mints credentials that can only read the one secret and expire
+    /// after --ttl, printing the instructions for fetching the secret.
+    /// On AWS the session of aws.role_arn is scoped down when set,
+    /// otherwise a federation token is requested (requires IAM user
+    /// credentials)
+    #[command(
+        after_long_help = "Examples:\n  secret-sync grant --file db-creds\n  secret-sync grant --file db-creds --ttl 30m"
+    )]
+    Grant {
+        /// Name of the file whose secret may be read
+        #[arg(long)]
+        file: String,
 
-            let config = match &config_path {
-                Some(path) => read_config_file(path).await?,
-                None => Config::default(),
-            };
+        /// How long the credentials are valid for (at least 15m)
+        #[arg(long, value_parser = parse_duration, default_value = "1h")]
+        ttl: Duration,
+    },
 
-            (
-                config_path.unwrap_or(current_path.clone()),
-                current_path,
-                config,
-            )
-        }
-    };
+    /// Create a secret for each file within a directory, for migrating an
+    /// existing set of secret files into the backend
+    ///
+    /// Writes are throttled to --rate secrets per second and progress is
+    /// recorded in .secret-sync/seed.json so an interrupted seed can be
+    /// re-run, secrets that already exist are never overwritten unless they
+    /// were created by a previous seed
+    #[command(
+        after_long_help = "Examples:\n  secret-sync seed --from-dir ./secrets\n  secret-sync --env prod seed --from-dir ./secrets --name \"{env}/{path}\" --rate 2"
+    )]
+    Seed {
+        /// Directory containing the files to create secrets from
+        #[arg(long)]
+        from_dir: PathBuf,
 
-    if let Some(profile) = args.profile {
-        config.aws.profile = Some(profile);
-    }
+        /// Template for the secret names, {path} is replaced with the
+        /// relative path of the file without its extension, {file} with
+        /// the file name, and {env} with the environment
+        #[arg(long, default_value = "{path}")]
+        name: String,
 
-    if let Some(region) = args.region {
-        config.aws.region = Some(region);
-    }
+        /// Maximum number of secrets to create per second
+        #[arg(long, default_value_t = 5)]
+        rate: u32,
 
-    let secret = match config.backend.provider {
-        BackendProvider::Aws => Box::new(AwsSecretManager::from_config(&config.aws).await?),
-    };
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+    },
 
-    let fs = RealFs;
+    /// Scan the working tree for files containing the current secret
+    /// values
+    ///
+    /// Files ignored by git are skipped. Values are compared by hash of
+    /// each line and token, the secret files themselves are not scanned
+    #[command(
+        after_long_help = "Examples:\n  secret-sync scan\n  secret-sync scan --glob \".env.*\""
+    )]
+    Scan {
+        #[command(flatten)]
+        filter: TargetFilter,
+    },
 
-    match args.command {
-        Commands::Pull { filter } => {
-            let files = filter_files(&config.files, &filter);
+    /// Show the summary of the last pull or push, including the action
+    /// taken for each file, durations, and errors
+    #[command(after_long_help = "Examples:\n  secret-sync last\n  secret-sync --format json last")]
+    Last,
 
-            if files.is_empty() && !config.files.is_empty() {
-                eyre::bail!(
-                    "no files matching filter within \"{}\"",
-                    config_path.display()
-                )
-            }
+    /// Summarize the config as anonymous statistics (entry counts, backend,
+    /// formats, local file sizes, and the age of the last run)
+    ///
+    /// Computed locally without contacting the backend and never sent
+    /// anywhere, secret names and file paths are not included so the
+    /// output can be aggregated across repositories
+    #[command(
+        after_long_help = "Examples:\n  secret-sync --format json stats\n  secret-sync --env production stats"
+    )]
+    Stats,
 
-            let total_files = files.len();
-            pull_secret_files(&fs, secret.as_ref(), &working_path, files).await?;
+    /// Write a desired state file from the current local files for use
+    /// with the reconcile subcommand
+    #[command(
+        after_long_help = "Examples:\n  secret-sync export-state\n  secret-sync export-state --state secrets.state.json"
+    )]
+    ExportState {
+        /// Path to the desired state file, defaults to secrets.state.json
+        /// relative to the config file
+        #[arg(short, long)]
+        state: Option<PathBuf>,
+    },
 
-            Ok(Output {
-                text: format!("successfully pulled {} secret file(s)", total_files),
-                json: json!({ "success": true }),
-            })
-        }
+    /// Output the variables from dotenv or JSON formatted secrets as a
+    /// single flat set of environment variables
+    ///
+    /// Use --format json for a flat JSON object suitable for tools such
+    /// as Nix (builtins.fromJSON)
+    #[command(
+        after_long_help = "Examples:\n  secret-sync env\n  secret-sync --format json env --out-link result.json"
+    )]
+    Env {
+        #[command(flatten)]
+        filter: TargetFilter,
 
-        Commands::Push { filter } => {
-            let files = filter_files(&config.files, &filter);
+        /// Stable path to additionally write the JSON object to, the
+        /// file is replaced atomically
+        #[arg(long)]
+        out_link: Option<PathBuf>,
+    },
 
-            if files.is_empty() && !config.files.is_empty() {
-                eyre::bail!(
-                    "no files matching filter within \"{}\"",
-                    config_path.display()
-                )
-            }
+    /// Write a .env next to a docker-compose file containing only the
+    /// variables the compose file references
+    ///
+    /// Variables are resolved from dotenv or JSON formatted secrets,
+    /// references without a matching variable are reported as warnings
+    #[command(
+        after_long_help = "Examples:\n  secret-sync compose-env\n  secret-sync compose-env deploy/compose.yaml --out deploy/.env"
+    )]
+    ComposeEnv {
+        #[command(flatten)]
+        filter: TargetFilter,
 
-            let total_files = files.len();
-            push_secret_files(&fs, secret.as_ref(), &working_path, files).await?;
+        /// Path to the compose file, defaults to the compose file within
+        /// the config directory
+        compose: Option<PathBuf>,
 
-            Ok(Output {
-                text: format!("successfully pushed {} secret file(s)", total_files),
-                json: json!({ "success": true }),
-            })
-        }
+        /// Path to write to, defaults to .env next to the compose file
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
 
-        Commands::QuickPull {
-            path,
-            secret: secret_value,
-        } => {
-            let file = SecretFile {
-                secret: secret_value,
-                path,
-                metadata: Default::default(),
-            };
+    /// Serve pull, push, and status operations over a small authenticated
+    /// HTTP API for sidecars and internal tools
+    ///
+    /// Clients must provide the token from SECRET_SYNC_SERVE_TOKEN as a
+    /// bearer token
+    #[command(
+        after_long_help = "Examples:\n  secret-sync serve\n  secret-sync serve --socket /run/secret-sync.sock"
+    )]
+    Serve {
+        /// Loopback address to listen on
+        #[arg(long, default_value = "127.0.0.1:7420")]
+        listen: SocketAddr,
 
-            pull_secret_files(&fs, secret.as_ref(), &working_path, [&file]).await?;
+        /// Unix socket path to listen on instead of the TCP address
+        #[arg(long, conflicts_with = "listen")]
+        socket: Option<PathBuf>,
+    },
 
-            Ok(Output {
-                text: "successfully pulled 1 secret file(s)".to_string(),
-                json: json!({ "success": true }),
-            })
-        }
+    /// Run or query an agent that holds pulled secrets in memory and
+    /// serves them to local processes over a unix socket
+    #[command(
+        after_long_help = "Examples:\n  secret-sync agent start &\n  secret-sync agent get app"
+    )]
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommand,
+    },
 
-        Commands::QuickPush {
-            path,
-            secret: secret_value,
-        } => {
-            let file = SecretFile {
-                secret: secret_value,
-                path,
-                metadata: Default::default(),
-            };
+    /// Pull secrets into a directory as one file per secret, keeping them
+    /// refreshed until stopped and removing the directory on exit
+    ///
+    /// Files are named after the file in the config and held in memory on
+    /// a tmpfs where the platform supports it, allowing docker-compose
+    /// services to bind mount secrets for local development
+    #[command(
+        after_long_help = "Examples:\n  secret-sync mount ./secrets\n  secret-sync mount ./secrets --file db --interval 30s"
+    )]
+    Mount {
+        /// Directory to mount the secrets at, must not exist or be empty
+        dir: PathBuf,
 
-            push_secret_files(&fs, secret.as_ref(), &working_path, [&file]).await?;
+        #[command(flatten)]
+        filter: TargetFilter,
 
-            Ok(Output {
-                text: "successfully pushed 1 secret file(s)".to_string(),
-                json: json!({ "success": true }),
-            })
-        }
-    }
-}
+        /// Time between refreshes of the mounted secrets (e.g. 30s, 5m)
+        #[arg(long, value_parser = parse_duration, default_value = "1m")]
+        interval: Duration,
+    },
 
-/// Filter a set of `files` only returning the results that match `filter`
-fn filter_files<'a>(
-    files: &'a IndexMap<String, SecretFile>,
-    filter: &TargetFilter,
-) -> Vec<&'a SecretFile> {
-    files
-        .iter()
-        .filter(|(name, _file)| {
-            // Nothing to filter against
-            if filter.file.is_none() && filter.glob.is_none() {
-                return true;
-            }
+    /// Manage named contexts stored in the user config
+    ///
+    /// Contexts are named combinations of backend, profile, region, and
+    /// environment applied to commands while active
+    #[command(
+        after_long_help = "Examples:\n  secret-sync context set staging --region us-east-1 --env staging\n  secret-sync context use staging"
+    )]
+    Context {
+        #[command(subcommand)]
+        command: ContextCommand,
+    },
 
-            let name_matches = filter
-                .file
-                .as_ref()
-                .is_some_and(|file_names| file_names.contains(name));
+    /// Install a scheduled pull that keeps the secret files of the project
+    /// up to date in the background
+    #[command(
+        after_long_help = "Examples:\n  sudo secret-sync service install --interval 15m\n  secret-sync service install --user --name app-secrets\n  secret-sync service install --print"
+    )]
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommand,
+    },
 
-            let glob_matches = filter.glob.as_ref().is_some_and(|globs| {
-                globs
-                    .iter()
-                    .any(|glob| fast_glob::glob_match(glob.as_bytes(), name.as_bytes()))
-            });
+    /// Manage the project config file
+    #[command(after_long_help = "Examples:\n  secret-sync config scrub")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 
-            name_matches || glob_matches
-        })
-        .map(|(_key, value)| value)
-        .collect()
+    /// Run a named task from the [tasks] section of the config
+    ///
+    /// Each step runs in order as a separate secret-sync invocation using
+    /// the same config and global flags, steps in the form
+    /// "exec -- <command>" run the command through the system shell
+    #[command(
+        after_long_help = "Examples:\n  secret-sync run deploy\n  secret-sync --env staging run deploy"
+    )]
+    Run {
+        /// Name of the task to run
+        task: String,
+    },
+
+    /// Generate man pages for secret-sync and each of its subcommands
+    ///
+    /// Pages are named in the form secret-sync-<subcommand>.1 so they can
+    /// be installed into a man directory for offline use
+    #[command(
+        after_long_help = "Examples:\n  secret-sync man\n  secret-sync man --out-dir /usr/local/share/man/man1"
+    )]
+    Man {
+        /// Directory to write the man pages to
+        #[arg(short, long, default_value = "man")]
+        out_dir: PathBuf,
+    },
+
+    /// Show build information such as the version, git commit,
+    /// build date, and enabled backends
+    #[command(after_long_help = "Examples:\n  secret-sync version\n  secret-sync version --json")]
+    Version {
+        /// Output the build information as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// List the secrets within the secret manager
+    ///
+    /// A configuration file is not required for this subcommand
+    /// but will be respected if provided or found.
+    #[command(
+        after_long_help = "Examples:\n  secret-sync list\n  secret-sync list --tag team=payments"
+    )]
+    List {
+        /// Tag to filter secrets by in the form KEY=VALUE
+        ///
+        /// This argument can be specified multiple times, secrets must have
+        /// every tag to be listed
+        #[arg(long = "tag", value_parser = parse_tag)]
+        tags: Vec<(String, String)>,
+
+        /// Number of secrets to request from the secret manager at a time
+        #[arg(long)]
+        page_size: Option<i32>,
+    },
+
+    /// Check the configured backend is reachable and the credentials are
+    /// valid, reporting the latency of each check
+    ///
+    /// Fails when any of the checks fail. A configuration file is not
+    /// required for this subcommand but will be respected if provided or found.
+    #[command(after_long_help = "Examples:\n  secret-sync ping\n  secret-sync --format json ping")]
+    Ping,
+
+    /// Describe the metadata of a secret without accessing its value
+    ///
+    /// A configuration file is not required for this subcommand
+    /// but will be respected if provided or found.
+    #[command(after_long_help = "Examples:\n  secret-sync describe app/production")]
+    Describe {
+        /// Name of the secret
+        secret: String,
+    },
+
+    /// Estimate the monthly cost of the secrets in AWS Secrets Manager
+    ///
+    /// Counts the unique secrets of the configured files (or every secret
+    /// in the backend with --all) along with the API calls made by pulls,
+    /// and the cost if they were consolidated into a single secret.
+    ///
+    /// A configuration file is not required for this subcommand
+    /// but will be respected if provided or found.
+    #[command(
+        after_long_help = "Examples:\n  secret-sync cost\n  secret-sync cost --pulls-per-day 24 --machines 10\n  secret-sync cost --all"
+    )]
+    Cost {
+        /// Count every secret within the backend instead of the secrets
+        /// of the configured files
+        #[arg(long, default_value_t = false)]
+        all: bool,
+
+        /// Number of pulls per day on each machine (e.g. 24 for an hourly
+        /// scheduled pull)
+        #[arg(long, default_value_t = 1)]
+        pulls_per_day: u64,
+
+        /// Number of machines pulling the secrets
+        #[arg(long, default_value_t = 1)]
+        machines: u64,
+
+        /// Price of storing a secret for a month in USD
+        #[arg(long, default_value_t = DEFAULT_PRICE_PER_SECRET)]
+        price_per_secret: f64,
+
+        /// Price of 10,000 API calls in USD
+        #[arg(long, default_value_t = DEFAULT_PRICE_PER_10K_CALLS)]
+        price_per_10k_calls: f64,
+    },
+
+    /// Perform a quick pull without a configuration file
+    ///
+    /// A configuration file is not required for this subcommand
+    /// but will be respected if provided or found.
+    #[command(
+        after_long_help = "Examples:\n  secret-sync quick-pull --path .env --secret app/production\n  secret-sync quick-pull --stdout --secret app/signing-key > key.der"
+    )]
+    QuickPull {
+        /// Path to the file to pull the secret into
+        #[arg(short, long, required_unless_present = "stdout")]
+        path: Option<PathBuf>,
+
+        /// Secret to pull from
+        #[arg(short, long)]
+        secret: String,
+
+        /// Write the raw secret value to stdout instead of a file, the
+        /// value is written exactly as stored without a trailing newline
+        #[arg(long, conflicts_with = "path")]
+        stdout: bool,
+    },
+
+    /// Perform a quick push without requiring a configuration file
+    ///
+    /// A configuration file is not required for this subcommand
+    /// but will be respected if provided or found.
+    #[command(
+        after_long_help = "Examples:\n  secret-sync quick-push --path .env --secret app/production\n  secret-sync quick-push --stdin --binary --secret app/signing-key < key.der"
+    )]
+    QuickPush {
+        /// Path to the file to push the secret from
+        #[arg(short, long, required_unless_present = "stdin")]
+        path: Option<PathBuf>,
+
+        /// Secret to push to
+        #[arg(short, long)]
+        secret: String,
+
+        /// Read the secret value from stdin instead of a file
+        #[arg(long, conflicts_with = "path")]
+        stdin: bool,
+
+        /// Store the value as binary, skipping the detection of UTF-8
+        /// values that would otherwise be stored as strings
+        #[arg(long)]
+        binary: bool,
+
+        /// Take ownership of existing secrets that were not created by
+        /// secret-sync, tagging them with managed-by=secret-sync
+        #[arg(long, default_value_t = false)]
+        adopt: bool,
+    },
+}
+
+impl Commands {
+    /// Filter for the targeted files of the command, when it has one
+    fn filter(&self) -> Option<&TargetFilter> {
+        match self {
+            Commands::Pull { filter, .. }
+            | Commands::Push { filter, .. }
+            | Commands::Promote { filter, .. }
+            | Commands::Migrate { filter, .. }
+            | Commands::Diff { filter, .. }
+            | Commands::Status { filter }
+            | Commands::Plan { filter, .. }
+            | Commands::Doctor { filter }
+            | Commands::Scan { filter, .. }
+            | Commands::Env { filter, .. }
+            | Commands::ComposeEnv { filter, .. }
+            | Commands::Mount { filter, .. }
+            | Commands::Agent {
+                command: AgentCommand::Start { filter, .. },
+            } => Some(filter),
+            _ => None,
+        }
+    }
+}
+
+/// Sub commands for the secret agent
+#[derive(Subcommand)]
+enum AgentCommand {
+    /// Pull the secrets into memory and serve them until interrupted
+    ///
+    /// Only processes running as the same user may connect
+    #[command(
+        after_long_help = "Examples:\n  secret-sync agent start &\n  secret-sync agent start --socket /tmp/agent.sock"
+    )]
+    Start {
+        #[command(flatten)]
+        filter: TargetFilter,
+
+        /// Socket path to listen on, defaults to .secret-sync/agent.sock
+        /// relative to the config file
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// Get the value of a file from a running agent
+    #[command(after_long_help = "Examples:\n  secret-sync agent get app")]
+    Get {
+        /// Name of the file within the config
+        file: String,
+
+        /// Socket path of the agent, defaults to SECRET_SYNC_AGENT_SOCK
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// List the files held by a running agent
+    #[command(after_long_help = "Examples:\n  secret-sync agent list")]
+    List {
+        /// Socket path of the agent, defaults to SECRET_SYNC_AGENT_SOCK
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+}
+
+/// Sub commands for managing the scheduled pull service
+#[derive(Subcommand)]
+enum ServiceCommand {
+    /// Register a pull of the config on an interval, as a hardened systemd
+    /// service and timer on Linux or a scheduled task on Windows
+    ///
+    /// The global --env, --profile, --region, --context, and --working-dir
+    /// flags are passed on to the scheduled pull
+    #[command(
+        after_long_help = "Examples:\n  sudo secret-sync service install --interval 15m\n  secret-sync service install --user"
+    )]
+    Install {
+        /// Name of the systemd units or scheduled task
+        #[arg(long, default_value = "secret-sync")]
+        name: String,
+
+        /// Time between pulls (e.g. 15m, 1h)
+        #[arg(long, value_parser = parse_duration, default_value = "15m")]
+        interval: Duration,
+
+        /// Install a systemd user service instead of a system service
+        #[arg(long, default_value_t = false)]
+        user: bool,
+
+        /// Print the generated units instead of installing them
+        #[arg(long, default_value_t = false)]
+        print: bool,
+    },
+}
+
+/// Sub commands for managing the project config file
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Move the aws.credentials out of the project config file and into
+    /// the user config so they are not committed alongside the project
+    #[command(
+        after_long_help = "Examples:\n  secret-sync config scrub\n  secret-sync config scrub --force"
+    )]
+    Scrub {
+        /// Replace credentials already stored in the user config for
+        /// this config file
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+/// Sub commands for managing named contexts
+#[derive(Subcommand)]
+enum ContextCommand {
+    /// List the available contexts
+    #[command(after_long_help = "Examples:\n  secret-sync context list")]
+    List,
+
+    /// Set the active context
+    #[command(after_long_help = "Examples:\n  secret-sync context use production")]
+    Use {
+        /// Name of the context
+        name: String,
+    },
+
+    /// Show the settings of a context, defaults to the active context
+    #[command(
+        after_long_help = "Examples:\n  secret-sync context show\n  secret-sync context show staging"
+    )]
+    Show {
+        /// Name of the context
+        name: Option<String>,
+    },
+
+    /// Create or update a context
+    #[command(
+        after_long_help = "Examples:\n  secret-sync context set staging --profile staging --region us-east-1"
+    )]
+    Set {
+        /// Name of the context
+        name: String,
+
+        /// Backend provider to use
+        #[arg(long)]
+        provider: Option<BackendProvider>,
+
+        /// AWS profile to use
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// AWS region to use
+        #[arg(long)]
+        region: Option<String>,
+
+        /// Environment to use
+        #[arg(long)]
+        environment: Option<String>,
+    },
+}
+
+/// Main app entrypoint, handles ensuring the [app] return type
+/// matches the requested output format
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    let format = match &args.command {
+        Commands::Version { json: true } => OutputFormat::Json,
+        _ => args.format.clone(),
+    };
+
+    let deadline = args.deadline;
+    let progress = Progress::default();
+
+    let summary_command = match &args.command {
+        Commands::Pull { .. } => Some("pull"),
+        Commands::Push { .. } => Some("push"),
+        _ => None,
+    };
+    // Raw secret output must not be followed by the rendered output
+    let raw_output = matches!(&args.command, Commands::QuickPull { stdout: true, .. });
+    let started_at = unix_timestamp();
+    let started = Instant::now();
+
+    // Cancelled once the deadline is exceeded, stopping the in-flight batch
+    let cancel = CancellationToken::new();
+    let app = app(args, progress.clone(), cancel.clone());
+
+    let result = match deadline {
+        Some(deadline) => {
+            let mut app = std::pin::pin!(app);
+            tokio::select! {
+                result = &mut app => result,
+                _ = tokio::time::sleep(deadline) => {
+                    cancel.cancel();
+
+                    // Batches stop gracefully reporting the files they completed,
+                    // other commands are dropped if they do not finish in time
+                    tokio::time::timeout(DEADLINE_GRACE, app)
+                        .await
+                        .unwrap_or_else(|_| Err(deadline_error(deadline)))
+                }
+            }
+        }
+        None => app.await,
+    };
+
+    if let Some(command) = summary_command
+        && let Some(path) = progress.last_run_path()
+    {
+        let run = LastRun {
+            command: command.to_string(),
+            started_at,
+            duration_ms: started.elapsed().as_millis() as u64,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|error| error.to_string()),
+            files: progress.files(),
+            warnings: progress.warnings(),
+        };
+
+        if let Err(error) = write_last_run(&path, &run).await {
+            tracing::warn!(?error, "failed to write last run summary");
+        }
+    }
+
+    let renderer = format.renderer();
+    let warnings = progress.warnings();
+
+    match result {
+        Ok(_) if raw_output => {
+            for warning in &warnings {
+                eprintln!("warning: {warning}");
+            }
+        }
+        Ok(output) => {
+            let output = output.with_warnings(&warnings);
+            println!("{}", renderer.render(&output)?);
+        }
+        Err(error) => {
+            match renderer.render_error(&error, &warnings)? {
+                Some(value) => {
+                    tracing::error!(?error, "error occurred");
+                    println!("{value}");
+                }
+                None => {
+                    for warning in &warnings {
+                        eprintln!("warning: {warning}");
+                    }
+                }
+            }
+
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Initialize the logging and indicator layers
+fn init_logging(verbose: bool, trace_file: Option<&Path>) -> eyre::Result<Option<FlushGuard>> {
+    let indicatif_layer = IndicatifLayer::new();
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_line_number(false)
+        .with_target(false)
+        .with_file(false)
+        .with_writer(indicatif_layer.get_stderr_writer());
+
+    // Spans are exported at debug level regardless of the output filter so
+    // traces include every backend call and the SDK request attempts
+    let (trace_layer, guard) = match trace_file {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("failed to create trace file \"{}\"", path.display()))?;
+
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .writer(file)
+                .include_args(true)
+                .trace_style(TraceStyle::Async)
+                .build();
+
+            let targets = Targets::new()
+                .with_target("secret_sync", LevelFilter::DEBUG)
+                .with_target("aws_sdk_secretsmanager", LevelFilter::DEBUG)
+                .with_target("aws_smithy_runtime", LevelFilter::DEBUG);
+
+            (Some(layer.with_filter(targets)), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(log_filter(verbose)?))
+        .with(indicatif_layer.with_filter(log_filter(verbose)?))
+        .with(trace_layer)
+        .init();
+
+    Ok(guard)
+}
+
+/// Create the filter for the logging output
+fn log_filter(verbose: bool) -> eyre::Result<EnvFilter> {
+    if verbose {
+        return Ok(EnvFilter::builder()
+            .with_default_directive(LevelFilter::DEBUG.into())
+            .from_env_lossy());
+    }
+
+    Ok(EnvFilter::from_default_env()
+        // Provide logging from secret-sync by default
+        .add_directive("secret_sync=info".parse()?)
+        //
+        .add_directive("aws_sdk_secretsmanager=info".parse()?)
+        .add_directive("aws_runtime=info".parse()?)
+        .add_directive("aws_smithy_runtime=info".parse()?)
+        .add_directive("hyper_util=info".parse()?))
+}
+
+/// Main logic entrypoint
+async fn app(args: Args, progress: Progress, cancel: CancellationToken) -> eyre::Result<Output> {
+    let deadline = args.deadline;
+
+    if !args.disable_color {
+        // Setup colorful error logging
+        color_eyre::install()?;
+    }
+
+    // Held until the command finishes so the trace file is flushed
+    let _trace_guard = init_logging(args.verbose, args.trace_file.as_deref())?;
+
+    let (config_path, working_path, state, mut config, _lock) = match &args.command {
+        Commands::Pull { .. }
+        | Commands::Push { .. }
+        | Commands::Diff { .. }
+        | Commands::Status { .. }
+        | Commands::Plan { .. }
+        | Commands::Promote { .. }
+        | Commands::Migrate { .. }
+        | Commands::Apply { .. }
+        | Commands::Approve { .. }
+        | Commands::Reconcile { .. }
+        | Commands::Doctor { .. }
+        | Commands::Grant { .. }
+        | Commands::Seed { .. }
+        | Commands::Scan { .. }
+        | Commands::ExportState { .. }
+        | Commands::Last
+        | Commands::Stats
+        | Commands::Env { .. }
+        | Commands::ComposeEnv { .. }
+        | Commands::Serve { .. }
+        | Commands::Mount { .. }
+        | Commands::Agent {
+            command: AgentCommand::Start { .. },
+        } => {
+            let config_path = match (&args.config, &args.config_secret) {
+                (Some(value), _) => resolve_config_path(value)?,
+                // The config file only provides the backend settings when the
+                // config is loaded from a secret, so it is optional
+                (None, Some(_)) => match discover_nearest_config_file().await {
+                    Ok(value) => value,
+                    Err(_) => current_dir()
+                        .context("failed to determine current directory")?
+                        .join(config::CONFIG_FILE_NAME_TOML),
+                },
+                (None, None) => discover_nearest_config_file().await?,
+            };
+
+            tracing::debug!(?config_path, "found config file");
+
+            let config_directory = config_working_path(&config_path)?;
+
+            let config = load_config(&args, &config_path, &progress).await?;
+
+            let (working_path, state) = project_paths(&args, &config, &config_directory)?;
+
+            tracing::debug!(?working_path, state_dir = ?state.dir(), "working path");
+
+            // Waiting is the default unless --no-wait was the last provided flag
+            let wait = args.wait || !args.no_wait;
+            let lock = acquire_project_lock(&state, wait).await?;
+
+            progress.set_last_run_path(state.last_run());
+
+            let duplicates = find_duplicate_entries(&config.files, &working_path);
+            if !duplicates.is_empty() {
+                if !args.allow_duplicates {
+                    let duplicates = duplicates
+                        .iter()
+                        .map(|duplicate| duplicate.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                    eyre::bail!(
+                        "duplicate files in config (use --allow-duplicates to ignore): {duplicates}"
+                    );
+                }
+
+                for duplicate in &duplicates {
+                    progress.warn(duplicate.to_string());
+                }
+            }
+
+            (config_path, working_path, state, config, Some(lock))
+        }
+        Commands::Agent { command } => return agent_command(command).await,
+        Commands::Init {
+            template,
+            name,
+            provider,
+            force,
+        } => return init_command(&args, *template, name.as_deref(), *provider, *force).await,
+        Commands::Context { command } => return context_command(command).await,
+        Commands::Service { command } => return service_command(command, &args).await,
+        Commands::Config { command } => return config_command(command, &args).await,
+        Commands::Run { task } => return run_command(&args, task).await,
+        Commands::Man { out_dir } => {
+            let pages = write_man_pages(&Args::command(), out_dir)?;
+
+            return Ok(Output {
+                text: format!(
+                    "wrote {} man pages to \"{}\"",
+                    pages.len(),
+                    out_dir.display()
+                ),
+                json: json!({ "success": true, "pages": pages }),
+            });
+        }
+        Commands::Version { .. } => {
+            let info = VersionInfo::current();
+
+            return Ok(Output {
+                text: info.to_text(),
+                json: serde_json::to_value(&info)?,
+            });
+        }
+        Commands::QuickPull { .. }
+        | Commands::QuickPush { .. }
+        | Commands::List { .. }
+        | Commands::Ping
+        | Commands::Cost { .. }
+        | Commands::Describe { .. } => {
+            let current_path = match &args.working_dir {
+                Some(value) => absolute(value).context("failed to get absolute working path")?,
+                None => current_dir().context("failed to determine current directory")?,
+            };
+
+            let config_path = match args.config {
+                Some(value) => Some(resolve_config_path(&value)?),
+                None => discover_nearest_config_file().await.ok(),
+            };
+
+            let config = match &config_path {
+                Some(path) => {
+                    let config = read_config_file(path).await?;
+                    check_plaintext_credentials(path, &config, args.strict, &progress)?;
+                    config
+                }
+                None => Config::default(),
+            };
+
+            // Quick commands do not persist any state
+            let state = StatePaths::for_project(&current_path, None);
+
+            (
+                config_path.unwrap_or(current_path.clone()),
+                current_path,
+                state,
+                config,
+                None,
+            )
+        }
+    };
+
+    if let Commands::Last = args.command {
+        let run = read_last_run(&state.last_run()).await?;
+
+        return Ok(Output {
+            text: run.to_text(),
+            json: json!({ "success": true, "last_run": run }),
+        });
+    }
+
+    let mut environment = args.environment;
+
+    let user_config = read_user_config().await?;
+    if config.aws.credentials.is_none()
+        && let Some(credentials) = user_config.project_credentials(&config_path)
+    {
+        tracing::debug!("using credentials from user config");
+        config.aws.credentials = Some(credentials.clone());
+    }
+
+    if let Some((name, context)) = user_config.resolve_context(args.context.as_deref())? {
+        tracing::debug!(%name, "applying context");
+
+        if let Some(provider) = context.provider {
+            config.backend.provider = provider;
+        }
+
+        if let Some(profile) = context.profile.as_ref() {
+            config.aws.profile = Some(profile.clone());
+        }
+
+        if let Some(region) = context.region.as_ref() {
+            config.aws.region = Some(region.clone());
+        }
+
+        if environment.is_none() {
+            environment = context.environment.clone();
+        }
+    }
+
+    let services = args
+        .command
+        .filter()
+        .and_then(|filter| filter.service.as_deref())
+        .unwrap_or_default();
+
+    if let Commands::Stats = args.command {
+        config.apply_services();
+
+        // Paths using {env} are only resolved when an environment is known
+        let environment =
+            environment.or_else(|| config.service_environment(services).map(str::to_string));
+        if environment.is_some() {
+            config.apply_environment(environment.as_deref())?;
+        }
+
+        let last_run = read_last_run(&state.last_run()).await.ok();
+        let stats = config_stats(&config, &working_path, last_run.as_ref(), unix_timestamp());
+
+        return Ok(Output {
+            text: stats.to_text(),
+            json: json!({ "success": true, "stats": stats }),
+        });
+    }
+
+    let environment = match args.command {
+        // Promotion resolves the secret names for both of its environments
+        Commands::Promote { .. } => {
+            config.apply_services();
+            config.apply_defaults();
+            environment
+        }
+        _ => config.prepare(environment.as_deref(), services)?,
+    };
+
+    if let Commands::QuickPull { secret, .. }
+    | Commands::QuickPush { secret, .. }
+    | Commands::Describe { secret } = &args.command
+    {
+        config.tenancy.check_secret(secret)?;
+    }
+
+    if let Some(profile) = args.profile {
+        config.aws.profile = Some(profile);
+    }
+
+    if let Some(region) = args.region {
+        config.aws.region = Some(region);
+    }
+
+    if let Some(mfa_token) = args.mfa_token {
+        config.aws.mfa_token = Some(mfa_token);
+    }
+
+    if args.dev {
+        config.backend.dev_endpoint_autodetect = true;
+    }
+
+    // Migrations only use the backends being migrated between
+    if let Commands::Migrate {
+        filter,
+        from,
+        to,
+        dry_run,
+        adopt,
+    } = &args.command
+    {
+        let files = filter_config_files(&config, &config_path, filter)?;
+        let from = create_named_backend(&config, from).await?;
+        let to = create_named_backend(&config, to).await?;
+
+        let entries = migrate_secrets(
+            from.as_ref(),
+            to.as_ref(),
+            files.into_iter().map(|(_name, file)| file),
+            *dry_run,
+            *adopt,
+        )
+        .await;
+
+        let text = migration_text(&entries, *dry_run);
+        let failed = migration_count(&entries, MigrationResult::Failed);
+        if failed > 0 {
+            eyre::bail!("{text}\nfailed to migrate {failed} secret(s)");
+        }
+
+        return Ok(Output {
+            text,
+            json: json!({ "success": true, "dry_run": dry_run, "secrets": entries }),
+        });
+    }
+
+    let secret = create_secret_manager(&config).await?;
+
+    let fs = RealFs;
+
+    match args.command {
+        Commands::Pull {
+            filter,
+            exec_on_change,
+            atomic,
+            discover,
+            tags,
+            discover_path,
+            no_resume,
+            concurrency,
+        } => {
+            let discovered = match discover {
+                true => discover_files(secret.as_ref(), &tags, &discover_path)
+                    .await?
+                    .into_iter()
+                    .filter(|file| match config.tenancy.check_secret(&file.secret) {
+                        Ok(()) => true,
+                        Err(error) => {
+                            progress.warn(format!(
+                                "skipping discovered secret \"{}\": {error}",
+                                file.secret
+                            ));
+                            false
+                        }
+                    })
+                    .collect(),
+                false => Vec::new(),
+            };
+
+            let generated = match discover {
+                true => Vec::new(),
+                false => filter_files(
+                    &config.generated,
+                    filter.file.as_deref(),
+                    filter.glob.as_deref(),
+                ),
+            };
+
+            let rendered = match discover {
+                true => Vec::new(),
+                false => filter_files(
+                    &config.render,
+                    filter.file.as_deref(),
+                    filter.glob.as_deref(),
+                ),
+            };
+
+            let keystores = match discover {
+                true => Vec::new(),
+                false => filter_files(
+                    &config.keystore,
+                    filter.file.as_deref(),
+                    filter.glob.as_deref(),
+                ),
+            };
+
+            let files: Vec<&SecretFile> = match discover {
+                true => discovered.iter().collect(),
+                // Filters may only match generated, rendered, or keystore files
+                false if !generated.is_empty() || !rendered.is_empty() || !keystores.is_empty() => {
+                    filter_files(
+                        &config.files,
+                        filter.file.as_deref(),
+                        filter.glob.as_deref(),
+                    )
+                    .into_iter()
+                    .map(|(_name, file)| file)
+                    .collect()
+                }
+                false => filter_config_files(&config, &config_path, &filter)?
+                    .into_iter()
+                    .map(|(_name, file)| file)
+                    .collect(),
+            };
+
+            if atomic && (!generated.is_empty() || !rendered.is_empty() || !keystores.is_empty()) {
+                eyre::bail!(
+                    "generated, rendered, and keystore files cannot be staged, they cannot be pulled atomically (select the secret files using --file or --glob)"
+                );
+            }
+
+            let total_files = files.len();
+
+            // Version of the secret pulled into each file, by file path
+            let mut versions = IndexMap::new();
+            let changed = match atomic {
+                true => {
+                    let started = Instant::now();
+                    let fs = HostFs::for_files(files.iter().copied())?;
+                    let changed = pull_secret_files_atomic(
+                        &fs,
+                        secret.as_ref(),
+                        &working_path,
+                        files.clone(),
+                    )
+                    .await?;
+
+                    for file in files {
+                        progress.complete(
+                            file.path.display().to_string(),
+                            FileAction::Pulled,
+                            started.elapsed(),
+                        );
+                    }
+
+                    changed
+                }
+                false => {
+                    let resume_path = state.resume();
+                    let batch = batch_id("pull", files.iter().copied());
+                    let mut resume = ResumeState::read(&resume_path, batch).await?;
+                    if no_resume {
+                        resume.completed.clear();
+                    }
+
+                    let files = skip_resumed(files, &resume, &working_path, &progress).await?;
+
+                    let options = PullOptions {
+                        concurrency,
+                        prewarm: config.network.prewarm,
+                        ..Default::default()
+                    };
+
+                    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+                    let pull = async {
+                        // Dropped once the batch finishes, ending the reporting
+                        let events = sender;
+                        pull_secret_files(
+                            &FileHosts,
+                            secret.as_ref(),
+                            &working_path,
+                            files.iter().copied(),
+                            options,
+                            &events,
+                            &cancel,
+                        )
+                        .await
+                    };
+                    let report = report_batch_events(
+                        receiver,
+                        &progress,
+                        &working_path,
+                        &files,
+                        FileAction::Pulled,
+                        &mut resume,
+                        &resume_path,
+                    );
+
+                    let (outcome, reported) = tokio::join!(pull, report);
+                    let outcome = outcome?;
+                    versions = reported?;
+
+                    // The resume state is kept so the next run continues the batch
+                    if outcome.cancelled {
+                        return Err(cancelled_error(deadline, &outcome, files.len()));
+                    }
+
+                    remove_resume(&resume_path).await?;
+
+                    outcome.changed
+                }
+            };
+
+            let mut changed = changed;
+
+            for (_name, file) in &generated {
+                let started = Instant::now();
+                let name = file.path.display().to_string();
+
+                match pull_generated_file(&fs, secret.as_ref(), &working_path, file).await {
+                    Ok(true) => {
+                        changed += 1;
+                        progress.complete(name, FileAction::Pulled, started.elapsed());
+                    }
+                    Ok(false) => progress.complete(name, FileAction::Unchanged, started.elapsed()),
+                    Err(error) => {
+                        progress.fail(name, &error, started.elapsed());
+                        return Err(error.into());
+                    }
+                }
+            }
+
+            for (_name, file) in &rendered {
+                let started = Instant::now();
+                let name = file.path.display().to_string();
+
+                match pull_render_file(&fs, secret.as_ref(), &working_path, file).await {
+                    Ok(true) => {
+                        changed += 1;
+                        progress.complete(name, FileAction::Pulled, started.elapsed());
+                    }
+                    Ok(false) => progress.complete(name, FileAction::Unchanged, started.elapsed()),
+                    Err(error) => {
+                        progress.fail(name, &error, started.elapsed());
+                        return Err(error);
+                    }
+                }
+            }
+
+            for (name, file) in &keystores {
+                let started = Instant::now();
+                let path = file.path.display().to_string();
+
+                match pull_keystore_file(&fs, secret.as_ref(), &working_path, name, file).await {
+                    Ok(true) => {
+                        changed += 1;
+                        progress.complete(path, FileAction::Pulled, started.elapsed());
+                    }
+                    Ok(false) => progress.complete(path, FileAction::Unchanged, started.elapsed()),
+                    Err(error) => {
+                        progress.fail(path, &error, started.elapsed());
+                        return Err(error.into());
+                    }
+                }
+            }
+
+            let total_files = total_files + generated.len() + rendered.len() + keystores.len();
+
+            if let Some(command) = exec_on_change.filter(|_| changed > 0) {
+                tracing::info!(%command, "secret files changed, running command");
+                run_shell_hook(&command).await?;
+            }
+
+            Ok(Output {
+                text: format!("successfully pulled {} secret file(s)", total_files),
+                json: json!({ "success": true, "changed": changed, "versions": versions }),
+            })
+        }
+
+        Commands::Push {
+            filter,
+            request_approval,
+            remote_lock,
+            steal_lock,
+            keys,
+            check_values,
+            strict_checks,
+            adopt,
+            no_resume,
+            max_files,
+            yes,
+        } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let mut warnings = Vec::new();
+            let files = skip_pull_only(files, &mut warnings);
+            for warning in warnings {
+                progress.warn(warning);
+            }
+
+            config.push.check_max_files(files.len(), max_files)?;
+
+            if check_values || strict_checks {
+                let mut warnings = Vec::new();
+                for (name, file) in files.iter().copied() {
+                    let fs = HostFs::for_file(file);
+                    warnings.extend(check_file_values(&fs, &working_path, [(name, file)]).await?);
+                }
+
+                for warning in &warnings {
+                    progress.warn(format!("{}: {}", warning.location, warning.warning));
+                }
+
+                if strict_checks && !warnings.is_empty() {
+                    eyre::bail!("refusing to push, {} value check(s) failed", warnings.len());
+                }
+            }
+
+            let info = PushInfo::detect(&working_path, &SystemClock);
+
+            if let Some(bundle_path) = request_approval {
+                reject_remote_files(&files)?;
+                let key = approval_key()?;
+                let requested_by = approval_identity(secret.as_ref()).await?;
+                let plan = create_plan(&fs, secret.as_ref(), &working_path, files, &info).await?;
+                let bundle = create_bundle(
+                    &key,
+                    &config.approval.signers,
+                    &SystemClock,
+                    requested_by,
+                    plan,
+                )?;
+                let value = serde_json::to_vec_pretty(&bundle)?;
+
+                tokio::fs::write(&bundle_path, value)
+                    .await
+                    .context("failed to write change request bundle")?;
+
+                return Ok(Output {
+                    text: format!(
+                        "{}\nchange request written to \"{}\"",
+                        bundle.request.plan.to_text(),
+                        bundle_path.display()
+                    ),
+                    json: json!({ "success": true, "bundle": bundle }),
+                });
+            }
+
+            let total_files = files.len();
+            let files: Vec<&SecretFile> = files.into_iter().map(|(_name, file)| file).collect();
+
+            // Skip the files completed by an interrupted run of the same push
+            let resume_path = state.resume();
+            let batch = batch_id("push", files.iter().copied());
+            let mut resume = ResumeState::read(&resume_path, batch).await?;
+            if no_resume {
+                resume.completed.clear();
+            }
+
+            for file in &files {
+                check_world_readable(file, &working_path, &progress);
+            }
+
+            let files = skip_resumed(files, &resume, &working_path, &progress).await?;
+
+            if !yes && config.push.requires_confirmation(files.len()) {
+                if !std::io::stdin().is_terminal() {
+                    eyre::bail!(
+                        "pushing {} secret(s) requires confirmation, use --yes when not running in a terminal",
+                        files.len()
+                    );
+                }
+
+                for file in &files {
+                    eprintln!(
+                        "  {} -> {}",
+                        file.path.display(),
+                        display_secret_name(&file.secret)
+                    );
+                }
+                if !confirm(&format!("Push {} secret(s)?", files.len()))? {
+                    eyre::bail!("push cancelled");
+                }
+            }
+
+            let names: Vec<&str> = files.iter().map(|file| file.secret.as_str()).collect();
+
+            for file in files
+                .iter()
+                .filter(|file| file.metadata != SecretMetadata::default())
+            {
+                if let Some(summary) = secret.describe_secret(&file.secret).await?
+                    && is_metadata_ignored(&summary, &info.render_file(file).metadata)
+                {
+                    progress.warn(format!(
+                        "metadata of secret \"{}\" is only applied when it is created, the changed metadata is ignored",
+                        file.secret
+                    ));
+                }
+            }
+
+            let locks = match remote_lock || config.remote_lock.enabled {
+                true => {
+                    config.tenancy.check_secret(&config.remote_lock.prefix)?;
+
+                    acquire_remote_locks(
+                        secret.as_ref(),
+                        &SystemClock,
+                        &config.remote_lock,
+                        &names,
+                        steal_lock,
+                    )
+                    .await?
+                }
+                false => Vec::new(),
+            };
+
+            let options = PushOptions {
+                adopt,
+                keys,
+                max_files: max_files.or(config.push.max_files),
+                info: Some(info),
+            };
+
+            let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+            let push = async {
+                // Dropped once the batch finishes, ending the reporting
+                let events = sender;
+                push_secret_files(
+                    &FileHosts,
+                    secret.as_ref(),
+                    &working_path,
+                    files.iter().copied(),
+                    &options,
+                    &events,
+                    &cancel,
+                )
+                .await
+            };
+            let report = report_batch_events(
+                receiver,
+                &progress,
+                &working_path,
+                &files,
+                FileAction::Pushed,
+                &mut resume,
+                &resume_path,
+            );
+
+            let (result, reported) = tokio::join!(push, report);
+            release_remote_locks(secret.as_ref(), locks).await;
+            let outcome = result?;
+            reported?;
+
+            // The resume state is kept so the next run continues the batch
+            if outcome.cancelled {
+                return Err(cancelled_error(deadline, &outcome, files.len()));
+            }
+
+            remove_resume(&resume_path).await?;
+
+            Ok(Output {
+                text: format!("successfully pushed {} secret file(s)", total_files),
+                json: json!({ "success": true }),
+            })
+        }
+
+        Commands::Diff { filter, redact } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let mut diffs = Vec::with_capacity(files.len());
+
+            for (name, file) in files {
+                if file.sink.is_some() {
+                    progress.warn(format!("skipping diff of file \"{name}\" piped to a sink"));
+                    continue;
+                }
+
+                let fs = HostFs::for_file(file);
+                diffs.push(
+                    diff_file(&fs, secret.as_ref(), &working_path, name, file, redact)
+                        .await
+                        .with_context(|| format!("failed to diff file \"{name}\""))?,
+                );
+            }
+
+            Ok(Output {
+                text: diff_text(&diffs),
+                json: json!({ "success": true, "files": diffs }),
+            })
+        }
+
+        Commands::Status { filter } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let mut statuses = Vec::with_capacity(files.len());
+
+            for (name, file) in files {
+                if file.sink.is_some() {
+                    progress.warn(format!(
+                        "skipping status of file \"{name}\" piped to a sink"
+                    ));
+                    continue;
+                }
+
+                let fs = HostFs::for_file(file);
+                statuses.push(
+                    file_status(&fs, secret.as_ref(), &working_path, name, file)
+                        .await
+                        .with_context(|| format!("failed to get status of file \"{name}\""))?,
+                );
+            }
+
+            Ok(Output {
+                text: status_text(&statuses),
+                json: json!({ "success": true, "files": statuses }),
+            })
+        }
+
+        Commands::Plan { filter, out } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let mut warnings = Vec::new();
+            let files = skip_pull_only(files, &mut warnings);
+            for warning in warnings {
+                progress.warn(warning);
+            }
+            reject_remote_files(&files)?;
+
+            let info = PushInfo::detect(&working_path, &SystemClock);
+            let plan = create_plan(&fs, secret.as_ref(), &working_path, files, &info).await?;
+
+            if let Some(out) = out {
+                let value = serde_json::to_vec_pretty(&plan)?;
+                tokio::fs::write(&out, value)
+                    .await
+                    .context("failed to write plan file")?;
+            }
+
+            Ok(Output {
+                text: plan.to_text(),
+                json: json!({ "success": true, "plan": plan }),
+            })
+        }
+
+        Commands::Promote {
+            filter,
+            from,
+            to,
+            yes,
+            adopt,
+        } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let promotion = create_promotion(secret.as_ref(), files, &from, &to).await?;
+
+            for entry in &promotion.entries {
+                config.tenancy.check_secret(&entry.to_secret)?;
+            }
+
+            let changes = promotion.count(PlanAction::Create) + promotion.count(PlanAction::Update);
+            if changes == 0 {
+                return Ok(Output {
+                    text: promotion.to_text(),
+                    json: json!({ "success": true, "promotion": promotion, "modified": 0 }),
+                });
+            }
+
+            if !yes {
+                if !std::io::stdin().is_terminal() {
+                    eyre::bail!(
+                        "promotion requires confirmation, use --yes when not running in a terminal"
+                    );
+                }
+
+                eprintln!("{}", promotion.to_text());
+                if !confirm(&format!("Promote {changes} secret(s) to \"{to}\"?"))? {
+                    eyre::bail!("promotion cancelled");
+                }
+            }
+
+            let text = promotion.to_text();
+            let json = serde_json::to_value(&promotion)?;
+            let modified = apply_promotion(secret.as_ref(), promotion, adopt).await?;
+
+            Ok(Output {
+                text: format!("{text}\npromoted {modified} secret(s) to \"{to}\""),
+                json: json!({ "success": true, "promotion": json, "modified": modified }),
+            })
+        }
+
+        Commands::Apply { plan, adopt } => {
+            let plan = read_plan_file(&plan).await?;
+            let summary = apply_plan(&fs, secret.as_ref(), &working_path, &plan, adopt).await?;
+
+            Ok(Output {
+                text: format!(
+                    "successfully applied plan: {} created, {} updated, {} unchanged",
+                    summary.created, summary.updated, summary.skipped
+                ),
+                json: json!({
+                    "success": true,
+                    "created": summary.created,
+                    "updated": summary.updated,
+                    "skipped": summary.skipped
+                }),
+            })
+        }
+
+        Commands::Approve { bundle, adopt } => {
+            let bundle = read_bundle_file(&bundle).await?;
+            let approval = approve_bundle(
+                &fs,
+                secret.as_ref(),
+                &working_path,
+                &config.approval.signers,
+                &bundle,
+                adopt,
+            )
+            .await?;
+            let summary = approval.applied;
+
+            Ok(Output {
+                text: format!(
+                    "successfully approved change request from {}: {} created, {} updated, {} unchanged",
+                    bundle.request.requested_by, summary.created, summary.updated, summary.skipped
+                ),
+                json: json!({
+                    "success": true,
+                    "requested_by": bundle.request.requested_by,
+                    "approved_by": approval.approved_by,
+                    "created": summary.created,
+                    "updated": summary.updated,
+                    "skipped": summary.skipped
+                }),
+            })
+        }
+
+        Commands::Grant { file, ttl } => {
+            let entry = config
+                .files
+                .get(&file)
+                .with_context(|| format!("file \"{file}\" not found within config"))?;
+
+            let grant = secret.grant_secret_access(&entry.secret, ttl).await?;
+
+            Ok(Output {
+                text: grant_instructions(&grant, &entry.path),
+                json: json!({ "success": true, "grant": grant }),
+            })
+        }
+
+        Commands::Seed {
+            from_dir,
+            name,
+            rate,
+            adopt,
+        } => {
+            let root = absolute(&from_dir).context("failed to get absolute seed path")?;
+            let options = SeedOptions {
+                root: &root,
+                template: &name,
+                environment: environment.as_deref(),
+                rate,
+                metadata: &config.defaults.metadata,
+                tenancy: &config.tenancy,
+                adopt,
+            };
+
+            let state_path = state.seed();
+            let summary = seed_files(secret.as_ref(), &options, &state_path).await?;
+            for name in &summary.existing {
+                progress.warn(format!("secret \"{name}\" already exists, skipped"));
+            }
+
+            Ok(Output {
+                text: format!(
+                    "seeded {} secret(s), {} already seeded, {} already existed",
+                    summary.created.len(),
+                    summary.skipped,
+                    summary.existing.len()
+                ),
+                json: json!({ "success": true, "seed": summary }),
+            })
+        }
+
+        Commands::Doctor { filter } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let fs = HostFs::for_files(files.iter().map(|(_name, file)| *file))?;
+            let duplicates = find_duplicate_values(&fs, &working_path, files).await?;
+
+            let mut text = String::new();
+            for duplicate in &duplicates {
+                let locations = duplicate
+                    .locations
+                    .iter()
+                    .map(|location| location.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                text.push_str(&format!("warning: same value used in {locations}\n"));
+            }
+
+            let credentials = secret.diagnose_credentials().await;
+            match (&credentials.source, &credentials.error) {
+                (Some(source), _) => text.push_str(&format!("credentials: {source}\n")),
+                (None, Some(error)) => text.push_str(&format!(
+                    "warning: failed to resolve credentials: {error}\n"
+                )),
+                (None, None) => {}
+            }
+
+            for problem in &credentials.problems {
+                text.push_str(&format!(
+                    "warning: {}\n  hint: {}\n",
+                    problem.problem, problem.hint
+                ));
+            }
+
+            text.push_str(&match (duplicates.len(), credentials.problems.len()) {
+                (0, 0) => "no problems found".to_string(),
+                (0, total) => format!("found {total} credentials problem(s)"),
+                (total, 0) => format!("found {total} duplicated value(s), consider consolidating them into a shared secret"),
+                (duplicates, problems) => format!("found {duplicates} duplicated value(s) and {problems} credentials problem(s)"),
+            });
+
+            Ok(Output {
+                text,
+                json: json!({ "success": true, "duplicates": duplicates, "credentials": credentials }),
+            })
+        }
+
+        Commands::Scan { filter } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+
+            let mut hashes = ScanHashes::default();
+            for (name, file) in files {
+                if let Some(value) = secret.find_secret(&file.secret).await? {
+                    hashes.add(name, &value);
+                }
+            }
+
+            if hashes.is_empty() {
+                return Ok(Output {
+                    text: "no secret values to scan for".to_string(),
+                    json: json!({ "success": true, "leaks": [] }),
+                });
+            }
+
+            let excluded: Vec<PathBuf> = config
+                .files
+                .values()
+                .map(|file| file.resolve_path(&working_path))
+                .collect();
+
+            let scan_paths = list_scan_files(&working_path)?;
+            let total_scanned = scan_paths.len();
+            let leaks = scan_files(&hashes, &working_path, scan_paths, &excluded).await?;
+
+            if !leaks.is_empty() {
+                let leaks = leaks
+                    .iter()
+                    .map(|leak| leak.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                eyre::bail!("found secret values in the working tree:\n{leaks}");
+            }
+
+            Ok(Output {
+                text: format!("no secret values found in {total_scanned} file(s)"),
+                json: json!({ "success": true, "leaks": leaks }),
+            })
+        }
+
+        Commands::Reconcile {
+            state,
+            dry_run,
+            adopt,
+        } => {
+            let state_path = state.unwrap_or_else(|| working_path.join(DEFAULT_STATE_FILE_NAME));
+            let state = read_state_file(&state_path).await?;
+            let fs = HostFs::for_files(config.files.values())?;
+
+            let entries = reconcile(
+                &fs,
+                secret.as_ref(),
+                &working_path,
+                &config.files,
+                &state,
+                dry_run,
+                adopt,
+            )
+            .await?;
+
+            Ok(Output {
+                text: reconcile_text(&entries, dry_run),
+                json: json!({ "success": true, "dry_run": dry_run, "secrets": entries }),
+            })
+        }
+
+        Commands::ExportState { state } => {
+            let state_path = state.unwrap_or_else(|| working_path.join(DEFAULT_STATE_FILE_NAME));
+
+            let existing = match state_path.exists() {
+                true => Some(read_state_file(&state_path).await?),
+                false => None,
+            };
+
+            let fs = HostFs::for_files(config.files.values())?;
+            let state = export_state(&fs, &working_path, &config.files, existing).await?;
+            let value = serde_json::to_vec_pretty(&state)?;
+
+            tokio::fs::write(&state_path, value)
+                .await
+                .context("failed to write desired state file")?;
+
+            Ok(Output {
+                text: format!(
+                    "successfully exported {} secret(s) to \"{}\"",
+                    state.secrets.len(),
+                    state_path.display()
+                ),
+                json: json!({ "success": true }),
+            })
+        }
+
+        Commands::Env { filter, out_link } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+
+            let files = files.into_iter().map(|(_name, file)| file);
+            let env = collect_env(secret.as_ref(), files).await?;
+
+            if let Some(out_link) = out_link {
+                write_env_out_link(&out_link, &env).await?;
+            }
+
+            Ok(Output {
+                text: render_dotenv(&env).trim_end().to_string(),
+                json: serde_json::to_value(&env)?,
+            })
+        }
+
+        Commands::ComposeEnv {
+            filter,
+            compose,
+            out,
+        } => {
+            let compose_path = match compose {
+                Some(value) => value,
+                None => find_compose_file(&working_path)?,
+            };
+
+            let contents = tokio::fs::read_to_string(&compose_path)
+                .await
+                .context("failed to read compose file")?;
+            let references = find_compose_references(&contents);
+
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let files = files.into_iter().map(|(_name, file)| file);
+            let env = collect_env(secret.as_ref(), files).await?;
+
+            let (selected, missing) = select_compose_env(&env, &references);
+            for name in &missing {
+                progress.warn(format!(
+                    "compose file references variable \"{name}\" not found in any secret"
+                ));
+            }
+
+            let out = match out {
+                Some(value) => value,
+                None => compose_path
+                    .parent()
+                    .context("compose file has no parent directory")?
+                    .join(".env"),
+            };
+
+            tokio::fs::write(
+                &out,
+                render_dotenv(selected.iter().map(|(key, value)| (*key, *value))),
+            )
+            .await
+            .context("failed to write compose env file")?;
+
+            Ok(Output {
+                text: format!(
+                    "wrote {} variable(s) to \"{}\"",
+                    selected.len(),
+                    out.display()
+                ),
+                json: json!({
+                    "success": true,
+                    "variables": selected.keys().collect::<Vec<_>>(),
+                    "missing": missing,
+                }),
+            })
+        }
+
+        Commands::Serve { listen, socket } => {
+            let token = serve_token()?;
+            let listener = match socket {
+                Some(path) => ServeListener::Unix(path),
+                None => ServeListener::Tcp(listen),
+            };
+
+            let fs = HostFs::for_files(config.files.values())?;
+            let context = ServeContext {
+                fs: &fs,
+                secret: secret.as_ref(),
+                config: &config,
+                working_path: &working_path,
+                token: &token,
+                cancel: &cancel,
+            };
+
+            serve(&context, listener).await?;
+
+            Ok(Output {
+                text: "server stopped".to_string(),
+                json: json!({ "success": true }),
+            })
+        }
+
+        Commands::Mount {
+            dir,
+            filter,
+            interval,
+        } => {
+            let files: IndexMap<String, String> =
+                filter_config_files(&config, &config_path, &filter)?
+                    .into_iter()
+                    .map(|(name, file)| (name.clone(), file.secret.clone()))
+                    .collect();
+
+            let dir = absolute(&dir).context("failed to get absolute mount path")?;
+            let mut mount = Mount::create(&dir).await?;
+            if let Err(error) = mount.refresh(secret.as_ref(), &files).await {
+                _ = mount.remove().await;
+                return Err(error);
+            }
+
+            // Allow other commands to run while the secrets are mounted
+            drop(_lock);
+
+            tracing::info!(
+                path = %dir.display(),
+                files = files.len(),
+                tmpfs = mount.is_tmpfs(),
+                "secrets mounted"
+            );
+            run_mount(mount, secret.as_ref(), &files, interval).await?;
+
+            Ok(Output {
+                text: format!("unmounted \"{}\"", dir.display()),
+                json: json!({ "success": true }),
+            })
+        }
+
+        Commands::Agent {
+            command: AgentCommand::Start { filter, socket },
+        } => {
+            let files = filter_config_files(&config, &config_path, &filter)?;
+            let values = load_agent_values(secret.as_ref(), files).await?;
+            let socket = socket.unwrap_or_else(|| state.agent_socket());
+
+            // Allow other commands to run while the agent is serving
+            drop(_lock);
+
+            eprintln!(
+                "{AGENT_SOCKET_ENV}={}; export {AGENT_SOCKET_ENV};",
+                socket.display()
+            );
+            run_agent(&socket, values).await?;
+
+            Ok(Output {
+                text: "agent stopped".to_string(),
+                json: json!({ "success": true }),
+            })
+        }
+
+        Commands::Agent { .. }
+        | Commands::Init { .. }
+        | Commands::Context { .. }
+        | Commands::Service { .. }
+        | Commands::Config { .. }
+        | Commands::Run { .. }
+        | Commands::Man { .. }
+        | Commands::Version { .. } => {
+            unreachable!("command is handled before loading config")
+        }
+
+        Commands::Last | Commands::Stats | Commands::Migrate { .. } => {
+            unreachable!("command is handled before creating the secret manager")
+        }
+
+        Commands::Ping => {
+            let backend = config.backend.provider.to_string();
+            let checks = ping(secret.as_ref()).await;
+            let text = ping_text(&backend, &checks);
+
+            if checks.iter().any(|check| !check.ok) {
+                eyre::bail!("backend checks failed\n{text}");
+            }
+
+            Ok(Output {
+                text,
+                json: json!({ "success": true, "backend": backend, "checks": checks }),
+            })
+        }
+
+        Commands::List { tags, page_size } => {
+            let options = ListSecretsOptions { tags, page_size };
+
+            // Secrets outside of the allowed prefixes are not listed
+            let summaries: Vec<SecretSummary> = secret
+                .list_secrets(options)
+                .try_filter(|summary| {
+                    std::future::ready(config.tenancy.check_secret(&summary.name).is_ok())
+                })
+                .try_collect()
+                .await?;
+
+            let text = summaries
+                .iter()
+                .map(|summary| match &summary.description {
+                    Some(description) => format!("{} - {description}", summary.name),
+                    None => summary.name.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            Ok(Output {
+                text,
+                json: json!({ "success": true, "secrets": summaries }),
+            })
+        }
+
+        Commands::Describe {
+            secret: secret_name,
+        } => {
+            let summary = secret
+                .describe_secret(&secret_name)
+                .await?
+                .with_context(|| format!("secret \"{secret_name}\" not found"))?;
+
+            let mut text = format!("name: {}", summary.name);
+            let fields = [
+                ("description", summary.description.clone()),
+                ("created", summary.created.clone()),
+                ("updated", summary.updated.clone()),
+                (
+                    "versions",
+                    summary.version_count.map(|value| value.to_string()),
+                ),
+                (
+                    "size",
+                    summary.size_hint.map(|value| format!("{value} bytes")),
+                ),
+            ];
+
+            for (label, value) in fields {
+                if let Some(value) = value {
+                    text.push_str(&format!("\n{label}: {value}"));
+                }
+            }
+
+            for (key, value) in &summary.tags {
+                text.push_str(&format!("\ntag: {key}={value}"));
+            }
+
+            Ok(Output {
+                text,
+                json: json!({ "success": true, "secret": summary }),
+            })
+        }
+
+        Commands::Cost {
+            all,
+            pulls_per_day,
+            machines,
+            price_per_secret,
+            price_per_10k_calls,
+        } => {
+            let secrets = match all {
+                // Secrets outside of the allowed prefixes are not counted
+                true => {
+                    secret
+                        .list_secrets(ListSecretsOptions::default())
+                        .try_filter(|summary| {
+                            std::future::ready(config.tenancy.check_secret(&summary.name).is_ok())
+                        })
+                        .try_fold(0, |count, _| std::future::ready(Ok(count + 1)))
+                        .await?
+                }
+                false => {
+                    let secrets: HashSet<&str> = config
+                        .files
+                        .values()
+                        .map(|file| file.secret.as_str())
+                        .collect();
+                    secrets.len()
+                }
+            };
+
+            if secrets == 0 {
+                eyre::bail!(
+                    "no secrets to estimate the cost of, use --all to count the secrets in the backend"
+                );
+            }
+
+            let usage = UsagePattern {
+                pulls_per_day,
+                machines,
+            };
+            let pricing = Pricing {
+                per_secret: price_per_secret,
+                per_10k_calls: price_per_10k_calls,
+            };
+
+            let estimate = CostEstimate::new(secrets, &usage, &pricing);
+            let consolidated = CostEstimate::new(1, &usage, &pricing);
+
+            let mut text = estimate.to_text();
+            if secrets > 1 {
+                text.push_str(&format!(
+                    "\nconsolidated into a single secret: ${:.2} (saves ${:.2})",
+                    consolidated.total,
+                    estimate.total - consolidated.total
+                ));
+            }
+
+            Ok(Output {
+                text,
+                json: json!({
+                    "success": true,
+                    "usage": usage,
+                    "pricing": pricing,
+                    "estimate": estimate,
+                    "consolidated": consolidated
+                }),
+            })
+        }
+
+        Commands::QuickPull {
+            path,
+            secret: secret_value,
+            stdout,
+        } => {
+            if stdout {
+                let value = secret.get_secret(&secret_value).await?.data;
+
+                // Written directly so binary values are not altered by rendering
+                let mut out = std::io::stdout().lock();
+                out.write_all(value.as_bytes())
+                    .and_then(|_| out.flush())
+                    .context("failed to write secret to stdout")?;
+
+                return Ok(Output {
+                    text: String::new(),
+                    json: json!({ "success": true }),
+                });
+            }
+
+            let file = SecretFile {
+                secret: secret_value,
+                path: path.context("missing path to pull into")?,
+                ..Default::default()
+            };
+
+            let outcome = pull_secret_files(
+                &fs,
+                secret.as_ref(),
+                &working_path,
+                [&file],
+                PullOptions::default(),
+                &(),
+                &cancel,
+            )
+            .await?;
+
+            if outcome.cancelled {
+                return Err(cancelled_error(deadline, &outcome, 1));
+            }
+
+            Ok(Output {
+                text: "successfully pulled 1 secret file(s)".to_string(),
+                json: json!({ "success": true }),
+            })
+        }
+
+        Commands::QuickPush {
+            path,
+            secret: secret_value,
+            stdin,
+            binary,
+            adopt,
+        } => {
+            let file = SecretFile {
+                secret: secret_value,
+                path: path.unwrap_or_default(),
+                ..Default::default()
+            };
+
+            let value = match stdin {
+                false => fs.read_file(&file.resolve_path(&working_path)).await?,
+                true => {
+                    let mut value = Vec::new();
+                    tokio::io::stdin()
+                        .read_to_end(&mut value)
+                        .await
+                        .context("failed to read secret from stdin")?;
+                    value
+                }
+            };
+
+            let value = match binary {
+                true => Secret::Binary(value),
+                false => Secret::from_bytes(value),
+            };
+
+            check_secret_ownership(secret.as_ref(), &[&file.secret], adopt).await?;
+            store_push_value(secret.as_ref(), &file, value).await?;
+
+            Ok(Output {
+                text: "successfully pushed 1 secret file(s)".to_string(),
+                json: json!({ "success": true }),
+            })
+        }
+    }
+}
+
+/// Run the steps of the `task` from the config
+async fn run_command(args: &Args, task: &str) -> eyre::Result<Output> {
+    let config_path = match &args.config {
+        Some(value) => resolve_config_path(value)?,
+        None => discover_nearest_config_file().await?,
+    };
+
+    let config = read_config_file(&config_path).await?;
+    let steps = task_steps(&config.tasks, task)?;
+
+    // Steps use the same config and global flags as the task
+    let mut forward: Vec<(&'static str, OsString)> =
+        vec![("SECRET_SYNC_CONFIG", config_path.into_os_string())];
+    if let Some(working_dir) = &args.working_dir {
+        let working_dir = absolute(working_dir).context("failed to get absolute working path")?;
+        forward.push(("SECRET_SYNC_WORKING_DIR", working_dir.into_os_string()));
+    }
+
+    for (key, value) in [
+        ("SECRET_SYNC_CONTEXT", &args.context),
+        ("SECRET_SYNC_ENV", &args.environment),
+        ("SECRET_SYNC_PROFILE", &args.profile),
+        ("SECRET_SYNC_REGION", &args.region),
+    ] {
+        if let Some(value) = value {
+            forward.push((key, value.into()));
+        }
+    }
+
+    let count = run_task_steps(&steps, &forward).await?;
+
+    Ok(Output {
+        text: format!("ran {count} step(s) of task \"{task}\""),
+        json: json!({ "success": true, "task": task, "steps": count }),
+    })
+}
+
+/// Handle the agent client sub commands
+async fn agent_command(command: &AgentCommand) -> eyre::Result<Output> {
+    match command {
+        AgentCommand::Get { file, socket } => {
+            let socket = agent_socket_path(socket.clone())?;
+            let request = AgentRequest::Get { file: file.clone() };
+            let response = agent_request(&socket, &request).await?;
+
+            Ok(Output {
+                text: response["value"].as_str().unwrap_or_default().to_string(),
+                json: response,
+            })
+        }
+
+        AgentCommand::List { socket } => {
+            let socket = agent_socket_path(socket.clone())?;
+            let response = agent_request(&socket, &AgentRequest::List).await?;
+
+            let text = response["files"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|file| file.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            Ok(Output {
+                text,
+                json: response,
+            })
+        }
+
+        AgentCommand::Start { .. } => unreachable!("agent start requires a config"),
+    }
+}
+
+/// Skip the `files` completed by an interrupted run of the same batch
+/// according to the `resume` state, recording them as resumed
+async fn skip_resumed<'a>(
+    files: Vec<&'a SecretFile>,
+    resume: &ResumeState,
+    working_path: &Path,
+    progress: &Progress,
+) -> eyre::Result<Vec<&'a SecretFile>> {
+    let mut pending = Vec::with_capacity(files.len());
+
+    for file in files {
+        let fs = HostFs::for_file(file);
+        let hash = local_hash(&fs, working_path, file).await?;
+        match resume.is_completed(file, hash.as_deref()) {
+            true => progress.complete(
+                file.path.display().to_string(),
+                FileAction::Resumed,
+                Duration::ZERO,
+            ),
+            false => pending.push(file),
+        }
+    }
+
+    Ok(pending)
+}
+
+/// Record the progress `events` of a batch of `files` until the batch
+/// finishes, changed files are recorded using `action`
+///
+/// Completed files are recorded within the `resume` state so an interrupted
+/// batch can skip them. Provides the version of each pulled secret by file
+async fn report_batch_events(
+    mut events: UnboundedReceiver<SyncEvent>,
+    progress: &Progress,
+    working_path: &Path,
+    files: &[&SecretFile],
+    action: FileAction,
+    resume: &mut ResumeState,
+    resume_path: &Path,
+) -> eyre::Result<IndexMap<String, String>> {
+    let files: HashMap<(&str, PathBuf), &SecretFile> = files
+        .iter()
+        .map(|file| {
+            (
+                (file.secret.as_str(), file.resolve_path(working_path)),
+                *file,
+            )
+        })
+        .collect();
+
+    let mut started = HashMap::new();
+    let mut versions = IndexMap::new();
+
+    while let Some(event) = events.recv().await {
+        match event {
+            SyncEvent::FileStarted { secret, path } => {
+                started.insert((secret, path), Instant::now());
+            }
+            SyncEvent::FileCompleted {
+                secret,
+                path,
+                changed,
+                version_id,
+            } => {
+                let elapsed = started
+                    .get(&(secret.clone(), path.clone()))
+                    .map(Instant::elapsed)
+                    .unwrap_or_default();
+                let Some(file) = files.get(&(secret.as_str(), path)) else {
+                    continue;
+                };
+
+                let name = file.path.display().to_string();
+                if let Some(version_id) = &version_id {
+                    versions.insert(name.clone(), version_id.clone());
+                }
+
+                let action = match changed {
+                    true => action,
+                    false => FileAction::Unchanged,
+                };
+                progress.complete_version(name, action, elapsed, version_id);
+
+                let fs = HostFs::for_file(file);
+                let hash = local_hash(&fs, working_path, file).await?;
+                resume.complete(resume_path, file, hash).await?;
+            }
+            SyncEvent::FileFailed {
+                secret,
+                path,
+                error,
+            } => {
+                let elapsed = started
+                    .get(&(secret.clone(), path.clone()))
+                    .map(Instant::elapsed)
+                    .unwrap_or_default();
+                let name = match files.get(&(secret.as_str(), path)) {
+                    Some(file) => file.path.display().to_string(),
+                    None => secret,
+                };
+
+                progress.fail(name, &error, elapsed);
+            }
+            SyncEvent::FileSkipped {
+                secret,
+                path,
+                reason,
+            } => {
+                let name = match files.get(&(secret.as_str(), path)) {
+                    Some(file) => file.path.display().to_string(),
+                    None => secret,
+                };
+
+                progress.warn(format!("skipping file \"{name}\" {reason}"));
+            }
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Resolve the working path and state directory of the project using the
+/// `config` within `config_directory`, the flags take priority over the
+/// config
+fn project_paths(
+    args: &Args,
+    config: &Config,
+    config_directory: &Path,
+) -> eyre::Result<(PathBuf, StatePaths)> {
+    let working_path = match &args.working_dir {
+        Some(value) => absolute(value).context("failed to get absolute working path")?,
+        None => config.paths.working_path(config_directory),
+    };
+
+    let state_dir = match &args.state_dir {
+        Some(value) => Some(absolute(value).context("failed to get absolute state directory")?),
+        None => config.paths.state_dir(config_directory),
+    };
+
+    let state = StatePaths::for_project(&working_path, state_dir);
+    Ok((working_path, state))
+}
+
+/// Prompt the user to confirm `prompt` through the terminal
+fn confirm(prompt: &str) -> eyre::Result<bool> {
+    eprint!("{prompt} [y/N] ");
+    std::io::stderr()
+        .flush()
+        .context("failed to write prompt")?;
+
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .context("failed to read input")?;
+
+    Ok(matches!(line.trim(), "y" | "Y" | "yes"))
+}
+
+/// Handle the config file management sub commands
+async fn config_command(command: &ConfigCommand, args: &Args) -> eyre::Result<Output> {
+    let config_path = match &args.config {
+        Some(value) => resolve_config_path(value)?,
+        None => discover_nearest_config_file().await?,
+    };
+
+    let config_directory = config_working_path(&config_path)?;
+    let config = read_config_file(&config_path).await?;
+    let (_working_path, state) = project_paths(args, &config, &config_directory)?;
+
+    let wait = args.wait || !args.no_wait;
+    let _lock = acquire_project_lock(&state, wait).await?;
+
+    match command {
+        ConfigCommand::Scrub { force } => {
+            let Some(credentials) = config.aws.credentials else {
+                return Ok(Output {
+                    text: "config file does not contain credentials".to_string(),
+                    json: json!({ "success": true, "scrubbed": false }),
+                });
+            };
+
+            let mut user_config = read_user_config().await?;
+            if let Some(existing) = user_config.project_credentials(&config_path)
+                && existing != &credentials
+                && !force
+            {
+                eyre::bail!(
+                    "the user config already contains different credentials for this config file, use --force to replace them"
+                );
+            }
+
+            let contents = tokio::fs::read_to_string(&config_path)
+                .await
+                .context("failed to read config file")?;
+            let extension = config_path.extension().and_then(|value| value.to_str());
+            let contents = config::remove_config_credentials(&contents, extension)?;
+
+            // Credentials are stored before being removed so they cannot be lost
+            user_config.set_project_credentials(&config_path, credentials)?;
+            write_user_config(&user_config).await?;
+
+            tokio::fs::write(&config_path, contents)
+                .await
+                .context("failed to write config file")?;
+
+            Ok(Output {
+                text: format!(
+                    "moved credentials from \"{}\" into the user config",
+                    config_path.display()
+                ),
+                json: json!({ "success": true, "scrubbed": true }),
+            })
+        }
+    }
+}
+
+/// Handle the service sub commands
+async fn service_command(command: &ServiceCommand, args: &Args) -> eyre::Result<Output> {
+    let ServiceCommand::Install {
+        name,
+        interval,
+        user,
+        print,
+    } = command;
+
+    let config_path = match &args.config {
+        Some(value) => resolve_config_path(value)?,
+        None => discover_nearest_config_file().await?,
+    };
+
+    let config_directory = config_working_path(&config_path)?;
+    let config = read_config_file(&config_path).await?;
+    let working_path = match &args.working_dir {
+        Some(value) => absolute(value).context("failed to get absolute working path")?,
+        None => config.paths.working_path(&config_directory),
+    };
+
+    let mut pull_args = vec!["--config".to_string(), config_path.display().to_string()];
+    let forwarded = [
+        ("--env", &args.environment),
+        ("--profile", &args.profile),
+        ("--region", &args.region),
+        ("--context", &args.context),
+    ];
+    for (flag, value) in forwarded {
+        if let Some(value) = value {
+            pull_args.extend([flag.to_string(), value.clone()]);
+        }
+    }
+    if args.working_dir.is_some() {
+        pull_args.extend([
+            "--working-dir".to_string(),
+            working_path.display().to_string(),
+        ]);
+    }
+    pull_args.push("pull".to_string());
+
+    let mut writable_paths = vec![config_directory];
+    if !writable_paths.contains(&working_path) {
+        writable_paths.push(working_path);
+    }
+
+    let options = ServiceOptions {
+        name: name.clone(),
+        executable: std::env::current_exe().context("failed to determine executable path")?,
+        args: pull_args,
+        writable_paths,
+        interval: *interval,
+    };
+
+    if *print {
+        let text = match cfg!(windows) {
+            true => format!("schtasks {}", schtasks_args(&options)?.join(" ")),
+            false => format!(
+                "# {name}.service\n{}\n# {name}.timer\n{}",
+                systemd_service_unit(&options),
+                systemd_timer_unit(&options)
+            ),
+        };
+
+        return Ok(Output {
+            text,
+            json: json!({ "success": true, "installed": false }),
+        });
+    }
+
+    let units = install_service(&options, *user).await?;
+
+    Ok(Output {
+        text: format!("installed \"{name}\" pulling every {}s", interval.as_secs()),
+        json: json!({ "success": true, "installed": true, "units": units }),
+    })
+}
+
+/// Warn when the config file at `config_path` contains plaintext
+/// credentials and is inside a git repository where it may be committed,
+/// failing instead when `strict`
+fn check_plaintext_credentials(
+    config_path: &Path,
+    config: &Config,
+    strict: bool,
+    progress: &Progress,
+) -> eyre::Result<()> {
+    // Named backends and files overriding the backend may specify their
+    // own aws.credentials
+    let overrides = config
+        .backends
+        .values()
+        .chain(config.files.values().map(|file| &file.backend_override));
+    let plaintext = std::iter::once(&config.aws)
+        .chain(overrides.filter_map(|backend| backend.aws.as_ref()))
+        .filter_map(|aws| aws.credentials.as_ref())
+        .any(|credentials| credentials.is_plaintext());
+
+    if !plaintext || !config::is_in_git_repository(config_path) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "config file \"{}\" is inside a git repository and contains plaintext aws.credentials, run \"secret-sync config scrub\" to move them into the user config or reference them using keyring:<account>",
+        config_path.display()
+    );
+
+    if strict {
+        eyre::bail!(message);
+    }
+
+    progress.warn(message);
+    Ok(())
+}
+
+/// Read the config file at `config_path`, loading the config from a secret
+/// instead when --config-secret is provided or the config file sets
+/// config.source = "secret"
+async fn load_config(args: &Args, config_path: &Path, progress: &Progress) -> eyre::Result<Config> {
+    let mut config = match args.config_secret.is_some() && !config_path.exists() {
+        true => Config::default(),
+        false => read_config_file(config_path).await?,
+    };
+    check_plaintext_credentials(config_path, &config, args.strict, progress)?;
+
+    let name = match &args.config_secret {
+        Some(name) => name.clone(),
+        None => match config.config.remote_secret()? {
+            Some(name) => name.to_string(),
+            None => return Ok(config),
+        },
+    };
+
+    // The flags also apply to the backend storing the config
+    if let Some(profile) = &args.profile {
+        config.aws.profile = Some(profile.clone());
+    }
+
+    if let Some(region) = &args.region {
+        config.aws.region = Some(region.clone());
+    }
+
+    tracing::debug!(%name, "loading config from secret");
+
+    let secret = create_secret_manager(&config).await?;
+    let config = read_remote_config(secret.as_ref(), &name).await?;
+    Ok(config)
+}
+
+/// Initialize a config from a project template
+async fn init_command(
+    args: &Args,
+    template: Option<InitTemplate>,
+    name: Option<&str>,
+    provider: BackendProvider,
+    force: bool,
+) -> eyre::Result<Output> {
+    let working_path = match &args.working_dir {
+        Some(value) => absolute(value).context("failed to get absolute working path")?,
+        None => current_dir().context("failed to determine current directory")?,
+    };
+
+    let config_path = match &args.config {
+        Some(value) => absolute(value).context("failed to get absolute config path")?,
+        None => working_path.join(config::CONFIG_FILE_NAME_TOML),
+    };
+
+    if !force && tokio::fs::try_exists(&config_path).await.unwrap_or(false) {
+        eyre::bail!(
+            "config file \"{}\" already exists, use --force to replace it",
+            config_path.display()
+        );
+    }
+
+    let project = match name {
+        Some(name) => project_name(name),
+        None => project_name(
+            &working_path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default(),
+        ),
+    };
+
+    let contents = init_config(template, provider, &project);
+    tokio::fs::write(&config_path, contents)
+        .await
+        .context("failed to write config file")?;
+
+    let files = init_file_paths(template);
+
+    Ok(Output {
+        text: format!(
+            "created \"{}\" for project \"{project}\", add the secret files to .gitignore:\n{}",
+            config_path.display(),
+            files.join("\n")
+        ),
+        json: json!({
+            "success": true,
+            "config": config_path,
+            "project": project,
+            "files": files,
+        }),
+    })
+}
+
+/// Handle the context management sub commands
+async fn context_command(command: &ContextCommand) -> eyre::Result<Output> {
+    let mut user_config = read_user_config().await?;
+
+    match command {
+        ContextCommand::List => {
+            let current = user_config.current_context.as_deref();
+            let text = user_config
+                .contexts
+                .keys()
+                .map(|name| match Some(name.as_str()) == current {
+                    true => format!("* {name}"),
+                    false => format!("  {name}"),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            Ok(Output {
+                text,
+                json: json!({
+                    "success": true,
+                    "current_context": current,
+                    "contexts": user_config.contexts,
+                }),
+            })
+        }
+
+        ContextCommand::Use { name } => {
+            if !user_config.contexts.contains_key(name) {
+                eyre::bail!("unknown context \"{name}\"");
+            }
+
+            user_config.current_context = Some(name.clone());
+            write_user_config(&user_config).await?;
+
+            Ok(Output {
+                text: format!("switched to context \"{name}\""),
+                json: json!({ "success": true }),
+            })
+        }
+
+        ContextCommand::Show { name } => {
+            let (name, context) = user_config
+                .resolve_context(name.as_deref())?
+                .context("no active context")?;
+
+            Ok(Output {
+                text: context.to_text(name),
+                json: json!({ "success": true, "name": name, "context": context }),
+            })
+        }
+
+        ContextCommand::Set {
+            name,
+            provider,
+            profile,
+            region,
+            environment,
+        } => {
+            let context = user_config
+                .contexts
+                .entry(name.clone())
+                .or_insert_with(NamedContext::default);
+
+            if provider.is_some() {
+                context.provider = *provider;
+            }
+
+            if profile.is_some() {
+                context.profile = profile.clone();
+            }
+
+            if region.is_some() {
+                context.region = region.clone();
+            }
+
+            if environment.is_some() {
+                context.environment = environment.clone();
+            }
+
+            write_user_config(&user_config).await?;
+
+            Ok(Output {
+                text: format!("saved context \"{name}\""),
+                json: json!({ "success": true }),
+            })
+        }
+    }
+}
+
+/// Parse a KEY=VALUE tag argument
+fn parse_tag(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("invalid tag \"{value}\", expected KEY=VALUE"))
+}
+
+/// Warn when the local secret `file` within the `working_path` can be read
+/// by every user of the machine
+#[cfg(unix)]
+fn check_world_readable(file: &SecretFile, working_path: &Path, progress: &Progress) {
+    use std::os::unix::fs::PermissionsExt;
+
+    if file.host.is_some() || file.sink.is_some() {
+        return;
+    }
+
+    let path = file.resolve_path(working_path);
+    if let Ok(metadata) = std::fs::metadata(&path)
+        && metadata.permissions().mode() & 0o004 != 0
+    {
+        progress.warn(format!(
+            "secret file \"{}\" is world-readable",
+            path.display()
+        ));
+    }
+}
+
+/// File permissions are not checked outside of unix
+#[cfg(not(unix))]
+fn check_world_readable(_file: &SecretFile, _working_path: &Path, _progress: &Progress) {}
+
+/// Plans are applied against the local file system so they cannot
+/// contain files on remote hosts
+fn reject_remote_files(files: &[(&String, &SecretFile)]) -> eyre::Result<()> {
+    if let Some((name, file)) = files.iter().find(|(_name, file)| file.host.is_some()) {
+        eyre::bail!(
+            "file \"{name}\" is on remote host \"{}\", plans only support local files",
+            file.host.as_deref().unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+/// Filter the files within the `config` only returning the results that
+/// match `filter`, fails if the filter excluded every file
+fn filter_config_files<'a>(
+    config: &'a Config,
+    config_path: &Path,
+    filter: &TargetFilter,
+) -> eyre::Result<Vec<(&'a String, &'a SecretFile)>> {
+    let mut files = filter_files(
+        &config.files,
+        filter.file.as_deref(),
+        filter.glob.as_deref(),
+    );
+
+    if let Some(services) = &filter.service {
+        files.retain(|(_, file)| {
+            file.service
+                .as_ref()
+                .is_some_and(|service| services.contains(service))
+        });
+    }
+
+    if files.is_empty() && !config.files.is_empty() {
+        eyre::bail!(
+            "no files matching filter within \"{}\"",
+            config_path.display()
+        )
+    }
+
+    Ok(files)
 }
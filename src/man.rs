@@ -0,0 +1,72 @@
+//! # Man
+//!
+//! Generation of man pages for the CLI and each of its subcommands, named
+//! in the same style as git (e.g. `secret-sync-push.1`) for offline use
+
+use eyre::Context;
+use std::path::{Path, PathBuf};
+
+/// Write a man page for the `command` and each of its nested subcommands
+/// into `out_dir`, providing the paths of the written pages
+pub fn write_man_pages(command: &clap::Command, out_dir: &Path) -> eyre::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(out_dir).context("failed to create man page directory")?;
+
+    let mut command = command.clone();
+    command.build();
+
+    let mut pages = Vec::new();
+    write_command_pages(&command, out_dir, &mut pages)?;
+    Ok(pages)
+}
+
+/// Write the man page for `command` followed by the pages for its
+/// subcommands, the built command provides the prefixed display names
+fn write_command_pages(
+    command: &clap::Command,
+    out_dir: &Path,
+    pages: &mut Vec<PathBuf>,
+) -> eyre::Result<()> {
+    let man = clap_mangen::Man::new(command.clone());
+
+    let path = out_dir.join(man.get_filename());
+    let mut buffer = Vec::new();
+    man.render(&mut buffer)
+        .with_context(|| format!("failed to render man page for \"{}\"", command.get_name()))?;
+    std::fs::write(&path, buffer)
+        .with_context(|| format!("failed to write man page \"{}\"", path.display()))?;
+    pages.push(path);
+
+    for subcommand in command.get_subcommands() {
+        if subcommand.is_hide_set() || subcommand.get_name() == "help" {
+            continue;
+        }
+
+        write_command_pages(subcommand, out_dir, pages)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Args, man::write_man_pages};
+    use clap::CommandFactory;
+
+    /// Tests that pages are written for nested subcommands
+    #[test]
+    fn test_write_man_pages() {
+        let out_dir = tempfile::tempdir().unwrap();
+        let pages = write_man_pages(&Args::command(), out_dir.path()).unwrap();
+
+        for name in [
+            "secret-sync.1",
+            "secret-sync-push.1",
+            "secret-sync-agent-start.1",
+        ] {
+            assert!(pages.contains(&out_dir.path().join(name)), "missing {name}");
+        }
+
+        let push = std::fs::read_to_string(out_dir.path().join("secret-sync-push.1")).unwrap();
+        assert!(push.contains("secret\\-sync push"));
+    }
+}
@@ -0,0 +1,241 @@
+//! # Migrate
+//!
+//! Migration of the secrets of the configured files from one backend to
+//! another (e.g. from Vault to AWS), copying each value along with its
+//! description and tags. Every secret is attempted and reported on its
+//! own so a single failure does not hide the state of the others
+
+use crate::{
+    config::{SecretFile, SecretMetadata},
+    plan::PlanAction,
+    push::check_secret_ownership,
+    secret::SecretManager,
+};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Result of migrating a single secret
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct MigrationEntry {
+    /// Name of the secret
+    pub secret: String,
+    /// Outcome of the migration
+    pub result: MigrationResult,
+    /// Error the migration of the secret failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of migrating a single secret
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationResult {
+    /// Secret was created within the target backend
+    Created,
+    /// Secret existed within the target backend with a different value
+    /// and was updated
+    Updated,
+    /// Secret already matches within the target backend
+    Unchanged,
+    /// Secret does not exist within the source backend
+    Missing,
+    /// Migrating the secret failed
+    Failed,
+}
+
+impl From<PlanAction> for MigrationResult {
+    fn from(action: PlanAction) -> Self {
+        match action {
+            PlanAction::Create => MigrationResult::Created,
+            PlanAction::Update => MigrationResult::Updated,
+            PlanAction::Skip => MigrationResult::Unchanged,
+        }
+    }
+}
+
+/// Count the number of `entries` with the provided `result`
+pub fn migration_count(entries: &[MigrationEntry], result: MigrationResult) -> usize {
+    entries
+        .iter()
+        .filter(|entry| entry.result == result)
+        .count()
+}
+
+/// Render a human readable version of the migration `entries`
+pub fn migration_text(entries: &[MigrationEntry], dry_run: bool) -> String {
+    let mut text = String::new();
+
+    for entry in entries {
+        let symbol = match entry.result {
+            MigrationResult::Created => "+ create ",
+            MigrationResult::Updated => "~ update ",
+            MigrationResult::Unchanged => "  skip   ",
+            MigrationResult::Missing => "? missing",
+            MigrationResult::Failed => "! failed ",
+        };
+
+        _ = write!(text, "{symbol} {}", entry.secret);
+        if let Some(error) = &entry.error {
+            _ = write!(text, ": {error}");
+        }
+        _ = writeln!(text);
+    }
+
+    let title = match dry_run {
+        true => "Migration (dry run)",
+        false => "Migration",
+    };
+
+    _ = write!(
+        text,
+        "{title}: {} created, {} updated, {} unchanged, {} missing, {} failed",
+        migration_count(entries, MigrationResult::Created),
+        migration_count(entries, MigrationResult::Updated),
+        migration_count(entries, MigrationResult::Unchanged),
+        migration_count(entries, MigrationResult::Missing),
+        migration_count(entries, MigrationResult::Failed),
+    );
+
+    text
+}
+
+/// Copy the secrets of the `files` from the `from` backend into the `to`
+/// backend, only reporting the changes when `dry_run` is set
+///
+/// The description and tags of the source secret are copied, the metadata
+/// of the file takes priority. Existing target secrets not created by
+/// secret-sync are only overwritten when `adopt` is set
+pub async fn migrate_secrets<'a>(
+    from: &dyn SecretManager,
+    to: &dyn SecretManager,
+    files: impl IntoIterator<Item = &'a SecretFile>,
+    dry_run: bool,
+    adopt: bool,
+) -> Vec<MigrationEntry> {
+    let mut entries: Vec<MigrationEntry> = Vec::new();
+
+    for file in files {
+        // Multiple files may use the same secret
+        if entries.iter().any(|entry| entry.secret == file.secret) {
+            continue;
+        }
+
+        let (result, error) = match migrate_secret(from, to, file, dry_run, adopt).await {
+            Ok(result) => (result, None),
+            Err(error) => (MigrationResult::Failed, Some(format!("{error:#}"))),
+        };
+
+        entries.push(MigrationEntry {
+            secret: file.secret.clone(),
+            result,
+            error,
+        });
+    }
+
+    entries
+}
+
+/// Copy the secret of a single `file`, see [migrate_secrets]
+async fn migrate_secret(
+    from: &dyn SecretManager,
+    to: &dyn SecretManager,
+    file: &SecretFile,
+    dry_run: bool,
+    adopt: bool,
+) -> eyre::Result<MigrationResult> {
+    let Some(value) = from.find_secret(&file.secret).await? else {
+        return Ok(MigrationResult::Missing);
+    };
+
+    let action = match to.find_secret(&file.secret).await? {
+        None => PlanAction::Create,
+        Some(existing) if existing.hash() == value.hash() => PlanAction::Skip,
+        Some(_) => PlanAction::Update,
+    };
+
+    if dry_run || action == PlanAction::Skip {
+        return Ok(action.into());
+    }
+
+    if action == PlanAction::Update {
+        check_secret_ownership(to, &[&file.secret], adopt).await?;
+    }
+
+    let mut metadata = SecretMetadata::default();
+    if let Some(summary) = from.describe_secret(&file.secret).await? {
+        metadata.description = summary.description;
+        metadata.tags = Some(summary.tags).filter(|tags| !tags.is_empty());
+    }
+
+    if let Some(description) = &file.metadata.description {
+        metadata.description = Some(description.clone());
+    }
+
+    if let Some(tags) = &file.metadata.tags {
+        metadata
+            .tags
+            .get_or_insert_default()
+            .extend(tags.iter().map(|(key, value)| (key.clone(), value.clone())));
+    }
+
+    to.set_secret(&file.secret, value, &metadata).await?;
+    Ok(action.into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{SecretFile, SecretMetadata},
+        migrate::{MigrationResult, migrate_secrets},
+        secret::{Secret, SecretManager, memory::MemorySecretManager},
+    };
+    use indexmap::IndexMap;
+
+    /// Tests that secrets are copied with their metadata and that each
+    /// secret is reported on its own
+    #[tokio::test]
+    async fn test_migrate_secrets() {
+        let from = MemorySecretManager::new();
+        let to = MemorySecretManager::new();
+
+        let metadata = SecretMetadata {
+            description: Some("database".to_string()),
+            tags: Some(IndexMap::from([("team".to_string(), "a".to_string())])),
+            ..Default::default()
+        };
+        for name in ["db", "same"] {
+            from.set_secret(name, Secret::String("value".to_string()), &metadata)
+                .await
+                .unwrap();
+        }
+        to.set_secret(
+            "same",
+            Secret::String("value".to_string()),
+            &SecretMetadata::default(),
+        )
+        .await
+        .unwrap();
+
+        let files = ["db", "same", "missing", "db"].map(|secret| SecretFile {
+            secret: secret.to_string(),
+            ..Default::default()
+        });
+
+        let entries = migrate_secrets(&from, &to, &files, true, false).await;
+        let results: Vec<MigrationResult> = entries.iter().map(|entry| entry.result).collect();
+        assert_eq!(
+            results,
+            vec![
+                MigrationResult::Created,
+                MigrationResult::Unchanged,
+                MigrationResult::Missing
+            ]
+        );
+        assert!(to.find_secret("db").await.unwrap().is_none());
+
+        migrate_secrets(&from, &to, &files, false, false).await;
+        let summary = to.describe_secret("db").await.unwrap().unwrap();
+        assert_eq!(summary.description.as_deref(), Some("database"));
+        assert_eq!(summary.tags.get("team").map(String::as_str), Some("a"));
+    }
+}
@@ -0,0 +1,324 @@
+//! # Mount
+//!
+//! Local development take on the Kubernetes secrets store CSI driver, the
+//! secrets of the configured files are written into a directory with one
+//! file per secret (named after the file in the config) which docker-compose
+//! services can bind mount. The files are refreshed on an interval and the
+//! directory is removed again once the command is stopped.
+//!
+//! On Linux the files are held in memory within a directory on the
+//! /dev/shm tmpfs which the mount directory links to, other platforms fall
+//! back to a regular directory only accessible by the current user
+
+use eyre::Context;
+use indexmap::IndexMap;
+use secret_sync::secret::{Secret, SecretManager};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Memory backed filesystem the mounted files are stored within
+#[cfg(target_os = "linux")]
+const TMPFS_PATH: &str = "/dev/shm";
+
+/// Directory the secret files are mounted into
+pub struct Mount {
+    /// Path the mount was requested at
+    path: PathBuf,
+    /// Directory on the tmpfs that `path` links to, [None] when `path` is
+    /// a regular directory
+    backing: Option<PathBuf>,
+    /// Values currently written to the mount by file name
+    values: IndexMap<String, Secret>,
+}
+
+impl Mount {
+    /// Create the mount directory at `path`, which must not exist or be
+    /// an empty directory
+    pub async fn create(path: &Path) -> eyre::Result<Mount> {
+        let backing = tmpfs_backing_path(path);
+
+        match tokio::fs::symlink_metadata(path).await {
+            Ok(metadata) if metadata.is_symlink() => {
+                // Left behind by a mount that was not stopped cleanly
+                let target = tokio::fs::read_link(path).await.ok();
+                if target.is_none() || target != backing {
+                    eyre::bail!("mount path \"{}\" is an existing link", path.display());
+                }
+
+                tokio::fs::remove_file(path)
+                    .await
+                    .context("failed to remove stale mount link")?;
+            }
+            Ok(metadata) if metadata.is_dir() => {
+                let mut entries = tokio::fs::read_dir(path)
+                    .await
+                    .context("failed to read mount directory")?;
+                if entries.next_entry().await?.is_some() {
+                    eyre::bail!("mount directory \"{}\" is not empty", path.display());
+                }
+
+                tokio::fs::remove_dir(path)
+                    .await
+                    .context("failed to replace mount directory")?;
+            }
+            Ok(_) => eyre::bail!("mount path \"{}\" is an existing file", path.display()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error).context("failed to read mount path"),
+        }
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("failed to create mount parent directory")?;
+        }
+
+        match &backing {
+            Some(backing) => {
+                if tokio::fs::try_exists(backing).await.unwrap_or(false) {
+                    tokio::fs::remove_dir_all(backing)
+                        .await
+                        .context("failed to remove stale tmpfs directory")?;
+                }
+
+                create_private_dir(backing).await?;
+                link_dir(backing, path).await?;
+            }
+            None => {
+                tracing::warn!("tmpfs is not available, mounted secrets are stored on disk");
+                create_private_dir(path).await?;
+            }
+        }
+
+        Ok(Mount {
+            path: path.to_path_buf(),
+            backing,
+            values: IndexMap::new(),
+        })
+    }
+
+    /// Whether the mounted files are held in memory
+    pub fn is_tmpfs(&self) -> bool {
+        self.backing.is_some()
+    }
+
+    /// Directory the files are written into
+    fn dir(&self) -> &Path {
+        self.backing.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Fetch the secret of each of the `files` (file name to secret name),
+    /// writing the values that changed. Provides the number of files that
+    /// were written
+    pub async fn refresh(
+        &mut self,
+        secret: &dyn SecretManager,
+        files: &IndexMap<String, String>,
+    ) -> eyre::Result<usize> {
+        let mut changed = 0;
+
+        for (name, secret_name) in files {
+            let value = secret
+                .get_secret(secret_name)
+                .await
+                .with_context(|| format!("failed to get secret for mounted file \"{name}\""))?
+                .data;
+
+            if self.values.get(name) == Some(&value) {
+                continue;
+            }
+
+            let path = self.dir().join(name);
+            write_mounted_file(&path, value.as_bytes())
+                .await
+                .with_context(|| format!("failed to write mounted file \"{name}\""))?;
+
+            tracing::info!(file = %name, "mounted file updated");
+            self.values.insert(name.clone(), value);
+            changed += 1;
+        }
+
+        Ok(changed)
+    }
+
+    /// Remove the mount directory along with the mounted files
+    pub async fn remove(self) -> eyre::Result<()> {
+        match &self.backing {
+            Some(backing) => {
+                tokio::fs::remove_file(&self.path)
+                    .await
+                    .context("failed to remove mount link")?;
+                tokio::fs::remove_dir_all(backing)
+                    .await
+                    .context("failed to remove tmpfs directory")
+            }
+            None => tokio::fs::remove_dir_all(&self.path)
+                .await
+                .context("failed to remove mount directory"),
+        }
+    }
+}
+
+/// Keep the `files` of the `mount` refreshed every `interval` until the
+/// process is interrupted or terminated, then remove the mount
+pub async fn run_mount(
+    mut mount: Mount,
+    secret: &dyn SecretManager,
+    files: &IndexMap<String, String>,
+    interval: Duration,
+) -> eyre::Result<()> {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                // Keep serving the previous values when the backend is unavailable
+                if let Err(error) = mount.refresh(secret, files).await {
+                    tracing::warn!("failed to refresh mounted secrets: {error:#}");
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    mount.remove().await
+}
+
+/// Path of the tmpfs directory backing the mount at `path`, derived from
+/// the path so a mount that was not stopped cleanly can be replaced
+#[cfg(target_os = "linux")]
+fn tmpfs_backing_path(path: &Path) -> Option<PathBuf> {
+    let tmpfs = Path::new(TMPFS_PATH);
+    if !tmpfs.is_dir() {
+        return None;
+    }
+
+    let hash = Sha256::digest(path.as_os_str().as_encoded_bytes());
+    Some(tmpfs.join(format!("secret-sync-{}", &hex::encode(hash)[..16])))
+}
+
+/// Path of the tmpfs directory backing the mount at `path`, tmpfs is only
+/// used on Linux
+#[cfg(not(target_os = "linux"))]
+fn tmpfs_backing_path(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// Create the directory at `path` only accessible by the current user
+async fn create_private_dir(path: &Path) -> eyre::Result<()> {
+    let mut builder = tokio::fs::DirBuilder::new();
+
+    #[cfg(unix)]
+    builder.mode(0o700);
+
+    builder
+        .create(path)
+        .await
+        .with_context(|| format!("failed to create mount directory \"{}\"", path.display()))
+}
+
+/// Link the mount `path` to the `backing` directory
+#[cfg(unix)]
+async fn link_dir(backing: &Path, path: &Path) -> eyre::Result<()> {
+    tokio::fs::symlink(backing, path)
+        .await
+        .with_context(|| format!("failed to link mount path \"{}\"", path.display()))
+}
+
+/// Link the mount `path` to the `backing` directory
+#[cfg(not(unix))]
+async fn link_dir(_backing: &Path, _path: &Path) -> eyre::Result<()> {
+    eyre::bail!("linking the mount directory is not supported on this platform")
+}
+
+/// Write the `value` to `path` through a temporary file so readers never
+/// see a partially written secret
+async fn write_mounted_file(path: &Path, value: &[u8]) -> eyre::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options.open(&temp_path).await?;
+    tokio::io::AsyncWriteExt::write_all(&mut file, value).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    tokio::fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
+/// Wait for the process to be interrupted (Ctrl+C) or terminated, as
+/// docker-compose and other supervisors stop processes with SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+
+    _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        mount::Mount,
+        secret::{MockSecretManager, Secret},
+    };
+    use indexmap::IndexMap;
+
+    /// Tests that secrets are written into the mount, only changed values
+    /// are rewritten, and the mount is removed afterwards
+    #[tokio::test]
+    async fn test_mount() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets");
+        std::fs::create_dir(&path).unwrap();
+
+        let mut secret = MockSecretManager::new();
+        let mut values = vec!["first", "first", "second"].into_iter();
+        secret
+            .expect_get_secret()
+            .times(3)
+            .returning(move |_name| Ok(Secret::String(values.next().unwrap().into()).into()));
+
+        let files = IndexMap::from([("db_password".to_string(), "app/db".to_string())]);
+
+        let mut mount = Mount::create(&path).await.unwrap();
+        assert_eq!(mount.refresh(&secret, &files).await.unwrap(), 1);
+        assert_eq!(mount.refresh(&secret, &files).await.unwrap(), 0);
+        assert_eq!(mount.refresh(&secret, &files).await.unwrap(), 1);
+        assert_eq!(
+            std::fs::read_to_string(path.join("db_password")).unwrap(),
+            "second"
+        );
+
+        // Only empty directories may be replaced by a mount
+        let occupied = directory.path().join("occupied");
+        std::fs::create_dir(&occupied).unwrap();
+        std::fs::write(occupied.join("file"), "").unwrap();
+        assert!(Mount::create(&occupied).await.is_err());
+
+        mount.remove().await.unwrap();
+        assert!(std::fs::symlink_metadata(&path).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! # Ping
+//!
+//! Health checks against the configured backend, each check performs a
+//! cheap authenticated call and reports whether it succeeded along with
+//! its latency
+
+use secret_sync::{error::SyncError, secret::SecretManager};
+use serde::Serialize;
+use std::{fmt::Write, time::Instant};
+
+/// Secret described to check the secrets API is reachable, the secret
+/// is not expected to exist
+const PING_SECRET_NAME: &str = "secret-sync/ping";
+
+/// Result of a single health check
+#[derive(Debug, Serialize)]
+pub struct PingCheck {
+    /// Name of the check
+    pub check: &'static str,
+    /// Whether the check succeeded
+    pub ok: bool,
+    /// Time taken by the check in milliseconds
+    pub latency_ms: u64,
+    /// Error the check failed with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Check the credentials are valid and the secrets API of the backend
+/// is reachable
+pub async fn ping(secret: &dyn SecretManager) -> Vec<PingCheck> {
+    let started = Instant::now();
+    let auth = secret.verify_access().await;
+    let auth = ping_check("auth", started, auth);
+
+    let started = Instant::now();
+    let secrets = secret.describe_secret(PING_SECRET_NAME).await;
+    let secrets = ping_check("secrets", started, secrets.map(|_| ()));
+
+    vec![auth, secrets]
+}
+
+/// Create the check `name` from its `result` and `started` time
+fn ping_check(name: &'static str, started: Instant, result: Result<(), SyncError>) -> PingCheck {
+    PingCheck {
+        check: name,
+        ok: result.is_ok(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error: result.err().map(|error| format!("{error:#}")),
+    }
+}
+
+/// Render a human readable version of the `checks` for the `backend`
+pub fn ping_text(backend: &str, checks: &[PingCheck]) -> String {
+    let mut text = format!("backend: {backend}");
+
+    for check in checks {
+        let status = match check.ok {
+            true => "ok",
+            false => "failed",
+        };
+
+        _ = write!(
+            text,
+            "\n  {}: {status} ({}ms)",
+            check.check, check.latency_ms
+        );
+
+        if let Some(error) = &check.error {
+            _ = write!(text, ": {error}");
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ping::ping;
+    use secret_sync::{error::SyncError, secret::MockSecretManager};
+
+    /// Tests that failing checks are reported with their error
+    #[tokio::test]
+    async fn test_ping() {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_verify_access()
+            .return_once(|| Err(SyncError::backend_auth("invalid credentials")));
+        secret
+            .expect_describe_secret()
+            .return_once(|_name| Ok(None));
+
+        let checks = ping(&secret).await;
+
+        assert_eq!(checks.len(), 2);
+        assert!(!checks[0].ok);
+        assert_eq!(checks[0].error.as_deref(), Some("invalid credentials"));
+        assert!(checks[1].ok);
+    }
+}
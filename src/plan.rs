@@ -0,0 +1,447 @@
+//! # Plan
+//!
+//! Structured push plans describing the action that will be taken for
+//! each secret file. Plans can be saved and later applied exactly, the
+//! apply step refuses to run if the local or remote state has changed
+//! since the plan was created.
+
+use crate::{
+    arn::display_secret_name,
+    config::{SecretFile, SecretMetadata},
+    fs::FileSystem,
+    push::{PushInfo, check_secret_ownership},
+    secret::{Secret, SecretManager},
+};
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, path::Path, path::PathBuf};
+
+/// Current version of the plan format
+const PLAN_VERSION: u32 = 1;
+
+/// Plan of actions to perform when pushing
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Plan {
+    /// Version of the plan format
+    pub version: u32,
+    /// Planned entries in the order they will be applied
+    pub entries: Vec<PlanEntry>,
+}
+
+/// Planned action for a single secret file
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlanEntry {
+    /// Name of the file entry within the config
+    pub name: String,
+    /// Name of the secret
+    pub secret: String,
+    /// Path to the local file relative to the config file, so the plan can
+    /// be applied from another checkout
+    pub path: PathBuf,
+    /// Action to perform
+    pub action: PlanAction,
+    /// Hash of the local file contents at the time of planning
+    pub local_hash: String,
+    /// Hash of the remote secret at the time of planning, [None] when
+    /// the secret did not exist
+    pub remote_hash: Option<String>,
+    /// Metadata to use when creating the secret, with the push placeholders
+    /// resolved at the time of planning
+    pub metadata: SecretMetadata,
+    /// Notes of the file entry, see [SecretFile::notes]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Ticket of the file entry, see [SecretFile::ticket]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+}
+
+/// Action to perform for a secret file
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    /// Secret does not exist and will be created
+    Create,
+    /// Secret exists with a different value and will be updated
+    Update,
+    /// Secret already matches the local file
+    Skip,
+}
+
+/// Outcome of applying a plan
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ApplySummary {
+    /// Number of secrets created
+    pub created: usize,
+    /// Number of secrets updated
+    pub updated: usize,
+    /// Number of secrets skipped
+    pub skipped: usize,
+}
+
+impl Plan {
+    /// Count the number of entries with the provided `action`
+    pub fn count(&self, action: PlanAction) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.action == action)
+            .count()
+    }
+
+    /// Render a human readable version of the plan
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for entry in &self.entries {
+            let symbol = match entry.action {
+                PlanAction::Create => "+ create",
+                PlanAction::Update => "~ update",
+                PlanAction::Skip => "  skip  ",
+            };
+
+            _ = writeln!(
+                text,
+                "{symbol} {} ({} -> {})",
+                entry.name,
+                entry.path.display(),
+                display_secret_name(&entry.secret)
+            );
+
+            if let Some(ticket) = &entry.ticket {
+                _ = writeln!(text, "    ticket: {ticket}");
+            }
+
+            if let Some(notes) = &entry.notes {
+                _ = writeln!(text, "    notes: {notes}");
+            }
+        }
+
+        _ = write!(
+            text,
+            "Plan: {} to create, {} to update, {} unchanged",
+            self.count(PlanAction::Create),
+            self.count(PlanAction::Update),
+            self.count(PlanAction::Skip)
+        );
+
+        text
+    }
+}
+
+/// Create a push plan for the provided `files`, the push placeholders within
+/// the metadata of the files are resolved using `info`
+pub async fn create_plan<'a, Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    files: impl IntoIterator<Item = (&'a String, &'a SecretFile)>,
+    info: &PushInfo,
+) -> eyre::Result<Plan> {
+    let mut entries = Vec::new();
+
+    for (name, file) in files {
+        let path = file.resolve_path(working_path);
+        let local = Secret::from_bytes(fs.read_file(&path).await?);
+        let local_hash = local.hash();
+
+        let remote_hash = secret
+            .find_secret(&file.secret)
+            .await
+            .with_context(|| format!("failed to get secret \"{}\"", file.secret))?
+            .map(|remote| remote.hash());
+
+        let action = match &remote_hash {
+            None => PlanAction::Create,
+            Some(remote_hash) if remote_hash == &local_hash => PlanAction::Skip,
+            Some(_) => PlanAction::Update,
+        };
+
+        entries.push(PlanEntry {
+            name: name.clone(),
+            secret: file.secret.clone(),
+            path: file.path.clone(),
+            action,
+            local_hash,
+            remote_hash,
+            metadata: info.render_file(file).metadata,
+            notes: file.notes.clone(),
+            ticket: file.ticket.clone(),
+        });
+    }
+
+    Ok(Plan {
+        version: PLAN_VERSION,
+        entries,
+    })
+}
+
+/// Apply a previously created `plan`, the paths of its entries are resolved
+/// relative to the `working_path`
+///
+/// All entries are verified against the current local and remote state
+/// before any secret is modified. Existing secrets not created by
+/// secret-sync are only overwritten when `adopt` is set
+pub async fn apply_plan<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    plan: &Plan,
+    adopt: bool,
+) -> eyre::Result<ApplySummary> {
+    if plan.version != PLAN_VERSION {
+        eyre::bail!("unsupported plan version {}", plan.version);
+    }
+
+    let mut values = Vec::with_capacity(plan.entries.len());
+
+    for entry in &plan.entries {
+        let path = working_path.join(&entry.path);
+        let local = Secret::from_bytes(fs.read_file(&path).await?);
+        if local.hash() != entry.local_hash {
+            eyre::bail!(
+                "file \"{}\" has changed since the plan was created",
+                entry.name
+            );
+        }
+
+        let remote_hash = secret
+            .find_secret(&entry.secret)
+            .await
+            .with_context(|| format!("failed to get secret \"{}\"", entry.secret))?
+            .map(|remote| remote.hash());
+
+        if remote_hash != entry.remote_hash {
+            eyre::bail!(
+                "secret \"{}\" has changed since the plan was created",
+                entry.secret
+            );
+        }
+
+        values.push(local);
+    }
+
+    let names: Vec<&str> = plan
+        .entries
+        .iter()
+        .filter(|entry| entry.action == PlanAction::Update)
+        .map(|entry| entry.secret.as_str())
+        .collect();
+    check_secret_ownership(secret, &names, adopt).await?;
+
+    let mut summary = ApplySummary::default();
+
+    for (entry, value) in plan.entries.iter().zip(values) {
+        match entry.action {
+            PlanAction::Create => summary.created += 1,
+            PlanAction::Update => summary.updated += 1,
+            PlanAction::Skip => {
+                summary.skipped += 1;
+                continue;
+            }
+        }
+
+        secret
+            .set_secret(&entry.secret, value, &entry.metadata)
+            .await
+            .context("failed to store secret")?;
+    }
+
+    Ok(summary)
+}
+
+/// Read a plan from the file at `path`
+#[cfg(not(target_family = "wasm"))]
+pub async fn read_plan_file(path: &Path) -> eyre::Result<Plan> {
+    let value = tokio::fs::read(path)
+        .await
+        .context("failed to read plan file")?;
+
+    serde_json::from_slice(&value).context("failed to parse plan file")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{SecretFile, SecretMetadata},
+        fs::MockFileSystem,
+        plan::{ApplySummary, PlanAction, apply_plan, create_plan},
+        push::PushInfo,
+        secret::{MANAGED_BY_TAG, MANAGED_BY_VALUE, MockSecretManager, Secret, SecretSummary},
+    };
+    use indexmap::IndexMap;
+    use mockall::predicate::eq;
+    use std::path::{Path, PathBuf};
+
+    /// Creates a set of test files for planning
+    fn test_files() -> IndexMap<String, SecretFile> {
+        let mut files = IndexMap::new();
+
+        for name in ["new", "changed", "same"] {
+            files.insert(
+                name.to_string(),
+                SecretFile {
+                    path: PathBuf::from(format!(".env.{name}")),
+                    secret: name.to_string(),
+                    metadata: SecretMetadata::default(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        files
+    }
+
+    /// Creates the push details used for planning
+    fn test_info() -> PushInfo {
+        PushInfo {
+            repo: None,
+            commit: None,
+            branch: None,
+            user: "jacob".to_string(),
+            time: 0,
+        }
+    }
+
+    /// Tests that each action is planned based on the remote state
+    #[tokio::test]
+    async fn test_create_plan() {
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .times(3)
+            .returning(|_path| Ok(b"local".to_vec()));
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("new"))
+            .return_once(|_name| Ok(None));
+        secret
+            .expect_find_secret()
+            .with(eq("changed"))
+            .return_once(|_name| Ok(Some(Secret::String("remote".to_string()))));
+        secret
+            .expect_find_secret()
+            .with(eq("same"))
+            .return_once(|_name| Ok(Some(Secret::String("local".to_string()))));
+
+        let mut files = test_files();
+        files["new"].ticket = Some("OPS-123".to_string());
+        files["new"].notes = Some("ask the payments team".to_string());
+        files["new"].metadata.description = Some("Pushed by {user}".to_string());
+        let plan = create_plan(&fs, &secret, Path::new("/"), &files, &test_info())
+            .await
+            .unwrap();
+
+        let text = plan.to_text();
+        assert!(text.contains("ticket: OPS-123"));
+        assert!(text.contains("notes: ask the payments team"));
+
+        let actions: Vec<PlanAction> = plan.entries.iter().map(|entry| entry.action).collect();
+        assert_eq!(
+            actions,
+            vec![PlanAction::Create, PlanAction::Update, PlanAction::Skip]
+        );
+        assert_eq!(plan.entries[0].path, Path::new(".env.new"));
+        assert_eq!(
+            plan.entries[0].metadata.description.as_deref(),
+            Some("Pushed by jacob")
+        );
+    }
+
+    /// Tests that applying a plan only sets secrets that need changes
+    #[tokio::test]
+    async fn test_apply_plan() {
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .returning(|_path| Ok(b"local".to_vec()));
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("new"))
+            .returning(|_name| Ok(None));
+        secret
+            .expect_find_secret()
+            .with(eq("changed"))
+            .returning(|_name| Ok(Some(Secret::String("remote".to_string()))));
+        secret
+            .expect_find_secret()
+            .with(eq("same"))
+            .returning(|_name| Ok(Some(Secret::String("local".to_string()))));
+
+        let files = test_files();
+        let plan = create_plan(&fs, &secret, Path::new("/"), &files, &test_info())
+            .await
+            .unwrap();
+
+        // Only the updated secret is checked for ownership
+        secret
+            .expect_describe_secret()
+            .times(1)
+            .with(eq("changed"))
+            .returning(|name| {
+                Ok(Some(SecretSummary {
+                    name: name.to_string(),
+                    tags: IndexMap::from([(
+                        MANAGED_BY_TAG.to_string(),
+                        MANAGED_BY_VALUE.to_string(),
+                    )]),
+                    ..Default::default()
+                }))
+            });
+        secret
+            .expect_set_secret()
+            .times(2)
+            .withf(|name, value, _metadata| {
+                (name == "new" || name == "changed")
+                    && value == &Secret::String("local".to_string())
+            })
+            .returning(|_name, _value, _metadata| Ok(()));
+
+        let summary = apply_plan(&fs, &secret, Path::new("/"), &plan, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            ApplySummary {
+                created: 1,
+                updated: 1,
+                skipped: 1
+            }
+        );
+    }
+
+    /// Tests that applying a plan fails when the local file has changed
+    #[tokio::test]
+    async fn test_apply_plan_stale() {
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .times(1)
+            .return_once(|_path| Ok(b"local".to_vec()));
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_find_secret().returning(|_name| Ok(None));
+
+        let mut files = test_files();
+        files.truncate(1);
+
+        let plan = create_plan(&fs, &secret, Path::new("/"), &files, &test_info())
+            .await
+            .unwrap();
+
+        // Local file is modified after planning
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .times(1)
+            .return_once(|_path| Ok(b"modified".to_vec()));
+
+        // No secrets should be set
+        secret.expect_set_secret().never();
+
+        assert!(
+            apply_plan(&fs, &secret, Path::new("/"), &plan, false)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! # Promote
+//!
+//! Promotion of secret values from one environment to another. Files with
+//! the {env} placeholder in their secret name map to a secret per
+//! environment, promoting copies the value of the source environment
+//! secret into the target environment secret
+//!
+//! Promotions describe the changes by key name only, values are never
+//! included so the diff can be shown before confirming
+
+use crate::{
+    config::{ENV_PLACEHOLDER, SecretFile, SecretMetadata, validate_environment},
+    plan::PlanAction,
+    push::check_secret_ownership,
+    secret::{Secret, SecretManager},
+    structured::StructuredValue,
+};
+use eyre::{Context, ContextCompat};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Planned promotion between two environments
+#[derive(Debug, Serialize)]
+pub struct Promotion {
+    /// Environment the values are copied from
+    pub from: String,
+    /// Environment the values are copied to
+    pub to: String,
+    /// Promoted entries in the order they will be applied
+    pub entries: Vec<PromotionEntry>,
+}
+
+/// Promotion of a single file
+#[derive(Debug, Serialize)]
+pub struct PromotionEntry {
+    /// Name of the file entry within the config
+    pub name: String,
+    /// Secret the value is copied from
+    pub from_secret: String,
+    /// Secret the value is copied to
+    pub to_secret: String,
+    /// Action to perform on the target secret
+    pub action: PlanAction,
+    /// Changed keys for dotenv or JSON secrets, empty when the value is
+    /// not structured
+    pub changes: Vec<KeyChange>,
+    /// Value to promote
+    #[serde(skip)]
+    value: Secret,
+    /// Metadata to use when creating the target secret
+    #[serde(skip)]
+    metadata: SecretMetadata,
+}
+
+/// Change to a single key within a structured secret
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct KeyChange {
+    /// Name of the key
+    pub key: String,
+    /// Kind of change made to the key
+    pub kind: KeyChangeKind,
+}
+
+/// Kind of change made to a key
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyChangeKind {
+    /// Key does not exist in the target
+    Added,
+    /// Key exists in the target with a different value
+    Changed,
+    /// Key only exists in the target and will be removed
+    Removed,
+}
+
+impl Promotion {
+    /// Count the number of entries with the provided `action`
+    pub fn count(&self, action: PlanAction) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.action == action)
+            .count()
+    }
+
+    /// Render a human readable redacted diff of the promotion
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for entry in &self.entries {
+            let symbol = match entry.action {
+                PlanAction::Create => "+ create",
+                PlanAction::Update => "~ update",
+                PlanAction::Skip => "  skip  ",
+            };
+
+            _ = writeln!(
+                text,
+                "{symbol} {} ({} -> {})",
+                entry.name, entry.from_secret, entry.to_secret
+            );
+
+            for change in &entry.changes {
+                let symbol = match change.kind {
+                    KeyChangeKind::Added => '+',
+                    KeyChangeKind::Changed => '~',
+                    KeyChangeKind::Removed => '-',
+                };
+
+                _ = writeln!(text, "    {symbol} {}", change.key);
+            }
+        }
+
+        _ = write!(
+            text,
+            "Promotion {} -> {}: {} to create, {} to update, {} unchanged",
+            self.from,
+            self.to,
+            self.count(PlanAction::Create),
+            self.count(PlanAction::Update),
+            self.count(PlanAction::Skip)
+        );
+
+        text
+    }
+}
+
+/// Resolve the secret name `template` for the `environment`, [None] when
+/// the secret name does not use the {env} placeholder
+pub fn environment_secret(template: &str, environment: &str) -> Option<String> {
+    template
+        .contains(ENV_PLACEHOLDER)
+        .then(|| template.replace(ENV_PLACEHOLDER, environment))
+}
+
+/// Create a promotion of the `files` from the `from` environment to the
+/// `to` environment, the files must not have had an environment applied
+///
+/// Files that do not use the {env} placeholder in their secret name share
+/// a single secret between environments and are skipped
+pub async fn create_promotion<'a>(
+    secret: &dyn SecretManager,
+    files: impl IntoIterator<Item = (&'a String, &'a SecretFile)>,
+    from: &str,
+    to: &str,
+) -> eyre::Result<Promotion> {
+    validate_environment(from)?;
+    validate_environment(to)?;
+
+    if from == to {
+        eyre::bail!("cannot promote environment \"{from}\" to itself");
+    }
+
+    let mut entries = Vec::new();
+
+    for (name, file) in files {
+        let (Some(from_secret), Some(to_secret)) = (
+            environment_secret(&file.secret, from),
+            environment_secret(&file.secret, to),
+        ) else {
+            tracing::debug!(%name, "file secret does not use {ENV_PLACEHOLDER}, skipping");
+            continue;
+        };
+
+        let value = secret
+            .find_secret(&from_secret)
+            .await
+            .with_context(|| format!("failed to get secret \"{from_secret}\""))?
+            .with_context(|| format!("secret \"{from_secret}\" does not exist in \"{from}\""))?;
+
+        let target = secret
+            .find_secret(&to_secret)
+            .await
+            .with_context(|| format!("failed to get secret \"{to_secret}\""))?;
+
+        let action = match &target {
+            None => PlanAction::Create,
+            Some(target) if target.hash() == value.hash() => PlanAction::Skip,
+            Some(_) => PlanAction::Update,
+        };
+
+        let changes = match action {
+            PlanAction::Skip => Vec::new(),
+            _ => key_changes(&value, target.as_ref()),
+        };
+
+        entries.push(PromotionEntry {
+            name: name.clone(),
+            from_secret,
+            to_secret,
+            action,
+            changes,
+            value,
+            metadata: target_metadata(&file.metadata, to),
+        });
+    }
+
+    if entries.is_empty() {
+        eyre::bail!("none of the files use {ENV_PLACEHOLDER} in their secret name");
+    }
+
+    Ok(Promotion {
+        from: from.to_string(),
+        to: to.to_string(),
+        entries,
+    })
+}
+
+/// Create the metadata for the target secret in the `to` environment,
+/// resolving the {env} placeholder left in the description by defaults
+fn target_metadata(metadata: &SecretMetadata, to: &str) -> SecretMetadata {
+    SecretMetadata {
+        description: metadata
+            .description
+            .as_ref()
+            .map(|description| description.replace(ENV_PLACEHOLDER, to)),
+        ..metadata.clone()
+    }
+}
+
+/// Determine the keys that change when replacing `target` with `value`,
+/// empty when either value is not structured
+fn key_changes(value: &Secret, target: Option<&Secret>) -> Vec<KeyChange> {
+    let Ok(value) = StructuredValue::parse(value.as_bytes()) else {
+        return Vec::new();
+    };
+
+    let target = match target.map(|target| StructuredValue::parse(target.as_bytes())) {
+        Some(Ok(target)) => target.values,
+        Some(Err(_)) => return Vec::new(),
+        None => Default::default(),
+    };
+
+    let mut changes: Vec<KeyChange> = value
+        .values
+        .iter()
+        .filter_map(|(key, value)| {
+            let kind = match target.get(key) {
+                None => KeyChangeKind::Added,
+                Some(target) if target != value => KeyChangeKind::Changed,
+                Some(_) => return None,
+            };
+
+            Some(KeyChange {
+                key: key.clone(),
+                kind,
+            })
+        })
+        .collect();
+
+    changes.extend(
+        target
+            .keys()
+            .filter(|key| !value.values.contains_key(*key))
+            .map(|key| KeyChange {
+                key: key.clone(),
+                kind: KeyChangeKind::Removed,
+            }),
+    );
+
+    changes
+}
+
+/// Apply the `promotion`, copying each changed value into its target
+/// secret. Provides the number of secrets that were modified
+///
+/// Existing target secrets not created by secret-sync are only overwritten
+/// when `adopt` is set
+pub async fn apply_promotion(
+    secret: &dyn SecretManager,
+    promotion: Promotion,
+    adopt: bool,
+) -> eyre::Result<usize> {
+    let names: Vec<&str> = promotion
+        .entries
+        .iter()
+        .filter(|entry| entry.action == PlanAction::Update)
+        .map(|entry| entry.to_secret.as_str())
+        .collect();
+    check_secret_ownership(secret, &names, adopt).await?;
+
+    let mut modified = 0;
+
+    for entry in promotion.entries {
+        if entry.action == PlanAction::Skip {
+            continue;
+        }
+
+        secret
+            .set_secret(&entry.to_secret, entry.value, &entry.metadata)
+            .await
+            .with_context(|| format!("failed to promote secret \"{}\"", entry.to_secret))?;
+        modified += 1;
+    }
+
+    Ok(modified)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::SecretFile,
+        plan::PlanAction,
+        promote::{KeyChange, KeyChangeKind, apply_promotion, create_promotion},
+        secret::{MANAGED_BY_TAG, MANAGED_BY_VALUE, MockSecretManager, Secret, SecretSummary},
+    };
+    use indexmap::IndexMap;
+    use mockall::predicate::eq;
+
+    /// Tests promoting a structured secret between environments
+    #[tokio::test]
+    async fn test_promote() {
+        let mut files = IndexMap::new();
+        files.insert(
+            "app".to_string(),
+            SecretFile {
+                path: ".env".into(),
+                secret: "{env}/app".to_string(),
+                ..Default::default()
+            },
+        );
+        files.insert(
+            "shared".to_string(),
+            SecretFile {
+                path: ".env.shared".into(),
+                secret: "shared".to_string(),
+                ..Default::default()
+            },
+        );
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("staging/app"))
+            .return_once(|_| Ok(Some(Secret::String("A=1\nB=2\n".to_string()))));
+        secret
+            .expect_find_secret()
+            .with(eq("production/app"))
+            .return_once(|_| Ok(Some(Secret::String("A=0\nC=3\n".to_string()))));
+        secret
+            .expect_set_secret()
+            .withf(|name, value, _| {
+                name == "production/app" && value == &Secret::String("A=1\nB=2\n".to_string())
+            })
+            .return_once(|_, _, _| Ok(()));
+
+        let promotion = create_promotion(&secret, &files, "staging", "production")
+            .await
+            .unwrap();
+
+        assert_eq!(promotion.entries.len(), 1);
+        assert_eq!(promotion.entries[0].action, PlanAction::Update);
+        assert_eq!(
+            promotion.entries[0].changes,
+            vec![
+                KeyChange {
+                    key: "A".to_string(),
+                    kind: KeyChangeKind::Changed,
+                },
+                KeyChange {
+                    key: "B".to_string(),
+                    kind: KeyChangeKind::Added,
+                },
+                KeyChange {
+                    key: "C".to_string(),
+                    kind: KeyChangeKind::Removed,
+                },
+            ]
+        );
+
+        // Values are never included in the diff
+        assert_eq!(
+            promotion.to_text(),
+            "~ update app (staging/app -> production/app)\n    ~ A\n    + B\n    - C\nPromotion staging -> production: 0 to create, 1 to update, 0 unchanged"
+        );
+
+        secret
+            .expect_describe_secret()
+            .with(eq("production/app"))
+            .return_once(|name| {
+                Ok(Some(SecretSummary {
+                    name: name.to_string(),
+                    tags: IndexMap::from([(
+                        MANAGED_BY_TAG.to_string(),
+                        MANAGED_BY_VALUE.to_string(),
+                    )]),
+                    ..Default::default()
+                }))
+            });
+        assert_eq!(apply_promotion(&secret, promotion, false).await.unwrap(), 1);
+    }
+}
@@ -1,53 +1,480 @@
-use crate::{config::SecretFile, fs::FileSystem, secret::SecretManager};
-use std::path::Path;
+//! # Pull
+//!
+//! Pulling secret values from the secret manager into their local files
+
+use crate::{
+    cancel::{BatchOutcome, CancellationToken},
+    clock::{Clock, SystemClock},
+    concurrency::{Concurrency, ConcurrencyLimit},
+    config::{
+        GeneratedFile, RegistryCredentialsConfig, RegistryCredentialsFormat, SecretFile,
+        SecretGenerator,
+    },
+    error::{ErrorContext, Result, ResultExt, SyncError},
+    events::{EventSink, SyncEvent, emit_file_cancelled, emit_file_result},
+    fs::{FileSystem, FileSystemProvider},
+    registry::{RegistryCredential, merge_docker_config},
+    secret::{ListSecretsOptions, Secret, SecretManager},
+    structured::materialize_file,
+};
+use futures_util::{StreamExt, TryStreamExt, stream::FuturesUnordered};
+use std::{
+    collections::VecDeque,
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Default path template for secrets found using discovery
+pub const DEFAULT_DISCOVER_PATH: &str = "discovered/{name}";
+
+/// Placeholder within a discovery path template replaced with the secret name
+const DISCOVER_NAME_PLACEHOLDER: &str = "{name}";
+
+/// Create the local file contents for the secret `value` of `file`, the
+/// `previous` contents of structured files are updated in place
+pub fn file_contents(file: &SecretFile, value: Secret, previous: Option<&[u8]>) -> Result<Secret> {
+    if !file.is_structured() {
+        return Ok(value);
+    }
+
+    let value = materialize_file(file, value.as_bytes(), previous)
+        .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+        .with_context(|| format!("failed to process structured secret \"{}\"", file.secret))?;
+
+    Ok(Secret::from_bytes(value))
+}
+
+/// Resolve the local path for the discovered secret `name` using the
+/// path `template`, the resulting path must stay within the working path
+pub fn discover_path(template: &str, name: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(template.replace(DISCOVER_NAME_PLACEHOLDER, name));
+
+    let escapes = path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(SyncError::validation(format!(
+            "discovered secret \"{name}\" resolves to \"{}\" which is outside of the working directory",
+            path.display()
+        )));
+    }
+
+    Ok(path)
+}
+
+/// Find the secrets that have all of the `tags`, creating a file for each
+/// using the path `template`
+pub async fn discover_files(
+    secret: &dyn SecretManager,
+    tags: &[(String, String)],
+    template: &str,
+) -> Result<Vec<SecretFile>> {
+    if tags.is_empty() {
+        return Err(SyncError::config(
+            "at least one tag is required to discover secrets",
+        ));
+    }
+
+    let options = ListSecretsOptions {
+        tags: tags.to_vec(),
+        ..Default::default()
+    };
+
+    secret
+        .list_secrets(options)
+        .and_then(|summary| async move {
+            Ok(SecretFile {
+                path: discover_path(template, &summary.name)?,
+                secret: summary.name,
+                ..Default::default()
+            })
+        })
+        .try_collect()
+        .await
+}
+
+/// Result of pulling a single secret file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PulledFile {
+    /// Whether the local file contents changed
+    pub changed: bool,
+    /// Version of the secret that was pulled, when provided by the backend
+    pub version_id: Option<String>,
+}
 
 /// Download a secret file from the secret manager
+///
+/// Files that already contain the secret value are not written
+#[tracing::instrument(level = "debug", skip_all, fields(secret = %file.secret))]
 pub async fn pull_secret_file<Fs: FileSystem>(
     fs: &Fs,
     secret: &dyn SecretManager,
     working_path: &Path,
     file: &SecretFile,
-) -> eyre::Result<()> {
+) -> Result<PulledFile> {
     let value = secret.get_secret(&file.secret).await?;
+    let version_id = value.version_id;
 
-    let file_path = if file.path.is_absolute() {
-        file.path.clone()
-    } else {
-        working_path.join(file.path.clone())
-    };
+    // The previous value piped into a sink is unknown so it is always run
+    if let Some(sink) = &file.sink {
+        let value = file_contents(file, value.data, None)?;
+        fs.write_command(&sink.command, value.as_bytes()).await?;
+        return Ok(PulledFile {
+            changed: true,
+            version_id,
+        });
+    }
+
+    let file_path = file.resolve_path(working_path);
+    let previous = fs.read_file_optional(&file_path).await?;
 
+    let value = file_contents(file, value.data, previous.as_deref())?;
     let value: &[u8] = value.as_bytes();
-    fs.write_file(&file_path, value).await?;
 
-    Ok(())
+    let changed = previous.as_deref() != Some(value);
+    match changed {
+        true => fs.write_file(&file_path, value).await?,
+        false => tracing::debug!(?file_path, "secret file unchanged, skipping write"),
+    }
+
+    Ok(PulledFile {
+        changed,
+        version_id,
+    })
 }
 
-/// Download a collection of files from the secret manager
-pub async fn pull_secret_files<Fs: FileSystem>(
+/// Generate a fresh value for a generated file and write it to the file
+///
+/// Returns whether the file changed, files already containing the
+/// generated value are not written
+pub async fn pull_generated_file<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    file: &GeneratedFile,
+) -> Result<bool> {
+    let value = secret
+        .generate_secret(&file.generator)
+        .await
+        .with_context(|| format!("failed to generate {} value", file.generator))?;
+
+    let file_path = file.resolve_path(working_path);
+    let previous = fs.read_file_optional(&file_path).await?;
+
+    let value = match &file.generator {
+        SecretGenerator::EcrCredentials(RegistryCredentialsConfig {
+            format: RegistryCredentialsFormat::DockerConfig,
+        }) => {
+            let credential: RegistryCredential =
+                serde_json::from_slice(value.as_bytes()).map_err(|error| {
+                    SyncError::backend("generated registry credential is invalid")
+                        .with_source(error)
+                })?;
+
+            merge_docker_config(previous.as_deref(), &credential)
+                .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))?
+        }
+        _ => value.as_bytes().to_vec(),
+    };
+
+    if previous.as_deref() == Some(value.as_slice()) {
+        tracing::debug!(?file_path, "generated file unchanged, skipping write");
+        return Ok(false);
+    }
+
+    fs.write_file(&file_path, &value).await?;
+
+    Ok(true)
+}
+
+/// Options of a batch pull
+#[derive(Debug, Clone, Copy)]
+pub struct PullOptions<'a> {
+    /// Number of files pulled at once
+    pub concurrency: Concurrency,
+    /// Open a connection for each concurrent request before pulling
+    /// instead of on demand
+    pub prewarm: bool,
+    /// Clock used to wait before retrying throttled requests
+    pub clock: &'a dyn Clock,
+}
+
+impl Default for PullOptions<'_> {
+    fn default() -> Self {
+        Self {
+            concurrency: Concurrency::default(),
+            prewarm: false,
+            clock: &SystemClock,
+        }
+    }
+}
+
+/// Download a collection of files from the secret manager, progress for
+/// each file is reported to the `events` sink
+///
+/// Files are pulled using the file system provided for each file by `fs`,
+/// running up to the concurrency of the `options` at once. Requests
+/// throttled by the backend are retried when the concurrency is adaptive,
+/// waiting an exponentially growing delay (see
+/// [ConcurrencyLimit::retry_delay]) before each retry
+///
+/// Cancelling the `cancel` token stops the batch, dropping the in-flight
+/// requests. The outcome describes the files completed before cancellation
+pub async fn pull_secret_files<'a, P: FileSystemProvider>(
+    fs: &P,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    files: impl IntoIterator<Item = &'a SecretFile>,
+    options: PullOptions<'_>,
+    events: &dyn EventSink,
+    cancel: &CancellationToken,
+) -> Result<BatchOutcome> {
+    let mut outcome = BatchOutcome::default();
+    let mut limit = ConcurrencyLimit::new(options.concurrency);
+
+    // Files yet to be pulled along with their number of throttled retries
+    // and the delay to wait before pulling them
+    let mut pending: VecDeque<(&SecretFile, usize, Duration)> = files
+        .into_iter()
+        .map(|file| (file, 0, Duration::ZERO))
+        .collect();
+
+    // A single connection is opened by the first request anyway
+    let connections = limit.current().min(pending.len());
+    if options.prewarm && connections > 1 {
+        secret.warm_connections(connections).await;
+    }
+
+    let mut in_flight = FuturesUnordered::new();
+
+    // Files with a request in flight, reported when the batch is cancelled
+    let mut active: Vec<&SecretFile> = Vec::new();
+
+    loop {
+        while in_flight.len() < limit.current()
+            && let Some((file, retries, delay)) = pending.pop_front()
+        {
+            if retries == 0 {
+                events.emit(SyncEvent::FileStarted {
+                    secret: file.secret.clone(),
+                    path: file.resolve_path(working_path),
+                });
+            }
+
+            active.push(file);
+            in_flight.push(async move {
+                if !delay.is_zero() {
+                    options.clock.sleep(delay).await;
+                }
+
+                let started = Instant::now();
+                let fs = fs.for_file(file);
+                let result = pull_secret_file(&fs, secret, working_path, file).await;
+                (file, retries, started.elapsed(), result)
+            });
+        }
+
+        let Some(next) = cancel.run_until_cancelled(in_flight.next()).await else {
+            for file in active {
+                emit_file_cancelled(events, &file.secret, file.resolve_path(working_path));
+            }
+
+            outcome.cancelled = true;
+            break;
+        };
+
+        let Some((file, retries, elapsed, result)) = next else {
+            break;
+        };
+
+        active.retain(|active| !std::ptr::eq(*active, file));
+
+        match result {
+            Err(error) if limit.should_retry(&error, retries) => {
+                limit.record_throttled();
+                let delay = limit.retry_delay(retries);
+                tracing::warn!(
+                    secret = %file.secret,
+                    concurrency = limit.current(),
+                    ?delay,
+                    "request throttled, reducing concurrency"
+                );
+                pending.push_back((file, retries + 1, delay));
+            }
+            result => {
+                if result.is_ok() {
+                    limit.record_success(elapsed);
+                }
+
+                emit_file_result(
+                    events,
+                    &file.secret,
+                    file.resolve_path(working_path),
+                    result
+                        .as_ref()
+                        .map(|pulled| (pulled.changed, pulled.version_id.clone())),
+                );
+
+                outcome.completed += 1;
+                if result?.changed {
+                    outcome.changed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Download a collection of files from the secret manager atomically
+///
+/// Every secret is fetched and written to a temporary file alongside its
+/// destination (see [staging_path]) before any file is modified. The
+/// temporary files are only moved into place once all of them have been
+/// written, so a failure while fetching or writing leaves every file
+/// untouched and each file is replaced as a whole. When moving one of the
+/// files into place fails the files already replaced are restored to their
+/// previous contents
+///
+/// Returns the number of files whose contents changed
+pub async fn pull_secret_files_atomic<Fs: FileSystem>(
     fs: &Fs,
     secret: &dyn SecretManager,
     working_path: &Path,
     files: impl IntoIterator<Item = &SecretFile>,
-) -> eyre::Result<()> {
+) -> Result<usize> {
+    // Fetch all the secret values before touching any files
+    let mut changed = Vec::new();
     for file in files {
-        pull_secret_file(fs, secret, working_path, file).await?;
+        if file.sink.is_some() {
+            return Err(SyncError::config(format!(
+                "secret \"{}\" is piped to a sink which cannot be staged, it cannot be pulled atomically",
+                file.secret
+            )));
+        }
+
+        let value = secret.get_secret(&file.secret).await?.data;
+        let file_path = file.resolve_path(working_path);
+        let previous = fs.read_file_optional(&file_path).await?;
+
+        // Structured files are updated within their existing contents
+        let value = file_contents(file, value, previous.as_deref())?;
+        if previous.as_deref() != Some(value.as_bytes()) {
+            changed.push((file_path, previous, value));
+        }
+    }
+
+    // Temporary files that have been written
+    let mut staged: Vec<StagedFile> = Vec::with_capacity(changed.len());
+
+    for (file_path, previous, value) in changed {
+        let temp_path = staging_path(&file_path);
+        if let Err(error) = fs.write_file(&temp_path, value.as_bytes()).await {
+            tracing::error!(?error, ?file_path, "failed to stage secret file");
+            remove_staged_files(fs, staged.into_iter().map(|file| file.temp_path)).await;
+            return Err(error);
+        }
+
+        staged.push(StagedFile {
+            temp_path,
+            file_path,
+            previous,
+        });
+    }
+
+    let total = staged.len();
+    let mut staged = staged.into_iter();
+
+    // Files that have been moved into place
+    let mut replaced: Vec<StagedFile> = Vec::with_capacity(total);
+
+    while let Some(file) = staged.next() {
+        if let Err(error) = fs.rename_file(&file.temp_path, &file.file_path).await {
+            tracing::error!(?error, file_path = ?file.file_path, "failed to move staged secret file");
+            let remaining = std::iter::once(file).chain(staged);
+            remove_staged_files(fs, remaining.map(|file| file.temp_path)).await;
+            restore_files(fs, replaced).await;
+            return Err(error);
+        }
+
+        replaced.push(file);
+    }
+
+    Ok(total)
+}
+
+/// Secret file staged by an atomic pull
+struct StagedFile {
+    /// Temporary file the new contents were written to
+    temp_path: PathBuf,
+    /// Destination the temporary file is moved to
+    file_path: PathBuf,
+    /// Contents of the destination before the pull, [None] when the file
+    /// did not exist
+    previous: Option<Vec<u8>>,
+}
+
+/// Path of the temporary file a secret file at `path` is staged at by an
+/// atomic pull, placed within the same directory so it can be renamed into
+/// place
+pub fn staging_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.secret-sync-tmp"))
+}
+
+/// Remove the temporary files of an atomic pull that were not moved into
+/// place
+async fn remove_staged_files<Fs: FileSystem>(fs: &Fs, staged: impl Iterator<Item = PathBuf>) {
+    for temp_path in staged {
+        if let Err(error) = fs.remove_file(&temp_path).await {
+            tracing::error!(?error, ?temp_path, "failed to remove staged secret file");
+        }
     }
+}
+
+/// Restore the files of an atomic pull that were already moved into place
+/// to their previous contents, removing the files that did not exist
+async fn restore_files<Fs: FileSystem>(fs: &Fs, replaced: Vec<StagedFile>) {
+    for file in replaced {
+        let result = match &file.previous {
+            Some(previous) => fs.write_file(&file.file_path, previous).await,
+            None => fs.remove_file(&file.file_path).await,
+        };
 
-    Ok(())
+        if let Err(error) = result {
+            tracing::error!(?error, file_path = ?file.file_path, "failed to restore secret file");
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        config::{SecretFile, SecretMetadata},
+        cancel::{BatchOutcome, CancellationToken},
+        clock::{Clock, ManualClock},
+        concurrency::Concurrency,
+        config::{
+            GeneratedFile, RdsIamTokenConfig, SecretFile, SecretGenerator, SecretMetadata,
+            SinkConfig,
+        },
+        error::SyncError,
+        events::SyncEvent,
         fs::MockFileSystem,
-        pull::{pull_secret_file, pull_secret_files},
-        secret::{MockSecretManager, Secret},
+        pull::{
+            PullOptions, discover_files, discover_path, pull_generated_file, pull_secret_file,
+            pull_secret_files, pull_secret_files_atomic,
+        },
+        secret::{MockSecretManager, Secret, SecretSummary, SecretValue},
     };
     use mockall::{Sequence, predicate::eq};
     use std::{
         collections::HashMap,
         path::{Path, PathBuf},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, UNIX_EPOCH},
     };
 
     /// Tests pull a secret file
@@ -60,10 +487,21 @@ mod test {
             .expect_get_secret()
             .times(1)
             .with(eq("test"))
-            .return_once(move |_key| Ok(Secret::String("test".to_string())));
+            .return_once(move |_key| {
+                Ok(SecretValue {
+                    version_id: Some("v1".to_string()),
+                    ..Secret::String("test".to_string()).into()
+                })
+            });
 
         let mut fs = MockFileSystem::new();
 
+        // Expect the existing ".env" file to be checked
+        fs.expect_read_file_optional()
+            .times(1)
+            .with(eq(Path::new("/.env")))
+            .return_once(move |_path| Ok(None));
+
         // Expect the ".env" file to be written to
         fs.expect_write_file()
             .times(1)
@@ -75,11 +513,14 @@ mod test {
             path: PathBuf::from(".env"),
             secret: "test".to_string(),
             metadata: SecretMetadata::default(),
+            ..Default::default()
         };
 
-        pull_secret_file(&fs, &secret, working_path, &file)
+        let pulled = pull_secret_file(&fs, &secret, working_path, &file)
             .await
             .unwrap();
+        assert!(pulled.changed);
+        assert_eq!(pulled.version_id.as_deref(), Some("v1"));
 
         // Ensure expectations are met
         fs.checkpoint();
@@ -99,6 +540,7 @@ mod test {
                 path: PathBuf::from(format!(".env.{i}")),
                 secret: format!("test-{i}"),
                 metadata: SecretMetadata::default(),
+                ..Default::default()
             });
 
             test_secrets_value.insert(
@@ -120,12 +562,17 @@ mod test {
                 .in_sequence(&mut get_secret_sequence)
                 .times(1)
                 .with(eq(secret_file.secret.clone()))
-                .return_once(move |_key| Ok(secret_value));
+                .return_once(move |_key| Ok(secret_value.into()));
         }
 
         let mut fs = MockFileSystem::new();
         let working_path = Path::new("/");
 
+        // Expect each existing file to be checked
+        fs.expect_read_file_optional()
+            .times(TOTAL_TEST_SECRETS)
+            .returning(|_path| Ok(None));
+
         let mut write_file_sequence = Sequence::new();
         for secret_file in &test_secrets {
             let secret_value = test_secrets_value.get(&secret_file.secret).unwrap().clone();
@@ -139,12 +586,546 @@ mod test {
                 .return_once(move |_path, _value| Ok(()));
         }
 
-        pull_secret_files(&fs, &secret, working_path, &test_secrets)
+        let completed = AtomicUsize::new(0);
+        let events = |event: SyncEvent| {
+            if let SyncEvent::FileCompleted { changed: true, .. } = event {
+                completed.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+
+        let cancel = CancellationToken::new();
+        let outcome = pull_secret_files(
+            &fs,
+            &secret,
+            working_path,
+            &test_secrets,
+            PullOptions::default(),
+            &events,
+            &cancel,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.changed, TOTAL_TEST_SECRETS);
+        assert!(!outcome.cancelled);
+        assert_eq!(completed.load(Ordering::Relaxed), TOTAL_TEST_SECRETS);
+
+        // Ensure expectations are met
+        fs.checkpoint();
+        secret.checkpoint();
+    }
+
+    /// Tests that a cancelled batch stops without requesting any secrets
+    #[tokio::test]
+    async fn test_pull_secret_files_cancelled() {
+        let files = ["test-1", "test-2"].map(|name| SecretFile {
+            path: PathBuf::from(format!(".env.{name}")),
+            secret: name.to_string(),
+            ..Default::default()
+        });
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_get_secret().never();
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_write_file().never();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let outcome = pull_secret_files(
+            &fs,
+            &secret,
+            Path::new("/"),
+            &files,
+            PullOptions::default(),
+            &sender,
+            &cancel,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            outcome,
+            BatchOutcome {
+                completed: 0,
+                changed: 0,
+                cancelled: true,
+            }
+        );
+
+        // The first file is reported as cancelled and the rest are skipped
+        let events: Vec<SyncEvent> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[1],
+            SyncEvent::FileFailed { error, .. } if error == "cancelled"
+        ));
+    }
+
+    /// Tests that throttled requests are retried after backing off when the
+    /// concurrency is adaptive, reporting the version of the pulled secret
+    #[tokio::test]
+    async fn test_pull_secret_files_throttled() {
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "test".to_string(),
+            ..Default::default()
+        };
+
+        let mut secret = MockSecretManager::new();
+        let mut sequence = Sequence::new();
+        secret
+            .expect_get_secret()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(|_key| Err(SyncError::throttled("rate exceeded")));
+        secret
+            .expect_get_secret()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(|_key| {
+                Ok(SecretValue {
+                    version_id: Some("v2".to_string()),
+                    ..Secret::String("A=1".to_string()).into()
+                })
+            });
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional().return_once(|_path| Ok(None));
+        fs.expect_write_file()
+            .times(1)
+            .return_once(|_path, _value| Ok(()));
+
+        let clock = ManualClock::new(0);
+        let options = PullOptions {
+            concurrency: Concurrency::Adaptive,
+            clock: &clock,
+            ..Default::default()
+        };
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let outcome = pull_secret_files(
+            &fs,
+            &secret,
+            Path::new("/"),
+            [&file],
+            options,
+            &sender,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.changed, 1);
+
+        // The retry waited for at least half of the initial delay
+        let waited = clock.now().duration_since(UNIX_EPOCH).unwrap();
+        assert!(waited >= Duration::from_millis(50) && waited <= Duration::from_millis(100));
+
+        let events: Vec<SyncEvent> = receiver.try_iter().collect();
+        assert_eq!(
+            events.last(),
+            Some(&SyncEvent::FileCompleted {
+                secret: "test".to_string(),
+                path: PathBuf::from("/.env"),
+                changed: true,
+                version_id: Some("v2".to_string()),
+            })
+        );
+    }
+
+    /// Tests that generated files are written with a freshly generated value
+    #[tokio::test]
+    async fn test_pull_generated_file() {
+        let file = GeneratedFile {
+            path: PathBuf::from(".db-token"),
+            generator: SecretGenerator::RdsIamToken(RdsIamTokenConfig {
+                host: "db.example.com".to_string(),
+                port: 5432,
+                user: "app".to_string(),
+                region: None,
+            }),
+        };
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_generate_secret()
+            .with(eq(file.generator.clone()))
+            .return_once(|_generator| Ok(Secret::String("token".to_string())));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.db-token")))
+            .return_once(|_path| Ok(Some(b"expired".to_vec())));
+        fs.expect_write_file()
+            .with(eq(Path::new("/.db-token")), eq(b"token".to_vec()))
+            .return_once(|_path, _value| Ok(()));
+
+        let changed = pull_generated_file(&fs, &secret, Path::new("/"), &file)
+            .await
+            .unwrap();
+        assert!(changed);
+
+        // Generated values matching the file contents are not written
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_generate_secret()
+            .return_once(|_generator| Ok(Secret::String("token".to_string())));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional()
+            .return_once(|_path| Ok(Some(b"token".to_vec())));
+        fs.expect_write_file().never();
+
+        let changed = pull_generated_file(&fs, &secret, Path::new("/"), &file)
             .await
             .unwrap();
+        assert!(!changed);
+    }
+
+    /// Tests pulling a secret file that already contains the secret value
+    #[tokio::test]
+    async fn test_pull_secret_file_unchanged() {
+        let mut secret = MockSecretManager::new();
+
+        // Expect the "test" secret to be requested
+        secret
+            .expect_get_secret()
+            .times(1)
+            .with(eq("test"))
+            .return_once(move |_key| Ok(Secret::String("test".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+
+        // Existing file already contains the value
+        fs.expect_read_file_optional()
+            .times(1)
+            .with(eq(Path::new("/.env")))
+            .return_once(move |_path| Ok(Some("test".to_string().into_bytes())));
+
+        // Expect the file to not be written
+        fs.expect_write_file().never();
+
+        let working_path = Path::new("/");
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "test".to_string(),
+            metadata: SecretMetadata::default(),
+            ..Default::default()
+        };
+
+        let changed = pull_secret_file(&fs, &secret, working_path, &file)
+            .await
+            .unwrap()
+            .changed;
+        assert!(!changed);
 
         // Ensure expectations are met
         fs.checkpoint();
         secret.checkpoint();
     }
+
+    /// Tests that a file with a sink is piped into the sink command instead
+    /// of being written
+    #[tokio::test]
+    async fn test_pull_secret_file_sink() {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .times(1)
+            .with(eq("test"))
+            .return_once(move |_key| Ok(Secret::String("test".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional().never();
+        fs.expect_write_file().never();
+        fs.expect_write_command()
+            .times(1)
+            .with(eq("kubectl apply -f -"), eq(b"test".to_vec()))
+            .return_once(|_command, _bytes| Ok(()));
+
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "test".to_string(),
+            sink: Some(SinkConfig {
+                command: "kubectl apply -f -".to_string(),
+            }),
+            ..Default::default()
+        };
+
+        let changed = pull_secret_file(&fs, &secret, Path::new("/"), &file)
+            .await
+            .unwrap()
+            .changed;
+        assert!(changed);
+    }
+
+    /// Tests that an atomic pull does not write any files when fetching
+    /// one of the secrets fails
+    #[tokio::test]
+    async fn test_pull_secret_files_atomic_fetch_failure() {
+        let files = vec![
+            SecretFile {
+                path: PathBuf::from(".env.1"),
+                secret: "test-1".to_string(),
+                metadata: SecretMetadata::default(),
+                ..Default::default()
+            },
+            SecretFile {
+                path: PathBuf::from(".env.2"),
+                secret: "test-2".to_string(),
+                metadata: SecretMetadata::default(),
+                ..Default::default()
+            },
+        ];
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .with(eq("test-1"))
+            .return_once(move |_key| Ok(Secret::String("test".to_string()).into()));
+        secret
+            .expect_get_secret()
+            .with(eq("test-2"))
+            .return_once(move |_key| Err(SyncError::throttled("rate exceeded")));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional().returning(|_path| Ok(None));
+        fs.expect_write_file().never();
+
+        let error = pull_secret_files_atomic(&fs, &secret, Path::new("/"), &files)
+            .await
+            .unwrap_err();
+        assert!(error.is_throttled());
+    }
+
+    /// Tests that an atomic pull stages every file before moving them into
+    /// place, skipping files that are unchanged
+    #[tokio::test]
+    async fn test_pull_secret_files_atomic() {
+        let files = ["1", "2", "3"].map(|index| SecretFile {
+            path: PathBuf::from(format!(".env.{index}")),
+            secret: format!("test-{index}"),
+            ..Default::default()
+        });
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .returning(move |_key| Ok(Secret::String("new".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+        let mut sequence = Sequence::new();
+
+        // First file existed previously, the third is already up to date
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.1")))
+            .return_once(|_path| Ok(Some(b"old".to_vec())));
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.2")))
+            .return_once(|_path| Ok(None));
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.3")))
+            .return_once(|_path| Ok(Some(b"new".to_vec())));
+
+        for index in ["1", "2"] {
+            fs.expect_write_file()
+                .times(1)
+                .in_sequence(&mut sequence)
+                .with(
+                    eq(PathBuf::from(format!("/..env.{index}.secret-sync-tmp"))),
+                    eq(b"new".to_vec()),
+                )
+                .return_once(|_path, _value| Ok(()));
+        }
+        for index in ["1", "2"] {
+            fs.expect_rename_file()
+                .times(1)
+                .in_sequence(&mut sequence)
+                .with(
+                    eq(PathBuf::from(format!("/..env.{index}.secret-sync-tmp"))),
+                    eq(PathBuf::from(format!("/.env.{index}"))),
+                )
+                .return_once(|_from, _to| Ok(()));
+        }
+
+        let changed = pull_secret_files_atomic(&fs, &secret, Path::new("/"), &files)
+            .await
+            .unwrap();
+        assert_eq!(changed, 2);
+
+        fs.checkpoint();
+    }
+
+    /// Tests that an atomic pull leaves every file untouched when staging
+    /// one of the files fails
+    #[tokio::test]
+    async fn test_pull_secret_files_atomic_write_failure() {
+        let files = ["1", "2", "3"].map(|index| SecretFile {
+            path: PathBuf::from(format!(".env.{index}")),
+            secret: format!("test-{index}"),
+            ..Default::default()
+        });
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .returning(move |_key| Ok(Secret::String("new".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional().returning(|_path| Ok(None));
+        fs.expect_write_file()
+            .times(2)
+            .withf(|path, _value| path != Path::new("/..env.3.secret-sync-tmp"))
+            .returning(|_path, _value| Ok(()));
+        fs.expect_write_file()
+            .times(1)
+            .with(
+                eq(Path::new("/..env.3.secret-sync-tmp")),
+                eq(b"new".to_vec()),
+            )
+            .return_once(|_path, _value| Err(SyncError::io("disk full")));
+
+        // Expect the staged files to be removed without replacing any file
+        fs.expect_rename_file().never();
+        fs.expect_remove_file()
+            .times(2)
+            .withf(|path| path.to_string_lossy().ends_with(".secret-sync-tmp"))
+            .returning(|_path| Ok(()));
+
+        let error = pull_secret_files_atomic(&fs, &secret, Path::new("/"), &files)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SyncError::Io(_)));
+
+        fs.checkpoint();
+    }
+
+    /// Tests that an atomic pull restores the files already moved into place
+    /// when moving one of the files fails
+    #[tokio::test]
+    async fn test_pull_secret_files_atomic_rename_failure() {
+        let files = ["1", "2", "3"].map(|index| SecretFile {
+            path: PathBuf::from(format!(".env.{index}")),
+            secret: format!("test-{index}"),
+            ..Default::default()
+        });
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .returning(move |_key| Ok(Secret::String("new".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/.env.1")))
+            .return_once(|_path| Ok(Some(b"old".to_vec())));
+        fs.expect_read_file_optional().returning(|_path| Ok(None));
+        fs.expect_write_file()
+            .times(3)
+            .withf(|path, _value| path.to_string_lossy().ends_with(".secret-sync-tmp"))
+            .returning(|_path, _value| Ok(()));
+
+        fs.expect_rename_file()
+            .times(1)
+            .with(
+                eq(Path::new("/..env.1.secret-sync-tmp")),
+                eq(Path::new("/.env.1")),
+            )
+            .return_once(|_from, _to| Ok(()));
+        fs.expect_rename_file()
+            .times(1)
+            .with(
+                eq(Path::new("/..env.2.secret-sync-tmp")),
+                eq(Path::new("/.env.2")),
+            )
+            .return_once(|_from, _to| Err(SyncError::io("permission denied")));
+
+        // Expect the remaining staged files to be removed
+        for index in ["2", "3"] {
+            fs.expect_remove_file()
+                .times(1)
+                .with(eq(PathBuf::from(format!("/..env.{index}.secret-sync-tmp"))))
+                .return_once(|_path| Ok(()));
+        }
+
+        // Expect the first file to be restored to its previous contents
+        fs.expect_write_file()
+            .times(1)
+            .with(eq(Path::new("/.env.1")), eq(b"old".to_vec()))
+            .return_once(|_path, _value| Ok(()));
+
+        let error = pull_secret_files_atomic(&fs, &secret, Path::new("/"), &files)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SyncError::Io(_)));
+
+        fs.checkpoint();
+    }
+
+    /// Tests that only the selected keys of a structured secret are pulled
+    #[tokio::test]
+    async fn test_pull_secret_file_keys() {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .with(eq("shared"))
+            .return_once(move |_key| Ok(Secret::String("A=1\nB=2\nC_1=3\n".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional()
+            .return_once(move |_path| Ok(None));
+        fs.expect_write_file()
+            .times(1)
+            .with(eq(Path::new("/.env")), eq(b"A=1\nC_1=3\n".to_vec()))
+            .return_once(move |_path, _value| Ok(()));
+
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "shared".to_string(),
+            keys: Some(vec!["A".to_string(), "C_*".to_string()]),
+            ..Default::default()
+        };
+
+        pull_secret_file(&fs, &secret, Path::new("/"), &file)
+            .await
+            .unwrap();
+    }
+
+    /// Tests that discovered secrets are placed using the path template
+    #[tokio::test]
+    async fn test_discover_files() {
+        let tags = vec![("app".to_string(), "myservice".to_string())];
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_list_secrets()
+            .withf(|options| options.tags == [("app".to_string(), "myservice".to_string())])
+            .return_once(|_options| {
+                let summaries = ["myservice/prod", "shared"].map(|name| {
+                    Ok(SecretSummary {
+                        name: name.to_string(),
+                        ..Default::default()
+                    })
+                });
+
+                Box::pin(futures_util::stream::iter(summaries))
+            });
+
+        let files = discover_files(&secret, &tags, "secrets/{name}.env")
+            .await
+            .unwrap();
+
+        let paths: Vec<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("secrets/myservice/prod.env"),
+                Path::new("secrets/shared.env")
+            ]
+        );
+
+        assert!(discover_path("{name}", "../escape").is_err());
+        assert!(discover_path("{name}", "/absolute").is_err());
+    }
 }
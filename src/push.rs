@@ -1,61 +1,638 @@
+//! # Push
+//!
+//! Pushing local secret files into the secret manager
+
 use crate::{
-    config::SecretFile,
-    fs::FileSystem,
-    secret::{Secret, SecretManager},
+    approval::current_user,
+    cancel::{BatchOutcome, CancellationToken},
+    clock::{Clock, SystemClock, format_unix_time, unix_seconds},
+    config::{SecretFile, SecretMetadata},
+    error::{ErrorContext, Result, ResultExt, SyncError},
+    events::{EventSink, SyncEvent, emit_file_cancelled, emit_file_result},
+    fs::{FileSystem, FileSystemProvider},
+    secret::{MANAGED_BY_TAG, MANAGED_BY_VALUE, Secret, SecretManager, SecretSummary},
+    structured::{StructuredValue, key_matches, merge_file, merge_file_keys},
 };
-use eyre::Context;
-use std::path::Path;
+use indexmap::IndexMap;
+use std::{path::Path, process::Command};
 
-/// Upload a secret file to the secret manager
-pub async fn push_secret_file<Fs: FileSystem>(
+/// Placeholder replaced with the name of the git repository when pushing
+pub const REPO_PLACEHOLDER: &str = "{repo}";
+
+/// Placeholder replaced with the short hash of the current git commit when
+/// pushing
+pub const COMMIT_PLACEHOLDER: &str = "{commit}";
+
+/// Placeholder replaced with the current git branch when pushing
+pub const BRANCH_PLACEHOLDER: &str = "{branch}";
+
+/// Placeholder replaced with the user pushing
+pub const USER_PLACEHOLDER: &str = "{user}";
+
+/// Placeholder replaced with the date of the push (e.g. `2024-01-31`)
+pub const DATE_PLACEHOLDER: &str = "{date}";
+
+/// Placeholder replaced with the time of the push as an RFC 3339 timestamp
+pub const TIMESTAMP_PLACEHOLDER: &str = "{timestamp}";
+
+/// Placeholders resolved by [PushInfo::render]
+const PUSH_PLACEHOLDERS: &[&str] = &[
+    REPO_PLACEHOLDER,
+    COMMIT_PLACEHOLDER,
+    BRANCH_PLACEHOLDER,
+    USER_PLACEHOLDER,
+    DATE_PLACEHOLDER,
+    TIMESTAMP_PLACEHOLDER,
+];
+
+/// Value used for git details that could not be determined
+const UNKNOWN_VALUE: &str = "unknown";
+
+/// Details about a push used to resolve the placeholders within the
+/// description and version comment of the pushed secrets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushInfo {
+    /// Name of the git repository
+    pub repo: Option<String>,
+    /// Short hash of the current git commit
+    pub commit: Option<String>,
+    /// Current git branch
+    pub branch: Option<String>,
+    /// User pushing
+    pub user: String,
+    /// Time of the push as unix seconds
+    pub time: u64,
+}
+
+impl PushInfo {
+    /// Determine the details of a push from the git repository containing
+    /// `working_path` and the current time of the `clock`, git details are
+    /// [None] outside of a git repository
+    pub fn detect(working_path: &Path, clock: &dyn Clock) -> Self {
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(working_path)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())?;
+
+            let value = String::from_utf8(output.stdout).ok()?;
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+
+        let repo = git(&["remote", "get-url", "origin"])
+            .and_then(|url| repo_name(&url))
+            .or_else(|| {
+                let root = git(&["rev-parse", "--show-toplevel"])?;
+                let name = Path::new(&root).file_name()?;
+                Some(name.to_string_lossy().into_owned())
+            });
+
+        Self {
+            repo,
+            commit: git(&["rev-parse", "--short", "HEAD"]),
+            branch: git(&["rev-parse", "--abbrev-ref", "HEAD"]),
+            user: current_user(),
+            time: unix_seconds(clock),
+        }
+    }
+
+    /// Replace the push placeholders within `text`
+    pub fn render(&self, text: &str) -> String {
+        let timestamp = format_unix_time(self.time);
+        let date = &timestamp[..timestamp.find('T').unwrap_or(timestamp.len())];
+
+        text.replace(
+            REPO_PLACEHOLDER,
+            self.repo.as_deref().unwrap_or(UNKNOWN_VALUE),
+        )
+        .replace(
+            COMMIT_PLACEHOLDER,
+            self.commit.as_deref().unwrap_or(UNKNOWN_VALUE),
+        )
+        .replace(
+            BRANCH_PLACEHOLDER,
+            self.branch.as_deref().unwrap_or(UNKNOWN_VALUE),
+        )
+        .replace(USER_PLACEHOLDER, &self.user)
+        .replace(DATE_PLACEHOLDER, date)
+        .replace(TIMESTAMP_PLACEHOLDER, &timestamp)
+    }
+
+    /// Copy of `file` with the placeholders within the description and
+    /// version comment of its metadata resolved
+    pub fn render_file(&self, file: &SecretFile) -> SecretFile {
+        let mut file = file.clone();
+        let metadata = &mut file.metadata;
+        metadata.description = metadata
+            .description
+            .as_deref()
+            .map(|description| self.render(description));
+        metadata.version_comment = metadata
+            .version_comment
+            .as_deref()
+            .map(|comment| self.render(comment));
+        file
+    }
+}
+
+/// Whether `text` contains any of the placeholders resolved when pushing
+pub fn has_push_placeholders(text: &str) -> bool {
+    PUSH_PLACEHOLDERS
+        .iter()
+        .any(|placeholder| text.contains(placeholder))
+}
+
+/// Name of the repository (e.g. `owner/repo`) from the git remote `url`,
+/// credentials within the url are never included
+fn repo_name(url: &str) -> Option<String> {
+    let path = url.trim_end_matches('/').trim_end_matches(".git");
+    let path = match path.split_once("://") {
+        // https://user@host/owner/repo
+        Some((_scheme, rest)) => rest.split_once('/')?.1,
+        // git@host:owner/repo
+        None => path.split_once(':')?.1,
+    };
+
+    let mut parts = path.rsplit('/');
+    let name = parts.next().filter(|name| !name.is_empty())?;
+    Some(match parts.next() {
+        Some(owner) if !owner.is_empty() => format!("{owner}/{name}"),
+        _ => name.to_string(),
+    })
+}
+
+/// Reason the `file` can only be pulled, [None] when it can be pushed
+fn pull_only_reason(file: &SecretFile) -> Option<String> {
+    if !file.is_pull_only() {
+        return None;
+    }
+
+    Some(match file.is_externally_managed() {
+        true => format!(
+            "managed by {}",
+            file.managed_by.as_deref().unwrap_or_default()
+        ),
+        false => "piped to a sink".to_string(),
+    })
+}
+
+/// Remove the files that can only be pulled from the `files` to push,
+/// adding a warning to `warnings` for each file that is skipped
+pub fn skip_pull_only<'a>(
+    files: Vec<(&'a String, &'a SecretFile)>,
+    warnings: &mut Vec<String>,
+) -> Vec<(&'a String, &'a SecretFile)> {
+    files
+        .into_iter()
+        .filter(|(name, file)| match pull_only_reason(file) {
+            Some(reason) => {
+                warnings.push(format!("skipping push of file \"{name}\" {reason}"));
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
+/// Ensure the existing secrets in `names` were created by secret-sync before
+/// they are overwritten, secrets without the [MANAGED_BY_TAG] marker are
+/// owned by another system (e.g. Terraform)
+///
+/// When `adopt` is set the marker is attached to the unmarked secrets
+/// instead, taking ownership of them for future pushes
+pub async fn check_secret_ownership(
+    secret: &dyn SecretManager,
+    names: &[&str],
+    adopt: bool,
+) -> Result<()> {
+    for name in find_foreign_secrets(secret, names, adopt).await? {
+        adopt_secret(secret, &name).await?;
+    }
+
+    Ok(())
+}
+
+/// Find the existing secrets in `names` that were not created by
+/// secret-sync without modifying them, failing unless `adopt` is set
+///
+/// Provides the names of the secrets that must be adopted (see
+/// [adopt_secret]) before they are overwritten
+pub async fn find_foreign_secrets(
+    secret: &dyn SecretManager,
+    names: &[&str],
+    adopt: bool,
+) -> Result<Vec<String>> {
+    let mut foreign = Vec::new();
+    let mut described = Vec::new();
+
+    for name in names {
+        let Some(summary) = secret.describe_secret(name).await? else {
+            continue;
+        };
+
+        if summary.is_managed() {
+            continue;
+        }
+
+        match summary.tags.get(MANAGED_BY_TAG) {
+            Some(owner) => described.push(format!("\"{name}\" ({MANAGED_BY_TAG}={owner})")),
+            None => described.push(format!("\"{name}\"")),
+        }
+        foreign.push(name.to_string());
+    }
+
+    if !foreign.is_empty() && !adopt {
+        return Err(SyncError::conflict(format!(
+            "refusing to overwrite secrets not created by secret-sync: {} (use --adopt to take ownership)",
+            described.join(", ")
+        )));
+    }
+
+    Ok(foreign)
+}
+
+/// Take ownership of the existing secret `name` by attaching the
+/// [MANAGED_BY_TAG] marker
+pub async fn adopt_secret(secret: &dyn SecretManager, name: &str) -> Result<()> {
+    tracing::info!(%name, "adopting secret");
+
+    let tags = IndexMap::from([(MANAGED_BY_TAG.to_string(), MANAGED_BY_VALUE.to_string())]);
+    secret
+        .tag_secret(name, &tags)
+        .await
+        .with_context(|| format!("failed to adopt secret \"{name}\""))
+}
+
+/// Whether the existing secret described by `summary` is missing some of the
+/// `metadata` of its file, metadata is only attached when a secret is created
+/// so pushing does not apply it
+///
+/// The description is only compared for backends that provide it, the
+/// `metadata` should have its push placeholders resolved (see
+/// [PushInfo::render_file]) so it matches the description that would be
+/// stored
+pub fn is_metadata_ignored(summary: &SecretSummary, metadata: &SecretMetadata) -> bool {
+    let description = metadata
+        .description
+        .as_ref()
+        .zip(summary.description.as_ref())
+        .is_some_and(|(description, existing)| description != existing);
+
+    let tags = metadata
+        .tags
+        .iter()
+        .flatten()
+        .any(|(key, value)| summary.tags.get(key) != Some(value));
+
+    description || tags
+}
+
+/// Read the local contents of `file` creating the value to push, structured
+/// files are merged into the current remote value
+pub async fn prepare_push_value<Fs: FileSystem>(
     fs: &Fs,
     secret: &dyn SecretManager,
     working_path: &Path,
     file: &SecretFile,
-) -> eyre::Result<()> {
-    let file_path = if file.path.is_absolute() {
-        file.path.clone()
-    } else {
-        working_path.join(file.path.clone())
-    };
+) -> Result<Secret> {
+    let file_path = file.resolve_path(working_path);
 
     let value = fs.read_file(&file_path).await?;
 
-    let value = match String::from_utf8(value) {
-        Ok(value) => Secret::String(value),
-        Err(error) => Secret::Binary(error.into_bytes()),
+    // Structured files are merged into the current remote value
+    let value = match file.is_structured() {
+        true => {
+            let remote = secret.find_secret(&file.secret).await?;
+            merge_file(file, remote.as_ref().map(Secret::as_bytes), &value)
+                .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+                .with_context(|| format!("failed to merge keys into \"{}\"", file.secret))?
+        }
+        false => value,
     };
 
+    Ok(Secret::from_bytes(value))
+}
+
+/// Store a `value` created using [prepare_push_value] for `file`
+#[tracing::instrument(level = "debug", skip_all, fields(secret = %file.secret))]
+pub async fn store_push_value(
+    secret: &dyn SecretManager,
+    file: &SecretFile,
+    value: Secret,
+) -> Result<()> {
     secret
         .set_secret(&file.secret, value, &file.metadata)
         .await
-        .context("failed to store secret")?;
+        .context("failed to store secret")
+}
 
-    Ok(())
+/// Upload a secret file to the secret manager
+pub async fn push_secret_file<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    file: &SecretFile,
+) -> Result<()> {
+    let value = prepare_push_value(fs, secret, working_path, file).await?;
+    store_push_value(secret, file, value).await
+}
+
+/// Value for pushing only some keys of a structured secret, created using
+/// [prepare_push_keys]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushKeysValue {
+    /// Remote value with the local keys merged in
+    value: Secret,
+    /// Hash of the remote value the keys were merged into, [None] when the
+    /// secret did not exist
+    remote_hash: Option<String>,
 }
 
-/// Upload a collection of secret files to the secret manager
-pub async fn push_secret_files<Fs: FileSystem>(
+/// Read the local contents of `file` for pushing only the `keys`, ensuring
+/// the keys are allowed for the file and the contents are structured
+///
+/// The keys are merged into the current remote value, which is remembered
+/// so [store_push_keys] can detect changes made to it in the meantime
+pub async fn prepare_push_keys<Fs: FileSystem>(
     fs: &Fs,
     secret: &dyn SecretManager,
     working_path: &Path,
-    files: impl IntoIterator<Item = &SecretFile>,
-) -> eyre::Result<()> {
-    for file in files {
-        push_secret_file(fs, secret, working_path, file).await?;
+    file: &SecretFile,
+    keys: &[String],
+) -> Result<PushKeysValue> {
+    if let Some(allowed) = &file.keys
+        && let Some(key) = keys.iter().find(|key| !key_matches(allowed, key))
+    {
+        return Err(SyncError::validation(format!(
+            "key \"{key}\" is not one of the keys of \"{}\"",
+            file.secret
+        )));
     }
 
-    Ok(())
+    let file_path = file.resolve_path(working_path);
+    let local = fs.read_file(&file_path).await?;
+
+    StructuredValue::parse(&local)
+        .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+        .with_context(|| format!("failed to parse \"{}\"", file_path.display()))?;
+
+    let remote = secret.find_secret(&file.secret).await?;
+    let value = merge_file_keys(
+        file,
+        Some(keys),
+        remote.as_ref().map(Secret::as_bytes),
+        &local,
+    )
+    .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+    .with_context(|| format!("failed to merge keys into \"{}\"", file.secret))?;
+
+    Ok(PushKeysValue {
+        value: Secret::from_bytes(value),
+        remote_hash: remote.as_ref().map(Secret::hash),
+    })
+}
+
+/// Store the `value` created using [prepare_push_keys] for `file`
+///
+/// The remote secret is checked right before writing, failing if it was
+/// modified by someone else since the keys were prepared. Backends do not
+/// provide conditional writes so a change landing between the check and
+/// the write is not detected
+pub async fn store_push_keys(
+    secret: &dyn SecretManager,
+    file: &SecretFile,
+    value: PushKeysValue,
+) -> Result<()> {
+    let current = secret
+        .find_secret(&file.secret)
+        .await?
+        .as_ref()
+        .map(Secret::hash);
+
+    if current != value.remote_hash {
+        return Err(SyncError::conflict(format!(
+            "secret \"{}\" was modified while pushing, pull the latest value and try again",
+            file.secret
+        )));
+    }
+
+    store_push_value(secret, file, value.value).await
+}
+
+/// Upload only the local values for the `keys` of a structured secret
+/// file, the other keys within the remote secret are left untouched
+pub async fn push_secret_file_keys<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    file: &SecretFile,
+    keys: &[String],
+) -> Result<()> {
+    let value = prepare_push_keys(fs, secret, working_path, file, keys).await?;
+    store_push_keys(secret, file, value).await
+}
+
+/// Options of a batch push
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PushOptions {
+    /// Take ownership of existing secrets that were not created by
+    /// secret-sync instead of refusing to overwrite them
+    pub adopt: bool,
+    /// Only push these keys of structured files, the other keys within the
+    /// remote secrets are left untouched
+    pub keys: Option<Vec<String>>,
+    /// Maximum number of secrets a single push may update
+    pub max_files: Option<usize>,
+    /// Details resolving the push placeholders within the metadata of the
+    /// files, detected from the working path when not provided
+    pub info: Option<PushInfo>,
+}
+
+/// Resolve the push placeholders within the metadata of the `files` using
+/// `info`, the details are only detected when some of the files use the
+/// placeholders as detecting them runs git
+fn render_push_files(
+    working_path: &Path,
+    files: Vec<&SecretFile>,
+    info: Option<&PushInfo>,
+) -> Vec<SecretFile> {
+    let uses_placeholders = files.iter().any(|file| {
+        let metadata = &file.metadata;
+        [&metadata.description, &metadata.version_comment]
+            .into_iter()
+            .flatten()
+            .any(|text| has_push_placeholders(text))
+    });
+
+    if !uses_placeholders {
+        return files.into_iter().cloned().collect();
+    }
+
+    let detected;
+    let info = match info {
+        Some(info) => info,
+        None => {
+            detected = PushInfo::detect(working_path, &SystemClock);
+            &detected
+        }
+    };
+
+    files
+        .into_iter()
+        .map(|file| info.render_file(file))
+        .collect()
+}
+
+/// Local value staged for pushing
+enum StagedValue {
+    /// Value replacing the remote secret
+    Value(Secret),
+    /// Value updating only some keys of the remote secret
+    Keys(PushKeysValue),
+}
+
+/// Upload a collection of secret files to the secret manager, progress
+/// for each file is reported to the `events` sink
+///
+/// Files that can only be pulled (e.g. managed by another system) are
+/// skipped, reporting a [SyncEvent::FileSkipped] event for each.
+///
+/// Files are read using the file system provided for each file by `fs`.
+/// Push placeholders within the metadata of the files are resolved using
+/// [PushOptions::info] (see [PushInfo::render_file]). The credentials and
+/// ownership of the existing secrets are verified (see
+/// [find_foreign_secrets]) and every file is read and prepared before any
+/// secret is modified so a problem with one of the files does not leave
+/// the push half completed, adopted secrets are only tagged right before
+/// they are written
+///
+/// Cancelling the `cancel` token stops the batch, dropping the in-flight
+/// request. The outcome describes the files completed before cancellation,
+/// the secret that was in-flight may or may not have been updated
+pub async fn push_secret_files<'a, P: FileSystemProvider>(
+    fs: &P,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    files: impl IntoIterator<Item = &'a SecretFile>,
+    options: &PushOptions,
+    events: &dyn EventSink,
+    cancel: &CancellationToken,
+) -> Result<BatchOutcome> {
+    let mut outcome = BatchOutcome::default();
+    let files: Vec<&SecretFile> = files
+        .into_iter()
+        .filter(|file| match pull_only_reason(file) {
+            Some(reason) => {
+                events.emit(SyncEvent::FileSkipped {
+                    secret: file.secret.clone(),
+                    path: file.resolve_path(working_path),
+                    reason,
+                });
+                false
+            }
+            None => true,
+        })
+        .collect();
+
+    if let Some(max_files) = options.max_files
+        && files.len() > max_files
+    {
+        return Err(SyncError::validation(format!(
+            "refusing to push {} secret(s), more than the maximum of {max_files}",
+            files.len()
+        )));
+    }
+
+    secret.verify_access().await?;
+
+    let names: Vec<&str> = files.iter().map(|file| file.secret.as_str()).collect();
+    let foreign = find_foreign_secrets(secret, &names, options.adopt).await?;
+
+    let files = render_push_files(working_path, files, options.info.as_ref());
+
+    let mut staged = Vec::with_capacity(files.len());
+    for file in &files {
+        let prepare = async {
+            let fs = fs.for_file(file);
+            match &options.keys {
+                Some(keys) => prepare_push_keys(&fs, secret, working_path, file, keys)
+                    .await
+                    .map(StagedValue::Keys),
+                None => prepare_push_value(&fs, secret, working_path, file)
+                    .await
+                    .map(StagedValue::Value),
+            }
+        };
+
+        let Some(value) = cancel.run_until_cancelled(prepare).await else {
+            outcome.cancelled = true;
+            return Ok(outcome);
+        };
+
+        let value = value.inspect_err(|error| {
+            let path = file.resolve_path(working_path);
+            emit_file_result(events, &file.secret, path, Err(error));
+        })?;
+        staged.push((file, value));
+    }
+
+    for (file, value) in staged {
+        let path = file.resolve_path(working_path);
+        events.emit(SyncEvent::FileStarted {
+            secret: file.secret.clone(),
+            path: path.clone(),
+        });
+
+        let store = async {
+            if foreign.contains(&file.secret) {
+                adopt_secret(secret, &file.secret).await?;
+            }
+
+            match value {
+                StagedValue::Value(value) => store_push_value(secret, file, value).await,
+                StagedValue::Keys(value) => store_push_keys(secret, file, value).await,
+            }
+        };
+
+        let Some(result) = cancel.run_until_cancelled(store).await else {
+            emit_file_cancelled(events, &file.secret, path);
+            outcome.cancelled = true;
+            break;
+        };
+
+        emit_file_result(
+            events,
+            &file.secret,
+            path,
+            result.as_ref().map(|_| (true, None)),
+        );
+        result?;
+
+        outcome.completed += 1;
+        outcome.changed += 1;
+    }
+
+    Ok(outcome)
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        config::{SecretFile, SecretMetadata},
+        cancel::CancellationToken,
+        config::{SecretFile, SecretMetadata, SinkConfig},
+        error::SyncError,
+        events::SyncEvent,
         fs::MockFileSystem,
-        push::{push_secret_file, push_secret_files},
-        secret::{MockSecretManager, Secret},
+        push::{
+            PushInfo, PushOptions, check_secret_ownership, is_metadata_ignored, push_secret_file,
+            push_secret_file_keys, push_secret_files, repo_name, skip_pull_only,
+        },
+        secret::{MockSecretManager, Secret, SecretSummary},
     };
+    use indexmap::IndexMap;
     use mockall::{Sequence, predicate::eq};
     use std::{
         collections::HashMap,
@@ -91,6 +668,7 @@ mod test {
             path: PathBuf::from(".env"),
             secret: "test".to_string(),
             metadata: SecretMetadata::default(),
+            ..Default::default()
         };
 
         push_secret_file(&fs, &secret, working_path, &file)
@@ -115,6 +693,7 @@ mod test {
                 path: PathBuf::from(format!(".env.{i}")),
                 secret: format!("test-{i}"),
                 metadata: SecretMetadata::default(),
+                ..Default::default()
             });
 
             test_secrets_value.insert(
@@ -124,6 +703,8 @@ mod test {
         }
 
         let mut secret = MockSecretManager::new();
+        secret.expect_verify_access().return_once(|| Ok(()));
+        secret.expect_describe_secret().returning(|_name| Ok(None));
 
         let mut set_secret_sequence = Sequence::new();
 
@@ -160,12 +741,522 @@ mod test {
                 .return_once(move |_path| Ok(secret_value.into_bytes()));
         }
 
-        push_secret_files(&fs, &secret, working_path, &test_secrets)
-            .await
-            .unwrap();
+        push_secret_files(
+            &fs,
+            &secret,
+            working_path,
+            &test_secrets,
+            &PushOptions::default(),
+            &(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
 
         // Ensure expectations are met
         fs.checkpoint();
         secret.checkpoint();
     }
+
+    /// Tests that pushing with keys only updates the selected keys
+    #[tokio::test]
+    async fn test_push_secret_file_keys() {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("shared"))
+            .return_once(move |_key| Ok(Some(Secret::String("A=1\nB=2\n".to_string()))));
+        secret
+            .expect_set_secret()
+            .times(1)
+            .with(
+                eq("shared"),
+                eq(Secret::String("A=updated\nB=2\n".to_string())),
+                eq(SecretMetadata::default()),
+            )
+            .return_once(move |_key, _secret, _metadata| Ok(()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .return_once(move |_path| Ok(b"A=updated\nB=local\n".to_vec()));
+
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "shared".to_string(),
+            keys: Some(vec!["A".to_string()]),
+            ..Default::default()
+        };
+
+        push_secret_file(&fs, &secret, Path::new("/"), &file)
+            .await
+            .unwrap();
+    }
+
+    /// Tests that pushing specific keys only updates those keys
+    #[tokio::test]
+    async fn test_push_secret_file_selected_keys() {
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .times(2)
+            .with(eq("shared"))
+            .returning(move |_key| Ok(Some(Secret::String(r#"{"A":"1","B":2}"#.to_string()))));
+        secret
+            .expect_set_secret()
+            .times(1)
+            .withf(|key, value, _metadata| {
+                let value: serde_json::Value = serde_json::from_slice(value.as_bytes()).unwrap();
+                key == "shared" && value == serde_json::json!({ "A": "updated", "B": 2 })
+            })
+            .return_once(move |_key, _secret, _metadata| Ok(()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .return_once(move |_path| Ok(b"A=updated\nB=3\n".to_vec()));
+
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "shared".to_string(),
+            ..Default::default()
+        };
+
+        push_secret_file_keys(&fs, &secret, Path::new("/"), &file, &["A".to_string()])
+            .await
+            .unwrap();
+    }
+
+    /// Tests that pushing specific keys fails when the remote secret is
+    /// modified between reading and writing
+    #[tokio::test]
+    async fn test_push_secret_file_keys_conflict() {
+        let mut secret = MockSecretManager::new();
+        let mut sequence = Sequence::new();
+        secret
+            .expect_find_secret()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(move |_key| Ok(Some(Secret::String("A=1\n".to_string()))));
+        secret
+            .expect_find_secret()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .return_once(move |_key| Ok(Some(Secret::String("A=2\n".to_string()))));
+        secret.expect_set_secret().never();
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .return_once(move |_path| Ok(b"A=updated\n".to_vec()));
+
+        let file = SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "shared".to_string(),
+            ..Default::default()
+        };
+
+        assert!(
+            push_secret_file_keys(&fs, &secret, Path::new("/"), &file, &["A".to_string()])
+                .await
+                .is_err()
+        );
+    }
+
+    /// Tests that no secrets are modified when reading one of the files
+    /// fails
+    #[tokio::test]
+    async fn test_push_secret_files_read_failure() {
+        let files = ["test-1", "test-2"].map(|name| SecretFile {
+            path: PathBuf::from(format!(".env.{name}")),
+            secret: name.to_string(),
+            ..Default::default()
+        });
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_verify_access().return_once(|| Ok(()));
+        secret.expect_describe_secret().returning(|_name| Ok(None));
+        secret.expect_set_secret().never();
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .with(eq(Path::new("/.env.test-1")))
+            .return_once(|_path| Ok(b"A=1".to_vec()));
+        fs.expect_read_file()
+            .with(eq(Path::new("/.env.test-2")))
+            .return_once(|_path| Err(SyncError::io("file not found")));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        assert!(
+            push_secret_files(
+                &fs,
+                &secret,
+                Path::new("/"),
+                &files,
+                &PushOptions::default(),
+                &sender,
+                &CancellationToken::new()
+            )
+            .await
+            .is_err()
+        );
+
+        // Only the failed file is reported as nothing was pushed
+        let events: Vec<SyncEvent> = receiver.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![SyncEvent::FileFailed {
+                secret: "test-2".to_string(),
+                path: PathBuf::from("/.env.test-2"),
+                error: "file not found".to_string(),
+            }]
+        );
+    }
+
+    /// Tests that foreign secrets are not adopted when staging one of the
+    /// files fails
+    #[tokio::test]
+    async fn test_push_secret_files_adopt_read_failure() {
+        let files = ["test-1", "test-2"].map(|name| SecretFile {
+            path: PathBuf::from(format!(".env.{name}")),
+            secret: name.to_string(),
+            ..Default::default()
+        });
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_verify_access().return_once(|| Ok(()));
+        secret.expect_describe_secret().returning(|name| {
+            Ok(Some(SecretSummary {
+                name: name.to_string(),
+                ..Default::default()
+            }))
+        });
+        secret.expect_tag_secret().never();
+        secret.expect_set_secret().never();
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .with(eq(Path::new("/.env.test-1")))
+            .return_once(|_path| Ok(b"A=1".to_vec()));
+        fs.expect_read_file()
+            .with(eq(Path::new("/.env.test-2")))
+            .return_once(|_path| Err(SyncError::io("file not found")));
+
+        let options = PushOptions {
+            adopt: true,
+            ..Default::default()
+        };
+
+        assert!(
+            push_secret_files(
+                &fs,
+                &secret,
+                Path::new("/"),
+                &files,
+                &options,
+                &(),
+                &CancellationToken::new()
+            )
+            .await
+            .is_err()
+        );
+    }
+
+    /// Tests that files managed by other systems are skipped by a batch push
+    #[tokio::test]
+    async fn test_push_secret_files_pull_only() {
+        let files = [
+            SecretFile {
+                path: PathBuf::from(".env"),
+                secret: "app".to_string(),
+                ..Default::default()
+            },
+            SecretFile {
+                path: PathBuf::from(".env.infra"),
+                secret: "infra".to_string(),
+                managed_by: Some("terraform".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_verify_access().return_once(|| Ok(()));
+        secret.expect_describe_secret().returning(|_name| Ok(None));
+        secret
+            .expect_set_secret()
+            .times(1)
+            .with(
+                eq("app"),
+                eq(Secret::from_bytes(b"A=1".to_vec())),
+                eq(SecretMetadata::default()),
+            )
+            .return_once(|_key, _secret, _metadata| Ok(()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .times(1)
+            .with(eq(Path::new("/.env")))
+            .return_once(|_path| Ok(b"A=1".to_vec()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let outcome = push_secret_files(
+            &fs,
+            &secret,
+            Path::new("/"),
+            &files,
+            &PushOptions::default(),
+            &sender,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.completed, 1);
+
+        let events: Vec<SyncEvent> = receiver.try_iter().collect();
+        assert_eq!(
+            events[0],
+            SyncEvent::FileSkipped {
+                secret: "infra".to_string(),
+                path: PathBuf::from("/.env.infra"),
+                reason: "managed by terraform".to_string(),
+            }
+        );
+    }
+
+    /// Tests that push placeholders are resolved before the secrets are
+    /// stored
+    #[tokio::test]
+    async fn test_push_secret_files_placeholders() {
+        let files = [SecretFile {
+            path: PathBuf::from(".env"),
+            secret: "app".to_string(),
+            metadata: SecretMetadata {
+                description: Some("Pushed by {user}".to_string()),
+                version_comment: Some("{commit} on {date}".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_verify_access().return_once(|| Ok(()));
+        secret.expect_describe_secret().returning(|_name| Ok(None));
+        secret
+            .expect_set_secret()
+            .times(1)
+            .with(
+                eq("app"),
+                eq(Secret::from_bytes(b"A=1".to_vec())),
+                eq(SecretMetadata {
+                    description: Some("Pushed by jacob".to_string()),
+                    version_comment: Some("abc1234 on 2023-11-14".to_string()),
+                    ..Default::default()
+                }),
+            )
+            .return_once(|_key, _secret, _metadata| Ok(()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .times(1)
+            .return_once(|_path| Ok(b"A=1".to_vec()));
+
+        let options = PushOptions {
+            info: Some(PushInfo {
+                repo: None,
+                commit: Some("abc1234".to_string()),
+                branch: None,
+                user: "jacob".to_string(),
+                time: 1_700_000_000,
+            }),
+            ..Default::default()
+        };
+
+        push_secret_files(
+            &fs,
+            &secret,
+            Path::new("/"),
+            &files,
+            &options,
+            &(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Tests that secrets without the managed marker are only overwritten
+    /// when adopted
+    #[tokio::test]
+    async fn test_check_secret_ownership() {
+        let summary = |name: &str, owner: Option<&str>| SecretSummary {
+            name: name.to_string(),
+            tags: owner
+                .map(|owner| IndexMap::from([("managed-by".to_string(), owner.to_string())]))
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_describe_secret()
+            .with(eq("managed"))
+            .returning(move |name| Ok(Some(summary(name, Some("secret-sync")))));
+        secret
+            .expect_describe_secret()
+            .with(eq("terraform"))
+            .returning(move |name| Ok(Some(summary(name, Some("terraform")))));
+        secret
+            .expect_describe_secret()
+            .with(eq("missing"))
+            .returning(|_| Ok(None));
+
+        let error = check_secret_ownership(&secret, &["managed", "terraform", "missing"], false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "refusing to overwrite secrets not created by secret-sync: \"terraform\" (managed-by=terraform) (use --adopt to take ownership)"
+        );
+
+        secret
+            .expect_tag_secret()
+            .withf(|name, tags| name == "terraform" && tags["managed-by"] == "secret-sync")
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        check_secret_ownership(&secret, &["managed", "terraform", "missing"], true)
+            .await
+            .unwrap();
+    }
+
+    /// Tests detecting metadata that differs from the existing secret
+    #[test]
+    fn test_is_metadata_ignored() {
+        let summary = SecretSummary {
+            name: "app".to_string(),
+            description: Some("App secrets".to_string()),
+            tags: IndexMap::from([("team".to_string(), "web".to_string())]),
+            ..Default::default()
+        };
+
+        assert!(!is_metadata_ignored(&summary, &SecretMetadata::default()));
+        assert!(!is_metadata_ignored(
+            &summary,
+            &SecretMetadata {
+                description: Some("App secrets".to_string()),
+                tags: Some(IndexMap::from([("team".to_string(), "web".to_string())])),
+                ..Default::default()
+            }
+        ));
+        assert!(is_metadata_ignored(
+            &summary,
+            &SecretMetadata {
+                description: Some("Worker secrets".to_string()),
+                ..Default::default()
+            }
+        ));
+        assert!(is_metadata_ignored(
+            &summary,
+            &SecretMetadata {
+                description: None,
+                tags: Some(IndexMap::from([("team".to_string(), "ops".to_string())])),
+                ..Default::default()
+            }
+        ));
+
+        // Descriptions are compared once their push placeholders are resolved
+        let info = PushInfo {
+            repo: Some("App".to_string()),
+            commit: None,
+            branch: None,
+            user: "jacob".to_string(),
+            time: 0,
+        };
+        let file = SecretFile {
+            metadata: SecretMetadata {
+                description: Some("{repo} secrets".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(!is_metadata_ignored(
+            &summary,
+            &info.render_file(&file).metadata
+        ));
+        assert!(is_metadata_ignored(&summary, &file.metadata));
+    }
+
+    /// Tests resolving the push placeholders within the metadata of a file
+    #[test]
+    fn test_push_info_render() {
+        let info = PushInfo {
+            repo: Some("acme/app".to_string()),
+            commit: Some("abc1234".to_string()),
+            branch: None,
+            user: "jacob".to_string(),
+            time: 1_700_000_000,
+        };
+
+        let file = SecretFile {
+            metadata: SecretMetadata {
+                description: Some("Synced from {repo}@{commit} on {date}".to_string()),
+                version_comment: Some("{user} on {branch} at {timestamp}".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let file = info.render_file(&file);
+        assert_eq!(
+            file.metadata.description.as_deref(),
+            Some("Synced from acme/app@abc1234 on 2023-11-14")
+        );
+        assert_eq!(
+            file.metadata.version_comment.as_deref(),
+            Some("jacob on unknown at 2023-11-14T22:13:20Z")
+        );
+
+        assert_eq!(
+            repo_name("git@github.com:acme/app.git").as_deref(),
+            Some("acme/app")
+        );
+        assert_eq!(
+            repo_name("https://token@github.com/acme/app").as_deref(),
+            Some("acme/app")
+        );
+        assert_eq!(repo_name("/srv/git/app.git").as_deref(), None);
+    }
+
+    /// Tests that files managed by other systems or piped to a sink are
+    /// not pushed
+    #[test]
+    fn test_skip_pull_only() {
+        let names = ["app", "infra", "owned", "cluster"].map(String::from);
+        let files = [
+            SecretFile::default(),
+            SecretFile {
+                managed_by: Some("terraform".to_string()),
+                ..Default::default()
+            },
+            SecretFile {
+                managed_by: Some("secret-sync".to_string()),
+                ..Default::default()
+            },
+            SecretFile {
+                sink: Some(SinkConfig {
+                    command: "kubectl apply -f -".to_string(),
+                }),
+                ..Default::default()
+            },
+        ];
+
+        let mut warnings = Vec::new();
+        let remaining = skip_pull_only(names.iter().zip(files.iter()).collect(), &mut warnings);
+        let remaining: Vec<&str> = remaining.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(remaining, ["app", "owned"]);
+        assert_eq!(
+            warnings,
+            [
+                "skipping push of file \"infra\" managed by terraform",
+                "skipping push of file \"cluster\" piped to a sink"
+            ]
+        );
+    }
 }
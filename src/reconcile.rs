@@ -0,0 +1,461 @@
+//! # Reconcile
+//!
+//! GitOps style reconciliation against a desired state file. The desired
+//! state file (secrets.state.json) records the name, value hash, and
+//! metadata of each secret without ever storing the value itself, which
+//! allows it to be committed and reviewed. Reconciling pushes the local
+//! files matching the desired hashes and deletes secrets marked as absent.
+
+use crate::{
+    config::{SecretFile, SecretMetadata},
+    fs::FileSystem,
+    push::check_secret_ownership,
+    secret::{Secret, SecretManager},
+};
+use eyre::{Context, ContextCompat};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, path::Path};
+
+/// Current version of the desired state format
+const STATE_VERSION: u32 = 1;
+
+/// Default file name for the desired state file
+pub const DEFAULT_STATE_FILE_NAME: &str = "secrets.state.json";
+
+/// Desired state of the remote secrets
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DesiredState {
+    /// Version of the desired state format
+    pub version: u32,
+    /// Desired secrets keyed by secret name
+    #[serde(default)]
+    pub secrets: IndexMap<String, DesiredSecret>,
+}
+
+/// Desired state of a single secret
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DesiredSecret {
+    /// Whether the secret should exist
+    #[serde(default)]
+    pub state: DesiredPresence,
+    /// Hash of the expected secret value, required for present secrets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Metadata to use when creating the secret
+    #[serde(default)]
+    pub metadata: SecretMetadata,
+}
+
+/// Whether a secret should exist
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DesiredPresence {
+    /// Secret should exist with the desired hash
+    #[default]
+    Present,
+    /// Secret should be deleted
+    Absent,
+}
+
+/// Action taken to reconcile a secret
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReconcileAction {
+    /// Secret was created
+    Create,
+    /// Secret was updated
+    Update,
+    /// Secret was deleted
+    Delete,
+    /// Secret already matched the desired state
+    Skip,
+}
+
+/// Reconcile outcome for a single secret
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ReconcileEntry {
+    /// Name of the secret
+    pub secret: String,
+    /// Action taken
+    pub action: ReconcileAction,
+}
+
+/// Render a human readable version of the reconcile `entries`
+pub fn reconcile_text(entries: &[ReconcileEntry], dry_run: bool) -> String {
+    let mut text = String::new();
+
+    for entry in entries {
+        let symbol = match entry.action {
+            ReconcileAction::Create => "+ create",
+            ReconcileAction::Update => "~ update",
+            ReconcileAction::Delete => "- delete",
+            ReconcileAction::Skip => "  skip  ",
+        };
+
+        _ = writeln!(text, "{symbol} {}", entry.secret);
+    }
+
+    let count = |action: ReconcileAction| {
+        entries
+            .iter()
+            .filter(|entry| entry.action == action)
+            .count()
+    };
+
+    _ = write!(
+        text,
+        "{}: {} created, {} updated, {} deleted, {} unchanged",
+        if dry_run { "Dry run" } else { "Reconciled" },
+        count(ReconcileAction::Create),
+        count(ReconcileAction::Update),
+        count(ReconcileAction::Delete),
+        count(ReconcileAction::Skip),
+    );
+
+    text
+}
+
+/// Reconcile the remote secrets against the desired `state` using the
+/// local `files` as the source of secret values
+///
+/// Every secret is verified before any changes are made, when `dry_run`
+/// is set no changes are made at all. Existing secrets not created by
+/// secret-sync are only updated or deleted when `adopt` is set
+pub async fn reconcile<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    files: &IndexMap<String, SecretFile>,
+    state: &DesiredState,
+    dry_run: bool,
+    adopt: bool,
+) -> eyre::Result<Vec<ReconcileEntry>> {
+    if state.version != STATE_VERSION {
+        eyre::bail!("unsupported desired state version {}", state.version);
+    }
+
+    let mut changes: Vec<(ReconcileEntry, Option<Secret>, &SecretMetadata)> = Vec::new();
+
+    for (name, desired) in &state.secrets {
+        let remote = secret
+            .find_secret(name)
+            .await
+            .with_context(|| format!("failed to get secret \"{name}\""))?;
+
+        let (action, value) = match desired.state {
+            DesiredPresence::Absent => match remote {
+                Some(_) => (ReconcileAction::Delete, None),
+                None => (ReconcileAction::Skip, None),
+            },
+            DesiredPresence::Present => {
+                let desired_hash = desired
+                    .hash
+                    .as_ref()
+                    .with_context(|| format!("desired secret \"{name}\" is missing a hash"))?;
+
+                let file = files
+                    .values()
+                    .find(|file| &file.secret == name)
+                    .with_context(|| format!("no configured file for secret \"{name}\""))?;
+
+                let local =
+                    Secret::from_bytes(fs.read_file(&file.resolve_path(working_path)).await?);
+
+                if &local.hash() != desired_hash {
+                    eyre::bail!(
+                        "local file for secret \"{name}\" does not match the desired state"
+                    );
+                }
+
+                match remote {
+                    None => (ReconcileAction::Create, Some(local)),
+                    Some(remote) if &remote.hash() == desired_hash => (ReconcileAction::Skip, None),
+                    Some(_) => (ReconcileAction::Update, Some(local)),
+                }
+            }
+        };
+
+        changes.push((
+            ReconcileEntry {
+                secret: name.clone(),
+                action,
+            },
+            value,
+            &desired.metadata,
+        ));
+    }
+
+    if !dry_run {
+        let names: Vec<&str> = changes
+            .iter()
+            .filter(|(entry, _, _)| {
+                matches!(
+                    entry.action,
+                    ReconcileAction::Update | ReconcileAction::Delete
+                )
+            })
+            .map(|(entry, _, _)| entry.secret.as_str())
+            .collect();
+        check_secret_ownership(secret, &names, adopt).await?;
+    }
+
+    let mut entries = Vec::with_capacity(changes.len());
+
+    for (entry, value, metadata) in changes {
+        if !dry_run {
+            match (entry.action, value) {
+                (ReconcileAction::Delete, _) => {
+                    secret.delete_secret(&entry.secret).await?;
+                }
+                (ReconcileAction::Create | ReconcileAction::Update, Some(value)) => {
+                    secret
+                        .set_secret(&entry.secret, value, metadata)
+                        .await
+                        .context("failed to store secret")?;
+                }
+                _ => {}
+            }
+        }
+
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Create a desired state from the current contents of the local `files`
+///
+/// Secrets marked as absent in the `existing` state are preserved so that
+/// pending deletions are not lost when the state is refreshed
+pub async fn export_state<Fs: FileSystem>(
+    fs: &Fs,
+    working_path: &Path,
+    files: &IndexMap<String, SecretFile>,
+    existing: Option<DesiredState>,
+) -> eyre::Result<DesiredState> {
+    let mut secrets = IndexMap::new();
+
+    for file in files.values() {
+        let local = Secret::from_bytes(fs.read_file(&file.resolve_path(working_path)).await?);
+
+        secrets.insert(
+            file.secret.clone(),
+            DesiredSecret {
+                state: DesiredPresence::Present,
+                hash: Some(local.hash()),
+                metadata: file.metadata.clone(),
+            },
+        );
+    }
+
+    if let Some(existing) = existing {
+        for (name, desired) in existing.secrets {
+            if desired.state == DesiredPresence::Absent && !secrets.contains_key(&name) {
+                secrets.insert(name, desired);
+            }
+        }
+    }
+
+    Ok(DesiredState {
+        version: STATE_VERSION,
+        secrets,
+    })
+}
+
+/// Read a desired state from the file at `path`
+#[cfg(not(target_family = "wasm"))]
+pub async fn read_state_file(path: &Path) -> eyre::Result<DesiredState> {
+    let value = tokio::fs::read(path)
+        .await
+        .context("failed to read desired state file")?;
+
+    serde_json::from_slice(&value).context("failed to parse desired state file")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{SecretFile, SecretMetadata},
+        fs::MockFileSystem,
+        reconcile::{
+            DesiredPresence, DesiredSecret, DesiredState, ReconcileAction, export_state, reconcile,
+        },
+        secret::{MANAGED_BY_TAG, MANAGED_BY_VALUE, MockSecretManager, Secret, SecretSummary},
+    };
+    use indexmap::IndexMap;
+    use mockall::predicate::eq;
+    use std::path::{Path, PathBuf};
+
+    /// Summary of the secret `name` created by secret-sync
+    fn managed_summary(name: &str) -> SecretSummary {
+        SecretSummary {
+            name: name.to_string(),
+            tags: IndexMap::from([(MANAGED_BY_TAG.to_string(), MANAGED_BY_VALUE.to_string())]),
+            ..Default::default()
+        }
+    }
+
+    /// Tests that secrets are created, updated, deleted, and skipped to
+    /// match the desired state
+    #[tokio::test]
+    async fn test_reconcile() {
+        let mut files = IndexMap::new();
+        for name in ["new", "changed", "same"] {
+            files.insert(
+                name.to_string(),
+                SecretFile {
+                    path: PathBuf::from(format!(".env.{name}")),
+                    secret: name.to_string(),
+                    metadata: SecretMetadata::default(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .returning(|_path| Ok(b"local".to_vec()));
+
+        let mut state = export_state(&fs, Path::new("/"), &files, None)
+            .await
+            .unwrap();
+        state.secrets.insert(
+            "removed".to_string(),
+            DesiredSecret {
+                state: DesiredPresence::Absent,
+                hash: None,
+                metadata: SecretMetadata::default(),
+            },
+        );
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("new"))
+            .return_once(|_name| Ok(None));
+        secret
+            .expect_find_secret()
+            .with(eq("changed"))
+            .return_once(|_name| Ok(Some(Secret::String("remote".to_string()))));
+        secret
+            .expect_find_secret()
+            .with(eq("same"))
+            .return_once(|_name| Ok(Some(Secret::String("local".to_string()))));
+        secret
+            .expect_find_secret()
+            .with(eq("removed"))
+            .return_once(|_name| Ok(Some(Secret::String("old".to_string()))));
+
+        secret
+            .expect_describe_secret()
+            .times(2)
+            .returning(|name| Ok(Some(managed_summary(name))));
+        secret
+            .expect_set_secret()
+            .times(2)
+            .returning(|_name, _value, _metadata| Ok(()));
+        secret
+            .expect_delete_secret()
+            .times(1)
+            .with(eq("removed"))
+            .returning(|_name| Ok(()));
+
+        let entries = reconcile(&fs, &secret, Path::new("/"), &files, &state, false, false)
+            .await
+            .unwrap();
+
+        let actions: Vec<ReconcileAction> = entries.iter().map(|entry| entry.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                ReconcileAction::Create,
+                ReconcileAction::Update,
+                ReconcileAction::Skip,
+                ReconcileAction::Delete
+            ]
+        );
+    }
+
+    /// Tests that nothing is changed when the local file does not match
+    /// the desired hash
+    #[tokio::test]
+    async fn test_reconcile_hash_mismatch() {
+        let mut files = IndexMap::new();
+        files.insert(
+            "test".to_string(),
+            SecretFile {
+                path: PathBuf::from(".env"),
+                secret: "test".to_string(),
+                metadata: SecretMetadata::default(),
+                ..Default::default()
+            },
+        );
+
+        let mut state = DesiredState {
+            version: 1,
+            secrets: IndexMap::new(),
+        };
+        state.secrets.insert(
+            "test".to_string(),
+            DesiredSecret {
+                state: DesiredPresence::Present,
+                hash: Some(Secret::String("reviewed".to_string()).hash()),
+                metadata: SecretMetadata::default(),
+            },
+        );
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .returning(|_path| Ok(b"unreviewed".to_vec()));
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_find_secret().returning(|_name| Ok(None));
+        secret.expect_set_secret().never();
+
+        assert!(
+            reconcile(&fs, &secret, Path::new("/"), &files, &state, false, false)
+                .await
+                .is_err()
+        );
+    }
+
+    /// Tests that secrets owned by another system are not deleted unless
+    /// adopted
+    #[tokio::test]
+    async fn test_reconcile_foreign_secret() {
+        let mut state = DesiredState {
+            version: 1,
+            secrets: IndexMap::new(),
+        };
+        state.secrets.insert(
+            "legacy".to_string(),
+            DesiredSecret {
+                state: DesiredPresence::Absent,
+                hash: None,
+                metadata: SecretMetadata::default(),
+            },
+        );
+
+        let fs = MockFileSystem::new();
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .returning(|_name| Ok(Some(Secret::String("old".to_string()))));
+        secret.expect_describe_secret().returning(|name| {
+            Ok(Some(SecretSummary {
+                name: name.to_string(),
+                tags: IndexMap::from([(MANAGED_BY_TAG.to_string(), "terraform".to_string())]),
+                ..Default::default()
+            }))
+        });
+        secret.expect_delete_secret().never();
+
+        let files = IndexMap::new();
+        assert!(
+            reconcile(&fs, &secret, Path::new("/"), &files, &state, false, false)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! # Registry
+//!
+//! Container registry credentials produced by the registry credential
+//! generators, written either as docker credential helper output or merged
+//! into a docker `config.json`
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use eyre::{Context, ContextCompat};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Credentials for a container registry, serialized in the format of the
+/// docker credential helper `get` output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct RegistryCredential {
+    /// URL of the registry the credentials are for
+    #[serde(rename = "ServerURL")]
+    pub server_url: String,
+    /// Username to authenticate with
+    pub username: String,
+    /// Password or token to authenticate with
+    pub secret: String,
+}
+
+impl RegistryCredential {
+    /// Create a credential from a base64 encoded `username:password`
+    /// `token` for the `server_url`
+    pub fn from_basic_token(server_url: String, token: &str) -> eyre::Result<Self> {
+        let decoded = STANDARD
+            .decode(token)
+            .context("registry token is not valid base64")?;
+        let decoded = String::from_utf8(decoded).context("registry token is not valid UTF-8")?;
+        let (username, secret) = decoded
+            .split_once(':')
+            .context("registry token is missing the username")?;
+
+        Ok(Self {
+            server_url,
+            username: username.to_string(),
+            secret: secret.to_string(),
+        })
+    }
+
+    /// Base64 encoded `username:password` used within the docker config
+    fn basic_token(&self) -> String {
+        STANDARD.encode(format!("{}:{}", self.username, self.secret))
+    }
+}
+
+/// Merge the `credential` into the `auths` of the `previous` docker config
+/// file contents, keeping every other registry and setting
+pub fn merge_docker_config(
+    previous: Option<&[u8]>,
+    credential: &RegistryCredential,
+) -> eyre::Result<Vec<u8>> {
+    let mut config: Map<String, Value> = match previous {
+        Some(previous) if !previous.is_empty() => {
+            serde_json::from_slice(previous).context("existing docker config is not valid JSON")?
+        }
+        _ => Map::new(),
+    };
+
+    // Docker keys registries by host without the scheme
+    let registry = credential
+        .server_url
+        .trim_start_matches("https://")
+        .trim_end_matches('/');
+
+    let auths = config
+        .entry("auths")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .context("existing docker config \"auths\" is not an object")?;
+
+    auths.insert(
+        registry.to_string(),
+        serde_json::json!({ "auth": credential.basic_token() }),
+    );
+
+    serde_json::to_vec_pretty(&config).context("failed to serialize docker config")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry::{RegistryCredential, merge_docker_config};
+
+    /// Tests that credentials are merged into an existing docker config
+    /// without removing other registries
+    #[test]
+    fn test_merge_docker_config() {
+        let credential = RegistryCredential::from_basic_token(
+            "https://123456789012.dkr.ecr.us-east-1.amazonaws.com".to_string(),
+            "QVdTOnBhc3N3b3Jk",
+        )
+        .unwrap();
+        assert_eq!(credential.username, "AWS");
+        assert_eq!(credential.secret, "password");
+
+        let previous = br#"{"auths":{"ghcr.io":{"auth":"b3RoZXI="}},"credsStore":"desktop"}"#;
+        let merged = merge_docker_config(Some(previous), &credential).unwrap();
+        let merged: serde_json::Value = serde_json::from_slice(&merged).unwrap();
+
+        assert_eq!(
+            merged,
+            serde_json::json!({
+                "auths": {
+                    "ghcr.io": { "auth": "b3RoZXI=" },
+                    "123456789012.dkr.ecr.us-east-1.amazonaws.com": { "auth": "QVdTOnBhc3N3b3Jk" }
+                },
+                "credsStore": "desktop"
+            })
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! # Remote Lock
+//!
+//! Short lived advisory locks stored within the backend itself, used to
+//! coordinate pushes between teammates. Each pushed secret has a matching
+//! lock secret containing the current holder and expiry, expired locks
+//! are considered stale and may be taken over.
+//!
+//! Locks are acquired by exclusively creating a claim secret for the next
+//! generation of the lock (see [SecretManager::create_secret]), so only one
+//! of several teammates acquiring at the same time succeeds. The lock
+//! secret records the latest generation to find the current claim.
+//!
+//! Claims are overwritten rather than deleted when released as some
+//! backends prevent re-creating a recently deleted secret, claims of
+//! earlier generations are removed once a newer one is acquired
+
+use crate::{
+    approval::current_user,
+    arn::SecretArn,
+    clock::{Clock, unix_seconds},
+    config::{RemoteLockConfig, SecretMetadata},
+    error::{ErrorContext, Result, ResultExt, SyncError},
+    secret::{Secret, SecretManager},
+};
+use serde::{Deserialize, Serialize};
+use std::time::UNIX_EPOCH;
+
+/// Lock information stored in a claim secret
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RemoteLockInfo {
+    /// User holding the lock
+    pub owner: String,
+    /// Unique token identifying the holder
+    pub token: String,
+    /// Unix timestamp (seconds) the lock expires at
+    pub expires_at: u64,
+}
+
+/// Latest generation of a lock stored in the lock secret, lock secrets
+/// without a generation are at generation zero
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+struct RemoteLockHead {
+    /// Generation of the most recent claim
+    generation: u64,
+}
+
+/// Held remote lock
+pub struct RemoteLock {
+    /// Name of the claim secret
+    name: String,
+}
+
+/// Create a token unique to this run for identifying lock ownership
+fn lock_token(clock: &dyn Clock) -> String {
+    let nanos = clock
+        .now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+
+    // Process IDs are not available within WASM hosts
+    #[cfg(not(target_family = "wasm"))]
+    let process_id = std::process::id();
+    #[cfg(target_family = "wasm")]
+    let process_id = 0;
+
+    format!("{process_id}-{nanos}")
+}
+
+/// Name of the lock secret for the secret `name`
+///
+/// An ARN is not a valid secret name, so secrets referenced by ARN are
+/// locked using their account, region, and name instead
+fn lock_name(config: &RemoteLockConfig, name: &str) -> String {
+    match SecretArn::parse(name) {
+        Some(arn) => format!(
+            "{}{}/{}/{}",
+            config.prefix, arn.account, arn.region, arn.name
+        ),
+        None => format!("{}{name}", config.prefix),
+    }
+}
+
+/// Name of the claim secret for the `generation` of the lock secret `name`
+fn claim_name(name: &str, generation: u64) -> String {
+    format!("{name}-claim-{generation}")
+}
+
+/// Serialize the lock `value` stored within a lock secret
+fn lock_json(value: &impl Serialize) -> Result<String> {
+    serde_json::to_string(value)
+        .map_err(|error| SyncError::Validation(ErrorContext::from_source(error)))
+        .context("failed to serialize lock")
+}
+
+/// Read the latest generation of the lock secret `name`
+async fn read_generation(secret: &dyn SecretManager, name: &str) -> Result<u64> {
+    let value = secret
+        .find_secret(name)
+        .await
+        .with_context(|| format!("failed to read lock \"{name}\""))?;
+
+    Ok(value
+        .and_then(|value| serde_json::from_slice::<RemoteLockHead>(value.as_bytes()).ok())
+        .unwrap_or_default()
+        .generation)
+}
+
+/// Read the current holder of the claim secret `name`, released, missing,
+/// and invalid claims are treated as not held
+async fn read_lock(secret: &dyn SecretManager, name: &str) -> Result<Option<RemoteLockInfo>> {
+    let value = secret
+        .find_secret(name)
+        .await
+        .with_context(|| format!("failed to read lock \"{name}\""))?;
+
+    Ok(value.and_then(|value| serde_json::from_slice(value.as_bytes()).ok()))
+}
+
+/// Acquire remote locks for each of the `secrets`
+///
+/// Fails if any lock is held by someone else and has not expired, unless
+/// `steal` is set. Locks acquired before a failure are released. Expiry
+/// is determined using the `clock`
+pub async fn acquire_remote_locks(
+    secret: &dyn SecretManager,
+    clock: &dyn Clock,
+    config: &RemoteLockConfig,
+    secrets: &[&str],
+    steal: bool,
+) -> Result<Vec<RemoteLock>> {
+    let token = lock_token(clock);
+    let owner = current_user();
+
+    let mut locks = Vec::new();
+
+    for name in secrets {
+        let info = RemoteLockInfo {
+            owner: owner.clone(),
+            token: token.clone(),
+            expires_at: unix_seconds(clock) + config.ttl,
+        };
+
+        let result = acquire_remote_lock(secret, clock, config, name, &info, steal).await;
+
+        match result {
+            Ok(lock) => locks.push(lock),
+            Err(error) => {
+                release_remote_locks(secret, locks).await;
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(locks)
+}
+
+/// Acquire the remote lock for the secret `name` by claiming the next
+/// generation of its lock secret for the holder `info`
+///
+/// `steal` only applies to the claim held when acquiring started, a claim
+/// created by someone acquiring at the same time is never taken over
+async fn acquire_remote_lock(
+    secret: &dyn SecretManager,
+    clock: &dyn Clock,
+    config: &RemoteLockConfig,
+    name: &str,
+    info: &RemoteLockInfo,
+    mut steal: bool,
+) -> Result<RemoteLock> {
+    let lock_name = lock_name(config, name);
+    let value = Secret::String(lock_json(info)?);
+    let metadata = SecretMetadata {
+        description: Some("secret-sync push lock".to_string()),
+        ..Default::default()
+    };
+
+    let previous = read_generation(secret, &lock_name).await?;
+    let mut generation = previous;
+
+    loop {
+        if generation > 0
+            && let Some(existing) = read_lock(secret, &claim_name(&lock_name, generation)).await?
+        {
+            let stale = existing.expires_at <= unix_seconds(clock);
+
+            if !stale && !steal {
+                match generation == previous {
+                    true => {
+                        return Err(SyncError::conflict(format!(
+                            "secret \"{name}\" is locked by {} (use --steal-lock to take over)",
+                            existing.owner
+                        )));
+                    }
+                    false => {
+                        return Err(SyncError::conflict(format!(
+                            "lost race acquiring lock for secret \"{name}\", it is now locked by {}",
+                            existing.owner
+                        )));
+                    }
+                }
+            }
+
+            tracing::warn!(
+                owner = %existing.owner,
+                stale,
+                "taking over remote lock for \"{name}\""
+            );
+        }
+
+        steal = false;
+        generation += 1;
+
+        let claim = claim_name(&lock_name, generation);
+        match secret.create_secret(&claim, value.clone(), &metadata).await {
+            Ok(()) => break,
+            // Someone else claimed the generation first, check whether
+            // they still hold it
+            Err(SyncError::Conflict(_)) => continue,
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to acquire lock \"{claim}\""));
+            }
+        }
+    }
+
+    let head = lock_json(&RemoteLockHead { generation })?;
+    secret
+        .set_secret(&lock_name, Secret::String(head), &metadata)
+        .await
+        .with_context(|| format!("failed to update lock \"{lock_name}\""))?;
+
+    // Earlier claims are never read again
+    for previous in previous.max(1)..generation {
+        let previous = claim_name(&lock_name, previous);
+        if let Err(error) = secret.delete_secret(&previous).await {
+            tracing::debug!(?error, lock = %previous, "failed to remove previous lock claim");
+        }
+    }
+
+    Ok(RemoteLock {
+        name: claim_name(&lock_name, generation),
+    })
+}
+
+/// Release the provided remote `locks`
+pub async fn release_remote_locks(secret: &dyn SecretManager, locks: Vec<RemoteLock>) {
+    let metadata = SecretMetadata::default();
+
+    for lock in locks {
+        // Released locks are stored as an empty object
+        let value = Secret::String("{}".to_string());
+
+        if let Err(error) = secret.set_secret(&lock.name, value, &metadata).await {
+            tracing::error!(?error, lock = %lock.name, "failed to release remote lock");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        clock::{ManualClock, SystemClock},
+        config::RemoteLockConfig,
+        remote_lock::{acquire_remote_locks, lock_name, release_remote_locks},
+        secret::{SecretManager, memory::MemorySecretManager},
+    };
+    use std::time::Duration;
+
+    /// Tests that a lock held by someone else prevents acquiring until it
+    /// is released
+    #[tokio::test]
+    async fn test_remote_lock_held() {
+        let secret = MemorySecretManager::new();
+        let config = RemoteLockConfig::default();
+
+        let locks = acquire_remote_locks(&secret, &SystemClock, &config, &["test"], false)
+            .await
+            .unwrap();
+        assert!(
+            acquire_remote_locks(&secret, &SystemClock, &config, &["test"], false)
+                .await
+                .is_err()
+        );
+
+        release_remote_locks(&secret, locks).await;
+        acquire_remote_locks(&secret, &SystemClock, &config, &["test"], false)
+            .await
+            .unwrap();
+
+        // Only the latest claim is kept
+        let claim = secret.find_secret("secret-sync/locks/test-claim-1").await;
+        assert!(claim.unwrap().is_none());
+    }
+
+    /// Tests that a lock is treated as stale and taken over once it expires
+    #[tokio::test]
+    async fn test_remote_lock_stale() {
+        let secret = MemorySecretManager::new();
+        let config = RemoteLockConfig {
+            ttl: 10,
+            ..Default::default()
+        };
+        let clock = ManualClock::new(1_000);
+
+        acquire_remote_locks(&secret, &clock, &config, &["test"], false)
+            .await
+            .unwrap();
+
+        clock.advance(Duration::from_secs(9));
+        assert!(
+            acquire_remote_locks(&secret, &clock, &config, &["test"], false)
+                .await
+                .is_err()
+        );
+
+        clock.advance(Duration::from_secs(1));
+        let locks = acquire_remote_locks(&secret, &clock, &config, &["test"], false)
+            .await
+            .unwrap();
+        assert_eq!(locks.len(), 1);
+    }
+
+    /// Tests that secrets referenced by ARN are locked using a valid secret
+    /// name
+    #[tokio::test]
+    async fn test_remote_lock_arn() {
+        let config = RemoteLockConfig::default();
+        let arn = "arn:aws:secretsmanager:eu-west-1:123456789012:secret:app/prod-AbCdEf";
+        assert_eq!(
+            lock_name(&config, arn),
+            "secret-sync/locks/123456789012/eu-west-1/app/prod"
+        );
+        assert_eq!(lock_name(&config, "app/prod"), "secret-sync/locks/app/prod");
+
+        let secret = MemorySecretManager::new();
+        let locks = acquire_remote_locks(&secret, &SystemClock, &config, &[arn], false)
+            .await
+            .unwrap();
+
+        let claim = secret
+            .find_secret("secret-sync/locks/123456789012/eu-west-1/app/prod-claim-1")
+            .await;
+        assert!(claim.unwrap().is_some());
+
+        release_remote_locks(&secret, locks).await;
+    }
+
+    /// Tests that only one of several concurrent attempts acquires a lock
+    #[tokio::test]
+    async fn test_remote_lock_race() {
+        let secret = MemorySecretManager::new();
+        let config = RemoteLockConfig::default();
+
+        let (first, second) = tokio::join!(
+            acquire_remote_locks(&secret, &SystemClock, &config, &["test"], false),
+            acquire_remote_locks(&secret, &SystemClock, &config, &["test"], false)
+        );
+        assert!(first.is_ok() != second.is_ok());
+    }
+}
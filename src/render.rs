@@ -0,0 +1,259 @@
+//! # Render
+//!
+//! Rendering of command output in the format requested through --format,
+//! each format is a [Renderer] over the same [Output]. Warnings raised
+//! during the command are listed after the text output and under the
+//! `warnings` key of the structured output
+
+use eyre::Context;
+use secret_sync::error::SyncError;
+use serde_json::json;
+use std::fmt::Write;
+
+/// Output data for a successful run
+pub struct Output {
+    /// Text version
+    pub text: String,
+    /// JSON version, used as the data for every structured format
+    pub json: serde_json::Value,
+}
+
+impl Output {
+    /// Attach the `warnings` raised while producing the output
+    pub fn with_warnings(mut self, warnings: &[String]) -> Self {
+        for warning in warnings {
+            if !self.text.is_empty() {
+                self.text.push('\n');
+            }
+            _ = write!(self.text, "warning: {warning}");
+        }
+
+        if let serde_json::Value::Object(values) = &mut self.json {
+            values.insert("warnings".to_string(), json!(warnings));
+        }
+
+        self
+    }
+}
+
+/// Renders command output and errors for a specific format
+pub trait Renderer {
+    /// Render the successful `output` of a command
+    fn render(&self, output: &Output) -> eyre::Result<String>;
+
+    /// Render the `error` of a failed command along with the `warnings`
+    /// raised before it failed, [None] when the error should only be
+    /// reported through the error handler
+    fn render_error(
+        &self,
+        error: &eyre::Report,
+        warnings: &[String],
+    ) -> eyre::Result<Option<String>>;
+}
+
+/// Human readable text output
+pub struct HumanRenderer;
+
+impl Renderer for HumanRenderer {
+    fn render(&self, output: &Output) -> eyre::Result<String> {
+        Ok(output.text.clone())
+    }
+
+    fn render_error(
+        &self,
+        _error: &eyre::Report,
+        _warnings: &[String],
+    ) -> eyre::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Machine readable JSON output
+pub struct JsonRenderer;
+
+impl Renderer for JsonRenderer {
+    fn render(&self, output: &Output) -> eyre::Result<String> {
+        serde_json::to_string_pretty(&output.json).context("failed to render JSON output")
+    }
+
+    fn render_error(
+        &self,
+        error: &eyre::Report,
+        warnings: &[String],
+    ) -> eyre::Result<Option<String>> {
+        let value = serde_json::to_string(&error_value(error, warnings))?;
+        Ok(Some(value))
+    }
+}
+
+/// Machine readable YAML output
+pub struct YamlRenderer;
+
+impl Renderer for YamlRenderer {
+    fn render(&self, output: &Output) -> eyre::Result<String> {
+        serde_norway::to_string(&output.json).context("failed to render YAML output")
+    }
+
+    fn render_error(
+        &self,
+        error: &eyre::Report,
+        warnings: &[String],
+    ) -> eyre::Result<Option<String>> {
+        let value = serde_norway::to_string(&error_value(error, warnings))?;
+        Ok(Some(value))
+    }
+}
+
+/// Machine readable TOML output
+///
+/// TOML has no null value so null fields are omitted, output that is not
+/// an object is placed under a `value` key as TOML documents must be tables
+pub struct TomlRenderer;
+
+impl Renderer for TomlRenderer {
+    fn render(&self, output: &Output) -> eyre::Result<String> {
+        render_toml(&output.json)
+    }
+
+    fn render_error(
+        &self,
+        error: &eyre::Report,
+        warnings: &[String],
+    ) -> eyre::Result<Option<String>> {
+        render_toml(&error_value(error, warnings)).map(Some)
+    }
+}
+
+/// Structured value reported for a failed command, failures of the core
+/// operations include the kind of the [SyncError]
+fn error_value(error: &eyre::Report, warnings: &[String]) -> serde_json::Value {
+    json!({
+        "success": false,
+        "error": error.to_string(),
+        "kind": SyncError::find(error.as_ref()).map(SyncError::kind),
+        "warnings": warnings,
+    })
+}
+
+/// Render a JSON `value` as a TOML document
+fn render_toml(value: &serde_json::Value) -> eyre::Result<String> {
+    let table = match json_to_toml(value) {
+        Some(toml::Value::Table(table)) => table,
+        Some(value) => toml::Table::from_iter([("value".to_string(), value)]),
+        None => toml::Table::new(),
+    };
+
+    toml::to_string_pretty(&table).context("failed to render TOML output")
+}
+
+/// Convert a JSON `value` to a TOML value, [None] for null values
+fn json_to_toml(value: &serde_json::Value) -> Option<toml::Value> {
+    Some(match value {
+        serde_json::Value::Null => return None,
+        serde_json::Value::Bool(value) => toml::Value::Boolean(*value),
+        serde_json::Value::Number(value) => match value.as_i64() {
+            Some(value) => toml::Value::Integer(value),
+            None => toml::Value::Float(value.as_f64()?),
+        },
+        serde_json::Value::String(value) => toml::Value::String(value.clone()),
+        serde_json::Value::Array(values) => {
+            toml::Value::Array(values.iter().filter_map(json_to_toml).collect())
+        }
+        serde_json::Value::Object(values) => toml::Value::Table(
+            values
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), json_to_toml(value)?)))
+                .collect(),
+        ),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::render::{JsonRenderer, Output, Renderer, TomlRenderer, YamlRenderer};
+    use secret_sync::error::SyncError;
+    use serde_json::json;
+
+    /// Tests rendering output in the structured formats
+    #[test]
+    fn test_render_structured() {
+        let output = Output {
+            text: String::new(),
+            json: json!({
+                "success": true,
+                "files": ["app", "worker"],
+                "note": null,
+            }),
+        };
+
+        assert_eq!(
+            YamlRenderer.render(&output).unwrap(),
+            "files:\n- app\n- worker\nnote: null\nsuccess: true\n"
+        );
+        assert_eq!(
+            TomlRenderer.render(&output).unwrap(),
+            "files = [\n    \"app\",\n    \"worker\",\n]\nsuccess = true\n"
+        );
+
+        let output = Output {
+            text: String::new(),
+            json: json!(["app"]),
+        };
+        assert_eq!(TomlRenderer.render(&output).unwrap(), "value = [\"app\"]\n");
+    }
+
+    /// Tests that warnings are listed after the text and under the
+    /// warnings key of the structured output
+    #[test]
+    fn test_render_warnings() {
+        let output = Output {
+            text: "successfully pushed 1 secret file(s)".to_string(),
+            json: json!({ "success": true }),
+        }
+        .with_warnings(&["secret file \".env\" is world-readable".to_string()]);
+
+        assert_eq!(
+            output.text,
+            "successfully pushed 1 secret file(s)\nwarning: secret file \".env\" is world-readable"
+        );
+        assert_eq!(
+            output.json,
+            json!({ "success": true, "warnings": ["secret file \".env\" is world-readable"] })
+        );
+
+        let output = Output {
+            text: String::new(),
+            json: json!(["app"]),
+        }
+        .with_warnings(&[]);
+        assert_eq!(output.json, json!(["app"]));
+    }
+
+    /// Tests that errors of the core operations report their kind
+    #[test]
+    fn test_render_error_kind() {
+        let error = eyre::Report::new(SyncError::not_found("secret \"app\" not found"))
+            .wrap_err("failed to pull \"app\"");
+        let value: serde_json::Value =
+            serde_json::from_str(&JsonRenderer.render_error(&error, &[]).unwrap().unwrap())
+                .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "success": false,
+                "error": "failed to pull \"app\"",
+                "kind": "backend-not-found",
+                "warnings": [],
+            })
+        );
+
+        let value: serde_json::Value = serde_json::from_str(
+            &JsonRenderer
+                .render_error(&eyre::eyre!("invalid argument"), &[])
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(value["kind"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,164 @@
+//! # Resume
+//!
+//! Per-file progress of a pull or push batch persisted into the state
+//! directory while the batch runs. When a batch is interrupted, re-running
+//! the same command skips the files that were already completed as long as
+//! their local contents have not changed since. The progress is removed
+//! once a batch completes successfully
+
+use crate::state::create_state_dir;
+use eyre::Context;
+use indexmap::IndexMap;
+use secret_sync::{config::SecretFile, fs::FileSystem, secret::Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// Progress of an in-progress or interrupted batch
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResumeState {
+    /// Subcommand and selection of files the progress belongs to, progress
+    /// is only resumed by a run of the same batch
+    pub batch: String,
+    /// Hashes of the local contents of each completed file after it was
+    /// completed, keyed by [file_key]
+    pub completed: IndexMap<String, String>,
+}
+
+/// Key identifying a file within a batch
+fn file_key(file: &SecretFile) -> String {
+    match &file.host {
+        Some(host) => format!("{} -> {host}:{}", file.secret, file.path.display()),
+        None => format!("{} -> {}", file.secret, file.path.display()),
+    }
+}
+
+/// Create the identifier of a `command` batch processing the `files`
+pub fn batch_id<'a>(command: &str, files: impl IntoIterator<Item = &'a SecretFile>) -> String {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file_key(file));
+        hasher.update([0]);
+    }
+
+    format!("{command}:{}", hex::encode(hasher.finalize()))
+}
+
+/// Hash of the local contents of `file`, files that are piped to a sink or
+/// do not exist have no local contents and are never resumed
+pub async fn local_hash<Fs: FileSystem>(
+    fs: &Fs,
+    working_path: &Path,
+    file: &SecretFile,
+) -> eyre::Result<Option<String>> {
+    if file.sink.is_some() {
+        return Ok(None);
+    }
+
+    let value = fs
+        .read_file_optional(&file.resolve_path(working_path))
+        .await?;
+
+    Ok(value.map(|value| Secret::from_bytes(value).hash()))
+}
+
+impl ResumeState {
+    /// Read the progress of the `batch` from `path`, providing empty
+    /// progress when there is none or it belongs to a different batch
+    pub async fn read(path: &Path, batch: String) -> eyre::Result<Self> {
+        let state: Option<ResumeState> = match tokio::fs::read(path).await {
+            Ok(value) => serde_json::from_slice(&value).ok(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error).context("failed to read batch progress"),
+        };
+
+        Ok(match state {
+            Some(state) if state.batch == batch => state,
+            _ => ResumeState {
+                batch,
+                completed: IndexMap::new(),
+            },
+        })
+    }
+
+    /// Whether the `file` was completed by an earlier run of the batch and
+    /// its local contents still have the `hash` recorded on completion
+    pub fn is_completed(&self, file: &SecretFile, hash: Option<&str>) -> bool {
+        hash.is_some_and(|hash| {
+            self.completed
+                .get(&file_key(file))
+                .is_some_and(|completed| completed == hash)
+        })
+    }
+
+    /// Record that `file` was completed with the local contents `hash` and
+    /// persist the progress to `path`
+    pub async fn complete(
+        &mut self,
+        path: &Path,
+        file: &SecretFile,
+        hash: Option<String>,
+    ) -> eyre::Result<()> {
+        let Some(hash) = hash else {
+            return Ok(());
+        };
+
+        self.completed.insert(file_key(file), hash);
+
+        if let Some(parent) = path.parent() {
+            create_state_dir(parent).await?;
+        }
+
+        let value = serde_json::to_vec_pretty(self)?;
+        tokio::fs::write(path, value)
+            .await
+            .context("failed to write batch progress")
+    }
+}
+
+/// Remove the batch progress at `path` once the batch has completed
+pub async fn remove_resume(path: &Path) -> eyre::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error).context("failed to remove batch progress"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::resume::{ResumeState, batch_id};
+    use secret_sync::config::SecretFile;
+    use std::path::PathBuf;
+
+    /// Tests that progress is only resumed by the same batch while the
+    /// local contents are unchanged
+    #[tokio::test]
+    async fn test_resume_state() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("resume.json");
+
+        let files = [".env.app", ".env.worker"].map(|path| SecretFile {
+            path: PathBuf::from(path),
+            secret: path.trim_start_matches(".env.").to_string(),
+            ..Default::default()
+        });
+        let batch = batch_id("push", &files);
+
+        let mut state = ResumeState::read(&path, batch.clone()).await.unwrap();
+        state
+            .complete(&path, &files[0], Some("a".to_string()))
+            .await
+            .unwrap();
+
+        let state = ResumeState::read(&path, batch).await.unwrap();
+        assert!(state.is_completed(&files[0], Some("a")));
+        assert!(!state.is_completed(&files[0], Some("b")));
+        assert!(!state.is_completed(&files[1], Some("a")));
+
+        let other = ResumeState::read(&path, batch_id("pull", &files))
+            .await
+            .unwrap();
+        assert!(!other.is_completed(&files[0], Some("a")));
+    }
+}
@@ -0,0 +1,251 @@
+//! # Scan
+//!
+//! Scanning of the working tree for occurrences of the configured secret
+//! values. Values are only compared by hash, lines and the tokens within
+//! them are hashed and matched against the hashes of the secret values
+
+use eyre::Context;
+use indexmap::IndexMap;
+use secret_sync::{
+    doctor::ValueLocation,
+    secret::Secret,
+    structured::{StructuredValue, value_to_string},
+};
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// Values shorter than this are too common to be reported as leaks
+const MIN_SCAN_VALUE_LENGTH: usize = 8;
+
+/// Files larger than this are not scanned
+const MAX_SCAN_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Occurrence of a secret value within a scanned file
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Leak {
+    /// Path of the file relative to the working path
+    pub path: PathBuf,
+    /// Line number the value appears on
+    pub line: usize,
+    /// Location of the value within the configured files
+    pub location: ValueLocation,
+}
+
+impl Display for Leak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{} contains the value of {}",
+            self.path.display(),
+            self.line,
+            self.location
+        )
+    }
+}
+
+/// Hashes of the secret values to scan for
+#[derive(Default)]
+pub struct ScanHashes {
+    hashes: IndexMap<String, ValueLocation>,
+}
+
+impl ScanHashes {
+    /// Add the hashes for the secret `value` of the file `name`, dotenv
+    /// and JSON values are added key by key
+    pub fn add(&mut self, name: &str, value: &Secret) {
+        let entries: Vec<(Option<String>, String)> = match StructuredValue::parse(value.as_bytes())
+        {
+            Ok(value) => value
+                .values
+                .iter()
+                .map(|(key, value)| (Some(key.clone()), value_to_string(value)))
+                .collect(),
+            Err(_) => match std::str::from_utf8(value.as_bytes()) {
+                Ok(value) => vec![(None, value.trim().to_string())],
+                Err(_) => return,
+            },
+        };
+
+        for (key, value) in entries {
+            if value.len() < MIN_SCAN_VALUE_LENGTH {
+                continue;
+            }
+
+            self.hashes
+                .entry(Secret::String(value).hash())
+                .or_insert_with(|| ValueLocation {
+                    file: name.to_string(),
+                    key,
+                });
+        }
+    }
+
+    /// Check if there are no hashes to scan for
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Find the lines within `contents` where the whole line or one of
+    /// its tokens matches a secret value
+    pub fn scan_contents(&self, path: &Path, contents: &str) -> Vec<Leak> {
+        let mut leaks = Vec::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line_trimmed = line.trim();
+            let tokens = line_trimmed.split(|char: char| {
+                char.is_whitespace()
+                    || matches!(
+                        char,
+                        '=' | '"' | '\'' | '`' | ',' | ';' | '(' | ')' | '[' | ']' | '{' | '}'
+                    )
+            });
+
+            let location = std::iter::once(line_trimmed)
+                .chain(tokens)
+                .filter(|token| token.len() >= MIN_SCAN_VALUE_LENGTH)
+                .find_map(|token| self.hashes.get(&Secret::String(token.to_string()).hash()));
+
+            if let Some(location) = location {
+                leaks.push(Leak {
+                    path: path.to_path_buf(),
+                    line: index + 1,
+                    location: location.clone(),
+                });
+            }
+        }
+
+        leaks
+    }
+}
+
+/// List the files within `working_path` that should be scanned
+///
+/// Within a git repository the files that are not ignored by .gitignore
+/// are used, otherwise every file outside of hidden directories is used
+pub fn list_scan_files(working_path: &Path) -> eyre::Result<Vec<PathBuf>> {
+    let output = std::process::Command::new("git")
+        .args([
+            "ls-files",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+            "-z",
+        ])
+        .current_dir(working_path)
+        .output();
+
+    if let Ok(output) = output
+        && output.status.success()
+    {
+        return Ok(output
+            .stdout
+            .split(|byte| *byte == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+            .collect());
+    }
+
+    tracing::debug!("not a git repository, scanning all files");
+
+    let mut files = Vec::new();
+    let mut directories = vec![PathBuf::new()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = std::fs::read_dir(working_path.join(&directory))
+            .with_context(|| format!("failed to read directory \"{}\"", directory.display()))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let path = directory.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    directories.push(path);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Scan each of the `files` relative to `working_path` for the secret
+/// values, the `excluded` paths (the secret files themselves) are skipped
+pub async fn scan_files(
+    hashes: &ScanHashes,
+    working_path: &Path,
+    files: Vec<PathBuf>,
+    excluded: &[PathBuf],
+) -> eyre::Result<Vec<Leak>> {
+    let mut leaks = Vec::new();
+
+    for path in files {
+        let full_path = working_path.join(&path);
+        if excluded.contains(&full_path) {
+            continue;
+        }
+
+        // Files may be listed by git but deleted from the working tree
+        let Ok(metadata) = tokio::fs::metadata(&full_path).await else {
+            continue;
+        };
+
+        if !metadata.is_file() || metadata.len() > MAX_SCAN_FILE_SIZE {
+            continue;
+        }
+
+        let contents = tokio::fs::read(&full_path)
+            .await
+            .with_context(|| format!("failed to read \"{}\"", path.display()))?;
+
+        // Binary files are skipped
+        let Ok(contents) = String::from_utf8(contents) else {
+            continue;
+        };
+
+        leaks.extend(hashes.scan_contents(&path, &contents));
+    }
+
+    Ok(leaks)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scan::ScanHashes;
+    use secret_sync::secret::Secret;
+    use std::path::Path;
+
+    /// Tests that secret values are found within lines and tokens
+    #[test]
+    fn test_scan_contents() {
+        let mut hashes = ScanHashes::default();
+        hashes.add(
+            "app",
+            &Secret::String("API_KEY=sk_live_abcdef123456\nPORT=8080\n".to_string()),
+        );
+        hashes.add(
+            "cert",
+            &Secret::String("wholefilesecretvalue\n".to_string()),
+        );
+
+        let leaks = hashes.scan_contents(
+            Path::new("src/config.js"),
+            "const port = 8080;\nconst key = \"sk_live_abcdef123456\";\nwholefilesecretvalue\n",
+        );
+
+        let leaks: Vec<String> = leaks.iter().map(|leak| leak.to_string()).collect();
+        assert_eq!(
+            leaks,
+            vec![
+                "src/config.js:2 contains the value of app:API_KEY",
+                "src/config.js:3 contains the value of cert",
+            ]
+        );
+    }
+}
@@ -5,122 +5,811 @@
 
 use super::Secret;
 use crate::{
-    config::{AwsConfig, SecretMetadata},
-    secret::SecretManager,
+    arn::SecretArn,
+    config::{
+        AwsConfig, CredentialsConfig, NetworkConfig, RdsIamTokenConfig, SecretGenerator,
+        SecretMetadata, WebIdentityConfig,
+    },
+    credentials::{
+        CredentialEnvironment, CredentialSource, diagnose_credentials, resolve_aws_credentials,
+        resolve_config_credentials, resolve_identity_token, resolve_mfa_token,
+    },
+    doctor::CredentialDiagnosis,
+    error::{ErrorContext, Result, ResultExt, SyncError},
+    registry::RegistryCredential,
+    secret::{
+        ListSecretsOptions, MANAGED_BY_TAG, MANAGED_BY_VALUE, SecretGrant, SecretManager,
+        SecretSummary, SecretValue,
+        dev::{DEV_CREDENTIAL, DevEndpoint},
+        http::TunedHttpClient,
+    },
+    structured::url_encode,
 };
 use async_trait::async_trait;
 use aws_config::{
     BehaviorVersion, Region,
+    credential_process::CredentialProcessProvider,
     meta::region::{ProvideRegion, RegionProviderChain},
 };
+use aws_credential_types::provider::{error::CredentialsError, future};
 use aws_sdk_secretsmanager::{
-    config::{Credentials, SharedCredentialsProvider},
-    primitives::Blob,
-    types::Tag,
+    config::{Credentials, ProvideCredentials, SharedCredentialsProvider},
+    operation::create_secret::builders::CreateSecretFluentBuilder,
+    primitives::{Blob, DateTime, DateTimeFormat},
+    types::{Filter, FilterNameStringType, Tag},
 };
-use eyre::Context;
+use aws_sdk_sts::error::ProvideErrorMetadata;
+use aws_sigv4::http_request::{
+    SignableBody, SignableRequest, SignatureLocation, SigningSettings, sign,
+};
+use futures_util::{
+    StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+};
+use indexmap::IndexMap;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::OnceCell;
+
+/// Shortest duration temporary credentials can be granted for
+const MIN_GRANT_DURATION: Duration = Duration::from_secs(15 * 60);
+
+/// Duration RDS IAM authentication tokens are valid for
+const RDS_IAM_TOKEN_EXPIRY: Duration = Duration::from_secs(15 * 60);
+
+/// Error codes the AWS APIs respond with when throttling requests
+const THROTTLING_ERROR_CODES: &[&str] = &[
+    "ThrottlingException",
+    "Throttling",
+    "TooManyRequestsException",
+    "RequestLimitExceeded",
+];
+
+/// Error codes the AWS APIs respond with when the credentials are invalid,
+/// expired, or not allowed to make the request
+const AUTH_ERROR_CODES: &[&str] = &[
+    "AccessDenied",
+    "AccessDeniedException",
+    "ExpiredToken",
+    "ExpiredTokenException",
+    "IncompleteSignature",
+    "InvalidClientTokenId",
+    "InvalidSignatureException",
+    "MissingAuthenticationToken",
+    "SignatureDoesNotMatch",
+    "UnrecognizedClientException",
+];
+
+/// Error codes the AWS APIs respond with when the request is invalid
+const VALIDATION_ERROR_CODES: &[&str] = &[
+    "InvalidParameterException",
+    "ParameterPatternMismatchException",
+    "ValidationException",
+];
+
+/// Categorize a failed request `error` using the error code of the
+/// response, errors without a known code are [SyncError::Backend] errors
+pub fn request_error<E>(error: E) -> SyncError
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let kind = match error.code() {
+        Some(code) if THROTTLING_ERROR_CODES.contains(&code) => SyncError::Throttled,
+        Some(code) if AUTH_ERROR_CODES.contains(&code) => SyncError::BackendAuth,
+        Some(code) if VALIDATION_ERROR_CODES.contains(&code) => SyncError::Validation,
+        Some("ResourceNotFoundException" | "ParameterNotFound") => SyncError::BackendNotFound,
+        Some("ResourceExistsException" | "ParameterAlreadyExists") => SyncError::Conflict,
+        _ => SyncError::Backend,
+    };
 
+    kind(ErrorContext::from_source(error))
+}
+
+/// Secret manager backed by AWS Secrets Manager
 pub struct AwsSecretManager {
     client: aws_sdk_secretsmanager::Client,
+    /// Clients for secrets referenced by ARNs in other regions by region
+    regional_clients: Mutex<HashMap<String, aws_sdk_secretsmanager::Client>>,
+    sdk_config: aws_config::SdkConfig,
+    sts: aws_sdk_sts::Client,
+    ecr: aws_sdk_ecr::Client,
+    credentials_provider: Option<SharedCredentialsProvider>,
+    region: String,
+    role_arn: Option<String>,
 }
 
 impl AwsSecretManager {
     /// Create a [AwsSecretManager] from the provided `config`
-    pub async fn from_config(config: &AwsConfig) -> eyre::Result<AwsSecretManager> {
-        // Setup the region provider
-        let region_provider: Box<dyn ProvideRegion> = match config.region.as_ref() {
-            Some(value) => Box::new(Region::new(value.clone())),
-            None => Box::new(RegionProviderChain::default_provider().or_else("us-east-1")),
-        };
+    ///
+    /// Credentials are resolved using [load_sdk_config]
+    pub async fn from_config(
+        config: &AwsConfig,
+        credentials_config: &CredentialsConfig,
+        network: &NetworkConfig,
+        dev: Option<&DevEndpoint>,
+    ) -> Result<AwsSecretManager> {
+        let (sdk_config, region) =
+            load_sdk_config(config, credentials_config, network, dev).await?;
 
-        // Load the base configuration from env variables
-        // (See https://docs.aws.amazon.com/sdkref/latest/guide/settings-reference.html#EVarSettings)
-        let mut builder = aws_config::from_env()
-            .region(region_provider)
-            .behavior_version(BehaviorVersion::v2026_01_12());
+        let client = aws_sdk_secretsmanager::Client::new(&sdk_config);
+        let sts = aws_sdk_sts::Client::new(&sdk_config);
+        let ecr = aws_sdk_ecr::Client::new(&sdk_config);
 
-        if let Some(profile) = config.profile.as_ref() {
-            builder = builder.profile_name(profile);
-        }
+        let credentials_provider = sdk_config.credentials_provider();
+
+        Ok(Self {
+            client,
+            regional_clients: Mutex::default(),
+            sdk_config,
+            sts,
+            ecr,
+            credentials_provider,
+            region,
+            role_arn: config.role_arn.clone(),
+        })
+    }
+
+    /// Client for requests about the secret `name`, secrets referenced by
+    /// an ARN in another region use a client for the region of the ARN
+    fn client_for(&self, name: &str) -> aws_sdk_secretsmanager::Client {
+        self.region_client(self.region_for(name))
+    }
 
-        if let Some(endpoint) = config.endpoint.as_ref() {
-            builder = builder.endpoint_url(endpoint);
+    /// Client for requests to the `region`
+    fn region_client(&self, region: &str) -> aws_sdk_secretsmanager::Client {
+        if region == self.region {
+            return self.client.clone();
         }
 
-        if let Some(credentials) = config.credentials.as_ref() {
-            let credentials = Credentials::new(
-                credentials.access_key_id.clone(),
-                credentials.access_key_secret.clone(),
-                None,
-                None,
-                "secret_sync",
-            );
+        let mut clients = self
+            .regional_clients
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
 
-            builder = builder.credentials_provider(SharedCredentialsProvider::new(credentials));
+        clients
+            .entry(region.to_string())
+            .or_insert_with(|| {
+                let config = aws_sdk_secretsmanager::config::Builder::from(&self.sdk_config)
+                    .region(Region::new(region.to_string()))
+                    .build();
+                aws_sdk_secretsmanager::Client::from_conf(config)
+            })
+            .clone()
+    }
+
+    /// Region storing the secret `name`
+    fn region_for<'a>(&'a self, name: &'a str) -> &'a str {
+        SecretArn::parse(name).map_or(&self.region, |arn| arn.region)
+    }
+
+    /// Request creating the secret `name` using the `client` of its region,
+    /// created secrets are marked as managed unless the marker is overridden
+    fn create_secret_request(
+        &self,
+        client: aws_sdk_secretsmanager::Client,
+        name: &str,
+        secret_binary: Option<Blob>,
+        secret_string: Option<String>,
+        metadata: &SecretMetadata,
+    ) -> CreateSecretFluentBuilder {
+        let mut tags = metadata.tags.clone().unwrap_or_default();
+        tags.entry(MANAGED_BY_TAG.to_string())
+            .or_insert_with(|| MANAGED_BY_VALUE.to_string());
+
+        client
+            .create_secret()
+            .set_secret_binary(secret_binary)
+            .set_secret_string(secret_string)
+            .set_description(metadata.description.clone())
+            .set_tags(Some(aws_tags(&tags)))
+            .name(name)
+    }
+
+    /// Create the secret referenced by the ARN `arn` when pushing a secret
+    /// that does not exist yet
+    ///
+    /// Secrets are created by name within the region of the ARN, which is
+    /// only possible for ARNs of the account of the credentials
+    async fn create_arn_secret(
+        &self,
+        arn: &SecretArn<'_>,
+        secret_binary: Option<Blob>,
+        secret_string: Option<String>,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        let identity = self
+            .sts
+            .get_caller_identity()
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to get caller identity");
+            })
+            .map_err(request_error)
+            .context("failed to get account of the AWS credentials")?;
+
+        if identity.account.as_deref() != Some(arn.account) {
+            return Err(SyncError::validation(format!(
+                "secret \"{}\" does not exist and cannot be created in account {}, \
+                 secrets can only be created in the account of the credentials",
+                arn.name, arn.account
+            )));
         }
 
-        let sdk_config = builder.load().await;
+        if arn.suffix.is_some() {
+            tracing::warn!(
+                secret = arn.name,
+                "secret is created with a new ARN suffix, update the ARN within the config"
+            );
+        }
 
-        let client = aws_sdk_secretsmanager::Client::new(&sdk_config);
+        self.create_secret_request(
+            self.region_client(arn.region),
+            arn.name,
+            secret_binary,
+            secret_string,
+            metadata,
+        )
+        .send()
+        .await
+        .inspect_err(|error| {
+            tracing::error!(?error, "failed to create secret");
+        })
+        .map_err(request_error)
+        .context("failed to create secret")?;
 
-        Ok(Self { client })
+        Ok(())
     }
-}
 
-#[async_trait]
-impl SecretManager for AwsSecretManager {
-    async fn get_secret(&self, name: &str) -> eyre::Result<Secret> {
-        let result = match self.client.get_secret_value().secret_id(name).send().await {
+    /// Find the current value of the secret `name` along with its version
+    /// metadata, providing [None] when the secret does not exist
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region_for(name)))]
+    async fn find_secret_value(&self, name: &str) -> Result<Option<SecretValue>> {
+        let result = match self
+            .client_for(name)
+            .get_secret_value()
+            .secret_id(name)
+            .send()
+            .await
+        {
             Ok(value) => value,
             Err(error) => {
                 if error
                     .as_service_error()
                     .is_some_and(|value| value.is_resource_not_found_exception())
                 {
-                    eyre::bail!("secret \"{name}\" not found")
+                    return Ok(None);
                 }
 
                 tracing::error!(?error, "failed to get secret value");
-                return Err(eyre::Report::new(error));
+                return Err(request_error(error));
             }
         };
 
-        if let Some(value) = result.secret_string {
-            return Ok(Secret::String(value));
+        let data = match (result.secret_string, result.secret_binary) {
+            (Some(value), _) => Secret::String(value),
+            (None, Some(value)) => Secret::Binary(value.into_inner()),
+            (None, None) => {
+                return Err(SyncError::backend(format!(
+                    "no valid secret found for \"{name}\" "
+                )));
+            }
+        };
+
+        Ok(Some(SecretValue {
+            data,
+            version_id: result.version_id,
+            arn: result.arn,
+            created: result.created_date.as_ref().and_then(format_date),
+        }))
+    }
+}
+
+/// Load the SDK config for the provided `config` along with the resolved
+/// region, shared by the backends built on the AWS SDK
+///
+/// When no credentials can be resolved on first use the `credentials_config`
+/// determines whether credentials are prompted for
+///
+/// When a `dev` emulator endpoint is provided it is used with dummy
+/// credentials unless credentials are configured
+///
+/// Requests are sent using an HTTP client tuned by the `network` config
+pub async fn load_sdk_config(
+    config: &AwsConfig,
+    credentials_config: &CredentialsConfig,
+    network: &NetworkConfig,
+    dev: Option<&DevEndpoint>,
+) -> Result<(aws_config::SdkConfig, String)> {
+    // Setup the region provider
+    let region_provider: Box<dyn ProvideRegion> = match config.region.as_ref() {
+        Some(value) => Box::new(Region::new(value.clone())),
+        None => Box::new(RegionProviderChain::default_provider().or_else("us-east-1")),
+    };
+
+    // Load the base configuration from env variables
+    // (See https://docs.aws.amazon.com/sdkref/latest/guide/settings-reference.html#EVarSettings)
+    let mut builder = aws_config::from_env()
+        .region(region_provider)
+        .behavior_version(BehaviorVersion::v2026_01_12())
+        .http_client(TunedHttpClient::new(network));
+
+    if let Some(profile) = config.profile.as_ref() {
+        builder = builder.profile_name(profile);
+    }
+
+    if let Some(endpoint) = config.endpoint.as_ref() {
+        builder = builder.endpoint_url(endpoint);
+    }
+
+    if let Some(dev) = dev {
+        tracing::info!(emulator = dev.name, endpoint = %dev.url, "using local development endpoint");
+        builder = builder.endpoint_url(&dev.url);
+
+        if config.credentials.is_none() {
+            let credentials = Credentials::new(
+                DEV_CREDENTIAL,
+                DEV_CREDENTIAL,
+                None,
+                None,
+                "secret_sync_dev",
+            );
+            builder = builder.credentials_provider(SharedCredentialsProvider::new(credentials));
+        }
+    }
+
+    if let Some(credentials) = config.credentials.as_ref() {
+        let credentials = resolve_config_credentials(credentials)
+            .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))?;
+        let credentials = Credentials::new(
+            credentials.access_key_id,
+            credentials.access_key_secret,
+            None,
+            None,
+            "secret_sync",
+        );
+
+        builder = builder.credentials_provider(SharedCredentialsProvider::new(credentials));
+    }
+
+    let credential_process = config
+        .credential_process
+        .as_ref()
+        .or(credentials_config.command.as_ref());
+
+    if let Some(command) = credential_process
+        && config.credentials.is_none()
+        && dev.is_none()
+    {
+        builder = builder.credentials_provider(SharedCredentialsProvider::new(
+            CredentialProcessProvider::new(command.clone()),
+        ));
+    }
+
+    if config.use_fips {
+        builder = builder.use_fips(true);
+    }
+
+    if config.use_dualstack {
+        builder = builder.use_dual_stack(true);
+    }
+
+    let mut sdk_config = builder.load().await;
+
+    let region = sdk_config
+        .region()
+        .ok_or_else(|| SyncError::config("failed to determine AWS region"))?
+        .to_string();
+    config
+        .validate_region(&region)
+        .map_err(|error| SyncError::Config(ErrorContext::from_source(error)))?;
+
+    if config.credentials.is_none()
+        && dev.is_none()
+        && credential_process.is_none()
+        && config.web_identity.is_none()
+        && let Some(provider) = sdk_config.credentials_provider()
+    {
+        let provider = PromptCredentialsProvider {
+            inner: provider,
+            credentials_config: credentials_config.clone(),
+            profile: config.profile.clone(),
+            prompted: OnceCell::new(),
+        };
+
+        sdk_config = sdk_config
+            .into_builder()
+            .credentials_provider(SharedCredentialsProvider::new(provider))
+            .build();
+    }
+
+    if let Some(web_identity) = config.web_identity.as_ref() {
+        let credentials = assume_role_with_web_identity(&sdk_config, web_identity).await?;
+
+        sdk_config = sdk_config
+            .into_builder()
+            .credentials_provider(SharedCredentialsProvider::new(credentials))
+            .build();
+    }
+
+    if let Some(role_arn) = config.role_arn.as_ref() {
+        let credentials = assume_role(&sdk_config, config, role_arn).await?;
+
+        sdk_config = sdk_config
+            .into_builder()
+            .credentials_provider(SharedCredentialsProvider::new(credentials))
+            .build();
+    }
+
+    Ok((sdk_config, region))
+}
+
+/// Assume the `role_arn` using STS, providing a MFA token code when
+/// the config specifies a MFA device
+async fn assume_role(
+    sdk_config: &aws_config::SdkConfig,
+    config: &AwsConfig,
+    role_arn: &str,
+) -> Result<Credentials> {
+    let client = aws_sdk_sts::Client::new(sdk_config);
+
+    let mut request = client
+        .assume_role()
+        .role_arn(role_arn)
+        .role_session_name(config.role_session_name.as_deref().unwrap_or("secret-sync"));
+
+    if let Some(serial) = config.mfa_serial.as_ref() {
+        let token = resolve_mfa_token(
+            serial,
+            config.mfa_token.as_deref(),
+            config.mfa_command.as_deref(),
+        )
+        .map_err(|error| SyncError::BackendAuth(ErrorContext::from_source(error)))?;
+
+        request = request.serial_number(serial).token_code(token);
+    }
+
+    let output = match request.send().await {
+        Ok(value) => value,
+        Err(error) => {
+            tracing::error!(?error, "failed to assume role");
+
+            let access_denied = error
+                .as_service_error()
+                .and_then(|error| error.code())
+                .is_some_and(|code| code == "AccessDenied");
+
+            if access_denied && config.mfa_serial.is_none() {
+                return Err(SyncError::backend_auth(format!(
+                    "access denied assuming role \"{role_arn}\", if the role requires MFA set aws.mfa_serial"
+                )));
+            }
+
+            return Err(
+                request_error(error).context(format!("failed to assume role \"{role_arn}\""))
+            );
+        }
+    };
+
+    let credentials = output
+        .credentials
+        .ok_or_else(|| SyncError::backend_auth("assume role response was missing credentials"))?;
+
+    Ok(Credentials::new(
+        credentials.access_key_id,
+        credentials.secret_access_key,
+        Some(credentials.session_token),
+        SystemTime::try_from(credentials.expiration).ok(),
+        "secret_sync_assume_role",
+    ))
+}
+
+/// Exchange the CI identity token for the credentials of the role from
+/// the `web_identity` config using STS
+async fn assume_role_with_web_identity(
+    sdk_config: &aws_config::SdkConfig,
+    web_identity: &WebIdentityConfig,
+) -> Result<Credentials> {
+    let token = resolve_identity_token(&web_identity.token)
+        .await
+        .map_err(|error| SyncError::BackendAuth(ErrorContext::from_source(error)))?;
+    let client = aws_sdk_sts::Client::new(sdk_config);
+    let role_arn = &web_identity.role_arn;
+
+    let output = client
+        .assume_role_with_web_identity()
+        .role_arn(role_arn)
+        .role_session_name(
+            web_identity
+                .role_session_name
+                .as_deref()
+                .unwrap_or("secret-sync"),
+        )
+        .web_identity_token(token)
+        .send()
+        .await
+        .inspect_err(|error| {
+            tracing::error!(?error, "failed to assume role with web identity");
+        })
+        .map_err(request_error)
+        .with_context(|| format!("failed to assume role \"{role_arn}\" with web identity"))?;
+
+    let credentials = output
+        .credentials
+        .ok_or_else(|| SyncError::backend_auth("assume role response was missing credentials"))?;
+
+    Ok(Credentials::new(
+        credentials.access_key_id,
+        credentials.secret_access_key,
+        Some(credentials.session_token),
+        SystemTime::try_from(credentials.expiration).ok(),
+        "secret_sync_web_identity",
+    ))
+}
+
+/// Credentials provider that falls back to prompting for credentials (or
+/// reading them from the keychain) when the ambient `inner` provider fails
+///
+/// The fallback is only attempted when a request first needs credentials
+/// so runs with working ambient credentials never wait on a probe
+#[derive(Debug)]
+struct PromptCredentialsProvider {
+    /// Ambient credentials provider from the SDK config
+    inner: SharedCredentialsProvider,
+    /// Configuration for resolving the fallback credentials
+    credentials_config: CredentialsConfig,
+    /// Profile the keychain entry is stored under
+    profile: Option<String>,
+    /// Credentials resolved by the fallback, shared so the user is only
+    /// prompted once
+    prompted: OnceCell<Credentials>,
+}
+
+impl PromptCredentialsProvider {
+    async fn resolve(&self) -> aws_credential_types::provider::Result {
+        if let Some(credentials) = self.prompted.get() {
+            return Ok(credentials.clone());
         }
 
-        if let Some(value) = result.secret_binary {
-            return Ok(Secret::Binary(value.into_inner()));
+        let error = match self.inner.provide_credentials().await {
+            Ok(credentials) => {
+                tracing::debug!(source = %credential_source(&credentials), "resolved ambient credentials");
+                return Ok(credentials);
+            }
+            Err(error) => error,
+        };
+
+        tracing::debug!(?error, "failed to resolve ambient credentials");
+
+        for problem in diagnose_credentials(&CredentialEnvironment::current(), None) {
+            tracing::debug!(%problem, "possible credentials misconfiguration");
         }
 
-        eyre::bail!("no valid secret found for \"{name}\" ")
+        self.prompted
+            .get_or_try_init(|| async {
+                match resolve_aws_credentials(&self.credentials_config, self.profile.as_deref()) {
+                    Ok(Some(credentials)) => Ok(Credentials::new(
+                        credentials.access_key_id,
+                        credentials.access_key_secret,
+                        None,
+                        None,
+                        "secret_sync_prompt",
+                    )),
+                    Ok(None) => Err(error),
+                    Err(error) => Err(CredentialsError::provider_error(error)),
+                }
+            })
+            .await
+            .cloned()
     }
+}
 
-    async fn set_secret(
-        &self,
-        name: &str,
-        value: Secret,
-        metadata: &SecretMetadata,
-    ) -> eyre::Result<()> {
+impl ProvideCredentials for PromptCredentialsProvider {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(self.resolve())
+    }
+}
+
+/// Diagnose the credentials resolved by the credentials `provider` of an
+/// SDK config
+pub async fn diagnose_credentials_provider(
+    provider: Option<&SharedCredentialsProvider>,
+) -> CredentialDiagnosis {
+    let source = match provider {
+        Some(provider) => provider
+            .provide_credentials()
+            .await
+            .map(|credentials| credential_source(&credentials))
+            .map_err(|error| {
+                format!(
+                    "{:#}",
+                    SyncError::BackendAuth(ErrorContext::from_source(error))
+                )
+            }),
+        None => Err("no credentials provider is configured".to_string()),
+    };
+
+    let problems = diagnose_credentials(&CredentialEnvironment::current(), source.as_ref().ok());
+
+    CredentialDiagnosis {
+        source: source.as_ref().ok().map(ToString::to_string),
+        error: source.err(),
+        problems,
+    }
+}
+
+/// ARN of the credentials used by the `sts` client
+pub(crate) async fn caller_arn(sts: &aws_sdk_sts::Client) -> Result<Option<String>> {
+    let identity = sts
+        .get_caller_identity()
+        .send()
+        .await
+        .inspect_err(|error| {
+            tracing::error!(?error, "failed to get caller identity");
+        })
+        .map_err(request_error)
+        .context("failed to get caller identity of the AWS credentials")?;
+
+    Ok(identity.arn)
+}
+
+/// Generate an RDS IAM authentication token for the `config` database
+/// signed using the `credentials` at `time`
+///
+/// The token is a presigned "connect" request URL without its scheme
+fn rds_iam_token(
+    config: &RdsIamTokenConfig,
+    credentials: Credentials,
+    region: &str,
+    time: SystemTime,
+) -> Result<String> {
+    let url = format!(
+        "https://{}:{}/?Action=connect&DBUser={}",
+        config.host,
+        config.port,
+        url_encode(&config.user)
+    );
+
+    let mut settings = SigningSettings::default();
+    settings.expires_in = Some(RDS_IAM_TOKEN_EXPIRY);
+    settings.signature_location = SignatureLocation::QueryParams;
+
+    let identity = credentials.into();
+    let params = aws_sigv4::sign::v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region)
+        .name("rds-db")
+        .time(time)
+        .settings(settings)
+        .build()
+        .map_err(|error| SyncError::config("invalid signing parameters").with_source(error))?
+        .into();
+
+    let request = SignableRequest::new("GET", &url, std::iter::empty(), SignableBody::Bytes(&[]))
+        .map_err(|error| SyncError::config("invalid database host").with_source(error))?;
+    let (instructions, _signature) = sign(request, &params)
+        .map_err(|error| SyncError::backend_auth("failed to sign token").with_source(error))?
+        .into_parts();
+
+    let mut token = url.strip_prefix("https://").unwrap_or(&url).to_string();
+
+    for (name, value) in instructions.params() {
+        _ = write!(token, "&{name}={}", url_encode(value));
+    }
+
+    Ok(token)
+}
+
+/// Create the session policy for granted credentials, only allowing the
+/// secret `arn` to be read (and decrypted when it uses a customer managed
+/// key) within the `region`
+fn grant_policy(arn: &str, region: &str) -> String {
+    serde_json::json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Action": ["secretsmanager:GetSecretValue", "secretsmanager:DescribeSecret"],
+                "Resource": arn
+            },
+            {
+                "Effect": "Allow",
+                "Action": "kms:Decrypt",
+                "Resource": "*",
+                "Condition": {
+                    "StringEquals": { "kms:ViaService": format!("secretsmanager.{region}.amazonaws.com") }
+                }
+            }
+        ]
+    })
+    .to_string()
+}
+
+/// Convert the `tags` into AWS tags
+fn aws_tags(tags: &IndexMap<String, String>) -> Vec<Tag> {
+    tags.iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect()
+}
+
+/// Determine the source of the resolved `credentials`, the SDK only
+/// exposes the name of the provider through the debug output
+fn credential_source(credentials: &Credentials) -> CredentialSource {
+    let debug = format!("{credentials:?}");
+    let name = debug
+        .split_once("provider_name: \"")
+        .and_then(|(_, rest)| rest.split_once('"'))
+        .map(|(name, _)| name)
+        .unwrap_or_default();
+
+    CredentialSource::from_provider_name(name)
+}
+
+#[async_trait]
+impl SecretManager for AwsSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        match self.find_secret_value(name).await? {
+            Some(value) => Ok(value),
+            None => Err(SyncError::not_found(format!("secret \"{name}\" not found"))),
+        }
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        Ok(self.find_secret_value(name).await?.map(|value| value.data))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region_for(name)))]
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
         let (secret_binary, secret_string) = match value {
             Secret::String(value) => (None, Some(value)),
             Secret::Binary(items) => (Some(Blob::new(items)), None),
         };
 
-        let tags = metadata.tags.as_ref().map(|tags| {
-            tags.iter()
-                .map(|(key, value)| Tag::builder().key(key).value(value).build())
-                .collect::<Vec<_>>()
-        });
+        // ARNs are not valid names for creating a secret, the referenced
+        // secret is updated and only created by name when missing
+        if let Some(arn) = SecretArn::parse(name) {
+            let error = match self
+                .client_for(name)
+                .update_secret()
+                .set_secret_binary(secret_binary.clone())
+                .set_secret_string(secret_string.clone())
+                .secret_id(name)
+                .send()
+                .await
+            {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+
+            if error
+                .as_service_error()
+                .is_some_and(|value| value.is_resource_not_found_exception())
+            {
+                tracing::debug!("secret does not exist, creating secret");
+                return self
+                    .create_arn_secret(&arn, secret_binary, secret_string, metadata)
+                    .await;
+            }
+
+            tracing::error!(?error, "failed to update secret");
+            return Err(request_error(error));
+        }
 
         let error = match self
-            .client
-            .create_secret()
-            .set_secret_binary(secret_binary.clone())
-            .set_secret_string(secret_string.clone())
-            .set_description(metadata.description.clone())
-            .set_tags(tags)
-            .name(name)
+            .create_secret_request(
+                self.client_for(name),
+                name,
+                secret_binary.clone(),
+                secret_string.clone(),
+                metadata,
+            )
             .send()
             .await
         {
@@ -145,12 +834,474 @@ impl SecretManager for AwsSecretManager {
                 .inspect_err(|error| {
                     tracing::error!(?error, "failed to update secret");
                 })
+                .map_err(request_error)
                 .context("failed to update secret")?;
 
             return Ok(());
         }
 
         tracing::error!(?error, "failed to create secret");
-        Err(eyre::Report::new(error))
+        Err(request_error(error))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region))]
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        if SecretArn::parse(name).is_some() {
+            return Err(SyncError::validation(format!(
+                "secret \"{name}\" is referenced by ARN and cannot be created exclusively"
+            )));
+        }
+
+        let (secret_binary, secret_string) = match value {
+            Secret::String(value) => (None, Some(value)),
+            Secret::Binary(items) => (Some(Blob::new(items)), None),
+        };
+
+        self.create_secret_request(
+            self.client_for(name),
+            name,
+            secret_binary,
+            secret_string,
+            metadata,
+        )
+        .send()
+        .await
+        .inspect_err(|error| {
+            tracing::debug!(?error, "failed to create secret");
+        })
+        .map_err(request_error)
+        .context("failed to create secret")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region_for(name)))]
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        self.client_for(name)
+            .tag_resource()
+            .secret_id(name)
+            .set_tags(Some(aws_tags(tags)))
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to tag secret");
+            })
+            .map_err(request_error)
+            .context("failed to tag secret")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region_for(name)))]
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        self.client_for(name)
+            .delete_secret()
+            .secret_id(name)
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to delete secret");
+            })
+            .map_err(request_error)
+            .context("failed to delete secret")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(%generator, backend = "aws", region = %self.region))]
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        match generator {
+            SecretGenerator::RdsIamToken(config) => {
+                let credentials = self
+                    .credentials_provider
+                    .as_ref()
+                    .ok_or_else(|| {
+                        SyncError::backend_auth("no credentials provider is configured")
+                    })?
+                    .provide_credentials()
+                    .await
+                    .map_err(|error| {
+                        SyncError::backend_auth("failed to resolve credentials").with_source(error)
+                    })?;
+
+                let region = config.region.as_deref().unwrap_or(&self.region);
+                let token = rds_iam_token(config, credentials, region, SystemTime::now())?;
+
+                Ok(Secret::String(token))
+            }
+            SecretGenerator::EcrCredentials(_) => {
+                let output = self
+                    .ecr
+                    .get_authorization_token()
+                    .send()
+                    .await
+                    .inspect_err(|error| {
+                        tracing::error!(?error, "failed to get ECR authorization token");
+                    })
+                    .map_err(request_error)
+                    .context("failed to get ECR authorization token")?;
+
+                let data = output
+                    .authorization_data
+                    .unwrap_or_default()
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| {
+                        SyncError::backend("ECR response was missing authorization data")
+                    })?;
+
+                let token = data.authorization_token.ok_or_else(|| {
+                    SyncError::backend("ECR response was missing the authorization token")
+                })?;
+                let endpoint = data.proxy_endpoint.ok_or_else(|| {
+                    SyncError::backend("ECR response was missing the registry endpoint")
+                })?;
+
+                let credential = RegistryCredential::from_basic_token(endpoint, &token)?;
+                let value = serde_json::to_string(&credential).map_err(|error| {
+                    SyncError::backend("failed to encode registry credential").with_source(error)
+                })?;
+
+                Ok(Secret::String(value))
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region_for(name)))]
+    async fn grant_secret_access(&self, name: &str, ttl: Duration) -> Result<SecretGrant> {
+        if ttl < MIN_GRANT_DURATION {
+            return Err(SyncError::validation(
+                "temporary credentials must be valid for at least 15 minutes",
+            ));
+        }
+
+        let arn = self
+            .client_for(name)
+            .describe_secret()
+            .secret_id(name)
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to describe secret");
+            })
+            .map_err(request_error)
+            .with_context(|| format!("failed to describe secret \"{name}\""))?
+            .arn
+            .ok_or_else(|| SyncError::backend("describe secret response was missing the ARN"))?;
+
+        let region = self.region_for(name);
+        let policy = grant_policy(&arn, region);
+        let duration =
+            i32::try_from(ttl.as_secs()).map_err(|_| SyncError::validation("ttl is too long"))?;
+
+        // Roles cannot request federation tokens, sessions of the configured
+        // role are scoped down instead
+        let credentials = match self.role_arn.as_ref() {
+            Some(role_arn) => {
+                self.sts
+                    .assume_role()
+                    .role_arn(role_arn)
+                    .role_session_name("secret-sync-grant")
+                    .policy(policy)
+                    .duration_seconds(duration)
+                    .send()
+                    .await
+                    .inspect_err(|error| {
+                        tracing::error!(?error, "failed to assume role for grant");
+                    })
+                    .map_err(request_error)
+                    .with_context(|| format!("failed to assume role \"{role_arn}\""))?
+                    .credentials
+            }
+            None => self
+                .sts
+                .get_federation_token()
+                .name("secret-sync-grant")
+                .policy(policy)
+                .duration_seconds(duration)
+                .send()
+                .await
+                .inspect_err(|error| {
+                    tracing::error!(?error, "failed to get federation token");
+                })
+                .map_err(request_error)
+                .context(
+                    "failed to get federation token, set aws.role_arn when using role credentials",
+                )?
+                .credentials,
+        };
+
+        let credentials = credentials
+            .ok_or_else(|| SyncError::backend_auth("STS response was missing credentials"))?;
+
+        let environment = IndexMap::from([
+            ("AWS_ACCESS_KEY_ID".to_string(), credentials.access_key_id),
+            (
+                "AWS_SECRET_ACCESS_KEY".to_string(),
+                credentials.secret_access_key,
+            ),
+            ("AWS_SESSION_TOKEN".to_string(), credentials.session_token),
+            ("AWS_REGION".to_string(), region.to_string()),
+        ]);
+
+        Ok(SecretGrant {
+            secret: name.to_string(),
+            environment,
+            expires: format_date(&credentials.expiration),
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(connections, backend = "aws", region = %self.region))]
+    async fn warm_connections(&self, connections: usize) {
+        // Concurrent requests each open their own connection, which are
+        // then pooled for the batch. Failures still establish the connection
+        let requests = (0..connections).map(|_| self.client.list_secrets().max_results(1).send());
+        let failed = futures_util::future::join_all(requests)
+            .await
+            .into_iter()
+            .filter(|result| result.is_err())
+            .count();
+
+        tracing::debug!(failed, "warmed backend connections");
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "aws", region = %self.region))]
+    async fn verify_access(&self) -> Result<()> {
+        let identity = self
+            .sts
+            .get_caller_identity()
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to get caller identity");
+            })
+            .map_err(request_error)
+            .context("failed to verify AWS credentials")?;
+
+        tracing::debug!(arn = ?identity.arn, "verified AWS credentials");
+        Ok(())
+    }
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        diagnose_credentials_provider(self.credentials_provider.as_ref()).await
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        caller_arn(&self.sts).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "aws", region = %self.region_for(name)))]
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        let result = match self
+            .client_for(name)
+            .describe_secret()
+            .secret_id(name)
+            .send()
+            .await
+        {
+            Ok(value) => value,
+            Err(error) => {
+                if error
+                    .as_service_error()
+                    .is_some_and(|value| value.is_resource_not_found_exception())
+                {
+                    return Ok(None);
+                }
+
+                tracing::error!(?error, "failed to describe secret");
+                return Err(request_error(error));
+            }
+        };
+
+        Ok(Some(SecretSummary {
+            name: result.name.unwrap_or_else(|| name.to_string()),
+            description: result.description,
+            tags: result
+                .tags
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|tag| Some((tag.key?, tag.value?)))
+                .collect(),
+            created: result.created_date.as_ref().and_then(format_date),
+            updated: result.last_changed_date.as_ref().and_then(format_date),
+            version_count: result.version_ids_to_stages.map(|versions| versions.len()),
+            size_hint: None,
+        }))
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        // Tag key and value filters are matched independently by AWS so
+        // the exact pairs are checked against the returned tags
+        let filters = options
+            .tags
+            .iter()
+            .flat_map(|(key, value)| {
+                [
+                    Filter::builder()
+                        .key(FilterNameStringType::TagKey)
+                        .values(key)
+                        .build(),
+                    Filter::builder()
+                        .key(FilterNameStringType::TagValue)
+                        .values(value)
+                        .build(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let client = self.client.clone();
+
+        let pages = stream::try_unfold(ListPage::First, move |page| {
+            let client = client.clone();
+            let filters = filters.clone();
+            let options = options.clone();
+
+            async move {
+                let next_token = match page {
+                    ListPage::First => None,
+                    ListPage::Next(token) => Some(token),
+                    ListPage::Done => return Ok(None),
+                };
+
+                let result = client
+                    .list_secrets()
+                    .set_filters(Some(filters))
+                    .set_max_results(options.page_size)
+                    .set_next_token(next_token)
+                    .send()
+                    .await
+                    .inspect_err(|error| {
+                        tracing::error!(?error, "failed to list secrets");
+                    })
+                    .map_err(request_error)
+                    .context("failed to list secrets")?;
+
+                let summaries = result
+                    .secret_list
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|entry| {
+                        let tags: IndexMap<String, String> = entry
+                            .tags
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter_map(|tag| Some((tag.key?, tag.value?)))
+                            .collect();
+
+                        let matches = options
+                            .tags
+                            .iter()
+                            .all(|(key, value)| tags.get(key) == Some(value));
+
+                        Some(SecretSummary {
+                            name: entry.name?,
+                            description: entry.description,
+                            tags,
+                            created: entry.created_date.as_ref().and_then(format_date),
+                            updated: entry.last_changed_date.as_ref().and_then(format_date),
+                            version_count: entry
+                                .secret_versions_to_stages
+                                .map(|versions| versions.len()),
+                            size_hint: None,
+                        })
+                        .filter(|_| matches)
+                    })
+                    .collect::<Vec<_>>();
+
+                let page = match result.next_token {
+                    Some(token) => ListPage::Next(token),
+                    None => ListPage::Done,
+                };
+
+                Ok::<_, SyncError>(Some((summaries, page)))
+            }
+        });
+
+        pages
+            .map_ok(|summaries| stream::iter(summaries.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+}
+
+/// Position within the pages of a secret listing
+enum ListPage {
+    /// First page has not been requested
+    First,
+    /// Next page to request
+    Next(String),
+    /// No pages remain
+    Done,
+}
+
+/// Format an AWS `date` as an RFC 3339 timestamp
+pub fn format_date(date: &DateTime) -> Option<String> {
+    date.fmt(DateTimeFormat::DateTime).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::RdsIamTokenConfig,
+        secret::aws::{grant_policy, rds_iam_token},
+    };
+    use aws_sdk_secretsmanager::config::Credentials;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Tests that RDS IAM tokens are presigned connect requests for the
+    /// database user
+    #[test]
+    fn test_rds_iam_token() {
+        let config = RdsIamTokenConfig {
+            host: "db.example.com".to_string(),
+            port: 5432,
+            user: "app user".to_string(),
+            region: None,
+        };
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", None, None, "test");
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let token = rds_iam_token(&config, credentials.clone(), "us-east-1", time).unwrap();
+
+        assert!(token.starts_with("db.example.com:5432/?Action=connect&DBUser=app%20user&"));
+        assert!(token.contains(
+            "X-Amz-Credential=AKIDEXAMPLE%2F20231114%2Fus-east-1%2Frds-db%2Faws4_request"
+        ));
+        assert!(token.contains("X-Amz-Expires=900"));
+        assert!(token.contains("X-Amz-Signature="));
+
+        // Signing is deterministic for the same inputs
+        let again = rds_iam_token(&config, credentials, "us-east-1", time).unwrap();
+        assert_eq!(token, again);
+    }
+
+    /// Tests that granted sessions can only read the granted secret
+    #[test]
+    fn test_grant_policy() {
+        let arn = "arn:aws:secretsmanager:us-east-1:123456789012:secret:db-AbCdEf";
+        let policy: serde_json::Value =
+            serde_json::from_str(&grant_policy(arn, "us-east-1")).unwrap();
+
+        let statements = policy["Statement"].as_array().unwrap();
+        assert_eq!(statements[0]["Resource"], arn);
+        assert_eq!(
+            statements[0]["Action"],
+            serde_json::json!([
+                "secretsmanager:GetSecretValue",
+                "secretsmanager:DescribeSecret"
+            ])
+        );
+        assert_eq!(
+            statements[1]["Condition"]["StringEquals"]["kms:ViaService"],
+            "secretsmanager.us-east-1.amazonaws.com"
+        );
     }
 }
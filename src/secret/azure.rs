@@ -0,0 +1,1138 @@
+//! # Azure
+//!
+//! Secret manager backed by Azure Key Vault secrets through its REST API.
+//!
+//! Requests are authenticated using Microsoft Entra access tokens from the
+//! first available source:
+//!
+//! - Federated credential of a CI identity token (`azure.workload_identity`)
+//! - Client secret of a service principal (`azure.client_secret` or
+//!   `AZURE_CLIENT_SECRET`)
+//! - Federated token file of AKS workload identity (`AZURE_FEDERATED_TOKEN_FILE`)
+//! - App Service managed identity (`IDENTITY_ENDPOINT`)
+//! - Managed identity through the instance metadata service, falling back
+//!   to the Azure CLI (`az login`) when the service is unavailable
+//!
+//! Key Vault secrets have no description, so the description of secrets
+//! is not stored
+
+use crate::{
+    clock::format_unix_time,
+    config::{AzureConfig, IdentityTokenSource, NetworkConfig, SecretGenerator, SecretMetadata},
+    credentials::{resolve_config_value, resolve_identity_token},
+    doctor::{CredentialDiagnosis, CredentialProblem},
+    error::{Result, ResultExt, SyncError},
+    secret::{
+        ListSecretsOptions, MANAGED_BY_TAG, MANAGED_BY_VALUE, Secret, SecretGrant, SecretManager,
+        SecretSummary, SecretValue,
+        rest::{
+            AccessToken, RestClient, RestResponse, TokenCache, TokenResponse, form_request,
+            json_request,
+        },
+    },
+    shell::run_shell_command,
+    structured::url_encode,
+};
+use async_trait::async_trait;
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+};
+use futures_util::{
+    StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+};
+use http::{Method, StatusCode};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the Key Vault API requests are made against
+const API_VERSION: &str = "7.4";
+
+/// Resource access tokens are requested for
+const VAULT_RESOURCE: &str = "https://vault.azure.net";
+
+/// Scope access tokens are requested for from Microsoft Entra
+const VAULT_SCOPE: &str = "https://vault.azure.net/.default";
+
+/// Authority client credentials are exchanged with unless overridden
+const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+
+/// Audience of GitHub Actions tokens exchanged for a federated credential
+const FEDERATED_TOKEN_AUDIENCE: &str = "api://AzureADTokenExchange";
+
+/// Endpoint of the managed identity token of the instance metadata service
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Time to wait for the instance metadata service, which does not exist
+/// outside of Azure
+const IMDS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lifetime assumed for tokens whose expiry is not provided
+const FALLBACK_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Content type of values stored base64 encoded, Key Vault only stores text
+const BINARY_CONTENT_TYPE: &str = "application/octet-stream; encoding=base64";
+
+/// Longest secret name allowed by Key Vault
+const MAX_SECRET_NAME_LENGTH: usize = 127;
+
+/// Most tags Key Vault allows on a secret
+const MAX_TAGS: usize = 15;
+
+/// Tag holding the version comment of each version, Key Vault keeps the
+/// tags of every version
+const VERSION_COMMENT_TAG: &str = "secret-sync-comment";
+
+/// Largest page size Key Vault allows when listing secrets
+const MAX_PAGE_SIZE: i32 = 25;
+
+/// Source of the client assertion exchanged for a federated credential
+enum AssertionSource {
+    /// Token file kept up to date by the platform
+    File(PathBuf),
+    /// Identity token of a CI provider
+    Identity(IdentityTokenSource),
+}
+
+/// Source of access tokens
+enum TokenSource {
+    /// Client secret of a service principal
+    ClientSecret {
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+    /// Federated credential of a service principal
+    ClientAssertion {
+        tenant_id: String,
+        client_id: String,
+        assertion: AssertionSource,
+    },
+    /// Managed identity of an App Service or Functions app
+    AppService {
+        endpoint: String,
+        header: String,
+        client_id: Option<String>,
+    },
+    /// Managed identity through the instance metadata service, falling back
+    /// to the Azure CLI
+    Ambient {
+        client_id: Option<String>,
+        tenant_id: Option<String>,
+    },
+}
+
+/// Token response of managed identity endpoints and the Azure CLI, which
+/// provide when the token expires rather than how long it is valid for
+#[derive(Deserialize)]
+struct ExpiringTokenResponse {
+    #[serde(alias = "accessToken")]
+    access_token: String,
+    /// Unix time the token expires at, provided as a string by managed
+    /// identity endpoints
+    #[serde(default)]
+    expires_on: Option<serde_json::Value>,
+}
+
+impl ExpiringTokenResponse {
+    /// Convert into an access token, expiring relative to `now`
+    fn into_access_token(self, now: SystemTime) -> AccessToken {
+        let expires_on = match self.expires_on {
+            Some(serde_json::Value::Number(value)) => value.as_u64(),
+            Some(serde_json::Value::String(value)) => value.parse().ok(),
+            _ => None,
+        };
+        let now_seconds = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let lifetime = match expires_on {
+            Some(expires_on) => Duration::from_secs(expires_on.saturating_sub(now_seconds)),
+            None => FALLBACK_TOKEN_LIFETIME,
+        };
+
+        AccessToken {
+            value: self.access_token,
+            expires: Some(Instant::now() + lifetime),
+        }
+    }
+}
+
+/// Resolve the source of access tokens for the `config` using the
+/// environment variables provided by `var`, along with a description of
+/// where the credentials were resolved from
+fn resolve_token_source(
+    config: &AzureConfig,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<(TokenSource, String)> {
+    let tenant_id = config.tenant_id.clone().or_else(|| var("AZURE_TENANT_ID"));
+    let client_id = config.client_id.clone().or_else(|| var("AZURE_CLIENT_ID"));
+
+    // Service principal credentials need both the tenant and client
+    let principal = |credential: &str| -> Result<(String, String)> {
+        let tenant_id = tenant_id.clone().ok_or_else(|| {
+            SyncError::config(format!(
+                "azure.tenant_id or AZURE_TENANT_ID must be set to use {credential}"
+            ))
+        })?;
+        let client_id = client_id.clone().ok_or_else(|| {
+            SyncError::config(format!(
+                "azure.client_id or AZURE_CLIENT_ID must be set to use {credential}"
+            ))
+        })?;
+        Ok((tenant_id, client_id))
+    };
+
+    if let Some(identity) = &config.workload_identity {
+        let (tenant_id, client_id) = principal("azure.workload_identity")?;
+
+        // GitHub tokens are issued for the Entra token exchange unless an
+        // audience is set
+        let identity = match identity {
+            IdentityTokenSource::GithubActions { audience: None } => {
+                IdentityTokenSource::GithubActions {
+                    audience: Some(FEDERATED_TOKEN_AUDIENCE.to_string()),
+                }
+            }
+            identity => identity.clone(),
+        };
+
+        return Ok((
+            TokenSource::ClientAssertion {
+                tenant_id,
+                client_id,
+                assertion: AssertionSource::Identity(identity),
+            },
+            "CI identity token (azure.workload_identity)".to_string(),
+        ));
+    }
+
+    if let Some(client_secret) = &config.client_secret {
+        let (tenant_id, client_id) = principal("azure.client_secret")?;
+        let client_secret = resolve_config_value(client_secret).map_err(|error| {
+            SyncError::config("failed to resolve azure.client_secret").with_source(error)
+        })?;
+        return Ok((
+            TokenSource::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            },
+            "client secret (azure.client_secret)".to_string(),
+        ));
+    }
+
+    if let Some(client_secret) = var("AZURE_CLIENT_SECRET") {
+        let (tenant_id, client_id) = principal("AZURE_CLIENT_SECRET")?;
+        return Ok((
+            TokenSource::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            },
+            "AZURE_CLIENT_SECRET".to_string(),
+        ));
+    }
+
+    if let Some(path) = var("AZURE_FEDERATED_TOKEN_FILE") {
+        let (tenant_id, client_id) = principal("AZURE_FEDERATED_TOKEN_FILE")?;
+        let description = format!("federated token file \"{path}\" (AZURE_FEDERATED_TOKEN_FILE)");
+        return Ok((
+            TokenSource::ClientAssertion {
+                tenant_id,
+                client_id,
+                assertion: AssertionSource::File(PathBuf::from(path)),
+            },
+            description,
+        ));
+    }
+
+    if let (Some(endpoint), Some(header)) = (var("IDENTITY_ENDPOINT"), var("IDENTITY_HEADER")) {
+        return Ok((
+            TokenSource::AppService {
+                endpoint,
+                header,
+                client_id,
+            },
+            "App Service managed identity".to_string(),
+        ));
+    }
+
+    Ok((
+        TokenSource::Ambient {
+            client_id,
+            tenant_id,
+        },
+        "managed identity or Azure CLI".to_string(),
+    ))
+}
+
+impl TokenSource {
+    /// Request a fresh access token from the `authority` using the `client`
+    async fn fetch(&self, client: &RestClient, authority: &str) -> Result<AccessToken> {
+        match self {
+            TokenSource::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => {
+                let request = form_request(
+                    &format!("{authority}/{tenant_id}/oauth2/v2.0/token"),
+                    &[
+                        ("grant_type", "client_credentials"),
+                        ("client_id", client_id),
+                        ("client_secret", client_secret),
+                        ("scope", VAULT_SCOPE),
+                    ],
+                )?;
+
+                let response: TokenResponse = client.send(request).await?.json()?;
+                Ok(response.into())
+            }
+
+            TokenSource::ClientAssertion {
+                tenant_id,
+                client_id,
+                assertion,
+            } => {
+                let assertion = match assertion {
+                    AssertionSource::File(path) => std::fs::read_to_string(path)
+                        .with_context(|| {
+                            format!("failed to read federated token \"{}\"", path.display())
+                        })?
+                        .trim()
+                        .to_string(),
+                    AssertionSource::Identity(identity) => {
+                        resolve_identity_token(identity).await.map_err(|error| {
+                            SyncError::backend_auth("failed to resolve identity token")
+                                .with_source(error)
+                        })?
+                    }
+                };
+
+                let request = form_request(
+                    &format!("{authority}/{tenant_id}/oauth2/v2.0/token"),
+                    &[
+                        ("grant_type", "client_credentials"),
+                        ("client_id", client_id),
+                        (
+                            "client_assertion_type",
+                            "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+                        ),
+                        ("client_assertion", &assertion),
+                        ("scope", VAULT_SCOPE),
+                    ],
+                )?;
+
+                let response: TokenResponse = client
+                    .send(request)
+                    .await?
+                    .json()
+                    .context("failed to exchange federated token")?;
+                Ok(response.into())
+            }
+
+            TokenSource::AppService {
+                endpoint,
+                header,
+                client_id,
+            } => {
+                let mut url = format!(
+                    "{endpoint}?api-version=2019-08-01&resource={}",
+                    url_encode(VAULT_RESOURCE)
+                );
+                if let Some(client_id) = client_id {
+                    url.push_str(&format!("&client_id={}", url_encode(client_id)));
+                }
+
+                let request = http::Request::get(url)
+                    .header("X-IDENTITY-HEADER", header)
+                    .body(String::new())
+                    .map_err(|error| {
+                        SyncError::config("invalid managed identity request").with_source(error)
+                    })?;
+
+                let response: ExpiringTokenResponse = client.send(request).await?.json()?;
+                Ok(response.into_access_token(SystemTime::now()))
+            }
+
+            TokenSource::Ambient {
+                client_id,
+                tenant_id,
+            } => {
+                let managed_identity = match tokio::time::timeout(
+                    IMDS_TIMEOUT,
+                    imds_token(client, client_id.as_deref()),
+                )
+                .await
+                {
+                    Ok(result) => result,
+                    Err(_) => Err(SyncError::backend_auth(
+                        "instance metadata service is unavailable",
+                    )),
+                };
+
+                let managed_identity_error = match managed_identity {
+                    Ok(token) => return Ok(token),
+                    Err(error) => error,
+                };
+                tracing::debug!(
+                    error = ?managed_identity_error,
+                    "managed identity unavailable, falling back to the Azure CLI"
+                );
+
+                azure_cli_token(tenant_id.as_deref()).await.map_err(|error| {
+                    SyncError::backend_auth(format!(
+                        "managed identity failed: {managed_identity_error:#}, Azure CLI failed: {error:#}"
+                    ))
+                })
+            }
+        }
+    }
+}
+
+/// Request a managed identity token from the instance metadata service,
+/// for the user-assigned identity `client_id` when provided
+async fn imds_token(client: &RestClient, client_id: Option<&str>) -> Result<AccessToken> {
+    let mut url = format!(
+        "{IMDS_TOKEN_URL}?api-version=2018-02-01&resource={}",
+        url_encode(VAULT_RESOURCE)
+    );
+    if let Some(client_id) = client_id {
+        url.push_str(&format!("&client_id={}", url_encode(client_id)));
+    }
+
+    let request = http::Request::get(url)
+        .header("Metadata", "true")
+        .body(String::new())
+        .map_err(|error| {
+            SyncError::config("invalid instance metadata request").with_source(error)
+        })?;
+
+    let response: ExpiringTokenResponse = client.send(request).await?.json()?;
+    Ok(response.into_access_token(SystemTime::now()))
+}
+
+/// Request a token from the account signed in to the Azure CLI, within
+/// the `tenant_id` when provided
+async fn azure_cli_token(tenant_id: Option<&str>) -> Result<AccessToken> {
+    let mut command =
+        format!("az account get-access-token --output json --resource {VAULT_RESOURCE}");
+    if let Some(tenant_id) = tenant_id {
+        command.push_str(&format!(" --tenant {tenant_id}"));
+    }
+
+    let output = tokio::task::spawn_blocking(move || run_shell_command(&command))
+        .await
+        .map_err(|error| SyncError::backend_auth("Azure CLI task failed").with_source(error))?
+        .map_err(|error| {
+            SyncError::backend_auth("failed to run the Azure CLI").with_source(error)
+        })?;
+
+    let response: ExpiringTokenResponse = serde_json::from_str(&output).map_err(|error| {
+        SyncError::backend_auth("invalid Azure CLI token output").with_source(error)
+    })?;
+    Ok(response.into_access_token(SystemTime::now()))
+}
+
+/// Provides access tokens for requests, cached until shortly before they
+/// expire
+struct TokenProvider {
+    source: TokenSource,
+    /// Where the credentials were resolved from
+    description: String,
+    /// Authority host client credentials are exchanged with
+    authority: String,
+    client: RestClient,
+    cache: TokenCache,
+}
+
+impl TokenProvider {
+    /// Get a valid access token, requesting a fresh one when the cached
+    /// token expired
+    ///
+    /// Any failure to get a token is a [SyncError::BackendAuth] error
+    async fn token(&self) -> Result<String> {
+        self.cache
+            .get_or_fetch(async || {
+                self.source
+                    .fetch(&self.client, &self.authority)
+                    .await
+                    .map_err(|error| {
+                        SyncError::backend_auth(format!(
+                            "failed to get Azure access token from {}",
+                            self.description
+                        ))
+                        .with_source(error)
+                    })
+            })
+            .await
+    }
+}
+
+/// Claims of an access token identifying the principal it was issued to
+#[derive(Deserialize)]
+struct TokenClaims {
+    /// Object ID of the user, service principal, or managed identity
+    oid: Option<String>,
+    /// Tenant the principal belongs to
+    tid: Option<String>,
+}
+
+/// Decode the claims of the JWT access `token`, the signature is not
+/// verified as the token was received directly from the authority
+fn token_claims(token: &str) -> Result<TokenClaims> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| SyncError::backend("Azure access token is not a JWT"))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|error| SyncError::backend("invalid Azure access token").with_source(error))?;
+
+    serde_json::from_slice(&payload)
+        .map_err(|error| SyncError::backend("invalid Azure access token").with_source(error))
+}
+
+/// Authenticated access to the secrets of a vault
+#[derive(Clone)]
+struct VaultApi {
+    client: RestClient,
+    tokens: Arc<TokenProvider>,
+    /// URL of the vault all paths are relative to
+    vault_url: String,
+}
+
+impl VaultApi {
+    /// Send a `method` request to the `path` relative to the vault with an
+    /// optional JSON `body`
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<RestResponse> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let url = format!(
+            "{}/{path}{separator}api-version={API_VERSION}",
+            self.vault_url
+        );
+        self.send_url(method, &url, body).await
+    }
+
+    /// Send a `method` request to the absolute `url`
+    async fn send_url(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<RestResponse> {
+        let token = self.tokens.token().await?;
+        self.client
+            .send(json_request(method, url, Some(&token), body)?)
+            .await
+    }
+}
+
+/// Secret bundle, the value is only present when reading a secret rather
+/// than listing them
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretBundle {
+    /// Identifier in the form "<vault>/secrets/<name>/<version>", without
+    /// the version when listing
+    id: String,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    tags: Option<IndexMap<String, String>>,
+    #[serde(default)]
+    attributes: SecretAttributes,
+}
+
+/// Attributes of a [SecretBundle]
+#[derive(Default, Deserialize)]
+struct SecretAttributes {
+    /// Unix time the secret was created at
+    #[serde(default)]
+    created: Option<u64>,
+    /// Unix time the secret was last updated at
+    #[serde(default)]
+    updated: Option<u64>,
+}
+
+impl SecretBundle {
+    /// Name and version from the secret identifier
+    fn name_and_version(&self) -> (String, Option<String>) {
+        let path = match self.id.split_once("/secrets/") {
+            Some((_, path)) => path,
+            None => &self.id,
+        };
+
+        match path.split_once('/') {
+            Some((name, version)) => (name.to_string(), Some(version.to_string())),
+            None => (path.to_string(), None),
+        }
+    }
+
+    /// Decode the stored value, base64 decoding values stored as binary
+    fn secret(&self) -> Result<Secret> {
+        let value = self.value.clone().unwrap_or_default();
+        match self.content_type.as_deref() {
+            Some(BINARY_CONTENT_TYPE) => {
+                STANDARD.decode(value).map(Secret::Binary).map_err(|error| {
+                    SyncError::backend("binary secret value is not valid base64").with_source(error)
+                })
+            }
+            _ => Ok(Secret::String(value)),
+        }
+    }
+
+    fn into_summary(self) -> SecretSummary {
+        let (name, _) = self.name_and_version();
+        let size_hint = self.value.as_ref().map(|value| value.len() as u64);
+
+        SecretSummary {
+            name,
+            description: None,
+            tags: self.tags.unwrap_or_default(),
+            created: self.attributes.created.map(format_unix_time),
+            updated: self.attributes.updated.map(format_unix_time),
+            version_count: None,
+            size_hint,
+        }
+    }
+}
+
+/// Response of listing secrets
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListSecretsResponse {
+    #[serde(default)]
+    value: Vec<SecretBundle>,
+    /// Absolute URL of the next page
+    #[serde(default)]
+    next_link: Option<String>,
+}
+
+/// Position within the pages of a secret listing
+enum ListPage {
+    /// First page has not been requested
+    First,
+    /// URL of the next page to request
+    Next(String),
+    /// No pages remain
+    Done,
+}
+
+/// Ensure the secret `name` is a valid Key Vault secret name
+fn validate_secret_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_SECRET_NAME_LENGTH
+        && name
+            .chars()
+            .all(|value| value.is_ascii_alphanumeric() || value == '-');
+
+    if !valid {
+        return Err(SyncError::validation(format!(
+            "secret name \"{name}\" is not a valid Azure Key Vault secret name, names may only contain letters, numbers, and hyphens (up to {MAX_SECRET_NAME_LENGTH} characters)"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Ensure the `tags` fit within the Key Vault tag limit
+fn validate_tags(tags: &IndexMap<String, String>) -> Result<()> {
+    if tags.len() > MAX_TAGS {
+        return Err(SyncError::validation(format!(
+            "Azure Key Vault secrets may have at most {MAX_TAGS} tags, {} were provided",
+            tags.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Secret manager backed by Azure Key Vault
+pub struct AzureSecretManager {
+    api: VaultApi,
+}
+
+impl AzureSecretManager {
+    /// Create an [AzureSecretManager] from the provided `config`
+    ///
+    /// Requests are sent using an HTTP client tuned by the `network` config
+    pub fn from_config(config: &AzureConfig, network: &NetworkConfig) -> Result<Self> {
+        let client = RestClient::new(network, "Azure")?;
+        let (source, description) = resolve_token_source(config, |name| std::env::var(name).ok())?;
+
+        let vault_url = config
+            .vault_url
+            .clone()
+            .or_else(|| std::env::var("AZURE_KEYVAULT_URL").ok())
+            .filter(|vault_url| !vault_url.is_empty())
+            .ok_or_else(|| {
+                SyncError::config(
+                    "Azure Key Vault URL is not set, set azure.vault_url or AZURE_KEYVAULT_URL",
+                )
+            })?;
+
+        let authority = config
+            .authority_host
+            .clone()
+            .or_else(|| std::env::var("AZURE_AUTHORITY_HOST").ok())
+            .unwrap_or_else(|| DEFAULT_AUTHORITY_HOST.to_string());
+
+        tracing::debug!(%vault_url, source = %description, "resolved Azure credentials");
+
+        Ok(Self {
+            api: VaultApi {
+                client: client.clone(),
+                tokens: Arc::new(TokenProvider {
+                    source,
+                    description,
+                    authority: authority.trim_end_matches('/').to_string(),
+                    client,
+                    cache: TokenCache::default(),
+                }),
+                vault_url: vault_url.trim_end_matches('/').to_string(),
+            },
+        })
+    }
+
+    /// Get the latest version of the secret `name`, providing [None] when
+    /// the secret does not exist
+    async fn find_bundle(&self, name: &str) -> Result<Option<SecretBundle>> {
+        validate_secret_name(name)?;
+
+        let response = self
+            .api
+            .send(Method::GET, &format!("secrets/{name}"), None)
+            .await?;
+
+        if response.status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let bundle = response
+            .json()
+            .inspect_err(|error| tracing::error!(?error, "failed to get secret"))?;
+        Ok(Some(bundle))
+    }
+
+    /// Find the latest value of the secret `name` along with its version,
+    /// providing [None] when the secret does not exist
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "azure", vault = %self.api.vault_url))]
+    async fn find_secret_value(&self, name: &str) -> Result<Option<SecretValue>> {
+        let Some(bundle) = self.find_bundle(name).await? else {
+            return Ok(None);
+        };
+
+        let (_, version_id) = bundle.name_and_version();
+        Ok(Some(SecretValue {
+            data: bundle.secret()?,
+            version_id,
+            arn: None,
+            created: bundle.attributes.created.map(format_unix_time),
+        }))
+    }
+}
+
+#[async_trait]
+impl SecretManager for AzureSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        match self.find_secret_value(name).await? {
+            Some(value) => Ok(value),
+            None => Err(SyncError::not_found(format!("secret \"{name}\" not found"))),
+        }
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        Ok(self.find_secret_value(name).await?.map(|value| value.data))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "azure", vault = %self.api.vault_url))]
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
+        // Tags belong to each version, so the tags of the current version
+        // are carried over to the new one
+        let mut tags = match self.find_bundle(name).await? {
+            Some(bundle) => bundle.tags.unwrap_or_default(),
+            None => {
+                let mut tags = metadata.tags.clone().unwrap_or_default();
+                tags.insert(MANAGED_BY_TAG.to_string(), MANAGED_BY_VALUE.to_string());
+                tags
+            }
+        };
+
+        // The comment only describes the version it was pushed with
+        tags.shift_remove(VERSION_COMMENT_TAG);
+        if let Some(comment) = &metadata.version_comment {
+            tags.insert(VERSION_COMMENT_TAG.to_string(), comment.clone());
+        }
+        validate_tags(&tags)?;
+
+        let body = match value {
+            Secret::String(value) => json!({ "value": value, "tags": tags }),
+            Secret::Binary(value) => json!({
+                "value": STANDARD.encode(value),
+                "contentType": BINARY_CONTENT_TYPE,
+                "tags": tags,
+            }),
+        };
+
+        self.api
+            .send(Method::PUT, &format!("secrets/{name}"), Some(&body))
+            .await?
+            .into_result()
+            .inspect_err(|error| tracing::error!(?error, "failed to set secret"))?;
+
+        Ok(())
+    }
+
+    async fn create_secret(
+        &self,
+        _name: &str,
+        _value: Secret,
+        _metadata: &SecretMetadata,
+    ) -> Result<()> {
+        // Key Vault creates a new version when setting an existing secret
+        // and has no way to only create missing secrets
+        Err(SyncError::config(
+            "exclusively creating secrets is not supported by the azure backend",
+        ))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "azure", vault = %self.api.vault_url))]
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        validate_secret_name(name)?;
+
+        self.api
+            .send(Method::DELETE, &format!("secrets/{name}"), None)
+            .await?
+            .into_result()
+            .inspect_err(|error| tracing::error!(?error, "failed to delete secret"))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "azure", vault = %self.api.vault_url))]
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        let bundle = self
+            .find_bundle(name)
+            .await?
+            .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" not found")))?;
+
+        // Tags are replaced as a whole so the existing tags are merged
+        let (_, version) = bundle.name_and_version();
+        let version = version
+            .ok_or_else(|| SyncError::backend("secret identifier is missing its version"))?;
+        let mut merged = bundle.tags.unwrap_or_default();
+        merged.extend(tags.clone());
+        validate_tags(&merged)?;
+
+        self.api
+            .send(
+                Method::PATCH,
+                &format!("secrets/{name}/{version}"),
+                Some(&json!({ "tags": merged })),
+            )
+            .await?
+            .into_result()
+            .inspect_err(|error| tracing::error!(?error, "failed to tag secret"))?;
+
+        Ok(())
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        Err(SyncError::config(format!(
+            "generator \"{generator}\" is not supported by the azure backend"
+        )))
+    }
+
+    async fn grant_secret_access(&self, _name: &str, _ttl: Duration) -> Result<SecretGrant> {
+        Err(SyncError::config(
+            "temporary access grants are not supported by the azure backend",
+        ))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "azure", vault = %self.api.vault_url))]
+    async fn verify_access(&self) -> Result<()> {
+        self.api
+            .send(Method::GET, "secrets?maxresults=1", None)
+            .await?
+            .into_result()
+            .inspect_err(|error| tracing::error!(?error, "failed to list secrets"))
+            .context("failed to verify Azure credentials")?;
+
+        Ok(())
+    }
+
+    async fn warm_connections(&self, connections: usize) {
+        // Concurrent requests each open their own connection, which are
+        // then pooled for the batch. Failures still establish the connection
+        let requests =
+            (0..connections).map(|_| self.api.send(Method::GET, "secrets?maxresults=1", None));
+        let failed = futures_util::future::join_all(requests)
+            .await
+            .into_iter()
+            .filter(|result| result.is_err())
+            .count();
+
+        tracing::debug!(failed, "warmed backend connections");
+    }
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        let tokens = &self.api.tokens;
+        let error = tokens.token().await.err().map(|error| format!("{error:#}"));
+
+        let mut problems = Vec::new();
+        if error.is_some() && matches!(tokens.source, TokenSource::Ambient { .. }) {
+            problems.push(CredentialProblem {
+                problem: "no Azure credentials were found, managed identity is unavailable, and the Azure CLI is not signed in"
+                    .to_string(),
+                hint: "run az login, set AZURE_TENANT_ID, AZURE_CLIENT_ID, and AZURE_CLIENT_SECRET, or configure azure.workload_identity in CI".to_string(),
+            });
+        }
+
+        CredentialDiagnosis {
+            source: Some(tokens.description.clone()),
+            error,
+            problems,
+        }
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        let token = self.api.tokens.token().await?;
+        let claims = token_claims(&token)?;
+
+        Ok(claims.oid.map(|oid| match claims.tid {
+            Some(tid) => format!("{tid}/{oid}"),
+            None => oid,
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "azure", vault = %self.api.vault_url))]
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        Ok(self
+            .find_bundle(name)
+            .await?
+            .map(SecretBundle::into_summary))
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        let api = self.api.clone();
+
+        let pages = stream::try_unfold(ListPage::First, move |page| {
+            let api = api.clone();
+            let options = options.clone();
+
+            async move {
+                let response = match page {
+                    ListPage::First => {
+                        let path = match options.page_size {
+                            Some(page_size) => {
+                                format!("secrets?maxresults={}", page_size.clamp(1, MAX_PAGE_SIZE))
+                            }
+                            None => "secrets".to_string(),
+                        };
+                        api.send(Method::GET, &path, None).await?
+                    }
+                    ListPage::Next(url) => api.send_url(Method::GET, &url, None).await?,
+                    ListPage::Done => return Ok(None),
+                };
+
+                let result: ListSecretsResponse = response
+                    .json()
+                    .inspect_err(|error| {
+                        tracing::error!(?error, "failed to list secrets");
+                    })
+                    .context("failed to list secrets")?;
+
+                // Key Vault cannot filter by tag so the tags are matched here
+                let summaries = result
+                    .value
+                    .into_iter()
+                    .map(SecretBundle::into_summary)
+                    .filter(|summary| {
+                        options
+                            .tags
+                            .iter()
+                            .all(|(key, value)| summary.tags.get(key) == Some(value))
+                    })
+                    .collect::<Vec<_>>();
+
+                let page = match result.next_link.filter(|link| !link.is_empty()) {
+                    Some(link) => ListPage::Next(link),
+                    None => ListPage::Done,
+                };
+
+                Ok::<_, SyncError>(Some((summaries, page)))
+            }
+        });
+
+        pages
+            .map_ok(|summaries| stream::iter(summaries.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        clock::format_unix_time,
+        config::{AzureConfig, IdentityTokenSource},
+        secret::{
+            Secret,
+            azure::{
+                AssertionSource, ExpiringTokenResponse, SecretBundle, TokenSource,
+                resolve_token_source, token_claims, validate_secret_name,
+            },
+        },
+    };
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant, UNIX_EPOCH},
+    };
+
+    /// Tests the order credentials are resolved in and that service
+    /// principal credentials require the tenant and client
+    #[test]
+    fn test_resolve_token_source() {
+        let mut vars = HashMap::new();
+        let (source, _) = resolve_token_source(&AzureConfig::default(), |name: &str| {
+            vars.get(name).cloned()
+        })
+        .unwrap();
+        assert!(matches!(
+            source,
+            TokenSource::Ambient {
+                client_id: None,
+                ..
+            }
+        ));
+
+        vars.insert("AZURE_CLIENT_SECRET", "secret".to_string());
+        assert!(
+            resolve_token_source(&AzureConfig::default(), |name: &str| vars
+                .get(name)
+                .cloned())
+            .is_err()
+        );
+
+        vars.insert("AZURE_TENANT_ID", "tenant".to_string());
+        vars.insert("AZURE_CLIENT_ID", "client".to_string());
+        vars.insert("AZURE_FEDERATED_TOKEN_FILE", "/token".to_string());
+        let var = |name: &str| vars.get(name).cloned();
+        let (source, description) = resolve_token_source(&AzureConfig::default(), var).unwrap();
+        assert!(matches!(
+            source,
+            TokenSource::ClientSecret { client_secret, .. } if client_secret == "secret"
+        ));
+        assert_eq!(description, "AZURE_CLIENT_SECRET");
+
+        // Configured credentials take priority over the environment
+        let config = AzureConfig {
+            workload_identity: Some(IdentityTokenSource::GithubActions { audience: None }),
+            ..Default::default()
+        };
+        let (source, _) = resolve_token_source(&config, var).unwrap();
+        let TokenSource::ClientAssertion {
+            assertion: AssertionSource::Identity(identity),
+            ..
+        } = source
+        else {
+            panic!("expected client assertion");
+        };
+        assert_eq!(
+            identity,
+            IdentityTokenSource::GithubActions {
+                audience: Some("api://AzureADTokenExchange".to_string())
+            }
+        );
+    }
+
+    /// Tests that expiry times are read from numbers and strings
+    #[test]
+    fn test_expiring_token() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let response: ExpiringTokenResponse =
+            serde_json::from_str(r#"{"access_token": "a", "expires_on": "1700003600"}"#).unwrap();
+        let token = response.into_access_token(now);
+        assert_eq!(token.value, "a");
+        let remaining = token.expires.unwrap() - Instant::now();
+        assert!(remaining > Duration::from_secs(3500) && remaining <= Duration::from_secs(3600));
+
+        // Azure CLI output
+        let response: ExpiringTokenResponse =
+            serde_json::from_str(r#"{"accessToken": "b", "expires_on": 1700000600}"#).unwrap();
+        let token = response.into_access_token(now);
+        assert_eq!(token.value, "b");
+        assert!(token.expires.unwrap() - Instant::now() <= Duration::from_secs(600));
+    }
+
+    /// Tests that secret bundles are decoded into their name, version, and
+    /// value
+    #[test]
+    fn test_secret_bundle() {
+        let bundle: SecretBundle = serde_json::from_str(
+            r#"{
+                "id": "https://vault.vault.azure.net/secrets/app-env/4387e9f3d6e14c459867679a90fd0f79",
+                "value": "AAE=",
+                "contentType": "application/octet-stream; encoding=base64",
+                "attributes": { "created": 1700000000, "updated": 1700000000 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            bundle.name_and_version(),
+            (
+                "app-env".to_string(),
+                Some("4387e9f3d6e14c459867679a90fd0f79".to_string())
+            )
+        );
+        assert_eq!(bundle.secret().unwrap(), Secret::Binary(vec![0, 1]));
+
+        let summary = bundle.into_summary();
+        assert_eq!(summary.created.as_deref(), Some("2023-11-14T22:13:20Z"));
+        assert!(summary.tags.is_empty());
+
+        assert_eq!(format_unix_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_unix_time(951_782_400), "2000-02-29T00:00:00Z");
+    }
+
+    /// Tests that names Key Vault rejects are reported before sending any
+    /// requests
+    #[test]
+    fn test_validate_secret_name() {
+        assert!(validate_secret_name("app-prod-env").is_ok());
+        assert!(validate_secret_name("app_prod_env").is_err());
+        assert!(validate_secret_name("app/prod").is_err());
+        assert!(validate_secret_name("").is_err());
+        assert!(validate_secret_name(&"a".repeat(128)).is_err());
+    }
+
+    /// Tests that the principal is read from the claims of an access token
+    #[test]
+    fn test_token_claims() {
+        // {"oid":"principal","tid":"tenant"}
+        let token = "eyJhbGciOiJub25lIn0.eyJvaWQiOiJwcmluY2lwYWwiLCJ0aWQiOiJ0ZW5hbnQifQ.";
+        let claims = token_claims(token).unwrap();
+        assert_eq!(claims.oid.as_deref(), Some("principal"));
+        assert_eq!(claims.tid.as_deref(), Some("tenant"));
+
+        assert!(token_claims("not-a-token").is_err());
+    }
+}
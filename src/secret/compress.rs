@@ -0,0 +1,254 @@
+//! # Compress
+//!
+//! Secret manager wrapping a backend to compress the values of secrets
+//! configured with `compress = "zstd"` before they are stored, letting
+//! values larger than the backend size limit fit.
+//!
+//! Compressed values are prefixed with [COMPRESSED_MARKER] and are
+//! decompressed when read regardless of the config, so a secret can be
+//! switched to compression without breaking pulls using an older config
+
+use crate::{
+    config::{Compression, Config, SecretGenerator, SecretMetadata},
+    doctor::CredentialDiagnosis,
+    error::{Result, ResultExt, SyncError},
+    secret::{ListSecretsOptions, Secret, SecretGrant, SecretManager, SecretSummary, SecretValue},
+};
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use indexmap::IndexMap;
+use ruzstd::{
+    decoding::StreamingDecoder,
+    encoding::{CompressionLevel, compress_to_vec},
+};
+use std::{collections::HashMap, io::Read, time::Duration};
+
+/// Marker prefixed to compressed values, starts with a NUL byte so that it
+/// never matches the start of a text value
+pub const COMPRESSED_MARKER: &[u8] = b"\0ssz";
+
+/// Largest size a compressed value may decompress to, guarding against
+/// values crafted to exhaust memory
+const MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Compress a `value` using the `compression`, prefixing it with the
+/// [COMPRESSED_MARKER]
+pub fn compress_value(value: &Secret, compression: Compression) -> Secret {
+    let compressed = match compression {
+        Compression::Zstd => compress_to_vec(value.as_bytes(), CompressionLevel::Fastest),
+    };
+
+    let mut output = Vec::with_capacity(COMPRESSED_MARKER.len() + compressed.len());
+    output.extend_from_slice(COMPRESSED_MARKER);
+    output.extend_from_slice(&compressed);
+    Secret::Binary(output)
+}
+
+/// Decompress a `value` when it starts with the [COMPRESSED_MARKER],
+/// values without the marker are provided as-is
+pub fn decompress_value(value: Secret) -> Result<Secret> {
+    let Some(compressed) = value.as_bytes().strip_prefix(COMPRESSED_MARKER) else {
+        return Ok(value);
+    };
+
+    let mut source = compressed;
+    let decoder = StreamingDecoder::new(&mut source).map_err(|error| {
+        SyncError::validation(format!("compressed secret value is invalid: {error}"))
+    })?;
+
+    let mut output = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut output)
+        .map_err(|error| {
+            SyncError::validation("failed to decompress secret value").with_source(error)
+        })?;
+
+    if output.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(SyncError::validation(format!(
+            "compressed secret value exceeds {MAX_DECOMPRESSED_SIZE} bytes"
+        )));
+    }
+
+    Ok(Secret::from_bytes(output))
+}
+
+/// Secret manager compressing the values of configured secrets before
+/// storing them in the `inner` secret manager
+pub struct CompressedSecretManager {
+    inner: Box<dyn SecretManager + Send + Sync>,
+    /// Compression used for each compressed secret by name
+    compressed: HashMap<String, Compression>,
+}
+
+impl CompressedSecretManager {
+    /// Wrap the `inner` secret manager compressing the secrets of the
+    /// files within `config` that have compression configured
+    pub fn new(inner: Box<dyn SecretManager + Send + Sync>, config: &Config) -> Self {
+        let compressed = config
+            .files
+            .values()
+            .filter_map(|file| Some((file.secret.clone(), file.compress?)))
+            .collect();
+
+        Self { inner, compressed }
+    }
+}
+
+#[async_trait]
+impl SecretManager for CompressedSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        let value = self.inner.get_secret(name).await?;
+        let data = decompress_value(value.data)
+            .with_context(|| format!("failed to read secret \"{name}\""))?;
+
+        Ok(SecretValue { data, ..value })
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        self.inner
+            .find_secret(name)
+            .await?
+            .map(decompress_value)
+            .transpose()
+            .with_context(|| format!("failed to read secret \"{name}\""))
+    }
+
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
+        let value = match self.compressed.get(name) {
+            Some(compression) => {
+                let compressed = compress_value(&value, *compression);
+                tracing::debug!(
+                    size = value.as_bytes().len(),
+                    compressed = compressed.as_bytes().len(),
+                    "compressed secret value"
+                );
+                compressed
+            }
+            None => value,
+        };
+
+        self.inner.set_secret(name, value, metadata).await
+    }
+
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        let value = match self.compressed.get(name) {
+            Some(compression) => compress_value(&value, *compression),
+            None => value,
+        };
+
+        self.inner.create_secret(name, value, metadata).await
+    }
+
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        self.inner.delete_secret(name).await
+    }
+
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        self.inner.tag_secret(name, tags).await
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        self.inner.generate_secret(generator).await
+    }
+
+    async fn grant_secret_access(&self, name: &str, ttl: Duration) -> Result<SecretGrant> {
+        self.inner.grant_secret_access(name, ttl).await
+    }
+
+    async fn verify_access(&self) -> Result<()> {
+        self.inner.verify_access().await
+    }
+
+    async fn warm_connections(&self, connections: usize) {
+        self.inner.warm_connections(connections).await
+    }
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        self.inner.diagnose_credentials().await
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        self.inner.caller_identity().await
+    }
+
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        self.inner.describe_secret(name).await
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        self.inner.list_secrets(options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{Compression, Config, MemoryConfig, SecretFile, SecretMetadata},
+        secret::{
+            Secret, SecretManager,
+            compress::{COMPRESSED_MARKER, CompressedSecretManager},
+            memory::MemorySecretManager,
+        },
+    };
+    use indexmap::IndexMap;
+
+    /// Tests that configured secrets are stored compressed and read back
+    /// decompressed while other secrets are left untouched
+    #[tokio::test]
+    async fn test_compressed_secret_manager() {
+        let config = Config {
+            files: IndexMap::from([(
+                "large".to_string(),
+                SecretFile {
+                    secret: "large".to_string(),
+                    compress: Some(Compression::Zstd),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+
+        let directory = tempfile::tempdir().unwrap();
+        let memory = MemoryConfig {
+            path: Some(directory.path().join("secrets.json")),
+        };
+        let inner = MemorySecretManager::from_config(&memory).unwrap();
+        let secret = CompressedSecretManager::new(Box::new(inner), &config);
+
+        let value = Secret::String(format!("{{\"items\": [{}]}}", "\"value\",".repeat(10_000)));
+        let metadata = SecretMetadata::default();
+        secret
+            .set_secret("large", value.clone(), &metadata)
+            .await
+            .unwrap();
+        secret
+            .set_secret("small", Secret::String("A=1".to_string()), &metadata)
+            .await
+            .unwrap();
+
+        // Read the stored values without decompressing them
+        let inner = MemorySecretManager::from_config(&memory).unwrap();
+        let stored = inner.get_secret("large").await.unwrap().data;
+        assert!(stored.as_bytes().starts_with(COMPRESSED_MARKER));
+        assert!(stored.as_bytes().len() < value.as_bytes().len() / 10);
+
+        assert_eq!(secret.get_secret("large").await.unwrap().data, value);
+        assert_eq!(
+            inner.get_secret("small").await.unwrap().data,
+            Secret::String("A=1".to_string())
+        );
+        assert_eq!(
+            secret.get_secret("small").await.unwrap().data,
+            Secret::String("A=1".to_string())
+        );
+    }
+}
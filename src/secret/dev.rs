@@ -0,0 +1,88 @@
+//! # Dev
+//!
+//! Detection of local secret manager emulators (LocalStack, Loker) so
+//! local development against an emulator requires no configuration
+
+use crate::error::{Result, SyncError};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Time to wait for each candidate endpoint to accept a connection
+const PROBE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Access key and secret used with emulators, which accept any credentials
+pub const DEV_CREDENTIAL: &str = "test";
+
+/// Local endpoints probed in order, the emulator name and its address
+pub const DEV_ENDPOINTS: &[(&str, &str)] = &[
+    ("localstack", "localhost:4566"),
+    ("localstack", "localstack:4566"),
+    ("loker", "localhost:8080"),
+    ("loker", "loker:8080"),
+];
+
+/// Emulator endpoint found by [detect_dev_endpoint]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevEndpoint {
+    /// Name of the emulator
+    pub name: &'static str,
+    /// URL of the endpoint
+    pub url: String,
+}
+
+/// Probe the `candidates` in order providing the first that accepts a
+/// connection
+pub async fn detect_dev_endpoint(candidates: &[(&'static str, &str)]) -> Result<DevEndpoint> {
+    for (name, address) in candidates {
+        let connected = tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(address)).await;
+        if matches!(connected, Ok(Ok(_))) {
+            tracing::debug!(%name, %address, "detected local development endpoint");
+            return Ok(DevEndpoint {
+                name,
+                url: format!("http://{address}"),
+            });
+        }
+    }
+
+    let tried = candidates
+        .iter()
+        .map(|(_, address)| *address)
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(SyncError::config(format!(
+        "no local development endpoint is running (tried {tried})"
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::secret::dev::detect_dev_endpoint;
+    use tokio::net::TcpListener;
+
+    /// Tests that the first listening candidate is used
+    #[tokio::test]
+    async fn test_detect_dev_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // Bind then drop a listener to find a port nothing listens on
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_address = closed.local_addr().unwrap().to_string();
+        drop(closed);
+
+        let endpoint = detect_dev_endpoint(&[
+            ("closed", closed_address.as_str()),
+            ("loker", address.as_str()),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(endpoint.name, "loker");
+        assert_eq!(endpoint.url, format!("http://{address}"));
+
+        assert!(
+            detect_dev_endpoint(&[("closed", closed_address.as_str())])
+                .await
+                .is_err()
+        );
+    }
+}
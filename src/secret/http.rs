@@ -0,0 +1,183 @@
+//! # HTTP
+//!
+//! HTTP clients for the backends built from the `[network]` config,
+//! allowing the connection pool and HTTP version to be tuned for batches
+//! of many small requests
+
+use crate::{
+    config::{HttpVersion, NetworkConfig},
+    error::{Result, SyncError},
+};
+#[cfg(feature = "aws")]
+use aws_sdk_secretsmanager::config::{
+    HttpClient, RuntimeComponents,
+    http::{HttpRequest, HttpResponse},
+};
+#[cfg(feature = "aws")]
+use aws_smithy_runtime_api::client::{
+    http::{HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector},
+    result::ConnectorError,
+};
+#[cfg(feature = "aws")]
+use aws_smithy_types::body::SdkBody;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{Builder, Client, connect::HttpConnector as TcpConnector},
+    rt::{TokioExecutor, TokioTimer},
+};
+use std::time::Duration;
+#[cfg(feature = "aws")]
+use std::{collections::HashMap, sync::Mutex};
+
+/// Create the HTTPS connector for the network `config` failing connections
+/// that take longer than the `connect_timeout`
+///
+/// Plain HTTP is allowed for local emulator endpoints
+pub fn https_connector(
+    config: &NetworkConfig,
+    connect_timeout: Option<Duration>,
+) -> Result<HttpsConnector<TcpConnector>> {
+    let mut tcp = TcpConnector::new();
+    tcp.enforce_http(false);
+    tcp.set_nodelay(true);
+    tcp.set_connect_timeout(connect_timeout);
+
+    let builder = HttpsConnectorBuilder::new()
+        .with_provider_and_native_roots(rustls::crypto::aws_lc_rs::default_provider())
+        .map_err(|error| SyncError::config("failed to load root certificates").with_source(error))?
+        .https_or_http();
+
+    Ok(match config.http_version {
+        HttpVersion::Auto => builder.enable_all_versions().wrap_connector(tcp),
+        HttpVersion::Http1 => builder.enable_http1().wrap_connector(tcp),
+        HttpVersion::Http2 => builder.enable_http2().wrap_connector(tcp),
+    })
+}
+
+/// Create the builder for a pooled client tuned by the network `config`
+pub fn client_builder(config: &NetworkConfig) -> Builder {
+    let mut builder = Client::builder(TokioExecutor::new());
+    builder
+        .pool_timer(TokioTimer::new())
+        .pool_idle_timeout(Duration::from_secs(config.keep_alive))
+        .http2_only(config.http_version == HttpVersion::Http2);
+
+    if let Some(max_idle_connections) = config.max_idle_connections {
+        builder.pool_max_idle_per_host(max_idle_connections);
+    }
+
+    builder
+}
+
+/// Connector timeouts a connector was created for
+#[cfg(feature = "aws")]
+type ConnectorKey = (Option<Duration>, Option<Duration>);
+
+/// HTTP client for the AWS SDK creating connectors tuned by the
+/// [NetworkConfig]
+#[cfg(feature = "aws")]
+#[derive(Debug)]
+pub struct TunedHttpClient {
+    /// Network config the connectors are tuned by
+    config: NetworkConfig,
+    /// Connectors created for each set of timeouts, reused so that
+    /// connections are pooled across requests
+    connectors: Mutex<HashMap<ConnectorKey, SharedHttpConnector>>,
+}
+
+#[cfg(feature = "aws")]
+impl TunedHttpClient {
+    /// Create a client from the network `config`
+    pub fn new(config: &NetworkConfig) -> Self {
+        Self {
+            config: config.clone(),
+            connectors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create a connector using the `settings` timeouts
+    fn create_connector(&self, settings: &HttpConnectorSettings) -> Result<TunedConnector> {
+        let https = https_connector(&self.config, settings.connect_timeout())?;
+
+        Ok(TunedConnector {
+            client: client_builder(&self.config).build(https),
+            read_timeout: settings.read_timeout(),
+        })
+    }
+}
+
+#[cfg(feature = "aws")]
+impl HttpClient for TunedHttpClient {
+    fn http_connector(
+        &self,
+        settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        let key = (settings.connect_timeout(), settings.read_timeout());
+        let mut connectors = self
+            .connectors
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        connectors
+            .entry(key)
+            .or_insert_with(|| match self.create_connector(settings) {
+                Ok(connector) => SharedHttpConnector::new(connector),
+                Err(error) => SharedHttpConnector::new(FailedConnector(error.to_string())),
+            })
+            .clone()
+    }
+}
+
+/// Connector sending requests through a pooled hyper client
+#[cfg(feature = "aws")]
+#[derive(Debug, Clone)]
+struct TunedConnector {
+    client: Client<HttpsConnector<TcpConnector>, SdkBody>,
+    read_timeout: Option<Duration>,
+}
+
+#[cfg(feature = "aws")]
+impl HttpConnector for TunedConnector {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let request = match request.try_into_http1x() {
+            Ok(value) => value,
+            Err(error) => {
+                return HttpConnectorFuture::ready(Err(ConnectorError::user(error.into())));
+            }
+        };
+
+        let response = self.client.request(request);
+        let read_timeout = self.read_timeout;
+
+        HttpConnectorFuture::new(async move {
+            let response = match read_timeout {
+                Some(read_timeout) => tokio::time::timeout(read_timeout, response)
+                    .await
+                    .map_err(|error| ConnectorError::timeout(error.into()))?,
+                None => response.await,
+            };
+
+            // Transport failures are io errors so the SDK retries them
+            let response = response
+                .map_err(|error| ConnectorError::io(error.into()))?
+                .map(SdkBody::from_body_1_x);
+
+            HttpResponse::try_from(response)
+                .map_err(|error| ConnectorError::other(error.into(), None))
+        })
+    }
+}
+
+/// Connector that could not be created, failing every request with the
+/// reason it could not be created
+#[cfg(feature = "aws")]
+#[derive(Debug)]
+struct FailedConnector(String);
+
+#[cfg(feature = "aws")]
+impl HttpConnector for FailedConnector {
+    fn call(&self, _request: HttpRequest) -> HttpConnectorFuture {
+        HttpConnectorFuture::ready(Err(ConnectorError::user(self.0.clone().into())))
+    }
+}
@@ -0,0 +1,464 @@
+//! # Local
+//!
+//! Secret manager storing each secret as an age encrypted file within a
+//! local directory, which can be committed alongside the project. Gives
+//! small teams a backend without any infrastructure while keeping the same
+//! pull and push workflow.
+//!
+//! Secrets are encrypted to every configured recipient (age public key)
+//! and decrypted using the identities (age private keys) from
+//! SECRET_SYNC_AGE_IDENTITY or `local.identity_file`. The secret `app/db`
+//! is stored at `<path>/app/db.age` as ASCII armored text containing the
+//! value along with its description, tags, and version count
+
+use crate::{
+    config::{LocalConfig, SecretGenerator, SecretMetadata},
+    doctor::CredentialDiagnosis,
+    error::{Result, ResultExt, SyncError},
+    secret::{
+        ListSecretsOptions, Secret, SecretGrant, SecretManager, SecretSummary, SecretValue,
+        list_secret_files,
+        memory::{StoredSecret, StoredValue},
+        secret_file_path,
+    },
+};
+use age::{
+    Decryptor, Encryptor,
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    x25519::{Identity, Recipient},
+};
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use indexmap::IndexMap;
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Environment variable containing age identities, used instead of the
+/// identity file (e.g. in CI)
+pub const AGE_IDENTITY_ENV: &str = "SECRET_SYNC_AGE_IDENTITY";
+
+/// Extension of the encrypted secret files
+const SECRET_FILE_EXTENSION: &str = "age";
+
+/// Secret manager storing age encrypted files in a local directory
+pub struct LocalSecretManager {
+    /// Directory the secret files are stored in
+    path: PathBuf,
+    /// Recipients every secret is encrypted to
+    recipients: Vec<Recipient>,
+    /// Identities used to decrypt the secrets
+    identities: Vec<Identity>,
+    /// Where the identities were loaded from
+    identity_source: String,
+}
+
+impl LocalSecretManager {
+    /// Create a [LocalSecretManager] from the provided `config`, loading
+    /// the identities used for decryption
+    pub fn from_config(config: &LocalConfig) -> Result<Self> {
+        let path = config
+            .path
+            .clone()
+            .ok_or_else(|| SyncError::config("local backend requires local.path to be set"))?;
+
+        if config.recipients.is_empty() {
+            return Err(SyncError::config(
+                "local backend requires at least one recipient in local.recipients",
+            ));
+        }
+
+        let recipients = config
+            .recipients
+            .iter()
+            .map(|recipient| {
+                recipient.trim().parse::<Recipient>().map_err(|error| {
+                    SyncError::config(format!("invalid age recipient \"{recipient}\": {error}"))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (identities, identity_source) = match std::env::var(AGE_IDENTITY_ENV) {
+            Ok(value) => (parse_identities(&value)?, AGE_IDENTITY_ENV.to_string()),
+            Err(_) => {
+                let identity_file = config.identity_file.as_ref().ok_or_else(|| {
+                    SyncError::backend_auth(format!(
+                        "no age identity available, set local.identity_file or {AGE_IDENTITY_ENV}"
+                    ))
+                })?;
+
+                let value = std::fs::read_to_string(identity_file).with_context(|| {
+                    format!(
+                        "failed to read age identity file \"{}\"",
+                        identity_file.display()
+                    )
+                })?;
+
+                let source = format!(
+                    "age identity file \"{}\" (local.identity_file)",
+                    identity_file.display()
+                );
+                (parse_identities(&value)?, source)
+            }
+        };
+
+        Ok(Self {
+            path,
+            recipients,
+            identities,
+            identity_source,
+        })
+    }
+
+    /// Path of the file storing the secret `name`
+    fn secret_path(&self, name: &str) -> Result<PathBuf> {
+        let mut path = secret_file_path(&self.path, name, "local")?.into_os_string();
+        path.push(".");
+        path.push(SECRET_FILE_EXTENSION);
+        Ok(PathBuf::from(path))
+    }
+
+    /// Read and decrypt the secret `name`, providing [None] when it does
+    /// not exist
+    fn read_secret(&self, name: &str) -> Result<Option<StoredSecret>> {
+        let path = self.secret_path(name)?;
+        let value = match std::fs::read(&path) {
+            Ok(value) => value,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read secret file \"{}\"", path.display()));
+            }
+        };
+
+        let value = self
+            .decrypt(&value)
+            .with_context(|| format!("failed to decrypt secret \"{name}\""))?;
+
+        serde_json::from_slice(&value).map(Some).map_err(|error| {
+            SyncError::validation(format!("secret file of \"{name}\" is not valid"))
+                .with_source(error)
+        })
+    }
+
+    /// Encrypt and write the secret `name`, failing with
+    /// [SyncError::Conflict] when `exclusive` is set and the secret already
+    /// exists
+    fn write_secret(&self, name: &str, secret: &StoredSecret, exclusive: bool) -> Result<()> {
+        let path = self.secret_path(name)?;
+        let value = serde_json::to_vec(secret)
+            .map_err(|error| SyncError::backend("failed to serialize secret").with_source(error))?;
+        let value = self.encrypt(&value)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create secret directory \"{}\"", parent.display())
+            })?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .create_new(exclusive)
+            .open(&path);
+
+        match file {
+            Ok(mut file) => file.write_all(&value),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(SyncError::conflict(format!(
+                    "secret \"{name}\" already exists"
+                )));
+            }
+            Err(error) => Err(error),
+        }
+        .with_context(|| format!("failed to write secret file \"{}\"", path.display()))
+    }
+
+    /// Encrypt the `value` to every recipient as ASCII armored text
+    fn encrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let recipients = self
+            .recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient);
+        let encryptor = Encryptor::with_recipients(recipients)
+            .map_err(|error| SyncError::backend("failed to encrypt secret").with_source(error))?;
+
+        let mut output = Vec::new();
+        let armor = ArmoredWriter::wrap_output(&mut output, Format::AsciiArmor)?;
+        let mut writer = encryptor.wrap_output(armor)?;
+        writer.write_all(value)?;
+        writer.finish()?.finish()?;
+        Ok(output)
+    }
+
+    /// Decrypt the `value` using the identities
+    fn decrypt(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let decryptor = Decryptor::new_buffered(ArmoredReader::new(value)).map_err(|error| {
+            SyncError::validation("secret file is not a valid age file").with_source(error)
+        })?;
+
+        let identities = self
+            .identities
+            .iter()
+            .map(|identity| identity as &dyn age::Identity);
+        let mut reader = decryptor.decrypt(identities).map_err(|error| {
+            SyncError::backend_auth("none of the age identities can decrypt the secret")
+                .with_source(error)
+        })?;
+
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    /// Names of every stored secret, found by walking the directory
+    fn secret_names(&self) -> Result<Vec<String>> {
+        let files = list_secret_files(&self.path)?;
+        let names = files
+            .into_iter()
+            .filter_map(|(_path, name)| {
+                name.strip_suffix(SECRET_FILE_EXTENSION)?
+                    .strip_suffix('.')
+                    .map(str::to_string)
+            })
+            .collect();
+        Ok(names)
+    }
+}
+
+/// Parse the age identities from the contents of an identity file,
+/// ignoring blank lines and comments
+fn parse_identities(value: &str) -> Result<Vec<Identity>> {
+    let identities = value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse::<Identity>()
+                .map_err(|error| SyncError::backend_auth(format!("invalid age identity: {error}")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if identities.is_empty() {
+        return Err(SyncError::backend_auth("no age identities found"));
+    }
+
+    Ok(identities)
+}
+
+#[async_trait]
+impl SecretManager for LocalSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        let secret = self
+            .read_secret(name)?
+            .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" does not exist")))?;
+
+        Ok(SecretValue {
+            version_id: Some(secret.version_count.to_string()),
+            ..secret.value.to_secret()?.into()
+        })
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        self.read_secret(name)?
+            .map(|secret| secret.value.to_secret())
+            .transpose()
+    }
+
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
+        let secret = match self.read_secret(name)? {
+            Some(mut secret) => {
+                secret.value = StoredValue::from_secret(value);
+                secret.version_count += 1;
+                secret
+            }
+            None => StoredSecret::create(value, metadata),
+        };
+
+        self.write_secret(name, &secret, false)
+    }
+
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        self.write_secret(name, &StoredSecret::create(value, metadata), true)
+    }
+
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        let path = self.secret_path(name)?;
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Err(
+                SyncError::not_found(format!("secret \"{name}\" does not exist")),
+            ),
+            Err(error) => Err(error)
+                .with_context(|| format!("failed to delete secret file \"{}\"", path.display())),
+        }
+    }
+
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        let mut secret = self
+            .read_secret(name)?
+            .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" does not exist")))?;
+        secret.tags.extend(tags.clone());
+        self.write_secret(name, &secret, false)
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        Err(SyncError::config(format!(
+            "generator \"{generator}\" is not supported by the local backend"
+        )))
+    }
+
+    async fn grant_secret_access(&self, _name: &str, _ttl: Duration) -> Result<SecretGrant> {
+        Err(SyncError::config(
+            "temporary access grants are not supported by the local backend",
+        ))
+    }
+
+    async fn verify_access(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn warm_connections(&self, _connections: usize) {}
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        CredentialDiagnosis {
+            source: Some(self.identity_source.clone()),
+            ..Default::default()
+        }
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        self.read_secret(name)?
+            .map(|secret| secret.summary(name))
+            .transpose()
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        let summaries = self.secret_names().and_then(|names| {
+            let mut summaries = Vec::new();
+            for name in names {
+                let Some(secret) = self.read_secret(&name)? else {
+                    continue;
+                };
+
+                let matches = options
+                    .tags
+                    .iter()
+                    .all(|(key, value)| secret.tags.get(key) == Some(value));
+
+                if matches {
+                    summaries.push(secret.summary(&name)?);
+                }
+            }
+            Ok(summaries)
+        });
+
+        match summaries {
+            Ok(summaries) => stream::iter(summaries.into_iter().map(Ok)).boxed(),
+            Err(error) => stream::once(async move { Err(error) }).boxed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{LocalConfig, SecretMetadata},
+        error::SyncError,
+        secret::{ListSecretsOptions, Secret, SecretManager, local::LocalSecretManager},
+    };
+    use age::{secrecy::ExposeSecret, x25519::Identity};
+    use futures_util::TryStreamExt;
+
+    /// Tests that secrets are stored encrypted, can be read back by the
+    /// identity of a recipient, and cannot be read by anyone else
+    #[tokio::test]
+    async fn test_local_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let identity = Identity::generate();
+        let identity_file = directory.path().join("key.txt");
+        std::fs::write(
+            &identity_file,
+            format!("# test key\n{}\n", identity.to_string().expose_secret()),
+        )
+        .unwrap();
+
+        let config = LocalConfig {
+            path: Some(directory.path().join("secrets")),
+            recipients: vec![identity.to_public().to_string()],
+            identity_file: Some(identity_file),
+        };
+
+        let secret = LocalSecretManager::from_config(&config).unwrap();
+        secret
+            .set_secret(
+                "app/db",
+                Secret::String("PASSWORD=1".to_string()),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+        secret
+            .set_secret(
+                "app/db",
+                Secret::String("PASSWORD=2".to_string()),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+
+        let stored = std::fs::read_to_string(directory.path().join("secrets/app/db.age")).unwrap();
+        assert!(stored.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+        assert!(!stored.contains("PASSWORD"));
+
+        let value = secret.get_secret("app/db").await.unwrap();
+        assert_eq!(value.data.as_bytes(), b"PASSWORD=2");
+        assert_eq!(value.version_id.as_deref(), Some("2"));
+
+        let listed: Vec<String> = secret
+            .list_secrets(ListSecretsOptions::default())
+            .map_ok(|summary| summary.name)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(listed, vec!["app/db".to_string()]);
+
+        assert!(matches!(
+            secret.get_secret("../outside").await,
+            Err(SyncError::Validation(_))
+        ));
+
+        // Identities that are not a recipient cannot decrypt the secrets
+        let other = Identity::generate();
+        let other_file = directory.path().join("other.txt");
+        std::fs::write(&other_file, other.to_string().expose_secret()).unwrap();
+        let other = LocalSecretManager::from_config(&LocalConfig {
+            identity_file: Some(other_file),
+            ..config
+        })
+        .unwrap();
+        assert!(matches!(
+            other.get_secret("app/db").await,
+            Err(SyncError::BackendAuth(_))
+        ));
+
+        secret.delete_secret("app/db").await.unwrap();
+        assert!(secret.find_secret("app/db").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,420 @@
+//! # Memory
+//!
+//! Deterministic in-memory secret manager, optionally persisted to a JSON
+//! file so the secrets survive between runs. Intended for integration tests
+//! of downstream projects and offline demos rather than real secrets, the
+//! JSON file is not encrypted
+
+use crate::{
+    config::{MemoryConfig, SecretGenerator, SecretMetadata},
+    doctor::CredentialDiagnosis,
+    error::{Result, ResultExt, SyncError},
+    secret::{
+        ListSecretsOptions, MANAGED_BY_TAG, MANAGED_BY_VALUE, Secret, SecretGrant, SecretManager,
+        SecretSummary, SecretValue,
+    },
+};
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Mutex, time::Duration};
+
+/// Contents of the memory store, as persisted to the JSON file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MemoryStore {
+    /// Stored secrets by name
+    secrets: IndexMap<String, StoredSecret>,
+}
+
+/// Secret within the memory store, also used as the encrypted contents
+/// of the files of the local backend
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StoredSecret {
+    /// Current value of the secret
+    pub(crate) value: StoredValue,
+    /// Description of the secret
+    #[serde(default)]
+    pub(crate) description: Option<String>,
+    /// Tags attached to the secret
+    #[serde(default)]
+    pub(crate) tags: IndexMap<String, String>,
+    /// Number of values the secret has had
+    pub(crate) version_count: usize,
+}
+
+/// Value of a stored secret, binary values are base64 encoded
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StoredValue {
+    String(String),
+    Binary(String),
+}
+
+impl StoredValue {
+    pub(crate) fn from_secret(secret: Secret) -> Self {
+        match secret {
+            Secret::String(value) => StoredValue::String(value),
+            Secret::Binary(value) => StoredValue::Binary(STANDARD.encode(value)),
+        }
+    }
+
+    pub(crate) fn to_secret(&self) -> Result<Secret> {
+        match self {
+            StoredValue::String(value) => Ok(Secret::String(value.clone())),
+            StoredValue::Binary(value) => {
+                STANDARD.decode(value).map(Secret::Binary).map_err(|error| {
+                    SyncError::validation("stored binary secret is not valid base64")
+                        .with_source(error)
+                })
+            }
+        }
+    }
+}
+
+impl StoredSecret {
+    /// Create the first version of a secret with the `value` and the
+    /// `metadata` of its file, tagged as managed by secret-sync
+    pub(crate) fn create(value: Secret, metadata: &SecretMetadata) -> Self {
+        let mut tags = metadata.tags.clone().unwrap_or_default();
+        tags.insert(MANAGED_BY_TAG.to_string(), MANAGED_BY_VALUE.to_string());
+
+        StoredSecret {
+            value: StoredValue::from_secret(value),
+            description: metadata.description.clone(),
+            tags,
+            version_count: 1,
+        }
+    }
+
+    pub(crate) fn summary(&self, name: &str) -> Result<SecretSummary> {
+        Ok(SecretSummary {
+            name: name.to_string(),
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            created: None,
+            updated: None,
+            version_count: Some(self.version_count),
+            size_hint: Some(self.value.to_secret()?.as_bytes().len() as u64),
+        })
+    }
+}
+
+/// Secret manager storing secrets in memory
+pub struct MemorySecretManager {
+    store: Mutex<MemoryStore>,
+    path: Option<PathBuf>,
+}
+
+impl MemorySecretManager {
+    /// Create an empty store that is not persisted
+    pub fn new() -> Self {
+        Self {
+            store: Mutex::new(MemoryStore::default()),
+            path: None,
+        }
+    }
+
+    /// Create a [MemorySecretManager] from the provided `config`, loading
+    /// the secrets from the JSON file when it exists
+    pub fn from_config(config: &MemoryConfig) -> Result<Self> {
+        let Some(path) = config.path.clone() else {
+            return Ok(Self::new());
+        };
+
+        let store = match std::fs::read(&path) {
+            Ok(value) => serde_json::from_slice(&value).map_err(|error| {
+                SyncError::config(format!(
+                    "failed to parse memory store \"{}\"",
+                    path.display()
+                ))
+                .with_source(error)
+            })?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => MemoryStore::default(),
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("failed to read memory store \"{}\"", path.display())
+                });
+            }
+        };
+
+        Ok(Self {
+            store: Mutex::new(store),
+            path: Some(path),
+        })
+    }
+
+    /// Run `action` against the store, persisting the store afterwards
+    /// when it is backed by a file and `action` succeeded
+    fn modify<T>(&self, action: impl FnOnce(&mut MemoryStore) -> Result<T>) -> Result<T> {
+        let mut store = self
+            .store
+            .lock()
+            .map_err(|_| SyncError::backend("memory store lock poisoned"))?;
+
+        let output = action(&mut store)?;
+
+        if let Some(path) = &self.path {
+            let value = serde_json::to_vec_pretty(&*store).map_err(|error| {
+                SyncError::backend("failed to serialize memory store").with_source(error)
+            })?;
+            std::fs::write(path, value)
+                .with_context(|| format!("failed to write memory store \"{}\"", path.display()))?;
+        }
+
+        Ok(output)
+    }
+
+    /// Run `action` against the store without modifying it
+    fn read<T>(&self, action: impl FnOnce(&MemoryStore) -> Result<T>) -> Result<T> {
+        let store = self
+            .store
+            .lock()
+            .map_err(|_| SyncError::backend("memory store lock poisoned"))?;
+        action(&store)
+    }
+}
+
+impl Default for MemorySecretManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SecretManager for MemorySecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        self.read(|store| {
+            let secret = store
+                .secrets
+                .get(name)
+                .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" does not exist")))?;
+
+            Ok(SecretValue {
+                version_id: Some(secret.version_count.to_string()),
+                ..secret.value.to_secret()?.into()
+            })
+        })
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        self.read(|store| {
+            store
+                .secrets
+                .get(name)
+                .map(|secret| secret.value.to_secret())
+                .transpose()
+        })
+    }
+
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
+        self.modify(|store| {
+            match store.secrets.get_mut(name) {
+                Some(secret) => {
+                    secret.value = StoredValue::from_secret(value);
+                    secret.version_count += 1;
+                }
+                None => {
+                    store
+                        .secrets
+                        .insert(name.to_string(), StoredSecret::create(value, metadata));
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        self.modify(|store| {
+            if store.secrets.contains_key(name) {
+                return Err(SyncError::conflict(format!(
+                    "secret \"{name}\" already exists"
+                )));
+            }
+
+            store
+                .secrets
+                .insert(name.to_string(), StoredSecret::create(value, metadata));
+            Ok(())
+        })
+    }
+
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        self.modify(|store| {
+            store
+                .secrets
+                .shift_remove(name)
+                .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" does not exist")))?;
+            Ok(())
+        })
+    }
+
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        self.modify(|store| {
+            let secret = store
+                .secrets
+                .get_mut(name)
+                .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" does not exist")))?;
+            secret.tags.extend(tags.clone());
+            Ok(())
+        })
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        Err(SyncError::config(format!(
+            "generator \"{generator}\" is not supported by the memory backend"
+        )))
+    }
+
+    async fn grant_secret_access(&self, _name: &str, _ttl: Duration) -> Result<SecretGrant> {
+        Err(SyncError::config(
+            "temporary access grants are not supported by the memory backend",
+        ))
+    }
+
+    async fn verify_access(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn warm_connections(&self, _connections: usize) {}
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        CredentialDiagnosis {
+            source: Some("memory backend (no credentials required)".to_string()),
+            ..Default::default()
+        }
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        self.read(|store| {
+            store
+                .secrets
+                .get(name)
+                .map(|secret| secret.summary(name))
+                .transpose()
+        })
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        let summaries = self.read(|store| {
+            let mut summaries = Vec::new();
+            for (name, secret) in &store.secrets {
+                let matches = options
+                    .tags
+                    .iter()
+                    .all(|(key, value)| secret.tags.get(key) == Some(value));
+
+                if matches {
+                    summaries.push(secret.summary(name)?);
+                }
+            }
+
+            // Listed in a deterministic order regardless of creation order
+            summaries.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(summaries)
+        });
+
+        match summaries {
+            Ok(summaries) => stream::iter(summaries.into_iter().map(Ok)).boxed(),
+            Err(error) => stream::once(async move { Err(error) }).boxed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{MemoryConfig, SecretMetadata},
+        error::SyncError,
+        secret::{ListSecretsOptions, Secret, SecretManager, memory::MemorySecretManager},
+    };
+    use futures_util::TryStreamExt;
+    use indexmap::IndexMap;
+
+    /// Tests that secrets persisted to the JSON file are loaded again
+    #[tokio::test]
+    async fn test_memory_store_persisted() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = MemoryConfig {
+            path: Some(directory.path().join("secrets.json")),
+        };
+
+        let secret = MemorySecretManager::from_config(&config).unwrap();
+        let metadata = SecretMetadata {
+            tags: Some(IndexMap::from([("team".to_string(), "web".to_string())])),
+            ..Default::default()
+        };
+        secret
+            .set_secret("b", Secret::String("1".to_string()), &metadata)
+            .await
+            .unwrap();
+        secret
+            .set_secret(
+                "a",
+                Secret::Binary(vec![0, 159]),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+        secret
+            .set_secret(
+                "b",
+                Secret::String("2".to_string()),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+
+        let secret = MemorySecretManager::from_config(&config).unwrap();
+        assert_eq!(
+            secret.get_secret("a").await.unwrap().data.as_bytes(),
+            &[0, 159]
+        );
+        assert_eq!(
+            secret.get_secret("b").await.unwrap().version_id.as_deref(),
+            Some("2")
+        );
+
+        assert!(matches!(
+            secret.get_secret("c").await,
+            Err(SyncError::BackendNotFound(_))
+        ));
+
+        let summary = secret.describe_secret("b").await.unwrap().unwrap();
+        assert_eq!(summary.version_count, Some(2));
+        assert!(summary.is_managed());
+
+        let listed: Vec<String> = secret
+            .list_secrets(ListSecretsOptions::default())
+            .map_ok(|summary| summary.name)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(listed, vec!["a".to_string(), "b".to_string()]);
+
+        let tagged: Vec<String> = secret
+            .list_secrets(ListSecretsOptions {
+                tags: vec![("team".to_string(), "web".to_string())],
+                ..Default::default()
+            })
+            .map_ok(|summary| summary.name)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(tagged, vec!["b".to_string()]);
+    }
+}
@@ -3,14 +3,58 @@
 //! This module contains a secret manager implementation generalizing the behavior
 //! a secret manager must have so that it is abstracted for pulling and pushing.
 //!
-//! - [`aws`] AWS Compatible secret manager backend
+//! - [`aws`] AWS Compatible secret manager backend (requires the "aws" feature)
+//! - [`dev`] Detection of local emulators of the AWS backend (requires the "aws" feature)
+//! - [`gcp`] Google Cloud Secret Manager backend (requires the "gcp" feature)
+//! - [`azure`] Azure Key Vault backend (requires the "azure" feature)
+//! - [`ssm`] AWS SSM Parameter Store backend (requires the "aws" feature)
+//! - [`http`] Tuned HTTP clients for the AWS, GCP, and Azure backends (requires the "aws", "gcp", or "azure" feature)
+//! - [`rest`] Shared plumbing of the REST API backends (requires the "gcp" or "azure" feature)
+//! - [`memory`] In-memory backend for tests and demos
+//! - [`local`] age encrypted secret files in a local directory (requires the "local" feature)
+//! - [`sops`] SOPS encrypted files through the sops executable
+//! - [`compress`] Compression of configured secret values, wrapping any backend
+//! - [`route`] Routing of secrets to the backends of files overriding the backend
 
-use crate::config::SecretMetadata;
+use crate::{
+    config::{
+        BackendOverride, BackendProvider, Config, SecretFile, SecretGenerator, SecretMetadata,
+    },
+    doctor::CredentialDiagnosis,
+    error::{Result, SyncError},
+};
 use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use indexmap::IndexMap;
 use mockall::automock;
-use std::fmt::Debug;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Debug,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
 
+#[cfg(feature = "aws")]
 pub mod aws;
+#[cfg(feature = "azure")]
+pub mod azure;
+pub mod compress;
+#[cfg(feature = "aws")]
+pub mod dev;
+#[cfg(feature = "gcp")]
+pub mod gcp;
+#[cfg(any(feature = "aws", feature = "gcp", feature = "azure"))]
+pub mod http;
+#[cfg(feature = "local")]
+pub mod local;
+pub mod memory;
+#[cfg(any(feature = "gcp", feature = "azure"))]
+pub mod rest;
+pub mod route;
+pub mod sops;
+#[cfg(feature = "aws")]
+pub mod ssm;
 
 /// Secret value
 #[derive(Clone, PartialEq, Eq)]
@@ -28,6 +72,21 @@ impl Debug for Secret {
 }
 
 impl Secret {
+    /// Create a secret from raw bytes, bytes that are valid UTF-8 become
+    /// a [Secret::String] while anything else becomes a [Secret::Binary]
+    pub fn from_bytes(value: Vec<u8>) -> Secret {
+        match String::from_utf8(value) {
+            Ok(value) => Secret::String(value),
+            Err(error) => Secret::Binary(error.into_bytes()),
+        }
+    }
+
+    /// Get a hex encoded SHA-256 hash of the secret value, allows comparing
+    /// secret values without storing the value itself
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.as_bytes()))
+    }
+
     /// Get the secret as a slice of bytes
     pub fn as_bytes(&self) -> &[u8] {
         match self {
@@ -46,18 +105,476 @@ impl Secret {
     }
 }
 
+/// Secret value along with metadata identifying the version it was read
+/// from, fields the backend does not provide are [None]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretValue {
+    /// Value of the secret
+    pub data: Secret,
+    /// Identifier of the version the value belongs to
+    pub version_id: Option<String>,
+    /// ARN of the secret, for backends that identify secrets by ARN
+    pub arn: Option<String>,
+    /// When the version was created as an RFC 3339 timestamp
+    pub created: Option<String>,
+}
+
+impl From<Secret> for SecretValue {
+    fn from(data: Secret) -> Self {
+        SecretValue {
+            data,
+            version_id: None,
+            arn: None,
+            created: None,
+        }
+    }
+}
+
+/// Tag attached to secrets created by secret-sync, secrets without the tag
+/// are owned by another system and are only overwritten when adopted
+pub const MANAGED_BY_TAG: &str = "managed-by";
+
+/// Value of the [MANAGED_BY_TAG] for secrets created by secret-sync
+pub const MANAGED_BY_VALUE: &str = "secret-sync";
+
+/// Backend agnostic summary of a secret's metadata, serialized with the
+/// same schema for every backend
+///
+/// Fields the backend does not provide are [None]
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct SecretSummary {
+    /// Name of the secret
+    pub name: String,
+    /// Description of the secret
+    pub description: Option<String>,
+    /// Tags attached to the secret
+    pub tags: IndexMap<String, String>,
+    /// When the secret was created as an RFC 3339 timestamp
+    pub created: Option<String>,
+    /// When the secret was last changed as an RFC 3339 timestamp
+    pub updated: Option<String>,
+    /// Number of versions of the secret value
+    pub version_count: Option<usize>,
+    /// Approximate size of the secret value in bytes
+    pub size_hint: Option<u64>,
+}
+
+impl SecretSummary {
+    /// Whether the secret has the marker tag of secrets created by secret-sync
+    pub fn is_managed(&self) -> bool {
+        self.tags
+            .get(MANAGED_BY_TAG)
+            .is_some_and(|value| value == MANAGED_BY_VALUE)
+    }
+}
+
+/// Temporary credentials that only allow reading a single secret, used to
+/// give someone without standing access a copy of one secret
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct SecretGrant {
+    /// Name of the secret that may be read
+    pub secret: String,
+    /// Environment variables providing the credentials to the backend
+    pub environment: IndexMap<String, String>,
+    /// When the credentials expire as an RFC 3339 timestamp
+    pub expires: Option<String>,
+}
+
+/// Options for listing secrets
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListSecretsOptions {
+    /// Only list the secrets that have all of these tags
+    pub tags: Vec<(String, String)>,
+    /// Maximum number of secrets to request from the backend per page,
+    /// uses the backend default when not set
+    pub page_size: Option<i32>,
+}
+
 /// Secret manager abstraction
 #[automock]
 #[async_trait]
 pub trait SecretManager {
-    /// Get a secret from the secret manager by `name`
-    async fn get_secret(&self, name: &str) -> eyre::Result<Secret>;
+    /// Get a secret from the secret manager by `name` along with the
+    /// metadata of the version that was read, fails with
+    /// [SyncError::BackendNotFound] when the secret does not exist
+    async fn get_secret(&self, name: &str) -> Result<SecretValue>;
 
-    /// Set a secret by `name` to `value` with some `metadata`
-    async fn set_secret(
+    /// Find a secret from the secret manager by `name`, providing [None]
+    /// when the secret does not exist
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>>;
+
+    /// Set a secret by `name` to `value` with some `metadata`, secrets
+    /// that are created are tagged with the [MANAGED_BY_TAG] marker
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()>;
+
+    /// Create the secret `name` with the `value` and `metadata`, failing
+    /// with [SyncError::Conflict] rather than overwriting the secret when
+    /// it already exists, so only one of several concurrent writers succeeds
+    async fn create_secret(
         &self,
         name: &str,
         value: Secret,
         metadata: &SecretMetadata,
-    ) -> eyre::Result<()>;
+    ) -> Result<()>;
+
+    /// Delete a secret by `name`
+    async fn delete_secret(&self, name: &str) -> Result<()>;
+
+    /// Attach the `tags` to the existing secret `name`, replacing the
+    /// values of any tags that are already attached
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()>;
+
+    /// Generate a fresh value using the `generator`, fails when the
+    /// generator is not supported by the backend
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret>;
+
+    /// Mint temporary credentials valid for `ttl` that only allow reading
+    /// the secret `name`
+    async fn grant_secret_access(&self, name: &str, ttl: Duration) -> Result<SecretGrant>;
+
+    /// Verify the credentials are valid and the backend is reachable,
+    /// used before making any changes
+    async fn verify_access(&self) -> Result<()>;
+
+    /// Open `connections` connections to the backend ahead of a concurrent
+    /// batch, backends without connections do nothing
+    async fn warm_connections(&self, connections: usize);
+
+    /// Diagnose the credentials used by the backend, reporting where they
+    /// were resolved from along with any detected misconfigurations
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis;
+
+    /// Identity the backend authenticates requests as (e.g. the ARN of the
+    /// AWS credentials), providing [None] for backends without a caller
+    /// identity
+    async fn caller_identity(&self) -> Result<Option<String>>;
+
+    /// Describe the metadata of a secret by `name` without accessing its
+    /// value, providing [None] when the secret does not exist
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>>;
+
+    /// List the secrets matching the `options`
+    ///
+    /// Pages are only requested from the backend as the stream is polled,
+    /// allowing large numbers of secrets to be processed without loading
+    /// them all into memory
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>>;
+}
+
+/// Resolve the secret `name` to a path within the `directory` of a file
+/// based backend, names must be relative paths so secrets cannot be read
+/// or written outside of the directory
+pub(crate) fn secret_file_path(directory: &Path, name: &str, backend: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    let valid = !name.is_empty()
+        && !name.contains('\\')
+        && relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+    if !valid {
+        return Err(SyncError::validation(format!(
+            "secret name \"{name}\" cannot be stored by the {backend} backend, names must be relative paths"
+        )));
+    }
+
+    Ok(directory.join(relative))
+}
+
+/// Find every file within the `directory` of a file based backend along
+/// with its path relative to the `directory` using forward slashes, sorted
+/// by the relative path. A missing directory contains no files
+pub(crate) fn list_secret_files(directory: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(current) = directories.pop() {
+        let entries = match std::fs::read_dir(&current) {
+            Ok(value) => value,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => {
+                return Err(SyncError::from(error).context(format!(
+                    "failed to read secret directory \"{}\"",
+                    current.display()
+                )));
+            }
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+
+            let Ok(relative) = path.strip_prefix(directory) else {
+                continue;
+            };
+
+            // Secret names always use forward slashes regardless of platform
+            let name = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((path, name));
+        }
+    }
+
+    // Listed in a deterministic order regardless of the directory order
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(files)
+}
+
+/// Create the secret manager for the backend provider selected in the `config`,
+/// files overriding the backend are routed to their own backend
+///
+/// Fails when the backend was not compiled into the binary
+pub async fn create_secret_manager(config: &Config) -> Result<Box<dyn SecretManager>> {
+    let mut secret = create_backend(config, &BackendOverride::default()).await?;
+
+    // Files with the same override share a backend
+    let mut overrides: Vec<(&BackendOverride, Vec<String>)> = Vec::new();
+    for (name, file) in &config.files {
+        let Some(backend) = file_backend(config, name, file)? else {
+            continue;
+        };
+
+        match overrides
+            .iter_mut()
+            .find(|(existing, _)| *existing == backend)
+        {
+            Some((_, secrets)) => secrets.push(file.secret.clone()),
+            None => overrides.push((backend, vec![file.secret.clone()])),
+        }
+    }
+
+    if !overrides.is_empty() {
+        let mut backends = Vec::with_capacity(overrides.len());
+        for (backend, secrets) in overrides {
+            backends.push((create_backend(config, backend).await?, secrets));
+        }
+
+        secret = Box::new(route::RoutedSecretManager::new(secret, backends)?);
+    }
+
+    // Compressed values are always decompressed, even when no file in this
+    // config is compressed
+    Ok(Box::new(compress::CompressedSecretManager::new(
+        secret, config,
+    )))
+}
+
+/// Name of the top-level backend when selecting a backend by name
+pub const DEFAULT_BACKEND_NAME: &str = "default";
+
+/// Create the backend named `name` within `backends`, or the top-level
+/// backend for [DEFAULT_BACKEND_NAME]
+pub async fn create_named_backend(
+    config: &Config,
+    name: &str,
+) -> Result<Box<dyn SecretManager + Send + Sync>> {
+    if name == DEFAULT_BACKEND_NAME {
+        return create_backend(config, &BackendOverride::default()).await;
+    }
+
+    let backend = config.backends.get(name).ok_or_else(|| {
+        SyncError::config(format!(
+            "unknown backend \"{name}\", expected \"{DEFAULT_BACKEND_NAME}\" or a backend from [backends.<name>]"
+        ))
+    })?;
+
+    create_backend(config, backend).await
+}
+
+/// Backend the `file` named `name` stores its secret in, either a named
+/// backend from `backends` or the override within the file. Provides [None]
+/// when the file uses the top-level backend
+fn file_backend<'a>(
+    config: &'a Config,
+    name: &str,
+    file: &'a SecretFile,
+) -> Result<Option<&'a BackendOverride>> {
+    let Some(backend) = &file.backend else {
+        return Ok(Some(&file.backend_override).filter(|backend| !backend.is_empty()));
+    };
+
+    if !file.backend_override.is_empty() {
+        return Err(SyncError::config(format!(
+            "file \"{name}\" cannot use backend \"{backend}\" alongside its own provider settings"
+        )));
+    }
+
+    config.backends.get(backend).map(Some).ok_or_else(|| {
+        SyncError::config(format!(
+            "file \"{name}\" uses unknown backend \"{backend}\""
+        ))
+    })
+}
+
+/// Create the backend for the provider of the `backend` override, using
+/// the top-level settings of the `config` for anything not overridden
+async fn create_backend(
+    config: &Config,
+    backend: &BackendOverride,
+) -> Result<Box<dyn SecretManager + Send + Sync>> {
+    let provider = backend.provider.unwrap_or(config.backend.provider);
+
+    Ok(match provider {
+        #[cfg(feature = "aws")]
+        BackendProvider::Aws => {
+            let aws = backend.aws.as_ref().unwrap_or(&config.aws);
+
+            // An explicitly configured endpoint takes priority over detection
+            let dev = match config.backend.dev_endpoint_autodetect && aws.endpoint.is_none() {
+                true => Some(dev::detect_dev_endpoint(dev::DEV_ENDPOINTS).await?),
+                false => None,
+            };
+
+            Box::new(
+                aws::AwsSecretManager::from_config(
+                    aws,
+                    &config.credentials,
+                    &config.network,
+                    dev.as_ref(),
+                )
+                .await?,
+            )
+        }
+
+        #[cfg(feature = "aws")]
+        BackendProvider::Ssm => Box::new(
+            ssm::SsmSecretManager::from_config(
+                backend.ssm.as_ref().unwrap_or(&config.ssm),
+                backend.aws.as_ref().unwrap_or(&config.aws),
+                &config.credentials,
+                &config.network,
+            )
+            .await?,
+        ),
+
+        #[cfg(feature = "gcp")]
+        BackendProvider::Gcp => Box::new(
+            gcp::GcpSecretManager::from_config(
+                backend.gcp.as_ref().unwrap_or(&config.gcp),
+                &config.network,
+            )
+            .await?,
+        ),
+
+        #[cfg(feature = "azure")]
+        BackendProvider::Azure => Box::new(azure::AzureSecretManager::from_config(
+            backend.azure.as_ref().unwrap_or(&config.azure),
+            &config.network,
+        )?),
+
+        BackendProvider::Memory => Box::new(memory::MemorySecretManager::from_config(
+            backend.memory.as_ref().unwrap_or(&config.memory),
+        )?),
+
+        #[cfg(feature = "local")]
+        BackendProvider::Local => Box::new(local::LocalSecretManager::from_config(
+            backend.local.as_ref().unwrap_or(&config.local),
+        )?),
+
+        BackendProvider::Sops => Box::new(sops::SopsSecretManager::from_config(
+            backend.sops.as_ref().unwrap_or(&config.sops),
+        )?),
+
+        #[allow(unreachable_patterns)]
+        provider => {
+            return Err(SyncError::config(format!(
+                "backend \"{provider}\" is not compiled into this binary, rebuild with the \"{provider}\" feature enabled"
+            )));
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{MemoryConfig, SecretMetadata, parse_config_file},
+        secret::{
+            Secret, SecretManager, SecretSummary, create_secret_manager,
+            memory::MemorySecretManager,
+        },
+    };
+
+    /// Tests that summaries always serialize every field so the schema is
+    /// the same regardless of what the backend provides
+    #[test]
+    fn test_secret_summary_schema() {
+        let summary = SecretSummary {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "name": "test",
+                "description": null,
+                "tags": {},
+                "created": null,
+                "updated": null,
+                "version_count": null,
+                "size_hint": null
+            })
+        );
+    }
+
+    /// Tests that files using a named backend store their secrets in that
+    /// backend and that unknown backends are rejected
+    #[tokio::test]
+    async fn test_named_backends() {
+        let directory = tempfile::tempdir().unwrap();
+        let config = format!(
+            r#"
+            [backend]
+            provider = "memory"
+
+            [backends.staging]
+            provider = "memory"
+            memory = {{ path = "{}" }}
+
+            [files.app]
+            path = ".env"
+            secret = "app"
+            backend = "staging"
+            "#,
+            directory.path().join("staging.json").display()
+        );
+        let config = parse_config_file(config.as_bytes(), None).unwrap();
+
+        let secret = create_secret_manager(&config).await.unwrap();
+        secret
+            .set_secret(
+                "app",
+                Secret::String("A=1".to_string()),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+
+        let staging = MemorySecretManager::from_config(&MemoryConfig {
+            path: Some(directory.path().join("staging.json")),
+        })
+        .unwrap();
+        assert!(staging.find_secret("app").await.unwrap().is_some());
+
+        let config = parse_config_file(
+            br#"
+            [files.app]
+            path = ".env"
+            secret = "app"
+            backend = "missing"
+            "#,
+            None,
+        )
+        .unwrap();
+        assert!(create_secret_manager(&config).await.is_err());
+    }
 }
@@ -0,0 +1,305 @@
+//! # REST
+//!
+//! Shared plumbing for the backends that talk to their service through a
+//! REST API rather than an SDK: sending requests, reporting API errors,
+//! and caching OAuth access tokens
+
+use crate::{
+    config::NetworkConfig,
+    error::{Result, SyncError},
+    secret::http::{client_builder, https_connector},
+    structured::url_encode,
+};
+use http::{Method, StatusCode, header};
+use http_body_util::BodyExt;
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::{Client, connect::HttpConnector as TcpConnector};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Time to wait for the response to each request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Access tokens are refreshed this long before they expire
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// Pooled HTTPS client for the REST API of a `service`
+#[derive(Clone)]
+pub struct RestClient {
+    client: Client<HttpsConnector<TcpConnector>, String>,
+    /// Name of the service used within error messages
+    service: &'static str,
+}
+
+impl RestClient {
+    /// Create a client for the `service` tuned by the network `config`
+    pub fn new(config: &NetworkConfig, service: &'static str) -> Result<Self> {
+        Ok(Self {
+            client: client_builder(config).build(https_connector(config, None)?),
+            service,
+        })
+    }
+
+    /// Send the `request`, failing only when no response was received
+    pub async fn send(&self, request: http::Request<String>) -> Result<RestResponse> {
+        let service = self.service;
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request))
+            .await
+            .map_err(|_| SyncError::backend(format!("{service} request timed out")))?
+            .map_err(|error| {
+                SyncError::backend(format!("failed to send {service} request")).with_source(error)
+            })?;
+
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|error| {
+                SyncError::backend(format!("failed to read {service} response")).with_source(error)
+            })?
+            .to_bytes()
+            .to_vec();
+
+        Ok(RestResponse {
+            status,
+            body,
+            service,
+        })
+    }
+}
+
+/// Status and body of a response
+pub struct RestResponse {
+    /// Status of the response
+    pub status: StatusCode,
+    /// Body of the response
+    pub body: Vec<u8>,
+    service: &'static str,
+}
+
+/// Error response, shared by the Google and Azure APIs
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetails,
+}
+
+/// Details of an [ErrorResponse]
+#[derive(Deserialize)]
+struct ErrorDetails {
+    message: String,
+}
+
+impl RestResponse {
+    /// Provide the response when successful, otherwise the error it
+    /// describes
+    pub fn into_result(self) -> Result<RestResponse> {
+        match self.status.is_success() {
+            true => Ok(self),
+            false => Err(self.error()),
+        }
+    }
+
+    /// Parse the JSON body of a successful response
+    pub fn json<T: DeserializeOwned>(self) -> Result<T> {
+        let response = self.into_result()?;
+        serde_json::from_slice(&response.body).map_err(|error| {
+            SyncError::backend(format!("invalid {} response", response.service)).with_source(error)
+        })
+    }
+
+    /// Create the error described by the response, categorized by the
+    /// status of the response
+    pub fn error(&self) -> SyncError {
+        let message = match serde_json::from_slice::<ErrorResponse>(&self.body) {
+            Ok(response) => response.error.message,
+            Err(_) => String::from_utf8_lossy(&self.body).trim().to_string(),
+        };
+
+        let message = format!(
+            "{} request failed ({}): {message}",
+            self.service, self.status
+        );
+        match self.status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => SyncError::backend_auth(message),
+            StatusCode::NOT_FOUND => SyncError::not_found(message),
+            StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => SyncError::conflict(message),
+            StatusCode::BAD_REQUEST => SyncError::validation(message),
+            StatusCode::TOO_MANY_REQUESTS => SyncError::throttled(message),
+            _ => SyncError::backend(message),
+        }
+    }
+}
+
+/// Create a POST request to `url` with a form encoded body of `fields`
+pub fn form_request(url: &str, fields: &[(&str, &str)]) -> Result<http::Request<String>> {
+    let body = fields
+        .iter()
+        .map(|(key, value)| format!("{key}={}", url_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    http::Request::post(url)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(body)
+        .map_err(|error| SyncError::config("invalid token request").with_source(error))
+}
+
+/// Create a `method` request to `url` with an optional JSON `body`,
+/// authorized by the bearer `token` when provided
+pub fn json_request(
+    method: Method,
+    url: &str,
+    token: Option<&str>,
+    body: Option<&serde_json::Value>,
+) -> Result<http::Request<String>> {
+    let mut request = http::Request::builder()
+        .method(method)
+        .uri(url)
+        .header(header::CONTENT_TYPE, "application/json");
+
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+
+    request
+        .body(body.map(ToString::to_string).unwrap_or_default())
+        .map_err(|error| SyncError::config("invalid request").with_source(error))
+}
+
+/// OAuth access token along with when it expires
+pub struct AccessToken {
+    /// Value of the token
+    pub value: String,
+    /// When the token expires, [None] for tokens without a known expiry
+    pub expires: Option<Instant>,
+}
+
+impl AccessToken {
+    /// Whether the token can still be used
+    fn is_valid(&self) -> bool {
+        self.expires
+            .is_none_or(|expires| Instant::now() + TOKEN_EXPIRY_MARGIN < expires)
+    }
+}
+
+/// Response of an OAuth token request
+#[derive(Deserialize)]
+pub struct TokenResponse {
+    /// The access token
+    pub access_token: String,
+    /// Seconds until the token expires, some endpoints provide this as a
+    /// string
+    #[serde(default, deserialize_with = "deserialize_expires_in")]
+    pub expires_in: Option<u64>,
+}
+
+impl From<TokenResponse> for AccessToken {
+    fn from(value: TokenResponse) -> Self {
+        AccessToken {
+            value: value.access_token,
+            expires: value
+                .expires_in
+                .map(|expires_in| Instant::now() + Duration::from_secs(expires_in)),
+        }
+    }
+}
+
+/// Deserialize a number of seconds provided as either a number or string
+fn deserialize_expires_in<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Number(u64),
+        String(String),
+    }
+
+    Ok(match Option::<Seconds>::deserialize(deserializer)? {
+        Some(Seconds::Number(value)) => Some(value),
+        Some(Seconds::String(value)) => value.parse().ok(),
+        None => None,
+    })
+}
+
+/// Cache of the current access token
+#[derive(Default)]
+pub struct TokenCache {
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl TokenCache {
+    /// Get the cached token, requesting a fresh token using `fetch` when
+    /// there is none or it expired
+    pub async fn get_or_fetch<F>(&self, fetch: F) -> Result<String>
+    where
+        F: AsyncFnOnce() -> Result<AccessToken>,
+    {
+        // Held while refreshing so concurrent requests share one refresh
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref()
+            && token.is_valid()
+        {
+            return Ok(token.value.clone());
+        }
+
+        let token = fetch().await?;
+        let value = token.value.clone();
+        *cached = Some(token);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        error::SyncError,
+        secret::rest::{AccessToken, TokenCache, TokenResponse},
+    };
+
+    /// Tests that token expiry is read from numbers and strings and that
+    /// cached tokens are reused until they expire
+    #[tokio::test]
+    async fn test_token_cache() {
+        let response: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "a", "expires_in": "3599"}"#).unwrap();
+        assert_eq!(response.expires_in, Some(3599));
+        let response: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "a", "expires_in": 3599}"#).unwrap();
+        assert_eq!(response.expires_in, Some(3599));
+
+        let cache = TokenCache::default();
+        let token = cache
+            .get_or_fetch(async || Ok(AccessToken::from(response)))
+            .await
+            .unwrap();
+        assert_eq!(token, "a");
+
+        let token = cache
+            .get_or_fetch(async || Err(SyncError::backend_auth("cached token should be used")))
+            .await
+            .unwrap();
+        assert_eq!(token, "a");
+
+        // Tokens expiring within the margin are refreshed
+        let expiring: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "b", "expires_in": 30}"#).unwrap();
+        let cache = TokenCache::default();
+        cache
+            .get_or_fetch(async || Ok(AccessToken::from(expiring)))
+            .await
+            .unwrap();
+        let token = cache
+            .get_or_fetch(async || {
+                Ok(AccessToken {
+                    value: "c".to_string(),
+                    expires: None,
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(token, "c");
+    }
+}
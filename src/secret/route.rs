@@ -0,0 +1,228 @@
+//! # Route
+//!
+//! Secret manager routing the secrets of files that override the backend
+//! (e.g. `provider = "ssm"` within a `[files.*]` entry) to their own
+//! backend, every other secret uses the top-level backend. Secrets are
+//! routed by name so the rest of secret-sync can treat the config as a
+//! single backend
+
+use crate::{
+    config::{SecretGenerator, SecretMetadata},
+    doctor::CredentialDiagnosis,
+    error::{Result, SyncError},
+    secret::{ListSecretsOptions, Secret, SecretGrant, SecretManager, SecretSummary, SecretValue},
+};
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use indexmap::IndexMap;
+use std::{collections::HashMap, time::Duration};
+
+/// Secret manager routing secrets to the backend of their file
+pub struct RoutedSecretManager {
+    /// Backend used for secrets without a route
+    default: Box<dyn SecretManager + Send + Sync>,
+    /// Backends of the files overriding the backend
+    backends: Vec<Box<dyn SecretManager + Send + Sync>>,
+    /// Index into `backends` for each routed secret by name
+    routes: HashMap<String, usize>,
+}
+
+impl RoutedSecretManager {
+    /// Create a secret manager routing the secrets listed alongside each
+    /// of the `backends` to that backend, the `default` backend is used
+    /// for all other secrets
+    ///
+    /// Fails when a secret is routed to more than one backend
+    pub fn new(
+        default: Box<dyn SecretManager + Send + Sync>,
+        backends: Vec<(Box<dyn SecretManager + Send + Sync>, Vec<String>)>,
+    ) -> Result<Self> {
+        let mut routes = HashMap::new();
+        let mut managers = Vec::with_capacity(backends.len());
+
+        for (index, (backend, secrets)) in backends.into_iter().enumerate() {
+            for secret in secrets {
+                if routes
+                    .insert(secret.clone(), index)
+                    .is_some_and(|existing| existing != index)
+                {
+                    return Err(SyncError::config(format!(
+                        "secret \"{secret}\" is used by files with different backends"
+                    )));
+                }
+            }
+
+            managers.push(backend);
+        }
+
+        Ok(Self {
+            default,
+            backends: managers,
+            routes,
+        })
+    }
+
+    /// Backend storing the secret `name`
+    fn backend(&self, name: &str) -> &(dyn SecretManager + Send + Sync) {
+        match self.routes.get(name) {
+            Some(index) => self.backends[*index].as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+
+    /// Every backend, starting with the default backend
+    fn all_backends(&self) -> impl Iterator<Item = &(dyn SecretManager + Send + Sync)> {
+        std::iter::once(self.default.as_ref())
+            .chain(self.backends.iter().map(|backend| backend.as_ref()))
+    }
+}
+
+#[async_trait]
+impl SecretManager for RoutedSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        self.backend(name).get_secret(name).await
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        self.backend(name).find_secret(name).await
+    }
+
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
+        self.backend(name).set_secret(name, value, metadata).await
+    }
+
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        self.backend(name)
+            .create_secret(name, value, metadata)
+            .await
+    }
+
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        self.backend(name).delete_secret(name).await
+    }
+
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        self.backend(name).tag_secret(name, tags).await
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        self.default.generate_secret(generator).await
+    }
+
+    async fn grant_secret_access(&self, name: &str, ttl: Duration) -> Result<SecretGrant> {
+        self.backend(name).grant_secret_access(name, ttl).await
+    }
+
+    async fn verify_access(&self) -> Result<()> {
+        for backend in self.all_backends() {
+            backend.verify_access().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn warm_connections(&self, connections: usize) {
+        for backend in self.all_backends() {
+            backend.warm_connections(connections).await;
+        }
+    }
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        self.default.diagnose_credentials().await
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        // The top-level backend identifies the user running secret-sync
+        self.default.caller_identity().await
+    }
+
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        self.backend(name).describe_secret(name).await
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        let streams: Vec<_> = self
+            .all_backends()
+            .map(|backend| backend.list_secrets(options.clone()))
+            .collect();
+
+        stream::iter(streams).flatten().boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{MemoryConfig, SecretMetadata},
+        secret::{
+            ListSecretsOptions, Secret, SecretManager, memory::MemorySecretManager,
+            route::RoutedSecretManager,
+        },
+    };
+    use futures_util::TryStreamExt;
+
+    /// Tests that routed secrets are stored in their own backend while
+    /// other secrets use the default backend
+    #[tokio::test]
+    async fn test_routed_secret_manager() {
+        let directory = tempfile::tempdir().unwrap();
+        let default_path = directory.path().join("default.json");
+        let routed_path = directory.path().join("routed.json");
+        let memory = |path: &std::path::Path| -> Box<dyn SecretManager + Send + Sync> {
+            Box::new(
+                MemorySecretManager::from_config(&MemoryConfig {
+                    path: Some(path.to_path_buf()),
+                })
+                .unwrap(),
+            )
+        };
+
+        let secret = RoutedSecretManager::new(
+            memory(&default_path),
+            vec![(memory(&routed_path), vec!["/app/db".to_string()])],
+        )
+        .unwrap();
+
+        let metadata = SecretMetadata::default();
+        for name in ["app", "/app/db"] {
+            secret
+                .set_secret(name, Secret::String(name.to_string()), &metadata)
+                .await
+                .unwrap();
+        }
+
+        let routed = memory(&routed_path);
+        assert!(routed.find_secret("/app/db").await.unwrap().is_some());
+        assert!(routed.find_secret("app").await.unwrap().is_none());
+
+        let value = secret.get_secret("/app/db").await.unwrap();
+        assert_eq!(value.data, Secret::String("/app/db".to_string()));
+
+        let mut listed: Vec<String> = secret
+            .list_secrets(ListSecretsOptions::default())
+            .map_ok(|summary| summary.name)
+            .try_collect()
+            .await
+            .unwrap();
+        listed.sort();
+        assert_eq!(listed, vec!["/app/db".to_string(), "app".to_string()]);
+
+        // A secret can only be stored in a single backend
+        let conflict = RoutedSecretManager::new(
+            memory(&default_path),
+            vec![
+                (memory(&routed_path), vec!["shared".to_string()]),
+                (memory(&default_path), vec!["shared".to_string()]),
+            ],
+        );
+        assert!(conflict.is_err());
+    }
+}
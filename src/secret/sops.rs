@@ -0,0 +1,421 @@
+//! # SOPS
+//!
+//! Secret manager reading and writing the SOPS encrypted files of an
+//! existing SOPS repository through the `sops` executable, which handles
+//! the age, KMS, and PGP envelopes of the data keys. Secret names are the
+//! paths of the files relative to `sops.path` (e.g. `production/app.env`)
+//! and values are the decrypted contents of the files.
+//!
+//! New and updated files are encrypted using the creation rules of the
+//! `.sops.yaml` of the repository. SOPS files cannot carry tags, so every
+//! file is treated as managed by secret-sync
+
+use crate::{
+    config::{SecretGenerator, SecretMetadata, SopsConfig},
+    doctor::CredentialDiagnosis,
+    error::{Result, ResultExt, SyncError},
+    secret::{
+        ListSecretsOptions, MANAGED_BY_TAG, MANAGED_BY_VALUE, Secret, SecretGrant, SecretManager,
+        SecretSummary, SecretValue, list_secret_files, secret_file_path,
+    },
+};
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use indexmap::IndexMap;
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// Default name of the sops executable
+const DEFAULT_SOPS_COMMAND: &str = "sops";
+
+/// Marker present within every file encrypted by SOPS, regardless of the
+/// format of the file
+const SOPS_VALUE_MARKER: &[u8] = b"ENC[AES256_GCM,";
+
+/// Secret manager for the files of a SOPS repository
+pub struct SopsSecretManager {
+    /// Directory containing the SOPS files
+    path: PathBuf,
+    /// sops executable
+    command: String,
+}
+
+impl SopsSecretManager {
+    /// Create a [SopsSecretManager] from the provided `config`
+    pub fn from_config(config: &SopsConfig) -> Result<Self> {
+        let path = config
+            .path
+            .clone()
+            .ok_or_else(|| SyncError::config("sops backend requires sops.path to be set"))?;
+
+        Ok(Self {
+            path,
+            command: config
+                .command
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SOPS_COMMAND.to_string()),
+        })
+    }
+
+    /// Run sops with the `args`, writing the `input` to its stdin and
+    /// providing its stdout
+    fn run_sops(&self, args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>> {
+        let mut child = Command::new(&self.command)
+            .args(args)
+            .stdin(match input {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to execute \"{}\"", self.command))?;
+
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(input)
+                .context("failed to write input to sops")?;
+        }
+
+        let output = child
+            .wait_with_output()
+            .context("failed to wait for sops")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = format!("sops exited with {}: {}", output.status, stderr.trim());
+
+            // Failing to decrypt the data key means none of the available
+            // keys (age identities, KMS access, PGP keys) are usable
+            return Err(match stderr.contains("data key") {
+                true => SyncError::backend_auth(message),
+                false => SyncError::backend(message),
+            });
+        }
+
+        Ok(output.stdout)
+    }
+
+    /// Read a summary of the file storing the secret `name`, providing
+    /// [None] when it does not exist
+    fn summary(&self, name: &str) -> Result<Option<SecretSummary>> {
+        let path = secret_file_path(&self.path, name, "sops")?;
+        let metadata = match std::fs::metadata(&path) {
+            Ok(value) => value,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read sops file \"{}\"", path.display()));
+            }
+        };
+
+        Ok(Some(SecretSummary {
+            name: name.to_string(),
+            tags: IndexMap::from([(MANAGED_BY_TAG.to_string(), MANAGED_BY_VALUE.to_string())]),
+            size_hint: Some(metadata.len()),
+            ..Default::default()
+        }))
+    }
+
+    /// Encrypt and write the `value` of the secret `name`, failing with
+    /// [SyncError::Conflict] when `exclusive` is set and the secret already
+    /// exists
+    fn write_secret(&self, name: &str, value: Secret, exclusive: bool) -> Result<()> {
+        let path = secret_file_path(&self.path, name, "sops")?;
+        let format = sops_format(&path);
+        let path_arg = path.to_string_lossy();
+
+        // The path selects the creation rule of the .sops.yaml to encrypt with
+        let encrypted = self
+            .run_sops(
+                &[
+                    "encrypt",
+                    "--filename-override",
+                    &path_arg,
+                    "--input-type",
+                    format,
+                    "--output-type",
+                    format,
+                ],
+                Some(value.as_bytes()),
+            )
+            .with_context(|| format!("failed to encrypt secret \"{name}\""))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create sops directory \"{}\"", parent.display())
+            })?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .create_new(exclusive)
+            .open(&path);
+
+        match file {
+            Ok(mut file) => file.write_all(&encrypted),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(SyncError::conflict(format!(
+                    "secret \"{name}\" already exists"
+                )));
+            }
+            Err(error) => Err(error),
+        }
+        .with_context(|| format!("failed to write sops file \"{}\"", path.display()))
+    }
+}
+
+/// Format sops reads and writes the file at `path` as, determined by the
+/// file name the same way sops does
+fn sops_format(path: &Path) -> &'static str {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    if name.ends_with(".yaml") || name.ends_with(".yml") {
+        "yaml"
+    } else if name.ends_with(".json") {
+        "json"
+    } else if name.ends_with(".env") {
+        "dotenv"
+    } else if name.ends_with(".ini") {
+        "ini"
+    } else {
+        "binary"
+    }
+}
+
+/// Whether the `value` of a file was encrypted by SOPS
+fn is_sops_file(value: &[u8]) -> bool {
+    value
+        .windows(SOPS_VALUE_MARKER.len())
+        .any(|window| window == SOPS_VALUE_MARKER)
+}
+
+#[async_trait]
+impl SecretManager for SopsSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        self.find_secret(name)
+            .await?
+            .map(SecretValue::from)
+            .ok_or_else(|| SyncError::not_found(format!("secret \"{name}\" does not exist")))
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        let path = secret_file_path(&self.path, name, "sops")?;
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let format = sops_format(&path);
+        let path_arg = path.to_string_lossy();
+        let value = self
+            .run_sops(
+                &[
+                    "decrypt",
+                    "--input-type",
+                    format,
+                    "--output-type",
+                    format,
+                    &path_arg,
+                ],
+                None,
+            )
+            .with_context(|| format!("failed to decrypt secret \"{name}\""))?;
+
+        Ok(Some(match String::from_utf8(value) {
+            Ok(value) => Secret::String(value),
+            Err(error) => Secret::Binary(error.into_bytes()),
+        }))
+    }
+
+    async fn set_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        _metadata: &SecretMetadata,
+    ) -> Result<()> {
+        self.write_secret(name, value, false)
+    }
+
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        _metadata: &SecretMetadata,
+    ) -> Result<()> {
+        self.write_secret(name, value, true)
+    }
+
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        let path = secret_file_path(&self.path, name, "sops")?;
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Err(
+                SyncError::not_found(format!("secret \"{name}\" does not exist")),
+            ),
+            Err(error) => Err(error)
+                .with_context(|| format!("failed to delete sops file \"{}\"", path.display())),
+        }
+    }
+
+    async fn tag_secret(&self, _name: &str, _tags: &IndexMap<String, String>) -> Result<()> {
+        Err(SyncError::config(
+            "tags are not supported by the sops backend",
+        ))
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        Err(SyncError::config(format!(
+            "generator \"{generator}\" is not supported by the sops backend"
+        )))
+    }
+
+    async fn grant_secret_access(&self, _name: &str, _ttl: Duration) -> Result<SecretGrant> {
+        Err(SyncError::config(
+            "temporary access grants are not supported by the sops backend",
+        ))
+    }
+
+    async fn verify_access(&self) -> Result<()> {
+        self.run_sops(&["--version"], None)
+            .map(|_| ())
+            .with_context(|| format!("sops executable \"{}\" is not usable", self.command))
+    }
+
+    async fn warm_connections(&self, _connections: usize) {}
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        CredentialDiagnosis {
+            source: Some(format!(
+                "keys available to the \"{}\" executable (age, KMS, or PGP)",
+                self.command
+            )),
+            ..Default::default()
+        }
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        self.summary(name)
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        let summaries = list_secret_files(&self.path).and_then(|files| {
+            let mut summaries = Vec::new();
+            for (path, name) in files {
+                // Skip the .sops.yaml and any other unencrypted files
+                let value = std::fs::read(&path)
+                    .with_context(|| format!("failed to read sops file \"{}\"", path.display()))?;
+                if !is_sops_file(&value) {
+                    continue;
+                }
+
+                let Some(summary) = self.summary(&name)? else {
+                    continue;
+                };
+
+                let matches = options
+                    .tags
+                    .iter()
+                    .all(|(key, value)| summary.tags.get(key) == Some(value));
+
+                if matches {
+                    summaries.push(summary);
+                }
+            }
+            Ok(summaries)
+        });
+
+        match summaries {
+            Ok(summaries) => stream::iter(summaries.into_iter().map(Ok)).boxed(),
+            Err(error) => stream::once(async move { Err(error) }).boxed(),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::{
+        config::{SecretMetadata, SopsConfig},
+        secret::{ListSecretsOptions, Secret, SecretManager, sops::SopsSecretManager},
+    };
+    use futures_util::TryStreamExt;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Tests that files are encrypted and decrypted through the sops
+    /// executable using the format of the file, using a stand-in for sops
+    /// that records its arguments and wraps the value in a fake envelope
+    #[tokio::test]
+    async fn test_sops_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let command = directory.path().join("sops");
+        let args = directory.path().join("args");
+        std::fs::write(
+            &command,
+            format!(
+                "#!/bin/sh\n\
+                 echo \"$@\" >> {args}\n\
+                 case \"$1\" in\n\
+                 encrypt) printf 'ENC[AES256_GCM,'; cat ;;\n\
+                 decrypt) for last; do :; done; tail -c +16 \"$last\" ;;\n\
+                 esac\n",
+                args = args.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path = directory.path().join("repo");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join(".sops.yaml"), "creation_rules: []\n").unwrap();
+
+        let secret = SopsSecretManager::from_config(&SopsConfig {
+            path: Some(path.clone()),
+            command: Some(command.display().to_string()),
+        })
+        .unwrap();
+
+        secret
+            .set_secret(
+                "production/app.env",
+                Secret::String("A=1\n".to_string()),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+
+        let value = secret.get_secret("production/app.env").await.unwrap();
+        assert_eq!(value.data.as_bytes(), b"A=1\n");
+        assert!(secret.find_secret("missing.yaml").await.unwrap().is_none());
+
+        let args = std::fs::read_to_string(&args).unwrap();
+        assert!(args.contains("encrypt --filename-override"));
+        assert!(args.contains("--input-type dotenv --output-type dotenv"));
+
+        let listed: Vec<String> = secret
+            .list_secrets(ListSecretsOptions::default())
+            .map_ok(|summary| summary.name)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(listed, vec!["production/app.env".to_string()]);
+
+        let summary = secret.describe_secret("production/app.env").await.unwrap();
+        assert!(summary.unwrap().is_managed());
+    }
+}
@@ -0,0 +1,653 @@
+//! # SSM
+//!
+//! Secret manager backed by AWS Systems Manager Parameter Store, storing
+//! each secret as a SecureString parameter under an optional path prefix.
+//!
+//! Credentials and the region are resolved from the `[aws]` config the
+//! same way as the AWS Secrets Manager backend. Parameters only hold text,
+//! so binary values are stored base64 encoded behind [BINARY_PREFIX]
+
+use crate::{
+    config::{
+        AwsConfig, CredentialsConfig, NetworkConfig, SecretGenerator, SecretMetadata, SsmConfig,
+        SsmTier,
+    },
+    doctor::CredentialDiagnosis,
+    error::{Result, ResultExt, SyncError},
+    secret::{
+        ListSecretsOptions, MANAGED_BY_TAG, MANAGED_BY_VALUE, Secret, SecretGrant, SecretManager,
+        SecretSummary, SecretValue,
+        aws::{
+            caller_arn, diagnose_credentials_provider, format_date, load_sdk_config, request_error,
+        },
+    },
+};
+use async_trait::async_trait;
+use aws_sdk_secretsmanager::config::SharedCredentialsProvider;
+use aws_sdk_ssm::operation::put_parameter::builders::PutParameterFluentBuilder;
+use aws_sdk_ssm::types::{
+    ParameterMetadata, ParameterStringFilter, ParameterTier, ParameterType, ResourceTypeForTagging,
+    Tag,
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use futures_util::{
+    StreamExt, TryStreamExt,
+    stream::{self, BoxStream},
+};
+use indexmap::IndexMap;
+use std::time::Duration;
+
+/// Prefix of values holding base64 encoded binary data
+pub const BINARY_PREFIX: &str = "secret-sync:base64:";
+
+/// Largest page size Parameter Store allows when describing parameters
+const MAX_PAGE_SIZE: i32 = 50;
+
+/// Secret manager backed by AWS SSM Parameter Store
+pub struct SsmSecretManager {
+    client: aws_sdk_ssm::Client,
+    sts: aws_sdk_sts::Client,
+    credentials_provider: Option<SharedCredentialsProvider>,
+    region: String,
+    /// Normalized path prefix, starting with "/" and without a trailing "/"
+    prefix: Option<String>,
+    tier: Option<SsmTier>,
+    kms_key_id: Option<String>,
+}
+
+impl SsmSecretManager {
+    /// Create a [SsmSecretManager] from the provided `config`
+    ///
+    /// Credentials are resolved from the `aws` config using
+    /// [load_sdk_config]
+    pub async fn from_config(
+        config: &SsmConfig,
+        aws: &AwsConfig,
+        credentials_config: &CredentialsConfig,
+        network: &NetworkConfig,
+    ) -> Result<Self> {
+        let (sdk_config, region) = load_sdk_config(aws, credentials_config, network, None).await?;
+
+        let prefix = config
+            .prefix
+            .as_deref()
+            .map(|prefix| prefix.trim_matches('/'))
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| format!("/{prefix}"));
+        if let Some(prefix) = &prefix {
+            validate_parameter_name(prefix)?;
+        }
+
+        Ok(Self {
+            client: aws_sdk_ssm::Client::new(&sdk_config),
+            sts: aws_sdk_sts::Client::new(&sdk_config),
+            credentials_provider: sdk_config.credentials_provider(),
+            region,
+            prefix,
+            tier: config.tier,
+            kms_key_id: config.kms_key_id.clone(),
+        })
+    }
+
+    /// Name of the parameter the secret `name` is stored in
+    fn parameter_name(&self, name: &str) -> Result<String> {
+        let parameter = parameter_name(self.prefix.as_deref(), name);
+        validate_parameter_name(&parameter)?;
+        Ok(parameter)
+    }
+
+    /// Request creating the `parameter` with the encoded `value`, created
+    /// parameters are marked as managed unless the marker is overridden
+    fn create_parameter_request(
+        &self,
+        parameter: &str,
+        value: &str,
+        metadata: &SecretMetadata,
+    ) -> Result<PutParameterFluentBuilder> {
+        let mut tags = metadata.tags.clone().unwrap_or_default();
+        tags.entry(MANAGED_BY_TAG.to_string())
+            .or_insert_with(|| MANAGED_BY_VALUE.to_string());
+
+        Ok(self
+            .client
+            .put_parameter()
+            .name(parameter)
+            .value(value)
+            .r#type(ParameterType::SecureString)
+            .set_key_id(self.kms_key_id.clone())
+            .set_tier(self.tier.map(parameter_tier))
+            .set_description(metadata.description.clone())
+            .set_tags(Some(ssm_tags(&tags)?)))
+    }
+
+    /// Find the current value of the secret `name` along with its version,
+    /// providing [None] when the secret does not exist
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "ssm", region = %self.region))]
+    async fn find_secret_value(&self, name: &str) -> Result<Option<SecretValue>> {
+        let parameter = self.parameter_name(name)?;
+
+        let result = match self
+            .client
+            .get_parameter()
+            .name(&parameter)
+            .with_decryption(true)
+            .send()
+            .await
+        {
+            Ok(value) => value,
+            Err(error) => {
+                if error
+                    .as_service_error()
+                    .is_some_and(|value| value.is_parameter_not_found())
+                {
+                    return Ok(None);
+                }
+
+                tracing::error!(?error, "failed to get parameter");
+                return Err(request_error(error));
+            }
+        };
+
+        let parameter = result
+            .parameter
+            .ok_or_else(|| SyncError::backend(format!("no parameter found for \"{name}\"")))?;
+
+        Ok(Some(SecretValue {
+            data: decode_value(parameter.value().unwrap_or_default())?,
+            version_id: Some(parameter.version().to_string()),
+            arn: parameter.arn().map(ToString::to_string),
+            created: parameter.last_modified_date().and_then(format_date),
+        }))
+    }
+}
+
+/// Name of the parameter the secret `name` is stored in under the
+/// `prefix`, parameter names containing a "/" must start with one
+fn parameter_name(prefix: Option<&str>, name: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}/{}", name.trim_start_matches('/')),
+        None if name.contains('/') && !name.starts_with('/') => format!("/{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// Name of the secret stored in the `parameter` under the `prefix`, the
+/// reverse of [parameter_name]
+fn secret_name(prefix: Option<&str>, parameter: &str) -> String {
+    let name = match prefix {
+        Some(prefix) => parameter
+            .strip_prefix(prefix)
+            .and_then(|name| name.strip_prefix('/'))
+            .unwrap_or(parameter),
+        None => parameter,
+    };
+
+    // Leading slashes are added for hierarchical names so are not part of
+    // the secret name
+    match name.strip_prefix('/') {
+        Some(stripped) if prefix.is_none() && stripped.contains('/') => stripped.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// Ensure the `parameter` is a valid Parameter Store parameter name
+fn validate_parameter_name(parameter: &str) -> Result<()> {
+    let valid = !parameter.is_empty()
+        && parameter
+            .chars()
+            .all(|value| value.is_ascii_alphanumeric() || matches!(value, '_' | '.' | '-' | '/'));
+
+    if !valid {
+        return Err(SyncError::validation(format!(
+            "\"{parameter}\" is not a valid SSM parameter name, names may only contain letters, numbers, and the symbols _ . - /"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Encode the `value` as parameter text
+fn encode_value(value: Secret) -> String {
+    match value {
+        Secret::String(value) => value,
+        Secret::Binary(value) => format!("{BINARY_PREFIX}{}", STANDARD.encode(value)),
+    }
+}
+
+/// Decode the parameter text `value`
+fn decode_value(value: &str) -> Result<Secret> {
+    match value.strip_prefix(BINARY_PREFIX) {
+        Some(encoded) => STANDARD
+            .decode(encoded)
+            .map(Secret::Binary)
+            .map_err(|error| {
+                SyncError::backend("binary parameter value is not valid base64").with_source(error)
+            }),
+        None => Ok(Secret::String(value.to_string())),
+    }
+}
+
+/// Convert the `tags` into SSM tags
+fn ssm_tags(tags: &IndexMap<String, String>) -> Result<Vec<Tag>> {
+    tags.iter()
+        .map(|(key, value)| {
+            Tag::builder()
+                .key(key)
+                .value(value)
+                .build()
+                .map_err(|error| SyncError::validation("invalid tag").with_source(error))
+        })
+        .collect()
+}
+
+/// Convert the `tier` into the SSM parameter tier
+fn parameter_tier(tier: SsmTier) -> ParameterTier {
+    match tier {
+        SsmTier::Standard => ParameterTier::Standard,
+        SsmTier::Advanced => ParameterTier::Advanced,
+        SsmTier::IntelligentTiering => ParameterTier::IntelligentTiering,
+    }
+}
+
+/// Tags attached to the `parameter`
+async fn parameter_tags(
+    client: &aws_sdk_ssm::Client,
+    parameter: &str,
+) -> Result<IndexMap<String, String>> {
+    let result = client
+        .list_tags_for_resource()
+        .resource_type(ResourceTypeForTagging::Parameter)
+        .resource_id(parameter)
+        .send()
+        .await
+        .map_err(request_error)
+        .with_context(|| format!("failed to get tags of parameter \"{parameter}\""))?;
+
+    Ok(result
+        .tag_list()
+        .iter()
+        .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+        .collect())
+}
+
+/// Create the summary of the parameter `metadata` with its `tags`
+fn parameter_summary(
+    prefix: Option<&str>,
+    metadata: &ParameterMetadata,
+    tags: IndexMap<String, String>,
+) -> SecretSummary {
+    SecretSummary {
+        name: secret_name(prefix, metadata.name().unwrap_or_default()),
+        description: metadata.description().map(ToString::to_string),
+        tags,
+        created: None,
+        updated: metadata.last_modified_date().and_then(format_date),
+        version_count: usize::try_from(metadata.version()).ok(),
+        size_hint: None,
+    }
+}
+
+#[async_trait]
+impl SecretManager for SsmSecretManager {
+    async fn get_secret(&self, name: &str) -> Result<SecretValue> {
+        match self.find_secret_value(name).await? {
+            Some(value) => Ok(value),
+            None => Err(SyncError::not_found(format!("secret \"{name}\" not found"))),
+        }
+    }
+
+    async fn find_secret(&self, name: &str) -> Result<Option<Secret>> {
+        Ok(self.find_secret_value(name).await?.map(|value| value.data))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "ssm", region = %self.region))]
+    async fn set_secret(&self, name: &str, value: Secret, metadata: &SecretMetadata) -> Result<()> {
+        let parameter = self.parameter_name(name)?;
+        let value = encode_value(value);
+
+        // Tags can only be set when creating a parameter, so the parameter
+        // is created first and overwritten when it already exists
+        let error = match self
+            .create_parameter_request(&parameter, &value, metadata)?
+            .send()
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(error) => error,
+        };
+
+        if error
+            .as_service_error()
+            .is_some_and(|value| value.is_parameter_already_exists())
+        {
+            tracing::debug!("parameter already exists, overwriting parameter");
+
+            self.client
+                .put_parameter()
+                .name(&parameter)
+                .value(value)
+                .r#type(ParameterType::SecureString)
+                .set_key_id(self.kms_key_id.clone())
+                .set_tier(self.tier.map(parameter_tier))
+                // Parameter history keeps the description of each version
+                .set_description(metadata.version_comment.clone())
+                .overwrite(true)
+                .send()
+                .await
+                .inspect_err(|error| {
+                    tracing::error!(?error, "failed to overwrite parameter");
+                })
+                .map_err(request_error)
+                .context("failed to overwrite parameter")?;
+
+            return Ok(());
+        }
+
+        tracing::error!(?error, "failed to create parameter");
+        Err(request_error(error))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "ssm", region = %self.region))]
+    async fn create_secret(
+        &self,
+        name: &str,
+        value: Secret,
+        metadata: &SecretMetadata,
+    ) -> Result<()> {
+        let parameter = self.parameter_name(name)?;
+        let value = encode_value(value);
+
+        self.create_parameter_request(&parameter, &value, metadata)?
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::debug!(?error, "failed to create parameter");
+            })
+            .map_err(request_error)
+            .context("failed to create parameter")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "ssm", region = %self.region))]
+    async fn delete_secret(&self, name: &str) -> Result<()> {
+        let parameter = self.parameter_name(name)?;
+
+        self.client
+            .delete_parameter()
+            .name(parameter)
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to delete parameter");
+            })
+            .map_err(request_error)
+            .context("failed to delete parameter")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "ssm", region = %self.region))]
+    async fn tag_secret(&self, name: &str, tags: &IndexMap<String, String>) -> Result<()> {
+        let parameter = self.parameter_name(name)?;
+
+        self.client
+            .add_tags_to_resource()
+            .resource_type(ResourceTypeForTagging::Parameter)
+            .resource_id(parameter)
+            .set_tags(Some(ssm_tags(tags)?))
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to tag parameter");
+            })
+            .map_err(request_error)
+            .context("failed to tag parameter")?;
+
+        Ok(())
+    }
+
+    async fn generate_secret(&self, generator: &SecretGenerator) -> Result<Secret> {
+        Err(SyncError::config(format!(
+            "generator \"{generator}\" is not supported by the ssm backend"
+        )))
+    }
+
+    async fn grant_secret_access(&self, _name: &str, _ttl: Duration) -> Result<SecretGrant> {
+        Err(SyncError::config(
+            "temporary access grants are not supported by the ssm backend",
+        ))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(backend = "ssm", region = %self.region))]
+    async fn verify_access(&self) -> Result<()> {
+        self.client
+            .describe_parameters()
+            .max_results(1)
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to describe parameters");
+            })
+            .map_err(request_error)
+            .context("failed to verify AWS credentials")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(connections, backend = "ssm", region = %self.region))]
+    async fn warm_connections(&self, connections: usize) {
+        // Concurrent requests each open their own connection, which are
+        // then pooled for the batch. Failures still establish the connection
+        let requests =
+            (0..connections).map(|_| self.client.describe_parameters().max_results(1).send());
+        let failed = futures_util::future::join_all(requests)
+            .await
+            .into_iter()
+            .filter(|result| result.is_err())
+            .count();
+
+        tracing::debug!(failed, "warmed backend connections");
+    }
+
+    async fn diagnose_credentials(&self) -> CredentialDiagnosis {
+        diagnose_credentials_provider(self.credentials_provider.as_ref()).await
+    }
+
+    async fn caller_identity(&self) -> Result<Option<String>> {
+        caller_arn(&self.sts).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(secret = %name, backend = "ssm", region = %self.region))]
+    async fn describe_secret(&self, name: &str) -> Result<Option<SecretSummary>> {
+        let parameter = self.parameter_name(name)?;
+
+        let filter = ParameterStringFilter::builder()
+            .key("Name")
+            .option("Equals")
+            .values(&parameter)
+            .build()
+            .map_err(|error| {
+                SyncError::validation("invalid parameter filter").with_source(error)
+            })?;
+
+        let result = self
+            .client
+            .describe_parameters()
+            .parameter_filters(filter)
+            .send()
+            .await
+            .inspect_err(|error| {
+                tracing::error!(?error, "failed to describe parameter");
+            })
+            .map_err(request_error)?;
+
+        let Some(metadata) = result.parameters().first() else {
+            return Ok(None);
+        };
+
+        let tags = parameter_tags(&self.client, &parameter).await?;
+        Ok(Some(parameter_summary(
+            self.prefix.as_deref(),
+            metadata,
+            tags,
+        )))
+    }
+
+    fn list_secrets(
+        &self,
+        options: ListSecretsOptions,
+    ) -> BoxStream<'static, Result<SecretSummary>> {
+        let mut filters = Vec::new();
+        if let Some(prefix) = &self.prefix {
+            filters.push(
+                ParameterStringFilter::builder()
+                    .key("Path")
+                    .option("Recursive")
+                    .values(prefix)
+                    .build(),
+            );
+        }
+        for (key, value) in &options.tags {
+            filters.push(
+                ParameterStringFilter::builder()
+                    .key(format!("tag:{key}"))
+                    .option("Equals")
+                    .values(value)
+                    .build(),
+            );
+        }
+
+        let filters = match filters
+            .into_iter()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|error| SyncError::validation("invalid parameter filter").with_source(error))
+        {
+            Ok(filters) => filters,
+            Err(error) => return stream::once(async move { Err(error) }).boxed(),
+        };
+
+        let client = self.client.clone();
+        let prefix = self.prefix.clone();
+
+        let pages = stream::try_unfold(ListPage::First, move |page| {
+            let client = client.clone();
+            let prefix = prefix.clone();
+            let filters = filters.clone();
+            let options = options.clone();
+
+            async move {
+                let next_token = match page {
+                    ListPage::First => None,
+                    ListPage::Next(token) => Some(token),
+                    ListPage::Done => return Ok(None),
+                };
+
+                let result = client
+                    .describe_parameters()
+                    .set_parameter_filters(Some(filters))
+                    .set_max_results(
+                        options
+                            .page_size
+                            .map(|page_size| page_size.clamp(1, MAX_PAGE_SIZE)),
+                    )
+                    .set_next_token(next_token)
+                    .send()
+                    .await
+                    .inspect_err(|error| {
+                        tracing::error!(?error, "failed to list parameters");
+                    })
+                    .map_err(request_error)
+                    .context("failed to list parameters")?;
+
+                // Tags are not included when describing parameters so they
+                // are requested for each parameter of the page
+                let tags =
+                    futures_util::future::try_join_all(result.parameters().iter().map(
+                        |metadata| parameter_tags(&client, metadata.name().unwrap_or_default()),
+                    ))
+                    .await?;
+
+                let summaries = result
+                    .parameters()
+                    .iter()
+                    .zip(tags)
+                    .map(|(metadata, tags)| parameter_summary(prefix.as_deref(), metadata, tags))
+                    .filter(|summary| {
+                        options
+                            .tags
+                            .iter()
+                            .all(|(key, value)| summary.tags.get(key) == Some(value))
+                    })
+                    .collect::<Vec<_>>();
+
+                let page = match result.next_token {
+                    Some(token) => ListPage::Next(token),
+                    None => ListPage::Done,
+                };
+
+                Ok::<_, SyncError>(Some((summaries, page)))
+            }
+        });
+
+        pages
+            .map_ok(|summaries| stream::iter(summaries.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+}
+
+/// Position within the pages of a parameter listing
+enum ListPage {
+    /// First page has not been requested
+    First,
+    /// Next page to request
+    Next(String),
+    /// No pages remain
+    Done,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::secret::{
+        Secret,
+        ssm::{decode_value, encode_value, parameter_name, secret_name, validate_parameter_name},
+    };
+
+    /// Tests that secret names map to parameter names under the prefix and
+    /// back again
+    #[test]
+    fn test_parameter_name() {
+        assert_eq!(parameter_name(Some("/app"), "prod-env"), "/app/prod-env");
+        assert_eq!(parameter_name(Some("/app"), "prod/env"), "/app/prod/env");
+        assert_eq!(parameter_name(None, "prod-env"), "prod-env");
+        assert_eq!(parameter_name(None, "app/prod/env"), "/app/prod/env");
+
+        for (prefix, name) in [
+            (Some("/app"), "prod-env"),
+            (Some("/app"), "prod/env"),
+            (None, "prod-env"),
+            (None, "app/prod/env"),
+        ] {
+            assert_eq!(secret_name(prefix, &parameter_name(prefix, name)), name);
+        }
+
+        assert!(validate_parameter_name("/app/prod.env_1").is_ok());
+        assert!(validate_parameter_name("/app/prod env").is_err());
+        assert!(validate_parameter_name("").is_err());
+    }
+
+    /// Tests that binary values survive being stored as parameter text
+    #[test]
+    fn test_encode_value() {
+        let binary = Secret::Binary(vec![0, 159, 1]);
+        let encoded = encode_value(binary.clone());
+        assert!(encoded.starts_with("secret-sync:base64:"));
+        assert_eq!(decode_value(&encoded).unwrap(), binary);
+
+        assert_eq!(
+            decode_value("A=1").unwrap(),
+            Secret::String("A=1".to_string())
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! # Seed
+//!
+//! Bulk creation of secrets from a directory of files, used when migrating
+//! an existing set of secret files into the backend for the first time.
+//!
+//! Creation is throttled to stay within backend rate limits and progress is
+//! recorded in a state file after each secret, so an interrupted seed can
+//! be re-run and only the remaining (or modified) files are uploaded
+
+use crate::state::create_state_dir;
+use eyre::Context;
+use indexmap::IndexMap;
+use secret_sync::{
+    config::{
+        ENV_PLACEHOLDER, NAME_PLACEHOLDER, SECRET_PLACEHOLDER, SecretMetadata, TenancyConfig,
+    },
+    push::check_secret_ownership,
+    secret::{Secret, SecretManager},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Component, Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Placeholder replaced with the relative path of the file without its
+/// extension (e.g. "app/db" for "app/db.env")
+const PATH_PLACEHOLDER: &str = "{path}";
+
+/// Placeholder replaced with the file name including its extension
+const FILE_PLACEHOLDER: &str = "{file}";
+
+/// Progress of previous seeds, mapping the created secret names to the hash
+/// of the value they were seeded with
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SeedState {
+    /// Secrets created by previous seeds
+    pub completed: IndexMap<String, String>,
+}
+
+/// Options for a seed
+pub struct SeedOptions<'a> {
+    /// Directory containing the files to seed
+    pub root: &'a Path,
+    /// Template for the secret names
+    pub template: &'a str,
+    /// Environment replacing {env} within the template
+    pub environment: Option<&'a str>,
+    /// Maximum number of secrets to write per second
+    pub rate: u32,
+    /// Metadata used when creating the secrets
+    pub metadata: &'a SecretMetadata,
+    /// Tenancy the secret names must be within
+    pub tenancy: &'a TenancyConfig,
+    /// Take ownership of previously seeded secrets that are no longer
+    /// marked as created by secret-sync instead of refusing to update them
+    pub adopt: bool,
+}
+
+/// Outcome of a seed
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct SeedSummary {
+    /// Secrets created or updated by this seed
+    pub created: Vec<String>,
+    /// Secrets that already existed and were not created by a seed
+    pub existing: Vec<String>,
+    /// Secrets already seeded with the current value
+    pub skipped: usize,
+}
+
+/// Read the seed progress from `path`, providing empty progress when no
+/// seed has been run
+async fn read_seed_state(path: &Path) -> eyre::Result<SeedState> {
+    match tokio::fs::read(path).await {
+        Ok(value) => serde_json::from_slice(&value).context("failed to parse seed state"),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(SeedState::default()),
+        Err(error) => Err(error).context("failed to read seed state"),
+    }
+}
+
+/// Write the seed progress to `path`
+async fn write_seed_state(path: &Path, state: &SeedState) -> eyre::Result<()> {
+    if let Some(parent) = path.parent() {
+        create_state_dir(parent).await?;
+    }
+
+    let value = serde_json::to_vec_pretty(state)?;
+    tokio::fs::write(path, value)
+        .await
+        .context("failed to write seed state")
+}
+
+/// List the files within `root` relative to it in a stable order, hidden
+/// directories are skipped
+fn list_seed_files(root: &Path) -> eyre::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![PathBuf::new()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = std::fs::read_dir(root.join(&directory))
+            .with_context(|| format!("failed to read directory \"{}\"", directory.display()))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let path = directory.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    directories.push(path);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Create the secret name for the file at the `relative` path using the
+/// name `template`
+pub fn seed_secret_name(
+    template: &str,
+    relative: &Path,
+    environment: Option<&str>,
+) -> eyre::Result<String> {
+    let components: Vec<String> = relative
+        .with_extension("")
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(value) => Some(value.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+
+    let file = relative
+        .file_name()
+        .map(|value| value.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut name = template
+        .replace(PATH_PLACEHOLDER, &components.join("/"))
+        .replace(FILE_PLACEHOLDER, &file);
+
+    if name.contains(ENV_PLACEHOLDER) {
+        let Some(environment) = environment else {
+            eyre::bail!(
+                "seed name template uses {ENV_PLACEHOLDER} but no environment was provided, use --env"
+            );
+        };
+
+        name = name.replace(ENV_PLACEHOLDER, environment);
+    }
+
+    Ok(name)
+}
+
+/// Create a secret for each file within the seed root, recording progress
+/// in the state file at `state_path` after each secret
+///
+/// Secrets that already exist are left untouched unless they were created
+/// by a previous seed, in which case they are updated when the file changed
+pub async fn seed_files(
+    secret: &dyn SecretManager,
+    options: &SeedOptions<'_>,
+    state_path: &Path,
+) -> eyre::Result<SeedSummary> {
+    let mut state = read_seed_state(state_path).await?;
+    let mut summary = SeedSummary::default();
+
+    let delay = Duration::from_secs(1) / options.rate.max(1);
+    let mut last_write: Option<Instant> = None;
+
+    for relative in list_seed_files(options.root)? {
+        let name = seed_secret_name(options.template, &relative, options.environment)?;
+        options.tenancy.check_secret(&name)?;
+
+        let path = options.root.join(&relative);
+        let value = tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("failed to read \"{}\"", path.display()))?;
+        let value = Secret::from_bytes(value);
+        let hash = value.hash();
+
+        match state.completed.get(&name) {
+            Some(previous) if previous == &hash => {
+                summary.skipped += 1;
+                continue;
+            }
+            Some(_) => check_secret_ownership(secret, &[&name], options.adopt).await?,
+            None => {
+                if secret.find_secret(&name).await?.is_some() {
+                    tracing::debug!(%name, "secret already exists, skipping");
+                    summary.existing.push(name);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(remaining) = last_write.and_then(|last| delay.checked_sub(last.elapsed())) {
+            tokio::time::sleep(remaining).await;
+        }
+        last_write = Some(Instant::now());
+
+        let relative_name = relative.display().to_string();
+        let metadata = SecretMetadata {
+            description: options.metadata.description.as_ref().map(|description| {
+                description
+                    .replace(NAME_PLACEHOLDER, &relative_name)
+                    .replace(SECRET_PLACEHOLDER, &name)
+            }),
+            ..options.metadata.clone()
+        };
+
+        secret
+            .set_secret(&name, value, &metadata)
+            .await
+            .with_context(|| format!("failed to seed \"{name}\""))?;
+
+        tracing::info!(%name, file = %relative_name, "seeded secret");
+
+        state.completed.insert(name.clone(), hash);
+        write_seed_state(state_path, &state).await?;
+        summary.created.push(name);
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::seed::{SeedOptions, seed_files, seed_secret_name};
+    use mockall::predicate::{always, eq};
+    use secret_sync::{
+        config::{SecretMetadata, TenancyConfig},
+        error::SyncError,
+        secret::{MockSecretManager, Secret},
+    };
+    use std::path::Path;
+
+    /// Tests the placeholders of the name template
+    #[test]
+    fn test_seed_secret_name() {
+        let path = Path::new("app/db.env");
+
+        assert_eq!(seed_secret_name("{path}", path, None).unwrap(), "app/db");
+        assert_eq!(
+            seed_secret_name("{env}/{path}", path, Some("prod")).unwrap(),
+            "prod/app/db"
+        );
+        assert_eq!(
+            seed_secret_name("legacy/{file}", path, None).unwrap(),
+            "legacy/db.env"
+        );
+        assert!(seed_secret_name("{env}/{path}", path, None).is_err());
+    }
+
+    /// Tests that a re-run seed skips completed files and existing secrets
+    #[tokio::test]
+    async fn test_seed_files_resume() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().join("secrets");
+        std::fs::create_dir_all(root.join("app")).unwrap();
+        std::fs::write(root.join("app/db.env"), "PASSWORD=1").unwrap();
+        std::fs::write(root.join("app/cache.env"), "PASSWORD=2").unwrap();
+        std::fs::write(root.join("legacy.env"), "PASSWORD=3").unwrap();
+
+        let state_path = directory.path().join("seed.json");
+        let metadata = SecretMetadata::default();
+        let tenancy = TenancyConfig::default();
+        let options = SeedOptions {
+            root: &root,
+            template: "{path}",
+            environment: None,
+            rate: 1000,
+            metadata: &metadata,
+            tenancy: &tenancy,
+            adopt: false,
+        };
+
+        // First run is interrupted after creating "app/cache"
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("app/cache"))
+            .return_once(|_name| Ok(None));
+        secret
+            .expect_set_secret()
+            .with(eq("app/cache"), always(), always())
+            .return_once(|_name, _value, _metadata| Ok(()));
+        secret
+            .expect_find_secret()
+            .with(eq("app/db"))
+            .return_once(|_name| Ok(None));
+        secret
+            .expect_set_secret()
+            .with(eq("app/db"), always(), always())
+            .return_once(|_name, _value, _metadata| Err(SyncError::backend("interrupted")));
+
+        assert!(seed_files(&secret, &options, &state_path).await.is_err());
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_find_secret()
+            .with(eq("app/db"))
+            .return_once(|_name| Ok(None));
+        secret
+            .expect_set_secret()
+            .with(
+                eq("app/db"),
+                eq(Secret::String("PASSWORD=1".to_string())),
+                always(),
+            )
+            .return_once(|_name, _value, _metadata| Ok(()));
+        secret
+            .expect_find_secret()
+            .with(eq("legacy"))
+            .return_once(|_name| Ok(Some(Secret::String("PASSWORD=3".to_string()))));
+
+        let summary = seed_files(&secret, &options, &state_path).await.unwrap();
+        assert_eq!(summary.created, vec!["app/db".to_string()]);
+        assert_eq!(summary.existing, vec!["legacy".to_string()]);
+        assert_eq!(summary.skipped, 1);
+    }
+}
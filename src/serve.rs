@@ -0,0 +1,754 @@
+//! # Serve
+//!
+//! Small authenticated HTTP API exposing pull, push, and status operations
+//! for sidecars and internal tools, bound to a loopback address or a unix
+//! socket.
+//!
+//! Requests must provide the token from SECRET_SYNC_SERVE_TOKEN using the
+//! `Authorization: Bearer <token>` header. Connections are handled
+//! concurrently while the pull, push, and status operations wait for one
+//! another so they never overlap.
+//!
+//! - `GET /status` Plan of the actions a push would perform
+//! - `POST /pull` Pull the secret files, responds with the changed count
+//! - `POST /push` Push the secret files
+//!
+//! Pull and push accept an optional JSON body of `{"files": [], "globs": []}`
+//! to filter the files
+//!
+//! Ctrl-C cancels any in-progress pull or push before shutting down, the
+//! response then reports `"cancelled": true`
+
+use eyre::{Context, ContextCompat};
+use futures_util::{StreamExt, stream::FuturesUnordered};
+use secret_sync::{
+    cancel::CancellationToken,
+    clock::SystemClock,
+    config::{Config, filter_files},
+    fs::FileSystem,
+    plan::create_plan,
+    pull::{PullOptions, pull_secret_files},
+    push::{PushInfo, PushOptions, push_secret_files, skip_pull_only},
+    secret::SecretManager,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader,
+    },
+    sync::Mutex,
+};
+
+/// Environment variable containing the token required by clients
+pub const SERVE_TOKEN_ENV: &str = "SECRET_SYNC_SERVE_TOKEN";
+
+/// Maximum size of a request body
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Maximum length of the request line and of each header
+pub const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Maximum number of headers in a request
+const MAX_HEADERS: usize = 64;
+
+/// Maximum time allowed for a client to send its request
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Address the server listens on
+pub enum ServeListener {
+    /// Loopback TCP address
+    Tcp(SocketAddr),
+    /// Unix socket path
+    Unix(PathBuf),
+}
+
+/// State shared with each request
+pub struct ServeContext<'a, Fs: FileSystem> {
+    /// File system to sync files with
+    pub fs: &'a Fs,
+    /// Secret manager to sync secrets with
+    pub secret: &'a dyn SecretManager,
+    /// Loaded config
+    pub config: &'a Config,
+    /// Directory file paths are relative to
+    pub working_path: &'a Path,
+    /// Token clients must provide
+    pub token: &'a str,
+    /// Cancelled to stop the server, aborting any in-progress pull or push
+    pub cancel: &'a CancellationToken,
+}
+
+/// Parsed HTTP request
+#[derive(Debug)]
+struct HttpRequest {
+    /// Request method
+    method: String,
+    /// Request path excluding any query
+    path: String,
+    /// Value of the Authorization header
+    authorization: Option<String>,
+    /// Request body
+    body: Vec<u8>,
+}
+
+/// HTTP response to write
+#[derive(Debug)]
+struct HttpResponse {
+    /// Status code
+    status: u16,
+    /// JSON body
+    body: Value,
+}
+
+impl HttpResponse {
+    /// Create an error response with the `status` and `message`
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        HttpResponse {
+            status,
+            body: json!({ "success": false, "error": message.into() }),
+        }
+    }
+}
+
+/// Filter accepted in pull and push request bodies
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RequestFilter {
+    /// File names to match
+    files: Option<Vec<String>>,
+    /// Globs for file names to match
+    globs: Option<Vec<String>>,
+}
+
+/// Load the token clients are required to provide
+pub fn serve_token() -> eyre::Result<String> {
+    std::env::var(SERVE_TOKEN_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .with_context(|| format!("missing serve token, set the {SERVE_TOKEN_ENV} variable"))
+}
+
+/// Serve requests on the `listener` until interrupted
+pub async fn serve<Fs: FileSystem>(
+    context: &ServeContext<'_, Fs>,
+    listener: ServeListener,
+) -> eyre::Result<()> {
+    // Stopping the server aborts the batch of the request being handled
+    let cancel = context.cancel.clone();
+    let signal = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel.cancel();
+        }
+    });
+
+    let result = serve_listener(context, listener).await;
+    signal.abort();
+    result
+}
+
+/// Accept and handle connections from the `listener` until the server is
+/// cancelled
+///
+/// Connections are handled concurrently on the accepting task, as the file
+/// system futures are not required to be [Send], so a slow client never
+/// blocks other clients. Once cancelled the in-progress requests are
+/// allowed to respond before returning
+async fn serve_listener<Fs: FileSystem>(
+    context: &ServeContext<'_, Fs>,
+    listener: ServeListener,
+) -> eyre::Result<()> {
+    // Held while running an operation so operations never overlap
+    let operation = Mutex::new(());
+
+    match listener {
+        ServeListener::Tcp(address) => {
+            if !address.ip().is_loopback() {
+                eyre::bail!("refusing to listen on non-loopback address {address}");
+            }
+
+            let listener = tokio::net::TcpListener::bind(address)
+                .await
+                .with_context(|| format!("failed to listen on {address}"))?;
+
+            tracing::info!(%address, "serving requests");
+
+            let mut connections = FuturesUnordered::new();
+
+            loop {
+                tokio::select! {
+                    result = listener.accept() => {
+                        let (stream, _) = result.context("failed to accept connection")?;
+                        connections.push(handle_connection(context, &operation, stream));
+                    }
+                    Some(()) = connections.next() => {}
+                    _ = context.cancel.cancelled() => break,
+                }
+            }
+
+            while connections.next().await.is_some() {}
+        }
+
+        #[cfg(unix)]
+        ServeListener::Unix(path) => {
+            let listener = bind_private_socket(&path)?;
+
+            tracing::info!(path = %path.display(), "serving requests");
+
+            let mut connections = FuturesUnordered::new();
+
+            let result = loop {
+                tokio::select! {
+                    result = listener.accept() => {
+                        match result {
+                            Ok((stream, _)) => {
+                                connections.push(handle_connection(context, &operation, stream));
+                            }
+                            Err(error) => break Err(error).context("failed to accept connection"),
+                        }
+                    }
+                    Some(()) = connections.next() => {}
+                    _ = context.cancel.cancelled() => break Ok(()),
+                }
+            };
+
+            while connections.next().await.is_some() {}
+
+            _ = std::fs::remove_file(&path);
+            result?;
+        }
+
+        #[cfg(not(unix))]
+        ServeListener::Unix(_) => eyre::bail!("unix sockets are not supported on this platform"),
+    }
+
+    Ok(())
+}
+
+/// Read a single request from the `stream` and write its response, the
+/// `operation` lock is held while running the requested operation
+async fn handle_connection<Fs, S>(
+    context: &ServeContext<'_, Fs>,
+    operation: &Mutex<()>,
+    mut stream: S,
+) where
+    Fs: FileSystem,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await;
+
+    let response = match request {
+        Ok(Ok(request)) => handle_request(context, operation, request).await,
+        Ok(Err(error)) => HttpResponse::error(400, error.to_string()),
+        Err(_) => HttpResponse::error(408, "timed out reading request"),
+    };
+
+    if let Err(error) = write_response(&mut stream, &response).await {
+        tracing::error!(?error, "failed to write response");
+    }
+}
+
+/// Authorize and route a `request`, operations wait for the `operation`
+/// lock so they never overlap
+async fn handle_request<Fs: FileSystem>(
+    context: &ServeContext<'_, Fs>,
+    operation: &Mutex<()>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let token = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), context.token.as_bytes())) {
+        return HttpResponse::error(401, "unauthorized");
+    }
+
+    let filter = match request.body.is_empty() {
+        true => Ok(RequestFilter::default()),
+        false => serde_json::from_slice::<RequestFilter>(&request.body),
+    };
+
+    let filter = match filter {
+        Ok(value) => value,
+        Err(error) => return HttpResponse::error(400, format!("invalid request body: {error}")),
+    };
+
+    let files = filter_files(
+        &context.config.files,
+        filter.files.as_deref(),
+        filter.globs.as_deref(),
+    );
+
+    let _operation = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") | ("POST", "/pull") | ("POST", "/push") => operation.lock().await,
+        _ => return HttpResponse::error(404, "not found"),
+    };
+
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => {
+            // Files that can only be pulled are never pushed
+            let mut warnings = Vec::new();
+            let files = skip_pull_only(files, &mut warnings);
+
+            let info = PushInfo::detect(context.working_path, &SystemClock);
+            create_plan(
+                context.fs,
+                context.secret,
+                context.working_path,
+                files,
+                &info,
+            )
+            .await
+            .map(|plan| json!({ "success": true, "plan": plan, "warnings": warnings }))
+        }
+
+        ("POST", "/pull") => {
+            let files = files.into_iter().map(|(_name, file)| file);
+            pull_secret_files(
+                context.fs,
+                context.secret,
+                context.working_path,
+                files,
+                PullOptions::default(),
+                &(),
+                context.cancel,
+            )
+            .await
+            .map(|outcome| {
+                json!({ "success": true, "changed": outcome.changed, "cancelled": outcome.cancelled })
+            })
+            .map_err(eyre::Report::from)
+        }
+
+        ("POST", "/push") => {
+            let files = files.into_iter().map(|(_name, file)| file);
+            let options = PushOptions {
+                max_files: context.config.push.max_files,
+                ..Default::default()
+            };
+            push_secret_files(
+                context.fs,
+                context.secret,
+                context.working_path,
+                files,
+                &options,
+                &(),
+                context.cancel,
+            )
+            .await
+            .map(|outcome| {
+                json!({ "success": true, "pushed": outcome.completed, "cancelled": outcome.cancelled })
+            })
+            .map_err(eyre::Report::from)
+        }
+
+        _ => unreachable!("unknown routes are rejected before running an operation"),
+    };
+
+    tracing::info!(method = %request.method, path = %request.path, success = result.is_ok(), "handled request");
+
+    match result {
+        Ok(body) => HttpResponse { status: 200, body },
+        Err(error) => {
+            tracing::error!(?error, "request failed");
+            HttpResponse::error(500, format!("{error:#}"))
+        }
+    }
+}
+
+/// Compare `a` and `b` in constant time
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Bind a unix socket at `path` that only the current user may connect to
+///
+/// The socket is bound and restricted within a private directory before
+/// being linked into place, so other users never see the socket before
+/// its permissions are restricted
+#[cfg(unix)]
+pub fn bind_private_socket(path: &Path) -> eyre::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let file_name = path.file_name().context("invalid socket path")?;
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let directory = parent.join(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&directory)
+        .with_context(|| {
+            format!(
+                "failed to create socket directory \"{}\"",
+                directory.display()
+            )
+        })?;
+
+    let staged = directory.join(file_name);
+    let result = tokio::net::UnixListener::bind(&staged)
+        .context("failed to bind socket")
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))
+                .context("failed to restrict socket permissions")?;
+
+            // Linking fails rather than replacing an existing socket
+            std::fs::hard_link(&staged, path)?;
+            Ok(listener)
+        })
+        .with_context(|| format!("failed to listen on \"{}\"", path.display()));
+
+    _ = std::fs::remove_dir_all(&directory);
+    result
+}
+
+/// Read a single line from the `reader`, failing when the line is longer
+/// than [MAX_LINE_LENGTH]
+pub async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> eyre::Result<String> {
+    let mut line = String::new();
+    reader
+        .take(MAX_LINE_LENGTH as u64)
+        .read_line(&mut line)
+        .await?;
+
+    if line.len() >= MAX_LINE_LENGTH && !line.ends_with('\n') {
+        eyre::bail!("request line too long");
+    }
+
+    Ok(line)
+}
+
+/// Read and parse a HTTP/1.1 request from `stream`
+async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> eyre::Result<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let line = read_line(&mut reader).await?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next().context("missing request method")?.to_string();
+    let target = parts.next().context("missing request path")?;
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut authorization = None;
+    let mut content_length = 0;
+
+    for _ in 0..MAX_HEADERS {
+        let line = read_line(&mut reader).await?;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).await?;
+
+            return Ok(HttpRequest {
+                method,
+                path,
+                authorization,
+                body,
+            });
+        }
+
+        let (name, value) = line.split_once(':').context("invalid header")?;
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().context("invalid content length")?;
+
+            if content_length > MAX_BODY_SIZE {
+                eyre::bail!("request body too large");
+            }
+        }
+    }
+
+    eyre::bail!("too many headers")
+}
+
+/// Write the HTTP/1.1 `response` to `stream`
+async fn write_response<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    response: &HttpResponse,
+) -> eyre::Result<()> {
+    let body = serde_json::to_vec(&response.body)?;
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        _ => "Internal Server Error",
+    };
+
+    let head = format!(
+        "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        body.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::serve::{MAX_LINE_LENGTH, ServeContext, handle_connection, read_request};
+    use indexmap::IndexMap;
+    use secret_sync::{
+        cancel::CancellationToken,
+        config::{Config, SecretFile},
+        fs::MockFileSystem,
+        secret::{MockSecretManager, Secret},
+    };
+    use std::path::{Path, PathBuf};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::Mutex;
+
+    /// Send the raw `request` to a server using `fs` and `secret`, returning
+    /// the raw response
+    async fn send(fs: &MockFileSystem, secret: &MockSecretManager, request: &str) -> String {
+        let mut files = IndexMap::new();
+        files.insert(
+            "test".to_string(),
+            SecretFile {
+                path: PathBuf::from("test.env"),
+                secret: "test".to_string(),
+                ..Default::default()
+            },
+        );
+
+        let config = Config {
+            files,
+            ..Default::default()
+        };
+
+        send_with_config(fs, secret, &config, request).await
+    }
+
+    /// Send the raw `request` to a server using `fs`, `secret`, and the
+    /// `config`, returning the raw response
+    async fn send_with_config(
+        fs: &MockFileSystem,
+        secret: &MockSecretManager,
+        config: &Config,
+        request: &str,
+    ) -> String {
+        let context = ServeContext {
+            fs,
+            secret,
+            config,
+            working_path: Path::new("/"),
+            token: "token",
+            cancel: &CancellationToken::new(),
+        };
+
+        let (mut client, server) = tokio::io::duplex(4096);
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        handle_connection(&context, &Mutex::new(()), server).await;
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    /// Tests that requests without the token are rejected
+    #[tokio::test]
+    async fn test_serve_unauthorized() {
+        let fs = MockFileSystem::new();
+        let mut secret = MockSecretManager::new();
+        secret.expect_get_secret().never();
+
+        let response = send(&fs, &secret, "POST /pull HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 401"));
+
+        let response = send(
+            &fs,
+            &secret,
+            "POST /pull HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 401"));
+    }
+
+    /// Tests that an authorized pull writes the secret file
+    #[tokio::test]
+    async fn test_serve_pull() {
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file_optional().returning(|_path| Ok(None));
+        fs.expect_write_file()
+            .times(1)
+            .returning(|_path, _bytes| Ok(()));
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .returning(|_name| Ok(Secret::String("TEST=1".to_string()).into()));
+
+        let response = send(
+            &fs,
+            &secret,
+            "POST /pull HTTP/1.1\r\nauthorization: Bearer token\r\nContent-Length: 18\r\n\r\n{\"files\":[\"test\"]}",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        let (_head, body) = response.split_once("\r\n\r\n").unwrap();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "success": true, "changed": 1, "cancelled": false })
+        );
+    }
+
+    /// Tests that the status plan skips files that can only be pulled
+    #[tokio::test]
+    async fn test_serve_status_pull_only() {
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file().never();
+
+        let mut secret = MockSecretManager::new();
+        secret.expect_get_secret().never();
+
+        let mut files = IndexMap::new();
+        files.insert(
+            "managed".to_string(),
+            SecretFile {
+                path: PathBuf::from("managed.env"),
+                secret: "managed".to_string(),
+                managed_by: Some("terraform".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let config = Config {
+            files,
+            ..Default::default()
+        };
+
+        let response = send_with_config(
+            &fs,
+            &secret,
+            &config,
+            "GET /status HTTP/1.1\r\nauthorization: Bearer token\r\n\r\n",
+        )
+        .await;
+
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        let (_head, body) = response.split_once("\r\n\r\n").unwrap();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["plan"]["entries"], serde_json::json!([]));
+        assert_eq!(
+            body["warnings"],
+            serde_json::json!(["skipping push of file \"managed\" managed by terraform"])
+        );
+    }
+
+    /// Tests that a client that has not finished sending its request does
+    /// not block other clients
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_concurrent_connections() {
+        use crate::serve::{ServeListener, serve_listener};
+        use std::time::Duration;
+        use tokio::net::UnixStream;
+
+        let fs = MockFileSystem::new();
+        let secret = MockSecretManager::new();
+        let config = Config::default();
+        let cancel = CancellationToken::new();
+
+        let context = ServeContext {
+            fs: &fs,
+            secret: &secret,
+            config: &config,
+            working_path: Path::new("/"),
+            token: "token",
+            cancel: &cancel,
+        };
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("serve.sock");
+
+        let server = serve_listener(&context, ServeListener::Unix(path.clone()));
+        let clients = async {
+            // Connects without sending a request
+            let slow = UnixStream::connect(&path).await.unwrap();
+
+            let mut client = UnixStream::connect(&path).await.unwrap();
+            client
+                .write_all(b"GET /missing HTTP/1.1\r\n\r\n")
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            tokio::time::timeout(Duration::from_secs(5), client.read_to_string(&mut response))
+                .await
+                .expect("request was blocked by the slow client")
+                .unwrap();
+            assert!(response.starts_with("HTTP/1.1 401"));
+
+            drop(slow);
+            cancel.cancel();
+        };
+
+        let (result, ()) = tokio::join!(server, clients);
+        result.unwrap();
+    }
+
+    /// Tests that request lines longer than the limit are rejected without
+    /// reading the rest of the line
+    #[tokio::test]
+    async fn test_read_request_line_limit() {
+        let request = "GET /status HTTP/1.1\r\nX: y\r\n\r\n";
+        let parsed = read_request(&mut request.as_bytes()).await.unwrap();
+        assert_eq!(parsed.path, "/status");
+
+        let request = format!(
+            "GET /status HTTP/1.1\r\nX: {}\r\n\r\n",
+            "y".repeat(MAX_LINE_LENGTH)
+        );
+        assert!(read_request(&mut request.as_bytes()).await.is_err());
+    }
+
+    /// Tests that sockets are only reachable by the current user and never
+    /// replace an existing socket
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_private_socket() {
+        use crate::serve::bind_private_socket;
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("serve.sock");
+
+        let _listener = bind_private_socket(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        assert!(bind_private_socket(&path).is_err());
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,249 @@
+//! # Service
+//!
+//! Installation of a scheduled pull that keeps the secret files of a
+//! project up to date, as a systemd service and timer on Linux or as a
+//! scheduled task on Windows
+
+use eyre::{Context, ContextCompat};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Longest interval supported by a minute based scheduled task
+const MAX_TASK_INTERVAL_MINUTES: u64 = 1439;
+
+/// Scheduled pull to install
+pub struct ServiceOptions {
+    /// Name of the unit or task
+    pub name: String,
+    /// Path to the secret-sync executable
+    pub executable: PathBuf,
+    /// Arguments for the pull, including the config path
+    pub args: Vec<String>,
+    /// Paths the pull writes to, the only paths writable by the unit
+    pub writable_paths: Vec<PathBuf>,
+    /// Time between pulls
+    pub interval: Duration,
+}
+
+/// Quote `value` for use within a systemd unit setting
+fn systemd_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Render the oneshot systemd service running the pull, hardened so that
+/// only the project paths are writable
+pub fn systemd_service_unit(options: &ServiceOptions) -> String {
+    let mut command = systemd_quote(&options.executable.display().to_string());
+    for arg in &options.args {
+        command.push(' ');
+        command.push_str(&systemd_quote(arg));
+    }
+
+    let writable_paths = options
+        .writable_paths
+        .iter()
+        .map(|path| systemd_quote(&path.display().to_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut unit = String::new();
+    _ = writeln!(unit, "[Unit]");
+    _ = writeln!(unit, "Description=secret-sync pull ({})", options.name);
+    _ = writeln!(unit, "Wants=network-online.target");
+    _ = writeln!(unit, "After=network-online.target");
+    _ = writeln!(unit);
+    _ = writeln!(unit, "[Service]");
+    _ = writeln!(unit, "Type=oneshot");
+    _ = writeln!(unit, "ExecStart={command}");
+    _ = writeln!(unit, "ProtectSystem=strict");
+    _ = writeln!(unit, "ProtectHome=read-only");
+    _ = writeln!(unit, "ReadWritePaths={writable_paths}");
+    _ = writeln!(unit, "PrivateTmp=yes");
+    _ = writeln!(unit, "PrivateDevices=yes");
+    _ = writeln!(unit, "NoNewPrivileges=yes");
+    _ = writeln!(unit, "ProtectKernelTunables=yes");
+    _ = writeln!(unit, "ProtectKernelModules=yes");
+    _ = writeln!(unit, "ProtectControlGroups=yes");
+    _ = writeln!(unit, "RestrictSUIDSGID=yes");
+    _ = writeln!(unit, "LockPersonality=yes");
+    _ = writeln!(unit, "UMask=0077");
+    unit
+}
+
+/// Render the systemd timer running the service every interval
+pub fn systemd_timer_unit(options: &ServiceOptions) -> String {
+    let interval = options.interval.as_secs().max(1);
+
+    let mut unit = String::new();
+    _ = writeln!(unit, "[Unit]");
+    _ = writeln!(
+        unit,
+        "Description=secret-sync pull every {interval}s ({})",
+        options.name
+    );
+    _ = writeln!(unit);
+    _ = writeln!(unit, "[Timer]");
+    _ = writeln!(unit, "OnBootSec=1min");
+    _ = writeln!(unit, "OnUnitActiveSec={interval}s");
+    _ = writeln!(unit, "Persistent=true");
+    _ = writeln!(unit);
+    _ = writeln!(unit, "[Install]");
+    _ = writeln!(unit, "WantedBy=timers.target");
+    unit
+}
+
+/// Arguments for `schtasks` creating a scheduled task running the pull
+/// every interval, rounded up to whole minutes
+pub fn schtasks_args(options: &ServiceOptions) -> eyre::Result<Vec<String>> {
+    let minutes = options.interval.as_secs().div_ceil(60).max(1);
+    if minutes > MAX_TASK_INTERVAL_MINUTES {
+        eyre::bail!("scheduled task interval must be less than a day");
+    }
+
+    let mut command = format!("\"{}\"", options.executable.display());
+    for arg in &options.args {
+        _ = write!(command, " \"{arg}\"");
+    }
+
+    Ok(vec![
+        "/Create".to_string(),
+        "/F".to_string(),
+        "/TN".to_string(),
+        options.name.clone(),
+        "/SC".to_string(),
+        "MINUTE".to_string(),
+        "/MO".to_string(),
+        minutes.to_string(),
+        "/TR".to_string(),
+        command,
+    ])
+}
+
+/// Directory systemd units are installed into, the per user directory is
+/// used for `user` services
+fn systemd_unit_directory(user: bool) -> eyre::Result<PathBuf> {
+    match user {
+        true => Ok(dirs::config_dir()
+            .context("failed to determine user config directory")?
+            .join("systemd")
+            .join("user")),
+        false => Ok(PathBuf::from("/etc/systemd/system")),
+    }
+}
+
+/// Run `program` with `args` failing when it does not exit successfully
+async fn run_program(program: &str, args: &[String]) -> eyre::Result<()> {
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .stdout(std::io::stderr())
+        .status()
+        .await
+        .with_context(|| format!("failed to execute {program}"))?;
+
+    if !status.success() {
+        eyre::bail!("{program} exited with {status}");
+    }
+
+    Ok(())
+}
+
+/// Install and start the scheduled pull, providing the paths of the
+/// created units (empty for scheduled tasks)
+pub async fn install_service(options: &ServiceOptions, user: bool) -> eyre::Result<Vec<PathBuf>> {
+    if cfg!(windows) {
+        run_program("schtasks", &schtasks_args(options)?).await?;
+        return Ok(Vec::new());
+    }
+
+    let directory = systemd_unit_directory(user)?;
+    tokio::fs::create_dir_all(&directory)
+        .await
+        .context("failed to create systemd unit directory")?;
+
+    let service_path = directory.join(format!("{}.service", options.name));
+    let timer_path = directory.join(format!("{}.timer", options.name));
+
+    write_unit(&service_path, &systemd_service_unit(options)).await?;
+    write_unit(&timer_path, &systemd_timer_unit(options)).await?;
+
+    let systemctl = |args: &[&str]| {
+        let mut all = Vec::new();
+        if user {
+            all.push("--user".to_string());
+        }
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        all
+    };
+
+    run_program("systemctl", &systemctl(&["daemon-reload"])).await?;
+    run_program(
+        "systemctl",
+        &systemctl(&["enable", "--now", &format!("{}.timer", options.name)]),
+    )
+    .await?;
+
+    Ok(vec![service_path, timer_path])
+}
+
+/// Write a unit file to `path`
+async fn write_unit(path: &Path, contents: &str) -> eyre::Result<()> {
+    tokio::fs::write(path, contents)
+        .await
+        .with_context(|| format!("failed to write \"{}\"", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::service::{ServiceOptions, schtasks_args, systemd_service_unit, systemd_timer_unit};
+    use std::{path::PathBuf, time::Duration};
+
+    fn options() -> ServiceOptions {
+        ServiceOptions {
+            name: "secret-sync-app".to_string(),
+            executable: PathBuf::from("/usr/local/bin/secret-sync"),
+            args: vec![
+                "--config".to_string(),
+                "/srv/app/secret-sync.toml".to_string(),
+                "pull".to_string(),
+            ],
+            writable_paths: vec![PathBuf::from("/srv/app")],
+            interval: Duration::from_secs(15 * 60),
+        }
+    }
+
+    /// Tests the generated units run the pull hardened on the interval
+    #[test]
+    fn test_systemd_units() {
+        let service = systemd_service_unit(&options());
+        assert!(service.contains(
+            "ExecStart=\"/usr/local/bin/secret-sync\" \"--config\" \"/srv/app/secret-sync.toml\" \"pull\"\n"
+        ));
+        assert!(service.contains("ProtectSystem=strict\n"));
+        assert!(service.contains("ReadWritePaths=\"/srv/app\"\n"));
+        assert!(service.contains("PrivateTmp=yes\n"));
+
+        let timer = systemd_timer_unit(&options());
+        assert!(timer.contains("OnUnitActiveSec=900s\n"));
+        assert!(timer.contains("WantedBy=timers.target\n"));
+    }
+
+    /// Tests the scheduled task interval is rounded up to whole minutes
+    #[test]
+    fn test_schtasks_args() {
+        let mut options = options();
+        options.interval = Duration::from_secs(90);
+
+        let args = schtasks_args(&options).unwrap();
+        assert_eq!(args[7], "2");
+        assert_eq!(
+            args[9],
+            "\"/usr/local/bin/secret-sync\" \"--config\" \"/srv/app/secret-sync.toml\" \"pull\""
+        );
+
+        options.interval = Duration::from_secs(24 * 60 * 60);
+        assert!(schtasks_args(&options).is_err());
+    }
+}
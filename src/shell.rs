@@ -0,0 +1,58 @@
+//! # Shell
+//!
+//! Helpers for running user provided commands through the system shell
+
+use eyre::Context;
+use std::process::Command;
+
+/// Create a [Command] that runs `command` through the system shell
+pub fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+/// Run `command` through the system shell returning its standard output
+pub fn run_shell_command(command: &str) -> eyre::Result<String> {
+    let output = shell_command(command)
+        .output()
+        .with_context(|| format!("failed to execute \"{command}\""))?;
+
+    if !output.status.success() {
+        eyre::bail!(
+            "command \"{command}\" exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8(output.stdout).context("command output was not valid UTF-8")
+}
+
+/// Run `command` through the system shell as a hook, the command output
+/// is written to stderr so that it does not interfere with command output
+#[cfg(not(target_family = "wasm"))]
+pub async fn run_shell_hook(command: &str) -> eyre::Result<()> {
+    let command = command.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let status = shell_command(&command)
+            .stdout(std::io::stderr())
+            .status()
+            .with_context(|| format!("failed to execute \"{command}\""))?;
+
+        if !status.success() {
+            eyre::bail!("command \"{command}\" exited with {status}");
+        }
+
+        Ok(())
+    })
+    .await
+    .context("hook command task failed")?
+}
@@ -0,0 +1,146 @@
+//! # State
+//!
+//! Project local state directory (.secret-sync) stored in the working
+//! directory, used for data that must persist between runs such as the
+//! project lock, the last run summary, and the progress of interrupted
+//! batches. The directory can be relocated (e.g. to a tmpfs or CI
+//! workspace) through --state-dir or `paths.state_dir` in the config
+//!
+//! The directory contains a `.gitignore` ignoring its contents so the
+//! state is never committed, entries can be added to it to track specific
+//! files as it is only created when missing
+
+use eyre::Context;
+use std::path::{Path, PathBuf};
+
+/// Name of the project local state directory
+pub const STATE_DIR_NAME: &str = ".secret-sync";
+
+/// Contents of the `.gitignore` created within the state directory
+const STATE_GITIGNORE: &str = "# Created by secret-sync, state is local to this checkout\n*\n";
+
+/// Name of the project lock file
+const LOCK_FILE_NAME: &str = "lock";
+
+/// Name of the last run summary file
+const LAST_RUN_FILE_NAME: &str = "last-run.json";
+
+/// Name of the batch progress file
+const RESUME_FILE_NAME: &str = "resume.json";
+
+/// Name of the seed progress file
+const SEED_FILE_NAME: &str = "seed.json";
+
+/// Default name of the agent socket
+const AGENT_SOCKET_NAME: &str = "agent.sock";
+
+/// Paths of the files within the state directory, every stateful part of
+/// the CLI resolves its paths through here so the directory can be moved
+#[derive(Debug, Clone)]
+pub struct StatePaths {
+    /// State directory
+    dir: PathBuf,
+}
+
+impl StatePaths {
+    /// Use the state directory at `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Use the `.secret-sync` directory within the `working_path` of the
+    /// project unless the state directory is `relocated`
+    pub fn for_project(working_path: &Path, relocated: Option<PathBuf>) -> Self {
+        Self::new(relocated.unwrap_or_else(|| working_path.join(STATE_DIR_NAME)))
+    }
+
+    /// Path of the state directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Create the state directory along with its `.gitignore` when they
+    /// do not exist
+    pub async fn create(&self) -> eyre::Result<()> {
+        create_state_dir(&self.dir).await
+    }
+
+    /// Path of the project lock file
+    pub fn lock(&self) -> PathBuf {
+        self.dir.join(LOCK_FILE_NAME)
+    }
+
+    /// Path of the last run summary
+    pub fn last_run(&self) -> PathBuf {
+        self.dir.join(LAST_RUN_FILE_NAME)
+    }
+
+    /// Path of the progress of an interrupted pull or push batch
+    pub fn resume(&self) -> PathBuf {
+        self.dir.join(RESUME_FILE_NAME)
+    }
+
+    /// Path of the progress of an interrupted seed
+    pub fn seed(&self) -> PathBuf {
+        self.dir.join(SEED_FILE_NAME)
+    }
+
+    /// Default path of the agent socket
+    pub fn agent_socket(&self) -> PathBuf {
+        self.dir.join(AGENT_SOCKET_NAME)
+    }
+}
+
+/// Create the state directory at `state_dir` along with its `.gitignore`
+/// when they do not exist
+pub async fn create_state_dir(state_dir: &Path) -> eyre::Result<()> {
+    tokio::fs::create_dir_all(state_dir)
+        .await
+        .context("failed to create state directory")?;
+
+    let gitignore = state_dir.join(".gitignore");
+    match tokio::fs::try_exists(&gitignore).await {
+        Ok(true) => Ok(()),
+        _ => tokio::fs::write(&gitignore, STATE_GITIGNORE)
+            .await
+            .context("failed to write state directory .gitignore"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::state::{STATE_GITIGNORE, StatePaths, create_state_dir};
+    use std::path::Path;
+
+    /// Tests that the state directory ignores its contents without
+    /// replacing an existing .gitignore
+    #[tokio::test]
+    async fn test_create_state_dir() {
+        let directory = tempfile::tempdir().unwrap();
+        let state_dir = StatePaths::for_project(directory.path(), None)
+            .dir()
+            .to_path_buf();
+
+        create_state_dir(&state_dir).await.unwrap();
+        let gitignore = state_dir.join(".gitignore");
+        assert_eq!(
+            std::fs::read_to_string(&gitignore).unwrap(),
+            STATE_GITIGNORE
+        );
+
+        std::fs::write(&gitignore, "*\n!hooks/\n").unwrap();
+        create_state_dir(&state_dir).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&gitignore).unwrap(), "*\n!hooks/\n");
+    }
+
+    /// Tests that a relocated state directory replaces the default
+    #[test]
+    fn test_state_paths() {
+        let paths = StatePaths::for_project(Path::new("/project"), None);
+        assert_eq!(paths.dir(), Path::new("/project/.secret-sync"));
+        assert_eq!(paths.lock(), Path::new("/project/.secret-sync/lock"));
+
+        let paths = StatePaths::for_project(Path::new("/project"), Some("/tmp/state".into()));
+        assert_eq!(paths.last_run(), Path::new("/tmp/state/last-run.json"));
+    }
+}
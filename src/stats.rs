@@ -0,0 +1,297 @@
+//! # Stats
+//!
+//! Anonymous statistics about the config of a project, computed locally
+//! from the config, the local files, and the last run summary without
+//! contacting the backend or sending anything anywhere. Secret names and
+//! file paths are never included so platform teams can aggregate the
+//! output across repositories to understand usage
+
+use crate::last_run::LastRun;
+use secret_sync::config::{Config, KeystoreFormat};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// Statistics about the config of a project
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct ConfigStats {
+    /// Backend provider of the config
+    pub backend: String,
+    /// Number of entries of each kind
+    pub entries: EntryCounts,
+    /// Number of entries by the format of the file, based on the file name
+    pub formats: BTreeMap<&'static str, usize>,
+    /// Number of files using each optional feature
+    pub features: FeatureCounts,
+    /// Sizes of the local secret files
+    pub local_files: LocalFileStats,
+    /// Seconds since the last pull or push and whether it succeeded
+    pub last_run: Option<LastRunStats>,
+}
+
+/// Number of entries of each kind within the config
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct EntryCounts {
+    /// Secret files
+    pub files: usize,
+    /// Generated files
+    pub generated: usize,
+    /// Rendered templates
+    pub render: usize,
+    /// Java keystores
+    pub keystore: usize,
+    /// All entries
+    pub total: usize,
+}
+
+/// Number of files using each optional feature
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct FeatureCounts {
+    /// Files processed key by key as a structured secret
+    pub structured: usize,
+    /// Files with compressed values
+    pub compressed: usize,
+    /// Files piped into a sink command
+    pub sinks: usize,
+    /// Files stored on a remote host
+    pub remote_hosts: usize,
+    /// Files managed by another system
+    pub managed_elsewhere: usize,
+}
+
+/// Sizes of the secret files stored locally
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct LocalFileStats {
+    /// Files that exist locally
+    pub present: usize,
+    /// Files that have not been pulled yet
+    pub missing: usize,
+    /// Combined size of the present files in bytes
+    pub total_bytes: u64,
+    /// Size of the largest file in bytes
+    pub largest_bytes: u64,
+    /// Seconds since the least recently modified file changed
+    pub oldest_age_seconds: Option<u64>,
+}
+
+/// Summary of the last pull or push
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LastRunStats {
+    /// Subcommand that was run
+    pub command: String,
+    /// Whether the run completed successfully
+    pub success: bool,
+    /// Seconds since the run started
+    pub age_seconds: u64,
+}
+
+impl ConfigStats {
+    /// Format the statistics as human readable text
+    pub fn to_text(&self) -> String {
+        let entries = &self.entries;
+        let formats = self
+            .formats
+            .iter()
+            .map(|(format, count)| format!("{format}={count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let local = &self.local_files;
+        let last_run = match &self.last_run {
+            Some(run) => format!(
+                "{} {} {}s ago",
+                run.command,
+                match run.success {
+                    true => "succeeded",
+                    false => "failed",
+                },
+                run.age_seconds
+            ),
+            None => "never".to_string(),
+        };
+
+        format!(
+            "backend: {}\nentries: {} ({} files, {} generated, {} rendered, {} keystores)\nformats: {formats}\nlocal files: {} present, {} missing, {} bytes total, largest {} bytes\nlast run: {last_run}",
+            self.backend,
+            entries.total,
+            entries.files,
+            entries.generated,
+            entries.render,
+            entries.keystore,
+            local.present,
+            local.missing,
+            local.total_bytes,
+            local.largest_bytes,
+        )
+    }
+}
+
+/// Compute the statistics of the `config`, local files are resolved
+/// against the `working_path` and ages are relative to `now` (unix seconds)
+pub fn config_stats(
+    config: &Config,
+    working_path: &Path,
+    last_run: Option<&LastRun>,
+    now: u64,
+) -> ConfigStats {
+    let mut stats = ConfigStats {
+        backend: config.backend.provider.to_string(),
+        entries: EntryCounts {
+            files: config.files.len(),
+            generated: config.generated.len(),
+            render: config.render.len(),
+            keystore: config.keystore.len(),
+            total: config.files.len()
+                + config.generated.len()
+                + config.render.len()
+                + config.keystore.len(),
+        },
+        last_run: last_run.map(|run| LastRunStats {
+            command: run.command.clone(),
+            success: run.success,
+            age_seconds: now.saturating_sub(run.started_at),
+        }),
+        ..Default::default()
+    };
+
+    let paths = config
+        .files
+        .values()
+        .map(|file| file.path.as_path())
+        .chain(config.generated.values().map(|file| file.path.as_path()))
+        .chain(config.render.values().map(|file| file.path.as_path()));
+    for path in paths {
+        *stats.formats.entry(file_format(path)).or_default() += 1;
+    }
+
+    for file in config.keystore.values() {
+        let format = match file.format {
+            KeystoreFormat::Pkcs12 => "pkcs12",
+            KeystoreFormat::Jks => "jks",
+        };
+        *stats.formats.entry(format).or_default() += 1;
+    }
+
+    for file in config.files.values() {
+        let features = &mut stats.features;
+        features.structured += usize::from(file.is_structured());
+        features.compressed += usize::from(file.compress.is_some());
+        features.sinks += usize::from(file.sink.is_some());
+        features.remote_hosts += usize::from(file.host.is_some());
+        features.managed_elsewhere += usize::from(file.is_externally_managed());
+
+        // Only files stored on this machine have a local size
+        if file.sink.is_some() || file.host.is_some() {
+            continue;
+        }
+
+        let local = &mut stats.local_files;
+        let Ok(metadata) = std::fs::metadata(file.resolve_path(working_path)) else {
+            local.missing += 1;
+            continue;
+        };
+
+        local.present += 1;
+        local.total_bytes += metadata.len();
+        local.largest_bytes = local.largest_bytes.max(metadata.len());
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|modified| now.saturating_sub(modified.as_secs()));
+        if let Some(age) = modified {
+            local.oldest_age_seconds = Some(local.oldest_age_seconds.unwrap_or(0).max(age));
+        }
+    }
+
+    stats
+}
+
+/// Format of a file based on its name
+fn file_format(path: &Path) -> &'static str {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if name == ".env" || name.starts_with(".env.") || name.ends_with(".env") {
+        return "dotenv";
+    }
+
+    match path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("json") => "json",
+        Some("yaml" | "yml") => "yaml",
+        Some("toml") => "toml",
+        Some("properties") => "properties",
+        Some("pem" | "crt" | "key") => "pem",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        last_run::LastRun,
+        stats::{config_stats, file_format},
+    };
+    use secret_sync::config::parse_config_file;
+    use std::path::Path;
+
+    /// Tests that entries, formats, and local files are counted without
+    /// exposing names or paths
+    #[test]
+    fn test_config_stats() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join(".env"), "A=1234").unwrap();
+
+        let config = parse_config_file(
+            br#"
+            [files.app]
+            path = ".env"
+            secret = "app"
+
+            [files.config]
+            path = "config.json"
+            secret = "config"
+            compress = "zstd"
+
+            [files.remote]
+            path = ".env.production"
+            secret = "remote"
+            host = "web-1"
+            "#,
+            None,
+        )
+        .unwrap();
+
+        let last_run = LastRun {
+            command: "pull".to_string(),
+            started_at: 900,
+            duration_ms: 10,
+            success: true,
+            error: None,
+            files: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let stats = config_stats(&config, directory.path(), Some(&last_run), 1000);
+        assert_eq!(stats.entries.total, 3);
+        assert_eq!(stats.formats["dotenv"], 2);
+        assert_eq!(stats.formats["json"], 1);
+        assert_eq!(stats.features.compressed, 1);
+        assert_eq!(stats.features.remote_hosts, 1);
+        assert_eq!(stats.local_files.present, 1);
+        assert_eq!(stats.local_files.missing, 1);
+        assert_eq!(stats.local_files.largest_bytes, 6);
+        assert_eq!(stats.last_run.unwrap().age_seconds, 100);
+
+        let value = serde_json::to_string(&config_stats(&config, directory.path(), None, 0));
+        assert!(!value.unwrap().contains("app"));
+
+        assert_eq!(file_format(Path::new("config/app.env")), "dotenv");
+        assert_eq!(file_format(Path::new("tls.key")), "pem");
+    }
+}
@@ -0,0 +1,304 @@
+//! # Status
+//!
+//! Read only summary of the drift between the local files and the remote
+//! secrets. Each file reports whether it exists locally and remotely and,
+//! when the values differ, which side was modified last based on the
+//! modification time of the local file and the last change of the secret
+
+use crate::diff::read_values;
+use secret_sync::{
+    arn::display_secret_name, config::SecretFile, fs::FileSystem, secret::SecretManager,
+};
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Status of a single file
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct FileStatus {
+    /// Name of the file entry within the config
+    pub name: String,
+    /// Path of the local file
+    pub path: PathBuf,
+    /// Name of the secret
+    pub secret: String,
+    /// Whether the local file exists
+    pub local: bool,
+    /// Whether the secret exists
+    pub remote: bool,
+    /// Drift between the local file and the secret
+    pub status: SyncStatus,
+    /// Modification time of the local file as unix seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_modified: Option<u64>,
+    /// Last change of the secret as an RFC 3339 timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_modified: Option<String>,
+    /// Notes of the file entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Ticket of the file entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+}
+
+/// Drift between a local file and its secret
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncStatus {
+    /// File and secret have the same value
+    InSync,
+    /// File was modified after the secret was last changed
+    LocalNewer,
+    /// Secret was changed after the file was last modified
+    RemoteNewer,
+    /// Values differ but the backend or file system does not provide the
+    /// times needed to tell which side is newer
+    Changed,
+    /// Either the file or the secret does not exist
+    Missing,
+}
+
+impl SyncStatus {
+    /// Name of the status
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncStatus::InSync => "in-sync",
+            SyncStatus::LocalNewer => "local-newer",
+            SyncStatus::RemoteNewer => "remote-newer",
+            SyncStatus::Changed => "changed",
+            SyncStatus::Missing => "missing",
+        }
+    }
+}
+
+/// Determine the status of the local file of `file` against its secret
+pub async fn file_status<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    name: &str,
+    file: &SecretFile,
+) -> eyre::Result<FileStatus> {
+    let path = file.resolve_path(working_path);
+    let (local, remote) = read_values(fs, secret, working_path, file).await?;
+
+    let mut local_modified = None;
+    let mut remote_modified = None;
+
+    let status = match (&local, &remote) {
+        (Some(local), Some(remote)) if local.as_slice() == remote.as_bytes() => SyncStatus::InSync,
+        (Some(_), Some(_)) => {
+            // Modification times of files on remote hosts are not available
+            if file.host.is_none() {
+                local_modified = modified_time(&path).await;
+            }
+
+            remote_modified = secret
+                .describe_secret(&file.secret)
+                .await?
+                .and_then(|summary| summary.updated);
+
+            let remote_time = remote_modified.as_deref().and_then(parse_timestamp);
+            match (local_modified, remote_time) {
+                (Some(local), Some(remote)) if local > remote => SyncStatus::LocalNewer,
+                (Some(_), Some(_)) => SyncStatus::RemoteNewer,
+                _ => SyncStatus::Changed,
+            }
+        }
+        _ => SyncStatus::Missing,
+    };
+
+    Ok(FileStatus {
+        name: name.to_string(),
+        path,
+        secret: file.secret.clone(),
+        local: local.is_some(),
+        remote: remote.is_some(),
+        status,
+        local_modified,
+        remote_modified,
+        notes: file.notes.clone(),
+        ticket: file.ticket.clone(),
+    })
+}
+
+/// Modification time of the local file at `path` as unix seconds
+async fn modified_time(path: &Path) -> Option<u64> {
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Parse an RFC 3339 `timestamp` into unix seconds, fractional seconds are
+/// ignored
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) = (date.next(), date.next(), date.next())
+    else {
+        return None;
+    };
+
+    // Split the offset from the time of day
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(index) => time.split_at(index),
+        None => (time, "Z"),
+    };
+
+    let offset = match offset {
+        "Z" | "z" => 0,
+        offset => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            sign * (hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (Some(Ok(hours)), Some(Ok(minutes)), Some(Ok(seconds))) =
+        (time.next(), time.next(), time.next())
+    else {
+        return None;
+    };
+
+    // Convert the civil date into days since the unix epoch
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + hours * 3600 + minutes * 60 + seconds - offset;
+    u64::try_from(seconds).ok()
+}
+
+/// Render the `statuses` along with a summary of the drift
+pub fn status_text(statuses: &[FileStatus]) -> String {
+    let mut text = String::new();
+
+    for status in statuses {
+        let symbol = match status.status {
+            SyncStatus::InSync => "  ",
+            SyncStatus::LocalNewer => "> ",
+            SyncStatus::RemoteNewer => "< ",
+            SyncStatus::Changed => "~ ",
+            SyncStatus::Missing => "? ",
+        };
+
+        let presence = match (status.local, status.remote) {
+            (true, true) => "",
+            (true, false) => " (only local)",
+            (false, true) => " (only remote)",
+            (false, false) => " (neither)",
+        };
+
+        _ = writeln!(
+            text,
+            "{symbol}{} ({} -> {}): {}{presence}",
+            status.name,
+            status.path.display(),
+            display_secret_name(&status.secret),
+            status.status.as_str()
+        );
+
+        if let Some(ticket) = &status.ticket {
+            _ = writeln!(text, "    ticket: {ticket}");
+        }
+
+        if let Some(notes) = &status.notes {
+            _ = writeln!(text, "    notes: {notes}");
+        }
+    }
+
+    let count = |value: SyncStatus| {
+        statuses
+            .iter()
+            .filter(|status| status.status == value)
+            .count()
+    };
+
+    _ = write!(
+        text,
+        "Status: {} in sync, {} local newer, {} remote newer, {} changed, {} missing",
+        count(SyncStatus::InSync),
+        count(SyncStatus::LocalNewer),
+        count(SyncStatus::RemoteNewer),
+        count(SyncStatus::Changed),
+        count(SyncStatus::Missing)
+    );
+
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use crate::status::{SyncStatus, file_status, parse_timestamp};
+    use secret_sync::{
+        config::{SecretFile, SecretMetadata},
+        fs::real::RealFs,
+        secret::{Secret, SecretManager, memory::MemorySecretManager},
+    };
+
+    /// Tests that RFC 3339 timestamps are parsed with their offset
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20Z"), Some(1_700_000_000));
+        assert_eq!(
+            parse_timestamp("2023-11-14T22:13:20.123Z"),
+            Some(1_700_000_000)
+        );
+        assert_eq!(
+            parse_timestamp("2023-11-15T00:13:20+02:00"),
+            Some(1_700_000_000)
+        );
+        assert_eq!(parse_timestamp("not a timestamp"), None);
+    }
+
+    /// Tests that files are reported as in sync, changed, or missing
+    #[tokio::test]
+    async fn test_file_status() {
+        let directory = tempfile::tempdir().unwrap();
+        let secret = MemorySecretManager::new();
+        let file = SecretFile {
+            path: ".env".into(),
+            secret: "app".to_string(),
+            ..Default::default()
+        };
+
+        let status = file_status(&RealFs, &secret, directory.path(), "app", &file)
+            .await
+            .unwrap();
+        assert_eq!((status.local, status.remote), (false, false));
+        assert_eq!(status.status, SyncStatus::Missing);
+
+        std::fs::write(directory.path().join(".env"), "A=1\n").unwrap();
+        secret
+            .set_secret(
+                "app",
+                Secret::String("A=1\n".to_string()),
+                &SecretMetadata::default(),
+            )
+            .await
+            .unwrap();
+        let status = file_status(&RealFs, &secret, directory.path(), "app", &file)
+            .await
+            .unwrap();
+        assert_eq!(status.status, SyncStatus::InSync);
+
+        // The memory backend does not record when secrets change
+        std::fs::write(directory.path().join(".env"), "A=2\n").unwrap();
+        let status = file_status(&RealFs, &secret, directory.path(), "app", &file)
+            .await
+            .unwrap();
+        assert_eq!(status.status, SyncStatus::Changed);
+        assert!(status.local_modified.is_some());
+    }
+}
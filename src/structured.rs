@@ -0,0 +1,505 @@
+//! # Structured
+//!
+//! Structured (dotenv or JSON object) secret values that are processed key
+//! by key rather than as a whole, used when a file only receives a subset
+//! of the keys from a shared secret
+
+use crate::{
+    config::{SecretFile, ValueTransform},
+    dotenv::{double_quote_value, find_closing_quote, parse_dotenv, quote_dotenv_value},
+};
+use eyre::{Context, ContextCompat};
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Format of a structured value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructuredFormat {
+    /// Dotenv formatted `KEY=VALUE` lines
+    Dotenv,
+    /// JSON object
+    Json,
+}
+
+/// Structured value parsed into its keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructuredValue {
+    /// Format the value was parsed from
+    pub format: StructuredFormat,
+    /// Values for each key, dotenv values are always strings
+    pub values: IndexMap<String, Value>,
+}
+
+impl StructuredValue {
+    /// Parse a structured `value`, JSON objects are detected and anything
+    /// else is parsed as dotenv
+    pub fn parse(value: &[u8]) -> eyre::Result<Self> {
+        let value = std::str::from_utf8(value).context("structured secret is not valid UTF-8")?;
+
+        if value.trim_start().starts_with('{') {
+            let values: IndexMap<String, Value> =
+                serde_json::from_str(value).context("failed to parse JSON secret")?;
+
+            return Ok(Self {
+                format: StructuredFormat::Json,
+                values,
+            });
+        }
+
+        let values = parse_dotenv(value)?
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect();
+
+        Ok(Self {
+            format: StructuredFormat::Dotenv,
+            values,
+        })
+    }
+
+    /// Render the value in its format
+    pub fn render(&self) -> eyre::Result<Vec<u8>> {
+        self.render_with(&IndexMap::new())
+    }
+
+    /// Render the value in its format, dotenv values for keys with a
+    /// matching [ValueTransform::Quote] in `transforms` are always quoted
+    pub fn render_with(
+        &self,
+        transforms: &IndexMap<String, ValueTransform>,
+    ) -> eyre::Result<Vec<u8>> {
+        match self.format {
+            StructuredFormat::Json => {
+                let mut value = serde_json::to_vec_pretty(&self.values)?;
+                value.push(b'\n');
+                Ok(value)
+            }
+            StructuredFormat::Dotenv => {
+                let mut output = String::new();
+
+                for (key, value) in &self.values {
+                    let value = render_dotenv_value(transforms, key, value);
+                    _ = writeln!(output, "{key}={value}");
+                }
+
+                Ok(output.into_bytes())
+            }
+        }
+    }
+
+    /// Percent-encode the values for keys with a matching
+    /// [ValueTransform::UrlEncode] in `transforms`, values are decoded
+    /// instead when `reverse` is set
+    pub fn url_encode_values(
+        &mut self,
+        transforms: &IndexMap<String, ValueTransform>,
+        reverse: bool,
+    ) -> eyre::Result<()> {
+        for (key, value) in self.values.iter_mut() {
+            if !has_transform(transforms, key, ValueTransform::UrlEncode) {
+                continue;
+            }
+
+            let current = value_to_string(value);
+            let updated = match reverse {
+                true => url_decode(&current)
+                    .with_context(|| format!("value for \"{key}\" is not valid URL encoding"))?,
+                false => url_encode(&current),
+            };
+
+            *value = Value::String(updated);
+        }
+
+        Ok(())
+    }
+
+    /// Only keep the keys matching any of the `patterns`
+    pub fn retain_keys(&mut self, patterns: &[String]) {
+        self.values.retain(|key, _| key_matches(patterns, key));
+    }
+
+    /// Rename keys using the `key_map` of remote to local names, keys are
+    /// renamed from local to remote names when `reverse` is set
+    pub fn rename_keys(&mut self, key_map: &IndexMap<String, String>, reverse: bool) {
+        if key_map.is_empty() {
+            return;
+        }
+
+        self.values = std::mem::take(&mut self.values)
+            .into_iter()
+            .map(|(key, value)| {
+                let renamed = match reverse {
+                    true => key_map
+                        .iter()
+                        .find(|(_, local)| **local == key)
+                        .map(|(remote, _)| remote),
+                    false => key_map.get(&key),
+                };
+
+                (renamed.cloned().unwrap_or(key), value)
+            })
+            .collect();
+    }
+}
+
+/// Convert a structured `value` into its string form, non string values
+/// use their JSON representation
+pub fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Render the dotenv `value` for `key`, the value is always quoted when
+/// the key has a matching [ValueTransform::Quote] in `transforms`
+fn render_dotenv_value(
+    transforms: &IndexMap<String, ValueTransform>,
+    key: &str,
+    value: &Value,
+) -> String {
+    let value = value_to_string(value);
+    match has_transform(transforms, key, ValueTransform::Quote) {
+        true => double_quote_value(&value),
+        false => quote_dotenv_value(&value),
+    }
+}
+
+/// Update the `existing` dotenv file contents with the dotenv `value`
+///
+/// Comments, blank lines and the order of the existing keys are kept,
+/// entries are only rewritten when their value changed. Keys missing from
+/// the `value` are removed and new keys are appended to the end
+fn update_dotenv(
+    existing: &str,
+    value: &StructuredValue,
+    transforms: &IndexMap<String, ValueTransform>,
+) -> eyre::Result<Vec<u8>> {
+    let mut remaining = value.values.clone();
+    let mut output = String::new();
+    let mut lines = existing.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        let entry = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+        let Some((key, raw_value)) = entry.split_once('=').filter(|_| !trimmed.starts_with('#'))
+        else {
+            // Comments, blank lines and anything else are kept as is
+            _ = writeln!(output, "{line}");
+            continue;
+        };
+
+        let exported = entry.len() != trimmed.len();
+        let key = key.trim();
+
+        // Double quoted values may span multiple lines
+        let mut raw = line.to_string();
+        if let Some(rest) = raw_value.trim_start().strip_prefix('"') {
+            let mut quoted = rest.to_string();
+            while find_closing_quote(&quoted).is_none() {
+                let Some(next) = lines.next() else {
+                    break;
+                };
+
+                quoted.push('\n');
+                quoted.push_str(next);
+                raw.push('\n');
+                raw.push_str(next);
+            }
+        }
+
+        // Keys that are no longer present are removed
+        let Some(value) = remaining.shift_remove(key) else {
+            continue;
+        };
+
+        let current = parse_dotenv(&raw)?.shift_remove(key);
+        if current.is_some_and(|current| current == value_to_string(&value)) {
+            _ = writeln!(output, "{raw}");
+            continue;
+        }
+
+        let prefix = if exported { "export " } else { "" };
+        let value = render_dotenv_value(transforms, key, &value);
+        _ = writeln!(output, "{prefix}{key}={value}");
+    }
+
+    for (key, value) in &remaining {
+        let value = render_dotenv_value(transforms, key, value);
+        _ = writeln!(output, "{key}={value}");
+    }
+
+    Ok(output.into_bytes())
+}
+
+/// Check if `key` matches any of the `patterns`, patterns may contain
+/// glob wildcards (e.g. `REDIS_*`)
+pub fn key_matches(patterns: &[String], key: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| fast_glob::glob_match(pattern.as_bytes(), key.as_bytes()))
+}
+
+/// Check if `key` has a `transform` within the `transforms`
+fn has_transform(
+    transforms: &IndexMap<String, ValueTransform>,
+    key: &str,
+    transform: ValueTransform,
+) -> bool {
+    transforms.iter().any(|(pattern, value)| {
+        *value == transform && fast_glob::glob_match(pattern.as_bytes(), key.as_bytes())
+    })
+}
+
+/// Percent-encode all but the unreserved URL characters in `value`
+pub(crate) fn url_encode(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                output.push(byte as char)
+            }
+            byte => _ = write!(output, "%{byte:02X}"),
+        }
+    }
+
+    output
+}
+
+/// Decode a percent-encoded `value`
+fn url_decode(value: &str) -> eyre::Result<String> {
+    let mut output = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            output.push(byte);
+            continue;
+        }
+
+        let hex = [
+            bytes.next().context("incomplete escape")?,
+            bytes.next().context("incomplete escape")?,
+        ];
+        let hex = std::str::from_utf8(&hex).context("invalid escape")?;
+        output.push(u8::from_str_radix(hex, 16).context("invalid escape")?);
+    }
+
+    String::from_utf8(output).context("decoded value is not valid UTF-8")
+}
+
+/// Create the local file contents of the structured `file` from the
+/// `remote` secret value
+///
+/// Only the keys matching the file `keys` are included, keys are renamed
+/// using the file `key_map`, then the file `transforms` are applied.
+///
+/// Dotenv values are written into the `existing` dotenv file contents
+/// when present, keeping its comments, blank lines and key ordering
+pub fn materialize_file(
+    file: &SecretFile,
+    remote: &[u8],
+    existing: Option<&[u8]>,
+) -> eyre::Result<Vec<u8>> {
+    let mut value = StructuredValue::parse(remote)?;
+
+    if let Some(keys) = &file.keys {
+        value.retain_keys(keys);
+    }
+
+    value.rename_keys(&file.key_map, false);
+    value.url_encode_values(&file.transforms, false)?;
+
+    let existing = existing
+        .and_then(|existing| std::str::from_utf8(existing).ok())
+        .filter(|existing| !existing.trim_start().starts_with('{'));
+
+    match (value.format, existing) {
+        (StructuredFormat::Dotenv, Some(existing)) => {
+            update_dotenv(existing, &value, &file.transforms)
+        }
+        _ => value.render_with(&file.transforms),
+    }
+}
+
+/// Create the remote secret value for the structured `file` from its
+/// `local` contents and the current `remote` value
+///
+/// Transformed values are decoded and keys are renamed back using the
+/// file `key_map`. When the file has
+/// `keys` only the matching keys are updated within the remote value,
+/// otherwise the local keys replace the remote keys
+pub fn merge_file(file: &SecretFile, remote: Option<&[u8]>, local: &[u8]) -> eyre::Result<Vec<u8>> {
+    merge_file_keys(file, file.keys.as_deref(), remote, local)
+}
+
+/// Create the remote secret value for the structured `file` using only
+/// the local values for the `keys`, see [merge_file]
+pub fn merge_file_keys(
+    file: &SecretFile,
+    keys: Option<&[String]>,
+    remote: Option<&[u8]>,
+    local: &[u8],
+) -> eyre::Result<Vec<u8>> {
+    let mut local = StructuredValue::parse(local)?;
+    local.url_encode_values(&file.transforms, true)?;
+    local.rename_keys(&file.key_map, true);
+
+    let mut remote = match remote {
+        Some(remote) => StructuredValue::parse(remote)?,
+        None => StructuredValue {
+            format: local.format,
+            values: IndexMap::new(),
+        },
+    };
+
+    match keys {
+        Some(keys) => local.retain_keys(keys),
+        None => remote
+            .values
+            .retain(|key, _| local.values.contains_key(key)),
+    }
+
+    for (key, value) in local.values {
+        // Keep the remote value type when the local format only has strings
+        let value = match (remote.values.get(&key), &value) {
+            (Some(existing), Value::String(local)) if value_to_string(existing) == *local => {
+                existing.clone()
+            }
+            _ => value,
+        };
+
+        remote.values.insert(key, value);
+    }
+
+    remote.render()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{SecretFile, ValueTransform},
+        structured::{materialize_file, merge_file},
+    };
+    use indexmap::IndexMap;
+
+    /// Create a structured file using `keys` and `key_map`
+    fn structured_file(keys: Option<&[&str]>, key_map: &[(&str, &str)]) -> SecretFile {
+        SecretFile {
+            keys: keys.map(|keys| keys.iter().map(|key| key.to_string()).collect()),
+            key_map: key_map
+                .iter()
+                .map(|(remote, local)| (remote.to_string(), local.to_string()))
+                .collect::<IndexMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    /// Tests that only the matching keys are materialized
+    #[test]
+    fn test_materialize_keys() {
+        let file = structured_file(Some(&["DATABASE_URL", "REDIS_*"]), &[]);
+
+        let value = materialize_file(
+            &file,
+            b"DATABASE_URL=postgres://\nREDIS_HOST=localhost\nREDIS_PORT=6379\nOTHER=1\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(value).unwrap(),
+            "DATABASE_URL=postgres://\nREDIS_HOST=localhost\nREDIS_PORT=6379\n"
+        );
+
+        let value =
+            materialize_file(&file, br#"{"REDIS_PORT": 6379, "OTHER": true}"#, None).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value, serde_json::json!({ "REDIS_PORT": 6379 }));
+    }
+
+    /// Tests that merging only updates the matching keys
+    #[test]
+    fn test_merge_keys() {
+        let file = structured_file(Some(&["REDIS_*"]), &[]);
+
+        let value = merge_file(
+            &file,
+            Some(br#"{"REDIS_PORT": 6379, "REDIS_HOST": "old", "OTHER": true}"#),
+            b"REDIS_PORT=6379\nREDIS_HOST=new\nOTHER=false\n",
+        )
+        .unwrap();
+
+        let value: serde_json::Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "REDIS_PORT": 6379, "REDIS_HOST": "new", "OTHER": true })
+        );
+    }
+
+    /// Tests that keys are renamed locally and reverse mapped when pushing
+    #[test]
+    fn test_key_map() {
+        let file = structured_file(None, &[("DB_URL", "DATABASE_URL")]);
+
+        let value = materialize_file(&file, b"DB_URL=postgres://\nOTHER=1\n", None).unwrap();
+        assert_eq!(
+            String::from_utf8(value).unwrap(),
+            "DATABASE_URL=postgres://\nOTHER=1\n"
+        );
+
+        let value = merge_file(
+            &file,
+            Some(b"DB_URL=old\nREMOVED=1\n"),
+            b"DATABASE_URL=new\nOTHER=1\n",
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(value).unwrap(), "DB_URL=new\nOTHER=1\n");
+    }
+
+    /// Tests that transforms are applied when materializing and reversed
+    /// when merging
+    #[test]
+    fn test_transforms() {
+        let mut file = structured_file(None, &[]);
+        file.transforms
+            .insert("DB_PASSWORD".to_string(), ValueTransform::UrlEncode);
+        file.transforms
+            .insert("*".to_string(), ValueTransform::Quote);
+
+        let value = materialize_file(&file, b"DB_PASSWORD=p@ss/word\nNAME=app\n", None).unwrap();
+        assert_eq!(
+            String::from_utf8(value.clone()).unwrap(),
+            "DB_PASSWORD=\"p%40ss%2Fword\"\nNAME=\"app\"\n"
+        );
+
+        let value = merge_file(&file, None, &value).unwrap();
+        assert_eq!(
+            String::from_utf8(value).unwrap(),
+            "DB_PASSWORD=p@ss/word\nNAME=app\n"
+        );
+    }
+
+    /// Tests that comments, blank lines and ordering are kept when
+    /// materializing into an existing dotenv file
+    #[test]
+    fn test_materialize_existing() {
+        let file = structured_file(None, &[]);
+
+        let existing = "# Database\nexport DB_URL=old # primary\n\n# Cache\nCACHE=\"multi\nline\"\nREMOVED=1\nNAME=app\n";
+        let value = materialize_file(
+            &file,
+            b"NAME=app\nCACHE=\"multi\\nline\"\nDB_URL=new\nADDED=1\n",
+            Some(existing.as_bytes()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(value).unwrap(),
+            "# Database\nexport DB_URL=new\n\n# Cache\nCACHE=\"multi\nline\"\nNAME=app\nADDED=1\n"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! # Task
+//!
+//! Named sequences of subcommands defined in the `[tasks]` section of the
+//! config, each step runs as a separate secret-sync process so that every
+//! step acquires the project lock and reads the config on its own
+
+use eyre::{Context, ContextCompat};
+use indexmap::IndexMap;
+use secret_sync::shell::shell_command;
+use std::{ffi::OsString, fmt::Display, path::Path, process::Command};
+
+/// Prefix of steps that run a command through the system shell instead
+/// of a secret-sync subcommand (e.g. `exec -- ./deploy.sh`)
+const EXEC_PREFIX: &str = "exec --";
+
+/// Single step of a task
+#[derive(Debug, PartialEq, Eq)]
+pub enum TaskStep<'a> {
+    /// Arguments of a secret-sync subcommand
+    Subcommand(&'a str),
+    /// Command to run through the system shell
+    Exec(&'a str),
+}
+
+impl Display for TaskStep<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskStep::Subcommand(arguments) => write!(f, "secret-sync {arguments}"),
+            TaskStep::Exec(command) => f.write_str(command),
+        }
+    }
+}
+
+impl TaskStep<'_> {
+    /// Create the process for the step, subcommands run using the
+    /// secret-sync `executable`
+    pub fn command(&self, executable: &Path) -> Command {
+        match self {
+            TaskStep::Subcommand(arguments) => {
+                shell_command(&format!("\"{}\" {arguments}", executable.display()))
+            }
+            TaskStep::Exec(command) => shell_command(command),
+        }
+    }
+}
+
+/// Parse the `step` of a task
+pub fn parse_task_step(step: &str) -> eyre::Result<TaskStep<'_>> {
+    let step = step.trim();
+
+    if let Some(command) = step.strip_prefix(EXEC_PREFIX) {
+        let command = command.trim();
+        if command.is_empty() {
+            eyre::bail!("step \"{step}\" is missing a command to execute");
+        }
+
+        return Ok(TaskStep::Exec(command));
+    }
+
+    match step.split_whitespace().next() {
+        None => eyre::bail!("task steps cannot be empty"),
+        // Tasks running other tasks could recurse forever
+        Some("run") => eyre::bail!("step \"{step}\" cannot run another task"),
+        Some(_) => Ok(TaskStep::Subcommand(step)),
+    }
+}
+
+/// Get the parsed steps of the task `name` from the `tasks`
+pub fn task_steps<'a>(
+    tasks: &'a IndexMap<String, Vec<String>>,
+    name: &str,
+) -> eyre::Result<Vec<TaskStep<'a>>> {
+    let steps = tasks.get(name).with_context(|| {
+        let available = tasks.keys().map(String::as_str).collect::<Vec<_>>();
+        format!(
+            "unknown task \"{name}\" (available: {})",
+            match available.is_empty() {
+                true => "none".to_string(),
+                false => available.join(", "),
+            }
+        )
+    })?;
+
+    steps
+        .iter()
+        .map(|step| {
+            parse_task_step(step).with_context(|| format!("invalid step in task \"{name}\""))
+        })
+        .collect()
+}
+
+/// Run the `steps` in order stopping at the first failure, `forward`
+/// provides the environment variables passing the global flags on to
+/// each step. Provides the number of steps that were run
+pub async fn run_task_steps(
+    steps: &[TaskStep<'_>],
+    forward: &[(&'static str, OsString)],
+) -> eyre::Result<usize> {
+    let executable = std::env::current_exe().context("failed to determine executable path")?;
+
+    for (index, step) in steps.iter().enumerate() {
+        tracing::info!(index = index + 1, total = steps.len(), %step, "running task step");
+
+        let mut command = step.command(&executable);
+        command.envs(forward.iter().map(|(key, value)| (key, value)));
+
+        let status = tokio::task::spawn_blocking(move || command.status())
+            .await
+            .context("task step failed")?
+            .with_context(|| format!("failed to execute step {}", index + 1))?;
+
+        if !status.success() {
+            eyre::bail!("step {} \"{step}\" exited with {status}", index + 1);
+        }
+    }
+
+    Ok(steps.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::task::{TaskStep, parse_task_step, task_steps};
+    use indexmap::IndexMap;
+
+    /// Tests parsing the steps of a task
+    #[test]
+    fn test_task_steps() {
+        let mut tasks = IndexMap::new();
+        tasks.insert(
+            "deploy".to_string(),
+            vec![
+                "pull --file app".to_string(),
+                "exec -- ./deploy.sh".to_string(),
+            ],
+        );
+
+        assert_eq!(
+            task_steps(&tasks, "deploy").unwrap(),
+            vec![
+                TaskStep::Subcommand("pull --file app"),
+                TaskStep::Exec("./deploy.sh")
+            ]
+        );
+
+        let error = task_steps(&tasks, "build").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown task \"build\" (available: deploy)"
+        );
+
+        assert!(parse_task_step("run deploy").is_err());
+        assert!(parse_task_step("exec --").is_err());
+        assert!(parse_task_step("  ").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! # Template
+//!
+//! Rendering of local templates with secret values injected, used for
+//! configuration files that are not secrets themselves but contain a few
+//! secret values.
+//!
+//! Templates reference secrets by the name given in the render entry:
+//! `{{ db }}` is replaced with the whole value of the secret and
+//! `{{ db.PASSWORD }}` with a single key of a structured (dotenv or JSON)
+//! secret.
+
+use crate::{config::RenderFile, dotenv::parse_structured, fs::FileSystem, secret::SecretManager};
+use eyre::{Context, ContextCompat};
+use indexmap::IndexMap;
+use std::path::Path;
+
+/// Start of a reference within a template
+const REFERENCE_START: &str = "{{";
+
+/// End of a reference within a template
+const REFERENCE_END: &str = "}}";
+
+/// Render the `template` replacing each reference with the matching value
+/// from the `secrets`, keyed by the name the template references them by
+pub fn render_template(
+    template: &str,
+    secrets: &IndexMap<String, Vec<u8>>,
+) -> eyre::Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut structured: IndexMap<&str, IndexMap<String, String>> = IndexMap::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(REFERENCE_START) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start + REFERENCE_START.len()..];
+
+        let end = rest
+            .find(REFERENCE_END)
+            .context("unterminated {{ reference in template")?;
+        let reference = rest[..end].trim();
+        rest = &rest[end + REFERENCE_END.len()..];
+
+        let (name, key) = match reference.split_once('.') {
+            Some((name, key)) => (name, Some(key)),
+            None => (reference, None),
+        };
+
+        let (name, value) = secrets
+            .get_key_value(name)
+            .with_context(|| format!("template references unknown secret \"{name}\""))?;
+
+        match key {
+            Some(key) => {
+                if !structured.contains_key(name.as_str()) {
+                    let values = parse_structured(value)
+                        .with_context(|| format!("secret \"{name}\" is not a structured secret"))?;
+                    structured.insert(name, values);
+                }
+
+                let value = structured[name.as_str()]
+                    .get(key)
+                    .with_context(|| format!("secret \"{name}\" has no key \"{key}\""))?;
+                output.push_str(value);
+            }
+            None => {
+                let value = std::str::from_utf8(value)
+                    .with_context(|| format!("secret \"{name}\" is not valid UTF-8"))?;
+                output.push_str(value);
+            }
+        }
+    }
+
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// Render the template of a render `file` using the current secret values
+/// and write the output
+///
+/// Returns whether the output changed, outputs that are already up to date
+/// are not written
+pub async fn pull_render_file<Fs: FileSystem>(
+    fs: &Fs,
+    secret: &dyn SecretManager,
+    working_path: &Path,
+    file: &RenderFile,
+) -> eyre::Result<bool> {
+    let template_path = file.resolve_template(working_path);
+    let template = fs.read_file(&template_path).await?;
+    let template = String::from_utf8(template).with_context(|| {
+        format!(
+            "template \"{}\" is not valid UTF-8",
+            template_path.display()
+        )
+    })?;
+
+    let mut secrets = IndexMap::new();
+    for (name, secret_name) in &file.secrets {
+        let value = secret.get_secret(secret_name).await?.data;
+        secrets.insert(name.clone(), value.as_bytes().to_vec());
+    }
+
+    let output = render_template(&template, &secrets)
+        .with_context(|| format!("failed to render \"{}\"", template_path.display()))?;
+
+    let file_path = file.resolve_path(working_path);
+    let previous = fs.read_file_optional(&file_path).await?;
+
+    if previous.as_deref() == Some(output.as_bytes()) {
+        tracing::debug!(?file_path, "rendered file unchanged, skipping write");
+        return Ok(false);
+    }
+
+    fs.write_file(&file_path, output.as_bytes()).await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::RenderFile,
+        fs::MockFileSystem,
+        secret::{MockSecretManager, Secret},
+        template::{pull_render_file, render_template},
+    };
+    use indexmap::IndexMap;
+    use mockall::predicate::eq;
+    use std::path::{Path, PathBuf};
+
+    /// Tests replacing whole secret and structured key references
+    #[test]
+    fn test_render_template() {
+        let secrets = IndexMap::from([
+            ("token".to_string(), b"abc123".to_vec()),
+            ("db".to_string(), b"USER=app\nPASSWORD=\"hunter2\"".to_vec()),
+        ]);
+
+        assert_eq!(
+            render_template(
+                "database:\n  user: {{ db.USER }}\n  password: {{db.PASSWORD}}\ntoken: {{ token }}\n",
+                &secrets
+            )
+            .unwrap(),
+            "database:\n  user: app\n  password: hunter2\ntoken: abc123\n"
+        );
+
+        assert!(render_template("{{ missing }}", &secrets).is_err());
+        assert!(render_template("{{ db.MISSING }}", &secrets).is_err());
+        assert!(render_template("{{ token", &secrets).is_err());
+    }
+
+    /// Tests rendering a template into its output file
+    #[tokio::test]
+    async fn test_pull_render_file() {
+        let file = RenderFile {
+            template: PathBuf::from("config.tmpl.yaml"),
+            path: PathBuf::from("config.yaml"),
+            secrets: IndexMap::from([("db".to_string(), "app/db".to_string())]),
+            host: None,
+        };
+
+        let mut secret = MockSecretManager::new();
+        secret
+            .expect_get_secret()
+            .with(eq("app/db"))
+            .return_once(|_name| Ok(Secret::String("PASSWORD=hunter2".to_string()).into()));
+
+        let mut fs = MockFileSystem::new();
+        fs.expect_read_file()
+            .with(eq(Path::new("/config.tmpl.yaml")))
+            .return_once(|_path| Ok(b"password: {{ db.PASSWORD }}".to_vec()));
+        fs.expect_read_file_optional()
+            .with(eq(Path::new("/config.yaml")))
+            .return_once(|_path| Ok(None));
+        fs.expect_write_file()
+            .with(
+                eq(Path::new("/config.yaml")),
+                eq(b"password: hunter2".to_vec()),
+            )
+            .return_once(|_path, _value| Ok(()));
+
+        assert!(
+            pull_render_file(&fs, &secret, Path::new("/"), &file)
+                .await
+                .unwrap()
+        );
+    }
+}
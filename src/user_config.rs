@@ -0,0 +1,202 @@
+//! # User Config
+//!
+//! User level configuration stored outside of any project, used for
+//! settings that are specific to the current user such as named contexts.
+//!
+//! Stored at `<config dir>/secret-sync/config.toml` or the path in the
+//! SECRET_SYNC_USER_CONFIG environment variable
+
+use crate::config::{AwsCredentials, BackendProvider};
+use eyre::{Context as _, ContextCompat};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable overriding the user config path
+const USER_CONFIG_ENV: &str = "SECRET_SYNC_USER_CONFIG";
+
+/// User level configuration
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct UserConfig {
+    /// Name of the active context
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_context: Option<String>,
+
+    /// Named contexts
+    pub contexts: IndexMap<String, NamedContext>,
+
+    /// Credentials moved out of project config files by `config scrub`,
+    /// keyed by the absolute path of the config file
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub credentials: IndexMap<String, AwsCredentials>,
+}
+
+/// Named combination of settings applied to commands while active
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct NamedContext {
+    /// Backend provider to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<BackendProvider>,
+
+    /// AWS profile to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// AWS region to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
+    /// Environment to use
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+impl NamedContext {
+    /// Render a human readable version of the context
+    pub fn to_text(&self, name: &str) -> String {
+        let mut text = format!("context: {name}");
+        let fields = [
+            ("provider", self.provider.map(|value| value.to_string())),
+            ("profile", self.profile.clone()),
+            ("region", self.region.clone()),
+            ("environment", self.environment.clone()),
+        ];
+
+        for (key, value) in fields {
+            if let Some(value) = value {
+                _ = write!(text, "\n  {key}: {value}");
+            }
+        }
+
+        text
+    }
+}
+
+impl UserConfig {
+    /// Get the context to apply, either the `requested` context or the
+    /// active context
+    pub fn resolve_context(
+        &self,
+        requested: Option<&str>,
+    ) -> eyre::Result<Option<(&str, &NamedContext)>> {
+        let Some(name) = requested.or(self.current_context.as_deref()) else {
+            return Ok(None);
+        };
+
+        let (name, context) = self
+            .contexts
+            .get_key_value(name)
+            .with_context(|| format!("unknown context \"{name}\""))?;
+
+        Ok(Some((name, context)))
+    }
+
+    /// Get the credentials stored for the config file at `config_path`
+    pub fn project_credentials(&self, config_path: &Path) -> Option<&AwsCredentials> {
+        let key = credentials_key(config_path).ok()?;
+        self.credentials.get(&key)
+    }
+
+    /// Store the `credentials` for the config file at `config_path`
+    pub fn set_project_credentials(
+        &mut self,
+        config_path: &Path,
+        credentials: AwsCredentials,
+    ) -> eyre::Result<()> {
+        let key = credentials_key(config_path)?;
+        self.credentials.insert(key, credentials);
+        Ok(())
+    }
+}
+
+/// Key that credentials for the config file at `config_path` are stored under
+fn credentials_key(config_path: &Path) -> eyre::Result<String> {
+    let path = std::path::absolute(config_path).context("failed to get absolute config path")?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Get the path to the user config file
+pub fn user_config_path() -> eyre::Result<PathBuf> {
+    if let Some(path) = std::env::var_os(USER_CONFIG_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let config_dir = dirs::config_dir().context("failed to determine user config directory")?;
+    Ok(config_dir.join("secret-sync").join("config.toml"))
+}
+
+/// Read the user config, providing the default config when
+/// no user config exists
+pub async fn read_user_config() -> eyre::Result<UserConfig> {
+    let path = user_config_path()?;
+
+    if !path.exists() {
+        return Ok(UserConfig::default());
+    }
+
+    let value = tokio::fs::read(&path)
+        .await
+        .context("failed to read user config file")?;
+
+    toml::from_slice(&value).context("failed to parse user config file")
+}
+
+/// Write the user config replacing any existing user config
+pub async fn write_user_config(config: &UserConfig) -> eyre::Result<()> {
+    let path = user_config_path()?;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .context("failed to create user config directory")?;
+    }
+
+    let value = toml::to_string_pretty(config).context("failed to serialize user config")?;
+
+    tokio::fs::write(&path, value)
+        .await
+        .context("failed to write user config file")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::user_config::{NamedContext, UserConfig};
+
+    /// Tests that the requested context takes priority over the active context
+    #[test]
+    fn test_resolve_context() {
+        let mut config = UserConfig::default();
+        config.contexts.insert(
+            "staging".to_string(),
+            NamedContext {
+                region: Some("us-east-1".to_string()),
+                ..Default::default()
+            },
+        );
+        config.contexts.insert(
+            "production".to_string(),
+            NamedContext {
+                region: Some("ap-southeast-2".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert!(config.resolve_context(None).unwrap().is_none());
+
+        config.current_context = Some("staging".to_string());
+        let (name, _) = config.resolve_context(None).unwrap().unwrap();
+        assert_eq!(name, "staging");
+
+        let (name, _) = config.resolve_context(Some("production")).unwrap().unwrap();
+        assert_eq!(name, "production");
+
+        assert!(config.resolve_context(Some("unknown")).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! # Version
+//!
+//! Build information about the current binary, used by wrappers and
+//! bug reports to pin behavior
+
+use serde::Serialize;
+
+/// Build information for the current binary
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    /// Semver version of the package
+    pub version: &'static str,
+    /// Short git commit hash the binary was built from
+    pub git_sha: &'static str,
+    /// Date the binary was built (YYYY-MM-DD)
+    pub build_date: &'static str,
+    /// Backends compiled into the binary
+    pub backends: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    /// Get the build information for the current binary
+    pub fn current() -> VersionInfo {
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("SECRET_SYNC_GIT_SHA"),
+            build_date: env!("SECRET_SYNC_BUILD_DATE"),
+            backends: compiled_backends(),
+        }
+    }
+
+    /// Render a human readable version of the build information
+    pub fn to_text(&self) -> String {
+        format!(
+            "secret-sync {} ({} {})\nbackends: {}",
+            self.version,
+            self.git_sha,
+            self.build_date,
+            self.backends.join(", ")
+        )
+    }
+}
+
+/// Get the names of the backends compiled into the binary
+pub fn compiled_backends() -> Vec<&'static str> {
+    let backends = [
+        ("aws", cfg!(feature = "aws")),
+        ("ssm", cfg!(feature = "aws")),
+        ("gcp", cfg!(feature = "gcp")),
+        ("azure", cfg!(feature = "azure")),
+        ("memory", true),
+        ("local", cfg!(feature = "local")),
+        ("sops", true),
+    ];
+
+    backends
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+}
@@ -64,7 +64,7 @@ async fn test_pull_aws_unknown() {
         .arg("pull")
         .assert()
         .failure()
-        .stdout("{\"error\":\"secret \\\"test-secret\\\" not found\",\"success\":false}\n");
+        .stdout("{\"error\":\"secret \\\"test-secret\\\" not found\",\"kind\":\"backend-not-found\",\"success\":false,\"warnings\":[]}\n");
 }
 
 /// Tests pulling a configuration file from a AWS secret manager using
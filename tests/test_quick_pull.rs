@@ -60,5 +60,5 @@ async fn test_quick_pull_aws_unknown() {
         .arg("test-secret")
         .assert()
         .failure()
-        .stdout("{\"error\":\"secret \\\"test-secret\\\" not found\",\"success\":false}\n");
+        .stdout("{\"error\":\"secret \\\"test-secret\\\" not found\",\"kind\":\"backend-not-found\",\"success\":false,\"warnings\":[]}\n");
 }
@@ -0,0 +1,24 @@
+use assert_cmd::Command;
+
+/// Tests that the version subcommand provides the build information as JSON
+#[test]
+fn test_version_json() {
+    let output = Command::new(assert_cmd::cargo_bin!())
+        .arg("version")
+        .arg("--json")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+
+    let info: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["git_sha"].is_string());
+    assert!(info["build_date"].is_string());
+    assert!(
+        info["backends"]
+            .as_array()
+            .is_some_and(|backends| !backends.is_empty())
+    );
+}